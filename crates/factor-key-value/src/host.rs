@@ -1,14 +1,22 @@
-use super::{Cas, SwapError};
+use super::{Cas, RestrictedStore, SwapError};
 use anyhow::{Context, Result};
 use spin_core::{async_trait, wasmtime::component::Resource};
+use spin_locked_app::locked::AccessMode;
 use spin_resource_table::Table;
 use spin_telemetry::traces::{self, Blame};
+use spin_world::spin::key_value::key_value as key_value3;
 use spin_world::v2::key_value;
 use spin_world::wasi::keyvalue as wasi_keyvalue;
-use std::{collections::HashSet, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
 use tracing::instrument;
 
 const DEFAULT_STORE_TABLE_CAPACITY: u32 = 256;
+/// The page size used for `wasi:keyvalue` key listing, which has no limit parameter.
+const WASI_LIST_KEYS_PAGE_SIZE: u32 = 1000;
 
 pub use key_value::Error;
 
@@ -32,10 +40,31 @@ pub trait Store: Sync + Send {
         Ok(())
     }
     async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error>;
+    /// Get a value along with the time it has left to live, if it was set with a TTL.
+    ///
+    /// Backends which can't report expiries return `None` for the TTL.
+    async fn get_with_ttl(&self, key: &str) -> Result<Option<(Vec<u8>, Option<Duration>)>, Error> {
+        Ok(self.get(key).await?.map(|value| (value, None)))
+    }
     async fn set(&self, key: &str, value: &[u8]) -> Result<(), Error>;
+    /// Set a value which the store treats as deleted once `ttl` has elapsed.
+    ///
+    /// A subsequent `set` of the same key clears the expiry.
+    async fn set_with_ttl(&self, key: &str, value: &[u8], ttl: Duration) -> Result<(), Error>;
     async fn delete(&self, key: &str) -> Result<(), Error>;
     async fn exists(&self, key: &str) -> Result<bool, Error>;
     async fn get_keys(&self) -> Result<Vec<String>, Error>;
+    /// List the keys starting with `prefix`, one page at a time.
+    ///
+    /// `cursor` is the [`KeyPage::cursor`] of the previous page, or `None` for the first
+    /// page; its format is backend-specific. A page holds at most roughly `limit` keys and
+    /// may be empty even when more keys follow: only a `None` cursor ends the listing.
+    async fn list_keys(
+        &self,
+        prefix: Option<&str>,
+        cursor: Option<&str>,
+        limit: u32,
+    ) -> Result<KeyPage, Error>;
     async fn get_many(&self, keys: Vec<String>) -> Result<Vec<(String, Option<Vec<u8>>)>, Error>;
     async fn set_many(&self, key_values: Vec<(String, Vec<u8>)>) -> Result<(), Error>;
     async fn delete_many(&self, keys: Vec<String>) -> Result<(), Error>;
@@ -44,8 +73,19 @@ pub trait Store: Sync + Send {
         -> Result<Arc<dyn Cas>, Error>;
 }
 
+/// A page of keys returned by [`Store::list_keys`].
+#[derive(Debug, Default, PartialEq)]
+pub struct KeyPage {
+    /// The keys in this page.
+    pub keys: Vec<String>,
+    /// The cursor for the next page, or `None` if this is the last page.
+    pub cursor: Option<String>,
+}
+
 pub struct KeyValueDispatch {
     allowed_stores: HashSet<String>,
+    /// The access mode of allowed stores whose access is restricted.
+    store_access: HashMap<String, AccessMode>,
     manager: Arc<dyn StoreManager>,
     stores: Table<Arc<dyn Store>>,
     compare_and_swaps: Table<Arc<dyn Cas>>,
@@ -63,6 +103,7 @@ impl KeyValueDispatch {
     ) -> Self {
         Self {
             allowed_stores,
+            store_access: HashMap::new(),
             manager,
             stores: Table::new(capacity),
             compare_and_swaps: Table::new(capacity),
@@ -87,6 +128,21 @@ impl KeyValueDispatch {
         &self.allowed_stores
     }
 
+    /// Restricts the operations allowed on the given stores.
+    ///
+    /// Stores which are not in `store_access` allow both reads and writes.
+    pub fn restrict_access(&mut self, store_access: HashMap<String, AccessMode>) {
+        self.store_access = store_access;
+    }
+
+    /// Wraps a newly opened store to enforce its access mode.
+    fn restrict(&self, name: &str, store: Arc<dyn Store>) -> Arc<dyn Store> {
+        match self.store_access.get(name) {
+            Some(&access) => RestrictedStore::wrap(store, access),
+            None => store,
+        }
+    }
+
     pub fn get_store_wasi<T: 'static>(
         &self,
         store: Resource<T>,
@@ -117,6 +173,7 @@ impl key_value::HostStore for KeyValueDispatch {
             if self.allowed_stores.contains(&name) {
                 let store = self.manager.get(&name).await?;
                 store.after_open().await?;
+                let store = self.restrict(&name, store);
                 let store_idx = self
                     .stores
                     .push(store)
@@ -185,6 +242,103 @@ impl key_value::HostStore for KeyValueDispatch {
     }
 }
 
+impl key_value3::Host for KeyValueDispatch {}
+
+impl key_value3::HostStore for KeyValueDispatch {
+    async fn open(&mut self, name: String) -> Result<Result<Resource<key_value3::Store>, Error>> {
+        let result = <Self as key_value::HostStore>::open(self, name).await?;
+        Ok(result.map(|s| Resource::new_own(s.rep())))
+    }
+
+    async fn get(
+        &mut self,
+        store: Resource<key_value3::Store>,
+        key: String,
+    ) -> Result<Result<Option<Vec<u8>>, Error>> {
+        let this = Resource::new_borrow(store.rep());
+        <Self as key_value::HostStore>::get(self, this, key).await
+    }
+
+    async fn set(
+        &mut self,
+        store: Resource<key_value3::Store>,
+        key: String,
+        value: Vec<u8>,
+    ) -> Result<Result<(), Error>> {
+        let this = Resource::new_borrow(store.rep());
+        <Self as key_value::HostStore>::set(self, this, key, value).await
+    }
+
+    #[instrument(name = "spin_key_value.set_with_ttl", skip_all, fields(otel.kind = "client"))]
+    async fn set_with_ttl(
+        &mut self,
+        store: Resource<key_value3::Store>,
+        key: String,
+        value: Vec<u8>,
+        ttl_seconds: u32,
+    ) -> Result<Result<(), Error>> {
+        let store = self.get_store(store)?;
+        if ttl_seconds == 0 {
+            return Ok(Err(Error::Other("ttl must be greater than zero".into())));
+        }
+        let ttl = Duration::from_secs(ttl_seconds.into());
+        Ok(store
+            .set_with_ttl(&key, &value, ttl)
+            .await
+            .map_err(track_error_on_span))
+    }
+
+    async fn delete(
+        &mut self,
+        store: Resource<key_value3::Store>,
+        key: String,
+    ) -> Result<Result<(), Error>> {
+        let this = Resource::new_borrow(store.rep());
+        <Self as key_value::HostStore>::delete(self, this, key).await
+    }
+
+    async fn exists(
+        &mut self,
+        store: Resource<key_value3::Store>,
+        key: String,
+    ) -> Result<Result<bool, Error>> {
+        let this = Resource::new_borrow(store.rep());
+        <Self as key_value::HostStore>::exists(self, this, key).await
+    }
+
+    async fn get_keys(
+        &mut self,
+        store: Resource<key_value3::Store>,
+    ) -> Result<Result<Vec<String>, Error>> {
+        let this = Resource::new_borrow(store.rep());
+        <Self as key_value::HostStore>::get_keys(self, this).await
+    }
+
+    #[instrument(name = "spin_key_value.list_keys", skip_all, fields(otel.kind = "client"))]
+    async fn list_keys(
+        &mut self,
+        store: Resource<key_value3::Store>,
+        prefix: Option<String>,
+        cursor: Option<String>,
+        limit: u32,
+    ) -> Result<Result<key_value3::KeyPage, Error>> {
+        let store = self.get_store(store)?;
+        Ok(store
+            .list_keys(prefix.as_deref(), cursor.as_deref(), limit)
+            .await
+            .map(|page| key_value3::KeyPage {
+                keys: page.keys,
+                cursor: page.cursor,
+            })
+            .map_err(track_error_on_span))
+    }
+
+    async fn drop(&mut self, store: Resource<key_value3::Store>) -> Result<()> {
+        self.stores.remove(store.rep());
+        Ok(())
+    }
+}
+
 /// Make sure that infrastructure related errors are tracked in the current span.
 fn track_error_on_span(err: Error) -> Error {
     let blame = match err {
@@ -213,6 +367,7 @@ impl wasi_keyvalue::store::Host for KeyValueDispatch {
         if self.allowed_stores.contains(&identifier) {
             let store = self.manager.get(&identifier).await.map_err(to_wasi_err)?;
             store.after_open().await.map_err(to_wasi_err)?;
+            let store = self.restrict(&identifier, store);
             let store_idx = self
                 .stores
                 .push(store)
@@ -280,16 +435,12 @@ impl wasi_keyvalue::store::HostBucket for KeyValueDispatch {
         self_: Resource<Bucket>,
         cursor: Option<String>,
     ) -> Result<wasi_keyvalue::store::KeyResponse, wasi_keyvalue::store::Error> {
-        match cursor {
-            Some(_) => Err(wasi_keyvalue::store::Error::Other(
-                "list_keys: cursor not supported".to_owned(),
-            )),
-            None => {
-                let store = self.get_store_wasi(self_)?;
-                let keys = store.get_keys().await.map_err(to_wasi_err)?;
-                Ok(wasi_keyvalue::store::KeyResponse { keys, cursor: None })
-            }
-        }
+        let store = self.get_store_wasi(self_)?;
+        let KeyPage { keys, cursor } = store
+            .list_keys(None, cursor.as_deref(), WASI_LIST_KEYS_PAGE_SIZE)
+            .await
+            .map_err(to_wasi_err)?;
+        Ok(wasi_keyvalue::store::KeyResponse { keys, cursor })
     }
 
     async fn drop(&mut self, rep: Resource<Bucket>) -> anyhow::Result<()> {
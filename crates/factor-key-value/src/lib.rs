@@ -1,5 +1,6 @@
 mod host;
 pub mod runtime_config;
+mod tiered;
 mod util;
 
 use std::{
@@ -12,14 +13,17 @@ use spin_factors::{
     ConfigureAppContext, Factor, FactorData, FactorInstanceBuilder, InitContext, PrepareContext,
     RuntimeFactors,
 };
-use spin_locked_app::MetadataKey;
+use spin_locked_app::{locked::AccessMode, MetadataKey};
 
 /// Metadata key for key-value stores.
 pub const KEY_VALUE_STORES_KEY: MetadataKey<Vec<String>> = MetadataKey::new("key_value_stores");
-pub use host::{log_cas_error, log_error, Error, KeyValueDispatch, Store, StoreManager};
+/// Metadata key for the access mode of key-value stores whose access is restricted.
+pub const KEY_VALUE_STORE_ACCESS_KEY: MetadataKey<HashMap<String, AccessMode>> =
+    MetadataKey::new("key_value_store_access");
+pub use host::{log_cas_error, log_error, Error, KeyPage, KeyValueDispatch, Store, StoreManager};
 pub use runtime_config::RuntimeConfig;
 use spin_core::async_trait;
-pub use util::DelegatingStoreManager;
+pub use util::{DelegatingStoreManager, RestrictedStore};
 
 /// A factor that provides key-value storage.
 #[derive(Default)]
@@ -42,6 +46,9 @@ impl Factor for KeyValueFactor {
     fn init(&mut self, ctx: &mut impl InitContext<Self>) -> anyhow::Result<()> {
         ctx.link_bindings(spin_world::v1::key_value::add_to_linker::<_, FactorData<Self>>)?;
         ctx.link_bindings(spin_world::v2::key_value::add_to_linker::<_, FactorData<Self>>)?;
+        ctx.link_bindings(
+            spin_world::spin::key_value::key_value::add_to_linker::<_, FactorData<Self>>,
+        )?;
         ctx.link_bindings(spin_world::wasi::keyvalue::store::add_to_linker::<_, FactorData<Self>>)?;
         ctx.link_bindings(spin_world::wasi::keyvalue::batch::add_to_linker::<_, FactorData<Self>>)?;
         ctx.link_bindings(
@@ -59,8 +66,9 @@ impl Factor for KeyValueFactor {
         let delegating_manager = DelegatingStoreManager::new(store_managers);
         let store_manager = Arc::new(delegating_manager);
 
-        // Build component -> allowed stores map
+        // Build component -> allowed stores and restricted access maps
         let mut component_allowed_stores = HashMap::new();
+        let mut component_store_access = HashMap::new();
         for component in ctx.app().components() {
             let component_id = component.id().to_string();
             let key_value_stores = component
@@ -75,6 +83,16 @@ impl Factor for KeyValueFactor {
                     "unknown key_value_stores label {label:?} for component {component_id:?}"
                 );
             }
+            let store_access = component
+                .get_metadata(KEY_VALUE_STORE_ACCESS_KEY)?
+                .unwrap_or_default();
+            for label in store_access.keys() {
+                ensure!(
+                    key_value_stores.contains(label),
+                    "key_value_store_access label {label:?} for component {component_id:?} is not in key_value_stores"
+                );
+            }
+            component_store_access.insert(component_id.clone(), store_access);
             component_allowed_stores.insert(component_id, key_value_stores);
             // TODO: warn (?) on unused store?
         }
@@ -82,6 +100,7 @@ impl Factor for KeyValueFactor {
         Ok(AppState {
             store_manager,
             component_allowed_stores,
+            component_store_access,
         })
     }
 
@@ -95,9 +114,15 @@ impl Factor for KeyValueFactor {
             .get(ctx.app_component().id())
             .expect("component should be in component_stores")
             .clone();
+        let store_access = app_state
+            .component_store_access
+            .get(ctx.app_component().id())
+            .cloned()
+            .unwrap_or_default();
         Ok(InstanceBuilder {
             store_manager: app_state.store_manager.clone(),
             allowed_stores,
+            store_access,
         })
     }
 }
@@ -116,6 +141,11 @@ pub struct AppState {
     /// This is a map from component ID to the set of store labels that the
     /// component is allowed to use.
     component_allowed_stores: HashMap<String, HashSet<String>>,
+    /// The access mode of each component's restricted stores.
+    ///
+    /// This is a map from component ID to a map from store label to access
+    /// mode. Allowed stores which are absent allow both reads and writes.
+    component_store_access: HashMap<String, HashMap<String, AccessMode>>,
 }
 
 impl AppState {
@@ -177,6 +207,8 @@ pub struct InstanceBuilder {
     store_manager: Arc<AppStoreManager>,
     /// The allowed stores for this component instance.
     allowed_stores: HashSet<String>,
+    /// The access mode of this component instance's restricted stores.
+    store_access: HashMap<String, AccessMode>,
 }
 
 impl FactorInstanceBuilder for InstanceBuilder {
@@ -186,11 +218,11 @@ impl FactorInstanceBuilder for InstanceBuilder {
         let Self {
             store_manager,
             allowed_stores,
+            store_access,
         } = self;
-        Ok(KeyValueDispatch::new_with_capacity(
-            allowed_stores,
-            store_manager,
-            u32::MAX,
-        ))
+        let mut dispatch =
+            KeyValueDispatch::new_with_capacity(allowed_stores, store_manager, u32::MAX);
+        dispatch.restrict_access(store_access);
+        Ok(dispatch)
     }
 }
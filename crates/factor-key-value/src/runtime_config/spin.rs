@@ -1,11 +1,17 @@
 //! Runtime configuration implementation used by Spin CLI.
 
+use crate::tiered::{TieredStoreConfig, TieredStoreManager};
 use crate::{RuntimeConfig, StoreManager};
+
+pub use crate::tiered::TIERED_STORE_TYPE;
 use anyhow::Context as _;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use spin_factors::runtime_config::toml::GetTomlValue;
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
 /// Defines the construction of a key value store from a serialized runtime config.
 pub trait MakeKeyValueStore: 'static + Send + Sync {
@@ -83,6 +89,9 @@ impl RuntimeConfigResolver {
         &mut self,
         store_type: T,
     ) -> anyhow::Result<()> {
+        if T::RUNTIME_CONFIG_TYPE == TIERED_STORE_TYPE {
+            anyhow::bail!("the key value store type {TIERED_STORE_TYPE:?} is reserved");
+        }
         if self
             .store_types
             .insert(T::RUNTIME_CONFIG_TYPE, store_from_toml_fn(store_type))
@@ -98,12 +107,25 @@ impl RuntimeConfigResolver {
 
     /// Resolves a toml table into a runtime config.
     ///
-    /// The default stores are also added to the runtime config.
+    /// The default stores are also added to the runtime config. Stores of type
+    /// [`TIERED_STORE_TYPE`] are resolved last, as they wrap another labeled store.
     pub fn resolve(&self, table: Option<&impl GetTomlValue>) -> anyhow::Result<RuntimeConfig> {
-        let mut runtime_config = self.resolve_from_toml(table)?.unwrap_or_default();
+        let configs = Self::store_configs_from_toml(table)?;
+        let (tiered, configs): (Vec<_>, Vec<_>) = configs
+            .into_iter()
+            .partition(|(_, config)| config.type_ == TIERED_STORE_TYPE);
+
+        let mut runtime_config = RuntimeConfig::default();
+        for (label, config) in configs {
+            let store_manager = self.store_manager_from_config(config).with_context(|| {
+                format!("could not configure key-value store with label '{label}'")
+            })?;
+            runtime_config.add_store_manager(label.clone(), store_manager);
+        }
 
         for (&label, config) in &self.defaults {
-            if !runtime_config.store_managers.contains_key(label) {
+            let is_tiered = tiered.iter().any(|(tiered_label, _)| tiered_label == label);
+            if !is_tiered && !runtime_config.store_managers.contains_key(label) {
                 let store_manager = self
                     .store_manager_from_config(config.clone())
                     .with_context(|| {
@@ -112,27 +134,28 @@ impl RuntimeConfigResolver {
                 runtime_config.add_store_manager(label.to_owned(), store_manager);
             }
         }
+
+        let tiered_labels = tiered
+            .iter()
+            .map(|(label, _)| label.clone())
+            .collect::<HashSet<_>>();
+        for (label, config) in tiered {
+            let store_manager = tiered_store_manager(&runtime_config, &tiered_labels, config)
+                .with_context(|| {
+                    format!("could not configure key-value store with label '{label}'")
+                })?;
+            runtime_config.add_store_manager(label, store_manager);
+        }
         Ok(runtime_config)
     }
 
-    fn resolve_from_toml(
-        &self,
+    fn store_configs_from_toml(
         table: Option<&impl GetTomlValue>,
-    ) -> anyhow::Result<Option<RuntimeConfig>> {
+    ) -> anyhow::Result<HashMap<String, StoreConfig>> {
         let Some(table) = table.and_then(|t| t.get("key_value_store")) else {
-            return Ok(None);
+            return Ok(HashMap::new());
         };
-        let table: HashMap<String, StoreConfig> = table.clone().try_into()?;
-
-        let mut runtime_config = RuntimeConfig::default();
-        for (label, config) in table {
-            let store_manager = self.store_manager_from_config(config).with_context(|| {
-                format!("could not configure key-value store with label '{label}'")
-            })?;
-            runtime_config.add_store_manager(label.clone(), store_manager);
-        }
-
-        Ok(Some(runtime_config))
+        Ok(table.clone().try_into()?)
     }
 
     /// Given a [`StoreConfig`], returns a store manager.
@@ -151,6 +174,33 @@ impl RuntimeConfigResolver {
     }
 }
 
+/// Wraps the already resolved store that a tiered store config refers to.
+fn tiered_store_manager(
+    runtime_config: &RuntimeConfig,
+    tiered_labels: &HashSet<String>,
+    config: StoreConfig,
+) -> anyhow::Result<Arc<dyn StoreManager>> {
+    let config: TieredStoreConfig = config
+        .config
+        .try_into()
+        .context("could not parse key-value runtime config")?;
+    if tiered_labels.contains(&config.store) {
+        anyhow::bail!(
+            "a tiered store must wrap a non-tiered store, but the store with label '{}' is tiered",
+            config.store
+        );
+    }
+    let inner = runtime_config
+        .get_store_manager(&config.store)
+        .with_context(|| {
+            format!(
+                "a tiered store must wrap a non-tiered store, but there is no such store with label '{}'",
+                config.store
+            )
+        })?;
+    Ok(Arc::new(TieredStoreManager::new(inner, config)))
+}
+
 #[derive(Deserialize, Clone)]
 pub struct StoreConfig {
     #[serde(rename = "type")]
@@ -0,0 +1,373 @@
+use crate::{Cas, Error, KeyPage, Store, StoreManager, SwapError};
+use moka::{
+    policy::{EvictionPolicy, Expiry},
+    sync::Cache,
+};
+use serde::Deserialize;
+use spin_core::async_trait;
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::sync::OnceCell;
+
+/// The runtime config type of a [`TieredStoreManager`].
+pub const TIERED_STORE_TYPE: &str = "tiered";
+
+const DEFAULT_TTL_SECONDS: u64 = 60;
+const DEFAULT_MAX_SIZE_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Runtime configuration for a tiered store.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TieredStoreConfig {
+    /// The label of the store to cache.
+    pub store: String,
+    /// How long a value may be served from the cache, in seconds.
+    #[serde(default = "default_ttl_seconds")]
+    pub ttl_seconds: u64,
+    /// The maximum total size of the cached keys and values, in bytes.
+    #[serde(default = "default_max_size_bytes")]
+    pub max_size_bytes: u64,
+}
+
+fn default_ttl_seconds() -> u64 {
+    DEFAULT_TTL_SECONDS
+}
+
+fn default_max_size_bytes() -> u64 {
+    DEFAULT_MAX_SIZE_BYTES
+}
+
+/// A [`StoreManager`] which serves reads of another labeled store from a bounded,
+/// in-process LRU cache.
+///
+/// The cache is shared by every instance of the app. A value is cached until the
+/// configured TTL elapses or the key itself expires, whichever is sooner. Writes made
+/// through the tiered store invalidate the affected keys, but writes made any other way
+/// (through the inner label or by another process) may not be seen until the cached
+/// value's TTL has elapsed.
+pub struct TieredStoreManager {
+    inner: Arc<dyn StoreManager>,
+    inner_label: String,
+    config: TieredStoreConfig,
+    store: OnceCell<Arc<TieredStore>>,
+}
+
+impl TieredStoreManager {
+    /// Creates a tiered store manager caching the store `config.store` of `inner`.
+    pub fn new(inner: Arc<dyn StoreManager>, config: TieredStoreConfig) -> Self {
+        Self {
+            inner,
+            inner_label: config.store.clone(),
+            config,
+            store: OnceCell::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl StoreManager for TieredStoreManager {
+    async fn get(&self, name: &str) -> Result<Arc<dyn Store>, Error> {
+        let _ = name;
+        let store = self
+            .store
+            .get_or_try_init(|| async {
+                let inner = self.inner.get(&self.inner_label).await?;
+                let entries = Cache::builder()
+                    .eviction_policy(EvictionPolicy::lru())
+                    .max_capacity(self.config.max_size_bytes)
+                    .weigher(|key: &String, value: &CachedValue| {
+                        (key.len() + value.value.len())
+                            .try_into()
+                            .unwrap_or(u32::MAX)
+                    })
+                    .time_to_live(Duration::from_secs(self.config.ttl_seconds))
+                    .expire_after(CachedValueExpiry)
+                    .build();
+                let cache = Arc::new(ReadCache {
+                    entries,
+                    generation: Mutex::new(0),
+                });
+                Ok::<_, Error>(Arc::new(TieredStore { inner, cache }))
+            })
+            .await?;
+        Ok(store.clone())
+    }
+
+    fn is_defined(&self, store_name: &str) -> bool {
+        let _ = store_name;
+        true
+    }
+
+    fn summary(&self, store_name: &str) -> Option<String> {
+        let _ = store_name;
+        let inner = self
+            .inner
+            .summary(&self.inner_label)
+            .unwrap_or_else(|| format!("store {:?}", self.inner_label));
+        Some(format!("{inner} with an in-memory cache"))
+    }
+}
+
+#[derive(Clone)]
+struct CachedValue {
+    value: Arc<[u8]>,
+    /// The time the key had left to live when it was read, if it was set with a TTL.
+    ttl: Option<Duration>,
+}
+
+/// Expires each cached value no later than its key expires in the inner store.
+struct CachedValueExpiry;
+
+impl Expiry<String, CachedValue> for CachedValueExpiry {
+    fn expire_after_create(
+        &self,
+        _key: &String,
+        value: &CachedValue,
+        _created_at: Instant,
+    ) -> Option<Duration> {
+        value.ttl
+    }
+
+    fn expire_after_update(
+        &self,
+        _key: &String,
+        value: &CachedValue,
+        _updated_at: Instant,
+        _duration_until_expiry: Option<Duration>,
+    ) -> Option<Duration> {
+        value.ttl
+    }
+}
+
+/// The cache shared by a tiered store and its compare-and-swaps.
+struct ReadCache {
+    entries: Cache<String, CachedValue>,
+    /// Bumped by every write through the tiered store, so that a read which raced with
+    /// a write doesn't cache the value it read from before the write.
+    generation: Mutex<u64>,
+}
+
+impl ReadCache {
+    fn get(&self, key: &str) -> Option<Vec<u8>> {
+        self.entries.get(key).map(|cached| cached.value.to_vec())
+    }
+
+    fn contains_key(&self, key: &str) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    /// The current generation, to be passed to [`Self::insert`] for a value read after
+    /// calling this.
+    fn generation(&self) -> u64 {
+        *self.generation.lock().unwrap()
+    }
+
+    /// Caches a value read from the inner store, unless the cache has been invalidated
+    /// since `generation` was taken.
+    fn insert(&self, generation: u64, key: &str, value: &[u8], ttl: Option<Duration>) {
+        let current = self.generation.lock().unwrap();
+        if *current == generation {
+            let value = CachedValue {
+                value: value.into(),
+                ttl,
+            };
+            self.entries.insert(key.to_owned(), value);
+        }
+    }
+
+    /// Invalidates `keys` after they have been written to the inner store.
+    fn invalidate<'a>(&self, keys: impl IntoIterator<Item = &'a str>) {
+        let mut generation = self.generation.lock().unwrap();
+        *generation += 1;
+        for key in keys {
+            self.entries.invalidate(key);
+        }
+    }
+}
+
+struct TieredStore {
+    inner: Arc<dyn Store>,
+    cache: Arc<ReadCache>,
+}
+
+#[async_trait]
+impl Store for TieredStore {
+    async fn after_open(&self) -> Result<(), Error> {
+        self.inner.after_open().await
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        if let Some(value) = self.cache.get(key) {
+            return Ok(Some(value));
+        }
+        Ok(self.get_with_ttl(key).await?.map(|(value, _)| value))
+    }
+
+    async fn get_with_ttl(&self, key: &str) -> Result<Option<(Vec<u8>, Option<Duration>)>, Error> {
+        // The cached TTL is out of date as soon as it is cached, so always read through.
+        let generation = self.cache.generation();
+        let entry = self.inner.get_with_ttl(key).await?;
+        if let Some((value, ttl)) = &entry {
+            self.cache.insert(generation, key, value, *ttl);
+        }
+        Ok(entry)
+    }
+
+    async fn set(&self, key: &str, value: &[u8]) -> Result<(), Error> {
+        let result = self.inner.set(key, value).await;
+        self.cache.invalidate([key]);
+        result
+    }
+
+    async fn set_with_ttl(&self, key: &str, value: &[u8], ttl: Duration) -> Result<(), Error> {
+        let result = self.inner.set_with_ttl(key, value, ttl).await;
+        self.cache.invalidate([key]);
+        result
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), Error> {
+        let result = self.inner.delete(key).await;
+        self.cache.invalidate([key]);
+        result
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool, Error> {
+        // Cached values expire no later than their keys do, so a cached key still exists.
+        if self.cache.contains_key(key) {
+            return Ok(true);
+        }
+        self.inner.exists(key).await
+    }
+
+    async fn get_keys(&self) -> Result<Vec<String>, Error> {
+        self.inner.get_keys().await
+    }
+
+    async fn list_keys(
+        &self,
+        prefix: Option<&str>,
+        cursor: Option<&str>,
+        limit: u32,
+    ) -> Result<KeyPage, Error> {
+        self.inner.list_keys(prefix, cursor, limit).await
+    }
+
+    async fn get_many(&self, keys: Vec<String>) -> Result<Vec<(String, Option<Vec<u8>>)>, Error> {
+        let mut results = Vec::with_capacity(keys.len());
+        let mut misses = vec![];
+        for key in keys {
+            match self.cache.get(&key) {
+                Some(value) => results.push((key, Some(value))),
+                None => misses.push(key),
+            }
+        }
+        // The misses aren't cached, since `get_many` doesn't report their expiries.
+        if !misses.is_empty() {
+            results.extend(self.inner.get_many(misses).await?);
+        }
+        Ok(results)
+    }
+
+    async fn set_many(&self, key_values: Vec<(String, Vec<u8>)>) -> Result<(), Error> {
+        let keys = key_values
+            .iter()
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+        let result = self.inner.set_many(key_values).await;
+        self.cache.invalidate(keys.iter().map(String::as_str));
+        result
+    }
+
+    async fn delete_many(&self, keys: Vec<String>) -> Result<(), Error> {
+        let result = self.inner.delete_many(keys.clone()).await;
+        self.cache.invalidate(keys.iter().map(String::as_str));
+        result
+    }
+
+    async fn increment(&self, key: String, delta: i64) -> Result<i64, Error> {
+        let result = self.inner.increment(key.clone(), delta).await;
+        self.cache.invalidate([key.as_str()]);
+        result
+    }
+
+    async fn new_compare_and_swap(
+        &self,
+        bucket_rep: u32,
+        key: &str,
+    ) -> Result<Arc<dyn Cas>, Error> {
+        let inner = self.inner.new_compare_and_swap(bucket_rep, key).await?;
+        Ok(Arc::new(TieredCas {
+            inner,
+            key: key.to_owned(),
+            cache: self.cache.clone(),
+        }))
+    }
+}
+
+/// A [`Cas`] which invalidates the cached value when it swaps.
+///
+/// `current` always reads through to the inner store, since the swap must be based
+/// on the inner store's view of the value.
+struct TieredCas {
+    inner: Arc<dyn Cas>,
+    key: String,
+    cache: Arc<ReadCache>,
+}
+
+#[async_trait]
+impl Cas for TieredCas {
+    async fn current(&self) -> anyhow::Result<Option<Vec<u8>>, Error> {
+        self.inner.current().await
+    }
+
+    async fn swap(&self, value: Vec<u8>) -> anyhow::Result<(), SwapError> {
+        let result = self.inner.swap(value).await;
+        self.cache.invalidate([self.key.as_str()]);
+        result
+    }
+
+    async fn bucket_rep(&self) -> u32 {
+        self.inner.bucket_rep().await
+    }
+
+    async fn key(&self) -> String {
+        self.inner.key().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_cache() -> ReadCache {
+        ReadCache {
+            entries: Cache::builder().expire_after(CachedValueExpiry).build(),
+            generation: Mutex::new(0),
+        }
+    }
+
+    #[test]
+    fn read_racing_a_write_is_not_cached() {
+        let cache = read_cache();
+
+        // A read starts, then a write completes before the read can cache its value.
+        let generation = cache.generation();
+        cache.invalidate(["key"]);
+        cache.insert(generation, "key", b"stale", None);
+        assert_eq!(cache.get("key"), None);
+
+        let generation = cache.generation();
+        cache.insert(generation, "key", b"fresh", None);
+        assert_eq!(cache.get("key").as_deref(), Some(&b"fresh"[..]));
+    }
+
+    #[test]
+    fn values_expire_with_their_keys() {
+        let cache = read_cache();
+        cache.insert(0, "key", b"value", Some(Duration::ZERO));
+        assert_eq!(cache.get("key"), None);
+        assert!(!cache.contains_key("key"));
+    }
+}
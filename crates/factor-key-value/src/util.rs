@@ -1,6 +1,7 @@
-use crate::{Error, Store, StoreManager};
+use crate::{Cas, Error, KeyPage, Store, StoreManager};
 use spin_core::async_trait;
-use std::{collections::HashMap, sync::Arc};
+use spin_locked_app::locked::AccessMode;
+use std::{collections::HashMap, sync::Arc, time::Duration};
 
 /// A [`StoreManager`] which delegates to other `StoreManager`s based on the store label.
 pub struct DelegatingStoreManager {
@@ -34,3 +35,121 @@ impl StoreManager for DelegatingStoreManager {
         None
     }
 }
+
+/// A [`Store`] which rejects the operations its [`AccessMode`] does not allow with
+/// [`Error::AccessDenied`].
+///
+/// Compare-and-swap reads and then writes, so it requires both.
+pub struct RestrictedStore {
+    inner: Arc<dyn Store>,
+    access: AccessMode,
+}
+
+impl RestrictedStore {
+    /// Wraps `inner` if `access` restricts it, or returns it as-is otherwise.
+    pub fn wrap(inner: Arc<dyn Store>, access: AccessMode) -> Arc<dyn Store> {
+        match access {
+            AccessMode::ReadWrite => inner,
+            access => Arc::new(Self { inner, access }),
+        }
+    }
+
+    fn check_read(&self) -> Result<(), Error> {
+        if self.access.can_read() {
+            Ok(())
+        } else {
+            Err(Error::AccessDenied)
+        }
+    }
+
+    fn check_write(&self) -> Result<(), Error> {
+        if self.access.can_write() {
+            Ok(())
+        } else {
+            Err(Error::AccessDenied)
+        }
+    }
+}
+
+#[async_trait]
+impl Store for RestrictedStore {
+    async fn after_open(&self) -> Result<(), Error> {
+        self.inner.after_open().await
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        self.check_read()?;
+        self.inner.get(key).await
+    }
+
+    async fn get_with_ttl(&self, key: &str) -> Result<Option<(Vec<u8>, Option<Duration>)>, Error> {
+        self.check_read()?;
+        self.inner.get_with_ttl(key).await
+    }
+
+    async fn set(&self, key: &str, value: &[u8]) -> Result<(), Error> {
+        self.check_write()?;
+        self.inner.set(key, value).await
+    }
+
+    async fn set_with_ttl(&self, key: &str, value: &[u8], ttl: Duration) -> Result<(), Error> {
+        self.check_write()?;
+        self.inner.set_with_ttl(key, value, ttl).await
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), Error> {
+        self.check_write()?;
+        self.inner.delete(key).await
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool, Error> {
+        self.check_read()?;
+        self.inner.exists(key).await
+    }
+
+    async fn get_keys(&self) -> Result<Vec<String>, Error> {
+        self.check_read()?;
+        self.inner.get_keys().await
+    }
+
+    async fn list_keys(
+        &self,
+        prefix: Option<&str>,
+        cursor: Option<&str>,
+        limit: u32,
+    ) -> Result<KeyPage, Error> {
+        self.check_read()?;
+        self.inner.list_keys(prefix, cursor, limit).await
+    }
+
+    async fn get_many(&self, keys: Vec<String>) -> Result<Vec<(String, Option<Vec<u8>>)>, Error> {
+        self.check_read()?;
+        self.inner.get_many(keys).await
+    }
+
+    async fn set_many(&self, key_values: Vec<(String, Vec<u8>)>) -> Result<(), Error> {
+        self.check_write()?;
+        self.inner.set_many(key_values).await
+    }
+
+    async fn delete_many(&self, keys: Vec<String>) -> Result<(), Error> {
+        self.check_write()?;
+        self.inner.delete_many(keys).await
+    }
+
+    async fn increment(&self, key: String, delta: i64) -> Result<i64, Error> {
+        self.check_read()?;
+        self.check_write()?;
+        self.inner.increment(key, delta).await
+    }
+
+    async fn new_compare_and_swap(
+        &self,
+        bucket_rep: u32,
+        key: &str,
+    ) -> Result<Arc<dyn Cas>, Error> {
+        self.check_read()?;
+        self.check_write()?;
+        self.inner.new_compare_and_swap(bucket_rep, key).await
+    }
+}
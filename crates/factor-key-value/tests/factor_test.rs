@@ -1,10 +1,12 @@
 use anyhow::bail;
-use spin_core::async_trait;
-use spin_factor_key_value::{Cas, KeyValueFactor, RuntimeConfig, Store, StoreManager};
+use spin_core::{async_trait, wasmtime::component::Resource};
+use spin_factor_key_value::runtime_config::spin::RuntimeConfigResolver;
+use spin_factor_key_value::{Cas, KeyPage, KeyValueFactor, RuntimeConfig, Store, StoreManager};
 use spin_factors::RuntimeFactors;
 use spin_factors_test::{toml, TestEnvironment};
+use spin_key_value_spin::SpinKeyValueStore;
 use spin_world::v2::key_value::{Error, HostStore};
-use std::{collections::HashSet, sync::Arc};
+use std::{collections::HashSet, sync::Arc, time::Duration};
 
 #[derive(RuntimeFactors)]
 struct TestFactors {
@@ -98,6 +100,186 @@ async fn errors_when_store_is_not_allowed() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn read_only_store_rejects_writes() -> anyhow::Result<()> {
+    let mut resolver = RuntimeConfigResolver::new();
+    resolver.register_store_type(SpinKeyValueStore::new(None))?;
+    let runtime_config = resolver.resolve(Some(&toml! {
+        [key_value_store.default]
+        type = "spin"
+    }))?;
+    let factors = TestFactors {
+        key_value: KeyValueFactor::new(),
+    };
+    let env = TestEnvironment::new(factors).extend_manifest(toml! {
+        [component.test-component]
+        source = "does-not-exist.wasm"
+        key_value_stores = [{ label = "default", access = "read" }]
+    });
+    let mut state = env
+        .runtime_config(runtime_config)?
+        .build_instance_state()
+        .await?;
+
+    let store = state.key_value.open("default".to_owned()).await??;
+    assert!(matches!(
+        state
+            .key_value
+            .get(Resource::new_borrow(store.rep()), "key".to_owned())
+            .await?,
+        Ok(None)
+    ));
+    assert!(matches!(
+        state
+            .key_value
+            .set(
+                Resource::new_borrow(store.rep()),
+                "key".to_owned(),
+                b"value".to_vec()
+            )
+            .await?,
+        Err(Error::AccessDenied)
+    ));
+    assert!(matches!(
+        state
+            .key_value
+            .delete(Resource::new_borrow(store.rep()), "key".to_owned())
+            .await?,
+        Err(Error::AccessDenied)
+    ));
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn tiered_store_caches_reads_until_written() -> anyhow::Result<()> {
+    let mut resolver = RuntimeConfigResolver::new();
+    resolver.register_store_type(SpinKeyValueStore::new(None))?;
+    let runtime_config = resolver.resolve(Some(&toml! {
+        [key_value_store.default]
+        type = "spin"
+
+        [key_value_store.cached]
+        type = "tiered"
+        store = "default"
+    }))?;
+    let inner = runtime_config
+        .get_store_manager("default")
+        .unwrap()
+        .get("default")
+        .await?;
+    let cached = runtime_config
+        .get_store_manager("cached")
+        .unwrap()
+        .get("cached")
+        .await?;
+
+    inner.set("key", b"one").await?;
+    assert_eq!(cached.get("key").await?.as_deref(), Some(&b"one"[..]));
+
+    // Writes which bypass the tiered store are not seen until the cache expires...
+    inner.set("key", b"two").await?;
+    assert_eq!(cached.get("key").await?.as_deref(), Some(&b"one"[..]));
+
+    // ...but writes through it invalidate the cached value.
+    cached.set("key", b"three").await?;
+    assert_eq!(cached.get("key").await?.as_deref(), Some(&b"three"[..]));
+    assert_eq!(cached.increment("counter".into(), 1).await?, 1);
+    assert_eq!(
+        cached.get("counter").await?.as_deref(),
+        Some(&1i64.to_le_bytes()[..])
+    );
+    assert_eq!(cached.increment("counter".into(), 1).await?, 2);
+    assert_eq!(
+        cached.get("counter").await?.as_deref(),
+        Some(&2i64.to_le_bytes()[..])
+    );
+    cached.delete("key").await?;
+    assert_eq!(cached.get("key").await?, None);
+    assert_eq!(inner.get("key").await?, None);
+
+    Ok(())
+}
+
+#[test]
+fn tiered_store_must_wrap_a_defined_store() {
+    let resolver = RuntimeConfigResolver::new();
+    let Err(err) = resolver.resolve(Some(&toml! {
+        [key_value_store.cached]
+        type = "tiered"
+        store = "missing"
+    })) else {
+        panic!("expected resolving a tiered store over an undefined store to fail");
+    };
+    assert!(format!("{err:#}").contains("no such store with label 'missing'"));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+async fn tiered_store_does_not_cache_past_expiry() -> anyhow::Result<()> {
+    let mut resolver = RuntimeConfigResolver::new();
+    resolver.register_store_type(SpinKeyValueStore::new(None))?;
+    let runtime_config = resolver.resolve(Some(&toml! {
+        [key_value_store.default]
+        type = "spin"
+
+        [key_value_store.cached]
+        type = "tiered"
+        store = "default"
+        ttl_seconds = 3600
+    }))?;
+    let cached = runtime_config
+        .get_store_manager("cached")
+        .unwrap()
+        .get("cached")
+        .await?;
+
+    cached
+        .set_with_ttl("key", b"short-lived", Duration::from_millis(50))
+        .await?;
+    assert_eq!(
+        cached.get("key").await?.as_deref(),
+        Some(&b"short-lived"[..])
+    );
+    assert!(cached.exists("key").await?);
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(cached.get("key").await?, None);
+    assert!(!cached.exists("key").await?);
+
+    Ok(())
+}
+
+#[test]
+fn tiered_store_must_not_wrap_a_tiered_store() {
+    let mut resolver = RuntimeConfigResolver::new();
+    resolver
+        .register_store_type(SpinKeyValueStore::new(None))
+        .unwrap();
+    // Each order of the labels must fail, whichever tiered store is resolved first.
+    for (outer, inner) in [("a", "b"), ("b", "a")] {
+        let table: toml::Table = format!(
+            r#"
+            [key_value_store.default]
+            type = "spin"
+
+            [key_value_store.{outer}]
+            type = "tiered"
+            store = "{inner}"
+
+            [key_value_store.{inner}]
+            type = "tiered"
+            store = "default"
+            "#
+        )
+        .parse()
+        .unwrap();
+        let Err(err) = resolver.resolve(Some(&table)) else {
+            panic!("expected resolving a tiered store over a tiered store to fail");
+        };
+        assert!(format!("{err:#}").contains(&format!("the store with label '{inner}' is tiered")));
+    }
+}
+
 fn mock_store_manager() -> Arc<dyn StoreManager> {
     Arc::new(MockStoreManager)
 }
@@ -129,6 +311,10 @@ impl Store for MockStore {
         let _ = (key, value);
         todo!()
     }
+    async fn set_with_ttl(&self, key: &str, value: &[u8], ttl: Duration) -> Result<(), Error> {
+        let _ = (key, value, ttl);
+        todo!()
+    }
     async fn delete(&self, key: &str) -> Result<(), Error> {
         let _ = key;
         todo!()
@@ -140,6 +326,15 @@ impl Store for MockStore {
     async fn get_keys(&self) -> Result<Vec<String>, Error> {
         todo!()
     }
+    async fn list_keys(
+        &self,
+        prefix: Option<&str>,
+        cursor: Option<&str>,
+        limit: u32,
+    ) -> Result<KeyPage, Error> {
+        let _ = (prefix, cursor, limit);
+        todo!()
+    }
 
     async fn get_many(
         &self,
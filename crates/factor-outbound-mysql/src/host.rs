@@ -1,5 +1,6 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use spin_core::wasmtime::component::Resource;
+use spin_world::spin::mysql::mysql as v3;
 use spin_world::v1::mysql as v1;
 use spin_world::v2::mysql::{self as v2, Connection};
 use spin_world::v2::rdbms_types as v2_types;
@@ -7,24 +8,47 @@ use spin_world::v2::rdbms_types::ParameterValue;
 use tracing::field::Empty;
 use tracing::{instrument, Level};
 
-use crate::client::Client;
+use crate::client::{Client, RowStream};
 use crate::InstanceState;
 
 impl<C: Client> InstanceState<C> {
-    async fn open_connection(&mut self, address: &str) -> Result<Resource<Connection>, v2::Error> {
+    async fn open_connection<Conn: 'static>(
+        &mut self,
+        address: &str,
+    ) -> Result<Resource<Conn>, v2::Error> {
+        let client = Self::new_client(address).await?;
         self.connections
-            .push(
-                C::build_client(address)
-                    .await
-                    .map_err(|e| v2::Error::ConnectionFailed(format!("{e:?}")))?,
-            )
+            .push(crate::Connection {
+                address: address.to_owned(),
+                client,
+            })
             .map_err(|_| v2::Error::ConnectionFailed("too many connections".into()))
             .map(Resource::new_own)
     }
 
-    async fn get_client(&mut self, connection: Resource<Connection>) -> Result<&mut C, v2::Error> {
+    async fn new_client(address: &str) -> Result<C, v2::Error> {
+        C::build_client(address)
+            .await
+            .map_err(|e| v2::Error::ConnectionFailed(format!("{e:?}")))
+    }
+
+    async fn get_client<Conn: 'static>(
+        &mut self,
+        connection: Resource<Conn>,
+    ) -> Result<&mut C, v2::Error> {
         self.connections
             .get_mut(connection.rep())
+            .map(|c| &mut c.client)
+            .ok_or_else(|| v2::Error::ConnectionFailed("no connection found".into()))
+    }
+
+    async fn get_address<Conn: 'static>(
+        &self,
+        connection: &Resource<Conn>,
+    ) -> Result<&str, v2::Error> {
+        self.connections
+            .get(connection.rep())
+            .map(|c| c.address.as_str())
             .ok_or_else(|| v2::Error::ConnectionFailed("no connection found".into()))
     }
 
@@ -84,7 +108,108 @@ impl<C: Client> v2::HostConnection for InstanceState<C> {
     }
 }
 
-impl<C: Send> v2_types::Host for InstanceState<C> {
+impl<C: Client> v3::Host for InstanceState<C> {}
+
+impl<C: Client> v3::HostConnection for InstanceState<C> {
+    #[instrument(name = "spin_outbound_mysql.open", skip(self, address), err(level = Level::INFO), fields(otel.kind = "client", db.system = "mysql", db.address = Empty, server.port = Empty, db.namespace = Empty))]
+    async fn open(&mut self, address: String) -> Result<Resource<v3::Connection>, v2::Error> {
+        spin_factor_outbound_networking::record_address_fields(&address);
+
+        if !self
+            .is_address_allowed(&address)
+            .await
+            .map_err(|e| v2::Error::Other(e.to_string()))?
+        {
+            return Err(v2::Error::ConnectionFailed(format!(
+                "address {address} is not permitted"
+            )));
+        }
+        self.open_connection(&address).await
+    }
+
+    #[instrument(name = "spin_outbound_mysql.execute", skip(self, connection, params), err(level = Level::INFO), fields(otel.kind = "client", db.system = "mysql", otel.name = statement))]
+    async fn execute(
+        &mut self,
+        connection: Resource<v3::Connection>,
+        statement: String,
+        params: Vec<ParameterValue>,
+    ) -> Result<(), v2::Error> {
+        self.get_client(connection)
+            .await?
+            .execute(statement, params)
+            .await
+    }
+
+    #[instrument(name = "spin_outbound_mysql.query", skip(self, connection, params), err(level = Level::INFO), fields(otel.kind = "client", db.system = "mysql", otel.name = statement))]
+    async fn query(
+        &mut self,
+        connection: Resource<v3::Connection>,
+        statement: String,
+        params: Vec<ParameterValue>,
+    ) -> Result<v2_types::RowSet, v2::Error> {
+        self.get_client(connection)
+            .await?
+            .query(statement, params)
+            .await
+    }
+
+    #[instrument(name = "spin_outbound_mysql.query_stream", skip(self, connection, params), err(level = Level::INFO), fields(otel.kind = "client", db.system = "mysql", otel.name = statement))]
+    async fn query_stream(
+        &mut self,
+        connection: Resource<v3::Connection>,
+        statement: String,
+        params: Vec<ParameterValue>,
+    ) -> Result<Resource<v3::RowStream>, v2::Error> {
+        // The stream gets a client of its own, which it holds until it is dropped.
+        let address = self.get_address(&connection).await?;
+        let client = Self::new_client(address).await?;
+        let stream = client.query_stream(statement, params).await?;
+        self.row_streams
+            .push(stream)
+            .map_err(|_| v2::Error::ConnectionFailed("too many row streams".into()))
+            .map(Resource::new_own)
+    }
+
+    async fn drop(&mut self, connection: Resource<v3::Connection>) -> Result<()> {
+        self.connections.remove(connection.rep());
+        Ok(())
+    }
+}
+
+impl<C: Client> v3::HostRowStream for InstanceState<C> {
+    async fn columns(&mut self, stream: Resource<v3::RowStream>) -> Result<Vec<v2_types::Column>> {
+        let stream = self
+            .row_streams
+            .get(stream.rep())
+            .context("no row stream found")?;
+        Ok(stream.columns())
+    }
+
+    #[instrument(name = "spin_outbound_mysql.next", skip(self, stream), err(level = Level::INFO), fields(otel.kind = "client", db.system = "mysql"))]
+    async fn next(
+        &mut self,
+        stream: Resource<v3::RowStream>,
+        max_rows: u32,
+    ) -> Result<Vec<v2_types::Row>, v2::Error> {
+        if max_rows == 0 {
+            return Err(v2::Error::BadParameter(
+                "max-rows must be greater than zero".into(),
+            ));
+        }
+        self.row_streams
+            .get_mut(stream.rep())
+            .ok_or_else(|| v2::Error::Other("no row stream found".into()))?
+            .next(max_rows as usize)
+            .await
+    }
+
+    async fn drop(&mut self, stream: Resource<v3::RowStream>) -> Result<()> {
+        self.row_streams.remove(stream.rep());
+        Ok(())
+    }
+}
+
+impl<C: Client> v2_types::Host for InstanceState<C> {
     fn convert_error(&mut self, error: v2::Error) -> Result<v2::Error> {
         Ok(error)
     }
@@ -7,6 +7,7 @@ use spin_factor_outbound_networking::{
     config::allowed_hosts::OutboundAllowedHosts, OutboundNetworkingFactor,
 };
 use spin_factors::{Factor, FactorData, InitContext, RuntimeFactors, SelfInstanceBuilder};
+use spin_world::spin::mysql::mysql as v3;
 use spin_world::v1::mysql as v1;
 use spin_world::v2::mysql::{self as v2};
 
@@ -22,6 +23,7 @@ impl<C: Send + Sync + Client + 'static> Factor for OutboundMysqlFactor<C> {
     fn init(&mut self, ctx: &mut impl InitContext<Self>) -> anyhow::Result<()> {
         ctx.link_bindings(v1::add_to_linker::<_, FactorData<Self>>)?;
         ctx.link_bindings(v2::add_to_linker::<_, FactorData<Self>>)?;
+        ctx.link_bindings(v3::add_to_linker::<_, FactorData<Self>>)?;
         Ok(())
     }
 
@@ -42,6 +44,7 @@ impl<C: Send + Sync + Client + 'static> Factor for OutboundMysqlFactor<C> {
         Ok(InstanceState {
             allowed_hosts,
             connections: Default::default(),
+            row_streams: Default::default(),
         })
     }
 }
@@ -60,9 +63,17 @@ impl<C> OutboundMysqlFactor<C> {
     }
 }
 
-pub struct InstanceState<C> {
+pub struct InstanceState<C: Client> {
     allowed_hosts: OutboundAllowedHosts,
-    connections: spin_resource_table::Table<C>,
+    connections: spin_resource_table::Table<Connection<C>>,
+    row_streams: spin_resource_table::Table<C::RowStream>,
 }
 
-impl<C: Send + 'static> SelfInstanceBuilder for InstanceState<C> {}
+/// An open connection, and the address it was opened to, from which the
+/// connections for its row streams are opened.
+struct Connection<C> {
+    address: String,
+    client: C,
+}
+
+impl<C: Client> SelfInstanceBuilder for InstanceState<C> {}
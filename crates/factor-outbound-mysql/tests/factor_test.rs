@@ -1,5 +1,5 @@
 use anyhow::{bail, Result};
-use spin_factor_outbound_mysql::client::Client;
+use spin_factor_outbound_mysql::client::{Client, RowStream};
 use spin_factor_outbound_mysql::OutboundMysqlFactor;
 use spin_factor_outbound_networking::OutboundNetworkingFactor;
 use spin_factor_variables::VariablesFactor;
@@ -8,7 +8,7 @@ use spin_factors_test::{toml, TestEnvironment};
 use spin_world::async_trait;
 use spin_world::v2::mysql::HostConnection;
 use spin_world::v2::mysql::{self as v2};
-use spin_world::v2::rdbms_types::{ParameterValue, RowSet};
+use spin_world::v2::rdbms_types::{Column, DbValue, ParameterValue, RowSet};
 
 #[derive(RuntimeFactors)]
 struct TestFactors {
@@ -105,8 +105,23 @@ async fn exercise_query() -> anyhow::Result<()> {
 // TODO: We can expand this mock to track calls and simulate return values
 pub struct MockClient {}
 
+pub struct MockRowStream {}
+
+#[async_trait]
+impl RowStream for MockRowStream {
+    fn columns(&self) -> Vec<Column> {
+        vec![]
+    }
+
+    async fn next(&mut self, _max_rows: usize) -> Result<Vec<Vec<DbValue>>, v2::Error> {
+        Ok(vec![])
+    }
+}
+
 #[async_trait]
 impl Client for MockClient {
+    type RowStream = MockRowStream;
+
     async fn build_client(_address: &str) -> anyhow::Result<Self>
     where
         Self: Sized,
@@ -132,4 +147,12 @@ impl Client for MockClient {
             rows: vec![],
         })
     }
+
+    async fn query_stream(
+        self,
+        _statement: String,
+        _params: Vec<ParameterValue>,
+    ) -> Result<MockRowStream, v2::Error> {
+        Ok(MockRowStream {})
+    }
 }
@@ -1,12 +1,17 @@
+use std::future::Future;
+use std::pin::Pin;
+
 use anyhow::{Context, Result};
+use futures::StreamExt;
 use native_tls::TlsConnector;
 use postgres_native_tls::MakeTlsConnector;
 use spin_world::async_trait;
-use spin_world::spin::postgres4_0_0::postgres::{
+use spin_world::spin::postgres4_1_0::postgres::{
     self as v4, Column, DbValue, ParameterValue, RowSet,
 };
+use tokio_postgres::error::SqlState;
 use tokio_postgres::types::ToSql;
-use tokio_postgres::{config::SslMode, NoTls, Row};
+use tokio_postgres::{config::SslMode, NoTls, Row, Statement};
 
 use crate::types::{convert_data_type, convert_entry, to_sql_parameter};
 
@@ -14,6 +19,8 @@ use crate::types::{convert_data_type, convert_entry, to_sql_parameter};
 const CONNECTION_POOL_SIZE: usize = 64;
 /// Max addresses for which to keep pools in cache.
 const CONNECTION_POOL_CACHE_CAPACITY: u64 = 16;
+/// Max prepared statements to keep in each connection's cache.
+const STATEMENT_CACHE_CAPACITY: usize = 128;
 
 /// A factory object for Postgres clients. This abstracts
 /// details of client creation such as pooling.
@@ -86,6 +93,9 @@ fn create_connection_pool(address: &str) -> Result<deadpool_postgres::Pool> {
 
 #[async_trait]
 pub trait Client: Send + Sync + 'static {
+    /// The type of stream returned by `query_stream`.
+    type RowStream: RowStream;
+
     async fn execute(
         &self,
         statement: String,
@@ -97,6 +107,41 @@ pub trait Client: Send + Sync + 'static {
         statement: String,
         params: Vec<ParameterValue>,
     ) -> Result<RowSet, v4::Error>;
+
+    /// Queries the database, returning a stream of the resulting rows which
+    /// holds the client until it is dropped.
+    async fn query_stream(
+        self,
+        statement: String,
+        params: Vec<ParameterValue>,
+    ) -> Result<Self::RowStream, v4::Error>
+    where
+        Self: Sized;
+
+    /// Runs statements which take no parameters and return no rows, such as
+    /// those beginning and ending transactions.
+    async fn batch_execute(&self, statements: &str) -> Result<(), v4::Error>;
+
+    /// Closes the client's connection rather than returning it to any pool it
+    /// came from, so that state the connection can't be relied on to reset,
+    /// such as an unfinished transaction, isn't passed on to its next user.
+    fn discard(self)
+    where
+        Self: Sized,
+    {
+    }
+}
+
+/// The rows resulting from a query, read from the database as they are
+/// needed rather than all at once.
+#[async_trait]
+pub trait RowStream: Send + Sync + 'static {
+    /// The columns of the rows.
+    fn columns(&self) -> Vec<Column>;
+
+    /// Reads up to `max_rows` more rows, returning fewer only once the stream
+    /// has ended.
+    async fn next(&mut self, max_rows: usize) -> Result<Vec<Vec<DbValue>>, v4::Error>;
 }
 
 /// Extract weak-typed error data for WIT purposes
@@ -141,8 +186,68 @@ fn query_failed(e: tokio_postgres::error::Error) -> v4::Error {
     v4::Error::QueryFailed(query_error)
 }
 
+/// Prepares `statement` on `client`, reusing the prepared statement from
+/// `client`'s cache if it has run `statement` before.
+async fn prepare_cached(
+    client: &deadpool_postgres::Object,
+    statement: &str,
+) -> Result<Statement, v4::Error> {
+    // Most clients run a small, fixed set of statements, so rather than track
+    // which to evict, start over in the rare case that the cache fills up.
+    if client.statement_cache.size() >= STATEMENT_CACHE_CAPACITY {
+        client.statement_cache.clear();
+    }
+    client.prepare_cached(statement).await.map_err(query_failed)
+}
+
+/// Runs `run` with `statement` prepared on `client`.
+///
+/// Postgres refuses to run a prepared statement whose result columns have
+/// changed since it was prepared, for instance by `ALTER TABLE`, so in that
+/// case the cached statement is evicted and `run` is tried once more with the
+/// statement prepared afresh.
+async fn run_prepared<T, F, Fut>(
+    client: &deadpool_postgres::Object,
+    statement: &str,
+    run: F,
+) -> Result<T, v4::Error>
+where
+    F: Fn(Statement) -> Fut,
+    Fut: Future<Output = Result<T, tokio_postgres::Error>>,
+{
+    let prepared = prepare_cached(client, statement).await?;
+    let err = match run(prepared).await {
+        Err(err) if is_stale_plan(&err) => err,
+        result => return result.map_err(query_failed),
+    };
+    client.statement_cache.remove(statement, &[]);
+    let retried = async { run(client.prepare_cached(statement).await?).await };
+    retried.await.map_err(|retry_err| {
+        // Inside a transaction, the first error aborted it, so the retry
+        // can't have succeeded and the first error is the one to report.
+        if retry_err.code() == Some(&SqlState::IN_FAILED_SQL_TRANSACTION) {
+            query_failed(err)
+        } else {
+            query_failed(retry_err)
+        }
+    })
+}
+
+/// Whether `err` is Postgres refusing to run a prepared statement because its
+/// result columns have changed.
+fn is_stale_plan(err: &tokio_postgres::Error) -> bool {
+    err.as_db_error().is_some_and(|err| {
+        *err.code() == SqlState::FEATURE_NOT_SUPPORTED
+            && err
+                .message()
+                .starts_with("cached plan must not change result type")
+    })
+}
+
 #[async_trait]
 impl Client for deadpool_postgres::Object {
+    type RowStream = PooledRowStream;
+
     async fn execute(
         &self,
         statement: String,
@@ -159,10 +264,11 @@ impl Client for deadpool_postgres::Object {
             .map(|b| b.as_ref() as &(dyn ToSql + Sync))
             .collect();
 
-        self.as_ref()
-            .execute(&statement, params_refs.as_slice())
-            .await
-            .map_err(query_failed)
+        let params_refs = params_refs.as_slice();
+        run_prepared(self, &statement, |statement| async move {
+            self.as_ref().execute(&statement, params_refs).await
+        })
+        .await
     }
 
     async fn query(
@@ -181,11 +287,11 @@ impl Client for deadpool_postgres::Object {
             .map(|b| b.as_ref() as &(dyn ToSql + Sync))
             .collect();
 
-        let results = self
-            .as_ref()
-            .query(&statement, params_refs.as_slice())
-            .await
-            .map_err(query_failed)?;
+        let params_refs = params_refs.as_slice();
+        let results = run_prepared(self, &statement, |statement| async move {
+            self.as_ref().query(&statement, params_refs).await
+        })
+        .await?;
 
         if results.is_empty() {
             return Ok(RowSet {
@@ -203,6 +309,99 @@ impl Client for deadpool_postgres::Object {
 
         Ok(RowSet { columns, rows })
     }
+
+    async fn query_stream(
+        self,
+        statement: String,
+        params: Vec<ParameterValue>,
+    ) -> Result<PooledRowStream, v4::Error> {
+        let params = params
+            .iter()
+            .map(to_sql_parameter)
+            .collect::<Result<Vec<_>>>()
+            .map_err(|e| v4::Error::BadParameter(format!("{e:?}")))?;
+
+        let (client, params) = (&self, params.as_slice());
+        let (statement, rows) = run_prepared(client, &statement, |statement| async move {
+            let rows = client
+                .as_ref()
+                .query_raw(
+                    &statement,
+                    params.iter().map(|p| p.as_ref() as &(dyn ToSql + Sync)),
+                )
+                .await?;
+            Ok((statement, rows))
+        })
+        .await?;
+        let columns = statement
+            .columns()
+            .iter()
+            .map(|column| Column {
+                name: column.name().to_owned(),
+                data_type: convert_data_type(column.type_()),
+            })
+            .collect();
+
+        Ok(PooledRowStream {
+            client: Some(self),
+            columns,
+            rows: Box::pin(rows),
+        })
+    }
+
+    async fn batch_execute(&self, statements: &str) -> Result<(), v4::Error> {
+        self.as_ref()
+            .batch_execute(statements)
+            .await
+            .map_err(query_failed)
+    }
+
+    fn discard(self) {
+        drop(deadpool_postgres::Object::take(self));
+    }
+}
+
+/// A `RowStream` holding the pooled client it was queried on.
+pub struct PooledRowStream {
+    // `None` only once the stream has ended, when the client has been
+    // returned to its pool.
+    client: Option<deadpool_postgres::Object>,
+    columns: Vec<Column>,
+    rows: Pin<Box<tokio_postgres::RowStream>>,
+}
+
+#[async_trait]
+impl RowStream for PooledRowStream {
+    fn columns(&self) -> Vec<Column> {
+        self.columns.clone()
+    }
+
+    async fn next(&mut self, max_rows: usize) -> Result<Vec<Vec<DbValue>>, v4::Error> {
+        let mut rows = vec![];
+        while self.client.is_some() && rows.len() < max_rows {
+            match self.rows.next().await {
+                Some(row) => {
+                    let row = row.map_err(query_failed)?;
+                    rows.push(convert_row(&row).map_err(|e| {
+                        v4::Error::QueryFailed(v4::QueryError::Text(format!("{e:?}")))
+                    })?);
+                }
+                None => self.client = None,
+            }
+        }
+        Ok(rows)
+    }
+}
+
+impl Drop for PooledRowStream {
+    fn drop(&mut self) {
+        // The server would go on sending the rest of the rows, to be read and
+        // thrown away before the client could be used again, so close the
+        // connection instead.
+        if let Some(client) = self.client.take() {
+            client.discard();
+        }
+    }
 }
 
 fn infer_columns(row: &Row) -> Vec<Column> {
@@ -249,3 +448,392 @@ impl std::fmt::Display for ArcError {
         std::fmt::Display::fmt(&self.0, f)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::transaction::Transaction;
+
+    /// The number of rows in the `numbers` table, each holding its index.
+    const NUMBER_COUNT: i32 = 5;
+
+    #[derive(Default)]
+    struct State {
+        connections: usize,
+        prepared: usize,
+        /// The commands which ended transactions, as reported to the client.
+        ended: Vec<String>,
+        /// Bumped whenever the schema changes, invalidating the statements
+        /// prepared before.
+        schema_version: usize,
+    }
+
+    /// A stand-in for a Postgres server, answering `SELECT n FROM numbers`
+    /// with `NUMBER_COUNT` rows and `SELECT n FROM endless` with rows for as
+    /// long as the client reads them.
+    #[derive(Clone)]
+    struct FakePostgres {
+        state: Arc<Mutex<State>>,
+        closed: mpsc::UnboundedSender<()>,
+    }
+
+    impl FakePostgres {
+        /// Starts the server, returning its address and a receiver notified
+        /// whenever a client connection closes.
+        async fn start() -> (Self, String, mpsc::UnboundedReceiver<()>) {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let address = format!(
+                "host=127.0.0.1 port={} user=spin sslmode=disable",
+                listener.local_addr().unwrap().port()
+            );
+            let (closed, closed_rx) = mpsc::unbounded_channel();
+            let postgres = Self {
+                state: Default::default(),
+                closed,
+            };
+            let server = postgres.clone();
+            tokio::spawn(async move {
+                loop {
+                    let (stream, _) = listener.accept().await.unwrap();
+                    server.state().connections += 1;
+                    tokio::spawn(server.clone().serve(stream));
+                }
+            });
+            (postgres, address, closed_rx)
+        }
+
+        fn state(&self) -> std::sync::MutexGuard<'_, State> {
+            self.state.lock().unwrap()
+        }
+
+        async fn serve(self, mut stream: TcpStream) {
+            let _ = self.serve_messages(&mut stream).await;
+            let _ = self.closed.send(());
+        }
+
+        async fn serve_messages(&self, stream: &mut TcpStream) -> std::io::Result<()> {
+            let len = stream.read_i32().await?;
+            let mut startup = vec![0; len as usize - 4];
+            stream.read_exact(&mut startup).await?;
+            let mut out = Vec::new();
+            message(&mut out, b'R', &0i32.to_be_bytes());
+            message(&mut out, b'Z', b"I");
+            stream.write_all(&out).await?;
+
+            // Statement names mapped to their queries and the schema version
+            // they were prepared against.
+            let mut statements = HashMap::new();
+            let mut bound = String::new();
+            // Whether an error has been sent, so that messages are ignored
+            // until the next `Sync`.
+            let mut failed = false;
+            // Whether the transaction in progress, if any, has been aborted.
+            let mut transaction: Option<bool> = None;
+            loop {
+                let tag = stream.read_u8().await?;
+                let len = stream.read_i32().await?;
+                let mut body = vec![0; len as usize - 4];
+                stream.read_exact(&mut body).await?;
+                let mut out = Vec::new();
+                match tag {
+                    b'S' => {
+                        failed = false;
+                        message(&mut out, b'Z', status(transaction));
+                    }
+                    _ if failed => {}
+                    b'P' | b'B' if transaction == Some(true) => {
+                        failed = true;
+                        error(&mut out, "25P02", "current transaction is aborted");
+                    }
+                    b'P' => {
+                        let [name, query] = strings(&body);
+                        if query == "SELECT n FROM missing" {
+                            failed = true;
+                            transaction = transaction.map(|_| true);
+                            error(&mut out, "42P01", "relation \"missing\" does not exist");
+                        } else {
+                            let mut state = self.state();
+                            state.prepared += 1;
+                            statements.insert(name, (query, state.schema_version));
+                            message(&mut out, b'1', &[]);
+                        }
+                    }
+                    b'D' => {
+                        let [name, _] = strings(&body[1..]);
+                        message(&mut out, b't', &0i16.to_be_bytes());
+                        let mut columns = 1i16.to_be_bytes().to_vec();
+                        columns.extend_from_slice(b"n\0");
+                        columns.extend_from_slice(&0i32.to_be_bytes());
+                        columns.extend_from_slice(&0i16.to_be_bytes());
+                        columns.extend_from_slice(&23i32.to_be_bytes()); // INT4
+                        columns.extend_from_slice(&4i16.to_be_bytes());
+                        columns.extend_from_slice(&(-1i32).to_be_bytes());
+                        columns.extend_from_slice(&0i16.to_be_bytes());
+                        assert!(statements.contains_key(&name));
+                        message(&mut out, b'T', &columns);
+                    }
+                    b'B' => {
+                        let [_, name] = strings(&body);
+                        let (query, version) = &statements[&name];
+                        if *version < self.state().schema_version {
+                            failed = true;
+                            transaction = transaction.map(|_| true);
+                            error(&mut out, "0A000", "cached plan must not change result type");
+                        } else {
+                            bound = query.clone();
+                            message(&mut out, b'2', &[]);
+                        }
+                    }
+                    b'E' => {
+                        let count = match bound.as_str() {
+                            "SELECT n FROM numbers" => NUMBER_COUNT,
+                            "SELECT n FROM endless" => i32::MAX,
+                            query => panic!("unexpected query {query:?}"),
+                        };
+                        for n in 0..count {
+                            let mut row = 1i16.to_be_bytes().to_vec();
+                            row.extend_from_slice(&4i32.to_be_bytes());
+                            row.extend_from_slice(&n.to_be_bytes());
+                            message(&mut out, b'D', &row);
+                            stream.write_all(&out).await?;
+                            out.clear();
+                        }
+                        message(&mut out, b'C', format!("SELECT {count}\0").as_bytes());
+                    }
+                    b'C' => message(&mut out, b'3', &[]),
+                    b'Q' => {
+                        let [query, _] = strings(&body);
+                        let command = match query.as_str() {
+                            "BEGIN" => {
+                                transaction = Some(false);
+                                "BEGIN"
+                            }
+                            "COMMIT" | "ROLLBACK" => {
+                                // An aborted transaction is rolled back
+                                // rather than committed.
+                                let command = match transaction.take() {
+                                    Some(false) => query.as_str(),
+                                    _ => "ROLLBACK",
+                                };
+                                self.state().ended.push(command.to_owned());
+                                command
+                            }
+                            query if query.starts_with("SAVEPOINT ") => "SAVEPOINT",
+                            query if query.starts_with("ROLLBACK TO SAVEPOINT ") => {
+                                transaction = Some(false);
+                                "ROLLBACK"
+                            }
+                            // Such as the query cleaning a pooled connection
+                            // for reuse.
+                            _ => "SET",
+                        };
+                        message(&mut out, b'C', format!("{command}\0").as_bytes());
+                        message(&mut out, b'Z', status(transaction));
+                    }
+                    b'X' => return Ok(()),
+                    tag => panic!("unexpected message {:?}", tag as char),
+                }
+                stream.write_all(&out).await?;
+            }
+        }
+
+        /// Changes the schema such that statements prepared so far can no
+        /// longer be run.
+        fn change_schema(&self) {
+            self.state().schema_version += 1;
+        }
+    }
+
+    /// The status reported by `ReadyForQuery` given whether the transaction in
+    /// progress, if any, has been aborted.
+    fn status(transaction: Option<bool>) -> &'static [u8] {
+        match transaction {
+            None => b"I",
+            Some(false) => b"T",
+            Some(true) => b"E",
+        }
+    }
+
+    fn error(out: &mut Vec<u8>, code: &str, text: &str) {
+        let body = format!("SERROR\0C{code}\0M{text}\0\0");
+        message(out, b'E', body.as_bytes());
+    }
+
+    fn message(out: &mut Vec<u8>, tag: u8, body: &[u8]) {
+        out.push(tag);
+        out.extend_from_slice(&(body.len() as i32 + 4).to_be_bytes());
+        out.extend_from_slice(body);
+    }
+
+    /// The first two null-terminated strings of `body`.
+    fn strings(body: &[u8]) -> [String; 2] {
+        let mut strings = body
+            .split(|b| *b == 0)
+            .map(|s| String::from_utf8_lossy(s).into_owned());
+        [strings.next().unwrap(), strings.next().unwrap()]
+    }
+
+    fn numbers(rows: Vec<Vec<DbValue>>) -> Vec<i32> {
+        rows.into_iter()
+            .map(|row| match row[..] {
+                [DbValue::Int32(n)] => n,
+                _ => panic!("unexpected row {row:?}"),
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn rows_are_streamed_in_batches() {
+        let (_server, address, _) = FakePostgres::start().await;
+        let client = PooledTokioClientFactory::default()
+            .get_client(&address)
+            .await
+            .unwrap();
+
+        let mut stream = client
+            .query_stream("SELECT n FROM numbers".into(), vec![])
+            .await
+            .unwrap();
+        let columns = stream.columns();
+        assert_eq!(columns.len(), 1);
+        assert_eq!(columns[0].name, "n");
+
+        let mut batches = vec![];
+        loop {
+            let rows = numbers(stream.next(2).await.unwrap());
+            let ended = rows.len() < 2;
+            batches.push(rows);
+            if ended {
+                break;
+            }
+        }
+        assert_eq!(batches, [vec![0, 1], vec![2, 3], vec![4]]);
+        assert!(stream.next(2).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn ended_streams_return_the_client_to_its_pool() {
+        let (server, address, _) = FakePostgres::start().await;
+        let factory = PooledTokioClientFactory::default();
+        let client = factory.get_client(&address).await.unwrap();
+
+        let mut stream = client
+            .query_stream("SELECT n FROM numbers".into(), vec![])
+            .await
+            .unwrap();
+        let rows = stream.next(NUMBER_COUNT as usize + 1).await.unwrap();
+        assert_eq!(rows.len(), NUMBER_COUNT as usize);
+        drop(stream);
+
+        let client = factory.get_client(&address).await.unwrap();
+        let rows = client
+            .query("SELECT n FROM numbers".into(), vec![])
+            .await
+            .unwrap()
+            .rows;
+        assert_eq!(rows.len(), NUMBER_COUNT as usize);
+        assert_eq!(server.state().connections, 1);
+    }
+
+    #[tokio::test]
+    async fn dropping_a_stream_early_closes_its_connection() {
+        let (_server, address, mut closed) = FakePostgres::start().await;
+        let client = PooledTokioClientFactory::default()
+            .get_client(&address)
+            .await
+            .unwrap();
+
+        let mut stream = client
+            .query_stream("SELECT n FROM endless".into(), vec![])
+            .await
+            .unwrap();
+        assert_eq!(numbers(stream.next(2).await.unwrap()), [0, 1]);
+        drop(stream);
+
+        // Were the rest of the rows read, the connection would never close.
+        tokio::time::timeout(Duration::from_secs(10), closed.recv())
+            .await
+            .expect("connection should close");
+    }
+
+    #[tokio::test]
+    async fn stale_cached_statements_are_prepared_again() {
+        let (server, address, _) = FakePostgres::start().await;
+        let client = PooledTokioClientFactory::default()
+            .get_client(&address)
+            .await
+            .unwrap();
+        let query = || client.query("SELECT n FROM numbers".into(), vec![]);
+
+        assert_eq!(query().await.unwrap().rows.len(), NUMBER_COUNT as usize);
+        assert_eq!(query().await.unwrap().rows.len(), NUMBER_COUNT as usize);
+        assert_eq!(server.state().prepared, 1);
+
+        server.change_schema();
+        assert_eq!(query().await.unwrap().rows.len(), NUMBER_COUNT as usize);
+        assert_eq!(server.state().prepared, 2);
+
+        server.change_schema();
+        let mut stream = client
+            .query_stream("SELECT n FROM numbers".into(), vec![])
+            .await
+            .unwrap();
+        assert_eq!(numbers(stream.next(2).await.unwrap()), [0, 1]);
+        assert_eq!(server.state().prepared, 3);
+    }
+
+    #[tokio::test]
+    async fn transactions_with_failed_statements_are_not_committed() {
+        let (server, address, _) = FakePostgres::start().await;
+        let client = PooledTokioClientFactory::default()
+            .get_client(&address)
+            .await
+            .unwrap();
+
+        let mut transaction = Transaction::begin(client).await.unwrap();
+        transaction
+            .execute("SELECT n FROM numbers".into(), vec![])
+            .await
+            .unwrap();
+        let res = transaction
+            .query("SELECT n FROM missing".into(), vec![])
+            .await;
+        assert!(res.is_err());
+
+        let res = transaction.commit().await;
+        assert!(matches!(res, Err(v4::Error::Other(_))), "{res:?}");
+        assert_eq!(server.state().ended, ["ROLLBACK"]);
+    }
+
+    #[tokio::test]
+    async fn transactions_rolled_back_to_a_savepoint_can_be_committed() {
+        let (server, address, _) = FakePostgres::start().await;
+        let client = PooledTokioClientFactory::default()
+            .get_client(&address)
+            .await
+            .unwrap();
+
+        let mut transaction = Transaction::begin(client).await.unwrap();
+        transaction.savepoint("before").await.unwrap();
+        let res = transaction
+            .query("SELECT n FROM missing".into(), vec![])
+            .await;
+        assert!(res.is_err());
+        transaction.rollback_to_savepoint("before").await.unwrap();
+        transaction
+            .query("SELECT n FROM numbers".into(), vec![])
+            .await
+            .unwrap();
+
+        transaction.commit().await.unwrap();
+        assert_eq!(server.state().ended, ["COMMIT"]);
+    }
+}
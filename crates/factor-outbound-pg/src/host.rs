@@ -1,7 +1,8 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use spin_core::wasmtime::component::Resource;
 use spin_world::spin::postgres3_0_0::postgres::{self as v3};
-use spin_world::spin::postgres4_0_0::postgres::{self as v4};
+use spin_world::spin::postgres4_0_0::postgres::{self as v4_0};
+use spin_world::spin::postgres4_1_0::postgres::{self as v4};
 use spin_world::v1::postgres as v1;
 use spin_world::v1::rdbms_types as v1_types;
 use spin_world::v2::postgres::{self as v2};
@@ -10,34 +11,66 @@ use tracing::field::Empty;
 use tracing::instrument;
 use tracing::Level;
 
-use crate::client::{Client, ClientFactory};
-use crate::InstanceState;
+use crate::client::{Client, ClientFactory, RowStream};
+use crate::transaction::Transaction;
+use crate::{Connection, InstanceState};
 
 impl<CF: ClientFactory> InstanceState<CF> {
     async fn open_connection<Conn: 'static>(
         &mut self,
         address: &str,
     ) -> Result<Resource<Conn>, v4::Error> {
+        let client = self.new_client(address).await?;
         self.connections
-            .push(
-                self.client_factory
-                    .get_client(address)
-                    .await
-                    .map_err(|e| v4::Error::ConnectionFailed(format!("{e:?}")))?,
-            )
+            .push(Connection {
+                address: address.to_owned(),
+                client,
+            })
             .map_err(|_| v4::Error::ConnectionFailed("too many connections".into()))
             .map(Resource::new_own)
     }
 
+    async fn new_client(&self, address: &str) -> Result<CF::Client, v4::Error> {
+        self.client_factory
+            .get_client(address)
+            .await
+            .map_err(|e| v4::Error::ConnectionFailed(format!("{e:?}")))
+    }
+
     async fn get_client<Conn: 'static>(
         &self,
         connection: Resource<Conn>,
     ) -> Result<&CF::Client, v4::Error> {
+        self.get_connection(&connection).await.map(|c| &c.client)
+    }
+
+    async fn get_connection<Conn: 'static>(
+        &self,
+        connection: &Resource<Conn>,
+    ) -> Result<&Connection<CF::Client>, v4::Error> {
         self.connections
             .get(connection.rep())
             .ok_or_else(|| v4::Error::ConnectionFailed("no connection found".into()))
     }
 
+    async fn get_transaction(
+        &mut self,
+        transaction: &Resource<v4::Transaction>,
+    ) -> Result<&mut Transaction<CF::Client>, v4::Error> {
+        self.transactions
+            .get_mut(transaction.rep())
+            .ok_or_else(|| v4::Error::Other("no transaction found".into()))
+    }
+
+    async fn take_transaction(
+        &mut self,
+        transaction: Resource<v4::Transaction>,
+    ) -> Result<Transaction<CF::Client>, v4::Error> {
+        self.transactions
+            .remove(transaction.rep())
+            .ok_or_else(|| v4::Error::Other("no transaction found".into()))
+    }
+
     async fn is_address_allowed(&self, address: &str) -> Result<bool> {
         let Ok(config) = address.parse::<tokio_postgres::Config>() else {
             return Ok(false);
@@ -77,6 +110,10 @@ fn v3_params_to_v4(params: Vec<v3::ParameterValue>) -> Vec<v4::ParameterValue> {
     params.into_iter().map(|p| p.into()).collect()
 }
 
+fn v4_0_params_to_v4(params: Vec<v4_0::ParameterValue>) -> Vec<v4::ParameterValue> {
+    params.into_iter().map(|p| p.into()).collect()
+}
+
 impl<CF: ClientFactory> v3::HostConnection for InstanceState<CF> {
     #[instrument(name = "spin_outbound_pg.open", skip(self, address), err(level = Level::INFO), fields(otel.kind = "client", db.system = "postgresql", db.address = Empty, server.port = Empty, db.namespace = Empty))]
     async fn open(&mut self, address: String) -> Result<Resource<v3::Connection>, v3::Error> {
@@ -129,6 +166,58 @@ impl<CF: ClientFactory> v3::HostConnection for InstanceState<CF> {
     }
 }
 
+impl<CF: ClientFactory> v4_0::HostConnection for InstanceState<CF> {
+    #[instrument(name = "spin_outbound_pg.open", skip(self, address), err(level = Level::INFO), fields(otel.kind = "client", db.system = "postgresql", db.address = Empty, server.port = Empty, db.namespace = Empty))]
+    async fn open(&mut self, address: String) -> Result<Resource<v4_0::Connection>, v4_0::Error> {
+        spin_factor_outbound_networking::record_address_fields(&address);
+
+        if !self
+            .is_address_allowed(&address)
+            .await
+            .map_err(|e| v4_0::Error::Other(e.to_string()))?
+        {
+            return Err(v4_0::Error::ConnectionFailed(format!(
+                "address {address} is not permitted"
+            )));
+        }
+        Ok(self.open_connection(&address).await?)
+    }
+
+    #[instrument(name = "spin_outbound_pg.execute", skip(self, connection, params), err(level = Level::INFO), fields(otel.kind = "client", db.system = "postgresql", otel.name = statement))]
+    async fn execute(
+        &mut self,
+        connection: Resource<v4_0::Connection>,
+        statement: String,
+        params: Vec<v4_0::ParameterValue>,
+    ) -> Result<u64, v4_0::Error> {
+        Ok(self
+            .get_client(connection)
+            .await?
+            .execute(statement, v4_0_params_to_v4(params))
+            .await?)
+    }
+
+    #[instrument(name = "spin_outbound_pg.query", skip(self, connection, params), err(level = Level::INFO), fields(otel.kind = "client", db.system = "postgresql", otel.name = statement))]
+    async fn query(
+        &mut self,
+        connection: Resource<v4_0::Connection>,
+        statement: String,
+        params: Vec<v4_0::ParameterValue>,
+    ) -> Result<v4_0::RowSet, v4_0::Error> {
+        Ok(self
+            .get_client(connection)
+            .await?
+            .query(statement, v4_0_params_to_v4(params))
+            .await?
+            .into())
+    }
+
+    async fn drop(&mut self, connection: Resource<v4_0::Connection>) -> anyhow::Result<()> {
+        self.connections.remove(connection.rep());
+        Ok(())
+    }
+}
+
 impl<CF: ClientFactory> v4::HostConnection for InstanceState<CF> {
     #[instrument(name = "spin_outbound_pg.open", skip(self, address), err(level = Level::INFO), fields(otel.kind = "client", db.system = "postgresql", db.address = Empty, server.port = Empty, db.namespace = Empty))]
     async fn open(&mut self, address: String) -> Result<Resource<v4::Connection>, v4::Error> {
@@ -172,12 +261,161 @@ impl<CF: ClientFactory> v4::HostConnection for InstanceState<CF> {
             .await
     }
 
+    #[instrument(name = "spin_outbound_pg.query_stream", skip(self, connection, params), err(level = Level::INFO), fields(otel.kind = "client", db.system = "postgresql", otel.name = statement))]
+    async fn query_stream(
+        &mut self,
+        connection: Resource<v4::Connection>,
+        statement: String,
+        params: Vec<v4::ParameterValue>,
+    ) -> Result<Resource<v4::RowStream>, v4::Error> {
+        // The stream gets a client of its own, which it holds until it is dropped.
+        let address = &self.get_connection(&connection).await?.address;
+        let client = self.new_client(address).await?;
+        let stream = client.query_stream(statement, params).await?;
+        self.row_streams
+            .push(stream)
+            .map_err(|_| v4::Error::ConnectionFailed("too many row streams".into()))
+            .map(Resource::new_own)
+    }
+
+    #[instrument(name = "spin_outbound_pg.begin_transaction", skip(self, connection), err(level = Level::INFO), fields(otel.kind = "client", db.system = "postgresql"))]
+    async fn begin_transaction(
+        &mut self,
+        connection: Resource<v4::Connection>,
+    ) -> Result<Resource<v4::Transaction>, v4::Error> {
+        // The transaction gets a client of its own, which it holds until it ends.
+        let address = &self.get_connection(&connection).await?.address;
+        let client = self.new_client(address).await?;
+        let transaction = Transaction::begin(client).await?;
+        self.transactions
+            .push(transaction)
+            .map_err(|_| v4::Error::ConnectionFailed("too many transactions".into()))
+            .map(Resource::new_own)
+    }
+
     async fn drop(&mut self, connection: Resource<v4::Connection>) -> anyhow::Result<()> {
         self.connections.remove(connection.rep());
         Ok(())
     }
 }
 
+impl<CF: ClientFactory> v4::HostTransaction for InstanceState<CF> {
+    #[instrument(name = "spin_outbound_pg.execute", skip(self, transaction, params), err(level = Level::INFO), fields(otel.kind = "client", db.system = "postgresql", otel.name = statement))]
+    async fn execute(
+        &mut self,
+        transaction: Resource<v4::Transaction>,
+        statement: String,
+        params: Vec<v4::ParameterValue>,
+    ) -> Result<u64, v4::Error> {
+        self.get_transaction(&transaction)
+            .await?
+            .execute(statement, params)
+            .await
+    }
+
+    #[instrument(name = "spin_outbound_pg.query", skip(self, transaction, params), err(level = Level::INFO), fields(otel.kind = "client", db.system = "postgresql", otel.name = statement))]
+    async fn query(
+        &mut self,
+        transaction: Resource<v4::Transaction>,
+        statement: String,
+        params: Vec<v4::ParameterValue>,
+    ) -> Result<v4::RowSet, v4::Error> {
+        self.get_transaction(&transaction)
+            .await?
+            .query(statement, params)
+            .await
+    }
+
+    #[instrument(name = "spin_outbound_pg.commit", skip(self, this), err(level = Level::INFO), fields(otel.kind = "client", db.system = "postgresql"))]
+    async fn commit(&mut self, this: Resource<v4::Transaction>) -> Result<(), v4::Error> {
+        self.take_transaction(this).await?.commit().await
+    }
+
+    #[instrument(name = "spin_outbound_pg.rollback", skip(self, this), err(level = Level::INFO), fields(otel.kind = "client", db.system = "postgresql"))]
+    async fn rollback(&mut self, this: Resource<v4::Transaction>) -> Result<(), v4::Error> {
+        self.take_transaction(this).await?.rollback().await
+    }
+
+    #[instrument(name = "spin_outbound_pg.savepoint", skip(self, transaction), err(level = Level::INFO), fields(otel.kind = "client", db.system = "postgresql"))]
+    async fn savepoint(
+        &mut self,
+        transaction: Resource<v4::Transaction>,
+        name: String,
+    ) -> Result<(), v4::Error> {
+        self.get_transaction(&transaction)
+            .await?
+            .savepoint(&name)
+            .await
+    }
+
+    #[instrument(name = "spin_outbound_pg.rollback_to_savepoint", skip(self, transaction), err(level = Level::INFO), fields(otel.kind = "client", db.system = "postgresql"))]
+    async fn rollback_to_savepoint(
+        &mut self,
+        transaction: Resource<v4::Transaction>,
+        name: String,
+    ) -> Result<(), v4::Error> {
+        self.get_transaction(&transaction)
+            .await?
+            .rollback_to_savepoint(&name)
+            .await
+    }
+
+    #[instrument(name = "spin_outbound_pg.release_savepoint", skip(self, transaction), err(level = Level::INFO), fields(otel.kind = "client", db.system = "postgresql"))]
+    async fn release_savepoint(
+        &mut self,
+        transaction: Resource<v4::Transaction>,
+        name: String,
+    ) -> Result<(), v4::Error> {
+        self.get_transaction(&transaction)
+            .await?
+            .release_savepoint(&name)
+            .await
+    }
+
+    async fn drop(&mut self, transaction: Resource<v4::Transaction>) -> anyhow::Result<()> {
+        // A transaction which the guest drops without ending is rolled back.
+        if let Some(transaction) = self.transactions.remove(transaction.rep()) {
+            if let Err(e) = transaction.rollback().await {
+                tracing::debug!("failed to roll back dropped transaction: {e:?}");
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<CF: ClientFactory> v4::HostRowStream for InstanceState<CF> {
+    async fn columns(&mut self, stream: Resource<v4::RowStream>) -> Result<Vec<v4::Column>> {
+        let stream = self
+            .row_streams
+            .get(stream.rep())
+            .context("no row stream found")?;
+        Ok(stream.columns())
+    }
+
+    #[instrument(name = "spin_outbound_pg.next", skip(self, stream), err(level = Level::INFO), fields(otel.kind = "client", db.system = "postgresql"))]
+    async fn next(
+        &mut self,
+        stream: Resource<v4::RowStream>,
+        max_rows: u32,
+    ) -> Result<Vec<v4::Row>, v4::Error> {
+        if max_rows == 0 {
+            return Err(v4::Error::BadParameter(
+                "max-rows must be greater than zero".into(),
+            ));
+        }
+        self.row_streams
+            .get_mut(stream.rep())
+            .ok_or_else(|| v4::Error::Other("no row stream found".into()))?
+            .next(max_rows as usize)
+            .await
+    }
+
+    async fn drop(&mut self, stream: Resource<v4::RowStream>) -> anyhow::Result<()> {
+        self.row_streams.remove(stream.rep());
+        Ok(())
+    }
+}
+
 impl<CF: ClientFactory> v2_types::Host for InstanceState<CF> {
     fn convert_error(&mut self, error: v2::Error) -> Result<v2::Error> {
         Ok(error)
@@ -190,6 +428,12 @@ impl<CF: ClientFactory> v3::Host for InstanceState<CF> {
     }
 }
 
+impl<CF: ClientFactory> v4_0::Host for InstanceState<CF> {
+    fn convert_error(&mut self, error: v4_0::Error) -> Result<v4_0::Error> {
+        Ok(error)
+    }
+}
+
 impl<CF: ClientFactory> v4::Host for InstanceState<CF> {
     fn convert_error(&mut self, error: v4::Error) -> Result<v4::Error> {
         Ok(error)
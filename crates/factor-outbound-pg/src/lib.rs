@@ -1,10 +1,11 @@
 pub mod client;
 mod host;
+mod transaction;
 mod types;
 
 use std::sync::Arc;
 
-use client::ClientFactory;
+use client::{Client, ClientFactory};
 use spin_factor_outbound_networking::{
     config::allowed_hosts::OutboundAllowedHosts, OutboundNetworkingFactor,
 };
@@ -12,6 +13,7 @@ use spin_factors::{
     anyhow, ConfigureAppContext, Factor, FactorData, PrepareContext, RuntimeFactors,
     SelfInstanceBuilder,
 };
+use transaction::Transaction;
 
 pub struct OutboundPgFactor<CF = crate::client::PooledTokioClientFactory> {
     _phantom: std::marker::PhantomData<CF>,
@@ -31,6 +33,9 @@ impl<CF: ClientFactory> Factor for OutboundPgFactor<CF> {
         ctx.link_bindings(
             spin_world::spin::postgres4_0_0::postgres::add_to_linker::<_, FactorData<Self>>,
         )?;
+        ctx.link_bindings(
+            spin_world::spin::postgres4_1_0::postgres::add_to_linker::<_, FactorData<Self>>,
+        )?;
         Ok(())
     }
 
@@ -52,6 +57,8 @@ impl<CF: ClientFactory> Factor for OutboundPgFactor<CF> {
             allowed_hosts,
             client_factory: ctx.app_state().clone(),
             connections: Default::default(),
+            transactions: Default::default(),
+            row_streams: Default::default(),
         })
     }
 }
@@ -73,7 +80,16 @@ impl<C> OutboundPgFactor<C> {
 pub struct InstanceState<CF: ClientFactory> {
     allowed_hosts: OutboundAllowedHosts,
     client_factory: Arc<CF>,
-    connections: spin_resource_table::Table<CF::Client>,
+    connections: spin_resource_table::Table<Connection<CF::Client>>,
+    transactions: spin_resource_table::Table<Transaction<CF::Client>>,
+    row_streams: spin_resource_table::Table<<CF::Client as Client>::RowStream>,
+}
+
+/// An open connection, and the address it was opened to, from which the
+/// connections for its transactions are opened.
+struct Connection<C> {
+    address: String,
+    client: C,
 }
 
 impl<CF: ClientFactory> SelfInstanceBuilder for InstanceState<CF> {}
@@ -0,0 +1,146 @@
+use spin_world::spin::postgres4_1_0::postgres::{self as v4, ParameterValue, RowSet};
+
+use crate::client::Client;
+
+/// A transaction in progress, holding the client it runs on until it is
+/// committed or rolled back.
+pub(crate) struct Transaction<C: Client> {
+    // `None` only once the transaction has ended.
+    client: Option<C>,
+    // Whether a statement has failed, after which Postgres refuses to run any
+    // more until the transaction is rolled back to a savepoint.
+    aborted: bool,
+}
+
+impl<C: Client> Transaction<C> {
+    /// Begins a transaction on `client`.
+    pub async fn begin(client: C) -> Result<Self, v4::Error> {
+        client.batch_execute("BEGIN").await?;
+        Ok(Self {
+            client: Some(client),
+            aborted: false,
+        })
+    }
+
+    /// The client the transaction runs on.
+    fn client(&self) -> &C {
+        self.client.as_ref().expect("transaction has ended")
+    }
+
+    pub async fn execute(
+        &mut self,
+        statement: String,
+        params: Vec<ParameterValue>,
+    ) -> Result<u64, v4::Error> {
+        let result = self.client().execute(statement, params).await;
+        self.check(&result);
+        result
+    }
+
+    pub async fn query(
+        &mut self,
+        statement: String,
+        params: Vec<ParameterValue>,
+    ) -> Result<RowSet, v4::Error> {
+        let result = self.client().query(statement, params).await;
+        self.check(&result);
+        result
+    }
+
+    pub async fn commit(self) -> Result<(), v4::Error> {
+        // Postgres answers `COMMIT` of an aborted transaction by rolling it
+        // back rather than with an error.
+        if self.aborted {
+            self.rollback().await?;
+            return Err(v4::Error::Other(
+                "transaction rolled back because a statement in it failed".into(),
+            ));
+        }
+        self.end("COMMIT").await
+    }
+
+    pub async fn rollback(self) -> Result<(), v4::Error> {
+        self.end("ROLLBACK").await
+    }
+
+    pub async fn savepoint(&mut self, name: &str) -> Result<(), v4::Error> {
+        self.run_savepoint_command("SAVEPOINT", name).await
+    }
+
+    pub async fn rollback_to_savepoint(&mut self, name: &str) -> Result<(), v4::Error> {
+        self.run_savepoint_command("ROLLBACK TO SAVEPOINT", name)
+            .await?;
+        self.aborted = false;
+        Ok(())
+    }
+
+    pub async fn release_savepoint(&mut self, name: &str) -> Result<(), v4::Error> {
+        self.run_savepoint_command("RELEASE SAVEPOINT", name).await
+    }
+
+    async fn run_savepoint_command(&mut self, command: &str, name: &str) -> Result<(), v4::Error> {
+        if name.is_empty() || name.contains('\0') {
+            return Err(v4::Error::BadParameter(format!(
+                "invalid savepoint name {name:?}"
+            )));
+        }
+        let result = self
+            .client()
+            .batch_execute(&format!("{command} {}", quote_identifier(name)))
+            .await;
+        self.check(&result);
+        result
+    }
+
+    /// Notes whether `result` is an error from the database, which aborts the
+    /// transaction.
+    fn check<T>(&mut self, result: &Result<T, v4::Error>) {
+        if let Err(v4::Error::QueryFailed(v4::QueryError::DbError(_))) = result {
+            self.aborted = true;
+        }
+    }
+
+    /// Ends the transaction by running `statement`. The client is returned to
+    /// its pool if that succeeds, and discarded otherwise, as the transaction
+    /// may still be open on it.
+    async fn end(mut self, statement: &str) -> Result<(), v4::Error> {
+        let client = self.client.take().expect("transaction has ended");
+        let result = client.batch_execute(statement).await;
+        if result.is_err() {
+            client.discard();
+        }
+        result
+    }
+}
+
+impl<C: Client> Drop for Transaction<C> {
+    fn drop(&mut self) {
+        // Rolling back needs a round trip to the server, which can't be made
+        // here, so close the connection instead: the server rolls back any
+        // transaction open on a connection when it closes.
+        if let Some(client) = self.client.take() {
+            client.discard();
+        }
+    }
+}
+
+/// Quotes `name` as a Postgres identifier, so that it may be used in a
+/// statement verbatim.
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identifiers_are_quoted() {
+        assert_eq!(quote_identifier("before_update"), r#""before_update""#);
+        assert_eq!(quote_identifier("Mixed Case"), r#""Mixed Case""#);
+        assert_eq!(
+            quote_identifier(r#"x"; DROP TABLE users; --"#),
+            r#""x""; DROP TABLE users; --""#
+        );
+    }
+}
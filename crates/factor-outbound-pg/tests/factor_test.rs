@@ -1,16 +1,21 @@
+use std::sync::Mutex;
+
 use anyhow::{bail, Result};
+use spin_core::wasmtime::component::Resource;
 use spin_factor_outbound_networking::OutboundNetworkingFactor;
-use spin_factor_outbound_pg::client::Client;
 use spin_factor_outbound_pg::client::ClientFactory;
+use spin_factor_outbound_pg::client::{Client, RowStream};
 use spin_factor_outbound_pg::OutboundPgFactor;
 use spin_factor_variables::VariablesFactor;
 use spin_factors::{anyhow, RuntimeFactors};
 use spin_factors_test::{toml, TestEnvironment};
 use spin_world::async_trait;
-use spin_world::spin::postgres4_0_0::postgres::Error as PgError;
-use spin_world::spin::postgres4_0_0::postgres::HostConnection;
-use spin_world::spin::postgres4_0_0::postgres::{self as v2};
-use spin_world::spin::postgres4_0_0::postgres::{ParameterValue, RowSet};
+use spin_world::spin::postgres4_1_0::postgres::Error as PgError;
+use spin_world::spin::postgres4_1_0::postgres::HostConnection;
+use spin_world::spin::postgres4_1_0::postgres::{self as v2};
+use spin_world::spin::postgres4_1_0::postgres::{
+    Column, DbDataType, DbValue, ParameterValue, RowSet,
+};
 
 #[derive(RuntimeFactors)]
 struct TestFactors {
@@ -104,37 +109,243 @@ async fn exercise_query() -> anyhow::Result<()> {
     Ok(())
 }
 
-// TODO: We can expand this mock to track calls and simulate return values
+#[tokio::test]
+async fn committed_transaction_runs_on_its_own_client() -> anyhow::Result<()> {
+    let address = "postgres://localhost:5432/commit";
+    let mut state = test_env().build_instance_state().await?;
+
+    let connection = state.pg.open(address.to_string()).await?;
+    let transaction = state.pg.begin_transaction(connection).await?;
+    v2::HostTransaction::execute(
+        &mut state.pg,
+        borrow(&transaction),
+        "INSERT INTO test".to_string(),
+        vec![],
+    )
+    .await?;
+    v2::HostTransaction::commit(&mut state.pg, transaction).await?;
+
+    assert_eq!(
+        statements(address),
+        ["1: BEGIN", "1: INSERT INTO test", "1: COMMIT"]
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn savepoint_names_are_quoted() -> anyhow::Result<()> {
+    let address = "postgres://localhost:5432/savepoint";
+    let mut state = test_env().build_instance_state().await?;
+
+    let connection = state.pg.open(address.to_string()).await?;
+    let transaction = state.pg.begin_transaction(connection).await?;
+    v2::HostTransaction::savepoint(
+        &mut state.pg,
+        borrow(&transaction),
+        "before \"update\"".to_string(),
+    )
+    .await?;
+    v2::HostTransaction::rollback_to_savepoint(
+        &mut state.pg,
+        borrow(&transaction),
+        "before \"update\"".to_string(),
+    )
+    .await?;
+    let res =
+        v2::HostTransaction::release_savepoint(&mut state.pg, borrow(&transaction), String::new())
+            .await;
+    assert!(matches!(res, Err(PgError::BadParameter(_))));
+    v2::HostTransaction::rollback(&mut state.pg, transaction).await?;
+
+    assert_eq!(
+        statements(address),
+        [
+            "1: BEGIN",
+            r#"1: SAVEPOINT "before ""update""""#,
+            r#"1: ROLLBACK TO SAVEPOINT "before ""update""""#,
+            "1: ROLLBACK",
+        ]
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn dropped_transaction_rolls_back() -> anyhow::Result<()> {
+    let address = "postgres://localhost:5432/drop";
+    let mut state = test_env().build_instance_state().await?;
+
+    let connection = state.pg.open(address.to_string()).await?;
+    let transaction = state.pg.begin_transaction(connection).await?;
+    v2::HostTransaction::drop(&mut state.pg, transaction).await?;
+
+    assert_eq!(statements(address), ["1: BEGIN", "1: ROLLBACK"]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn unfinished_transaction_is_discarded_with_instance() -> anyhow::Result<()> {
+    let address = "postgres://localhost:5432/unfinished";
+    let mut state = test_env().build_instance_state().await?;
+
+    let connection = state.pg.open(address.to_string()).await?;
+    state.pg.begin_transaction(connection).await?;
+    drop(state);
+
+    assert_eq!(statements(address), ["1: BEGIN", "1: <discarded>"]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn rows_are_streamed_in_batches() -> anyhow::Result<()> {
+    let address = "postgres://localhost:5432/stream";
+    let mut state = test_env().build_instance_state().await?;
+
+    let connection = state.pg.open(address.to_string()).await?;
+    let stream = state
+        .pg
+        .query_stream(connection, "SELECT * FROM test".to_string(), vec![])
+        .await?;
+    let columns = v2::HostRowStream::columns(&mut state.pg, borrow(&stream)).await?;
+    assert_eq!(columns.len(), 1);
+
+    let mut batch_sizes = vec![];
+    loop {
+        let rows = v2::HostRowStream::next(&mut state.pg, borrow(&stream), 2).await?;
+        batch_sizes.push(rows.len());
+        if rows.len() < 2 {
+            break;
+        }
+    }
+    assert_eq!(batch_sizes, [2, 2, 1]);
+    let rows = v2::HostRowStream::next(&mut state.pg, borrow(&stream), 2).await?;
+    assert!(rows.is_empty());
+
+    let res = v2::HostRowStream::next(&mut state.pg, borrow(&stream), 0).await;
+    assert!(matches!(res, Err(PgError::BadParameter(_))));
+
+    assert_eq!(statements(address), ["1: SELECT * FROM test"]);
+    Ok(())
+}
+
+fn borrow<T: 'static>(resource: &Resource<T>) -> Resource<T> {
+    Resource::new_borrow(resource.rep())
+}
+
+/// Statements run by mock clients, with the address and ID of the client
+/// which ran each.
+static STATEMENTS: Mutex<Vec<(String, usize, String)>> = Mutex::new(Vec::new());
+
+fn record(client: &MockClient, statement: &str) {
+    STATEMENTS
+        .lock()
+        .unwrap()
+        .push((client.address.clone(), client.id, statement.to_owned()));
+}
+
+/// The statements run by mock clients for `address`, each prefixed with the
+/// ID of the client which ran it.
+fn statements(address: &str) -> Vec<String> {
+    STATEMENTS
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(a, _, _)| a == address)
+        .map(|(_, id, statement)| format!("{id}: {statement}"))
+        .collect()
+}
+
 #[derive(Default)]
-pub struct MockClientFactory {}
-pub struct MockClient {}
+pub struct MockClientFactory {
+    clients: Mutex<usize>,
+}
+
+/// A client which records the statements it runs. Clients are numbered in
+/// the order they were created by their factory, from zero.
+pub struct MockClient {
+    address: String,
+    id: usize,
+}
 
 #[async_trait]
 impl ClientFactory for MockClientFactory {
     type Client = MockClient;
-    async fn get_client(&self, _address: &str) -> Result<Self::Client> {
-        Ok(MockClient {})
+    async fn get_client(&self, address: &str) -> Result<Self::Client> {
+        let mut clients = self.clients.lock().unwrap();
+        let id = *clients;
+        *clients += 1;
+        Ok(MockClient {
+            address: address.to_owned(),
+            id,
+        })
+    }
+}
+
+/// A stream of `MOCK_ROW_COUNT` rows, each holding its index.
+pub struct MockRowStream {
+    next_row: i32,
+}
+
+const MOCK_ROW_COUNT: i32 = 5;
+
+#[async_trait]
+impl RowStream for MockRowStream {
+    fn columns(&self) -> Vec<Column> {
+        vec![Column {
+            name: "index".to_owned(),
+            data_type: DbDataType::Int32,
+        }]
+    }
+
+    async fn next(&mut self, max_rows: usize) -> Result<Vec<Vec<DbValue>>, v2::Error> {
+        let end = MOCK_ROW_COUNT.min(self.next_row + max_rows as i32);
+        let rows = (self.next_row..end)
+            .map(|index| vec![DbValue::Int32(index)])
+            .collect();
+        self.next_row = end;
+        Ok(rows)
     }
 }
 
 #[async_trait]
 impl Client for MockClient {
+    type RowStream = MockRowStream;
+
     async fn execute(
         &self,
-        _statement: String,
+        statement: String,
         _params: Vec<ParameterValue>,
     ) -> Result<u64, v2::Error> {
+        record(self, &statement);
         Ok(0)
     }
 
     async fn query(
         &self,
-        _statement: String,
+        statement: String,
         _params: Vec<ParameterValue>,
     ) -> Result<RowSet, v2::Error> {
+        record(self, &statement);
         Ok(RowSet {
             columns: vec![],
             rows: vec![],
         })
     }
+
+    async fn query_stream(
+        self,
+        statement: String,
+        _params: Vec<ParameterValue>,
+    ) -> Result<MockRowStream, v2::Error> {
+        record(&self, &statement);
+        Ok(MockRowStream { next_row: 0 })
+    }
+
+    async fn batch_execute(&self, statements: &str) -> Result<(), v2::Error> {
+        record(self, statements);
+        Ok(())
+    }
+
+    fn discard(self) {
+        record(&self, "<discarded>");
+    }
 }
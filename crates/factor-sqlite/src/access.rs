@@ -0,0 +1,126 @@
+use async_trait::async_trait;
+use spin_factors::anyhow;
+use spin_locked_app::locked::AccessMode;
+use spin_world::spin::sqlite::sqlite as v3;
+
+use crate::Connection;
+
+/// A [`Connection`] which rejects the statements its [`AccessMode`] does not allow with
+/// [`v3::Error::AccessDenied`].
+///
+/// Statements are classified by [`Connection::is_read_only`]. Batches are always
+/// treated as writes.
+pub struct RestrictedConnection {
+    inner: Box<dyn Connection>,
+    access: AccessMode,
+}
+
+impl RestrictedConnection {
+    /// Wraps `inner` if `access` restricts it, or returns it as-is otherwise.
+    pub fn wrap(inner: Box<dyn Connection>, access: AccessMode) -> Box<dyn Connection> {
+        match access {
+            AccessMode::ReadWrite => inner,
+            access => Box::new(Self { inner, access }),
+        }
+    }
+}
+
+#[async_trait]
+impl Connection for RestrictedConnection {
+    async fn query(
+        &self,
+        query: &str,
+        parameters: Vec<v3::Value>,
+    ) -> Result<v3::QueryResult, v3::Error> {
+        let allowed = if self.inner.is_read_only(query).await? {
+            self.access.can_read()
+        } else {
+            self.access.can_write()
+        };
+        if !allowed {
+            return Err(v3::Error::AccessDenied);
+        }
+        self.inner.query(query, parameters).await
+    }
+
+    async fn execute_batch(&self, statements: &str) -> anyhow::Result<()> {
+        if !self.access.can_write() {
+            anyhow::bail!("access denied: database is read-only");
+        }
+        self.inner.execute_batch(statements).await
+    }
+
+    async fn changes(&self) -> Result<u64, v3::Error> {
+        self.inner.changes().await
+    }
+
+    async fn last_insert_rowid(&self) -> Result<i64, v3::Error> {
+        self.inner.last_insert_rowid().await
+    }
+
+    async fn is_read_only(&self, query: &str) -> Result<bool, v3::Error> {
+        self.inner.is_read_only(query).await
+    }
+
+    fn summary(&self) -> Option<String> {
+        self.inner.summary()
+    }
+}
+
+/// Returns true if the statement's leading keyword marks it as a read.
+///
+/// Everything other than `SELECT`, `VALUES` and `EXPLAIN`, including `WITH` (which may
+/// introduce an `INSERT`, `UPDATE` or `DELETE`) and `PRAGMA`, is treated as a write.
+pub(crate) fn is_read_statement(statement: &str) -> bool {
+    let keyword: String = skip_comments(statement)
+        .chars()
+        .take_while(|c| c.is_ascii_alphabetic())
+        .collect();
+    ["SELECT", "VALUES", "EXPLAIN"]
+        .iter()
+        .any(|read| keyword.eq_ignore_ascii_case(read))
+}
+
+/// Skips leading whitespace and SQL comments.
+fn skip_comments(mut statement: &str) -> &str {
+    loop {
+        statement = statement.trim_start();
+        if let Some(rest) = statement.strip_prefix("--") {
+            statement = rest.split_once('\n').map_or("", |(_, rest)| rest);
+        } else if let Some(rest) = statement.strip_prefix("/*") {
+            statement = rest.split_once("*/").map_or("", |(_, rest)| rest);
+        } else {
+            return statement;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_statements_by_leading_keyword() {
+        for read in [
+            "SELECT * FROM t",
+            "  select 1",
+            "-- comment\nSELECT 1",
+            "/* comment */ values (1)",
+            "EXPLAIN DELETE FROM t",
+        ] {
+            assert!(is_read_statement(read), "{read:?} should be a read");
+        }
+        for write in [
+            "INSERT INTO t VALUES (1)",
+            "delete from t",
+            "WITH x AS (SELECT 1) DELETE FROM t",
+            "PRAGMA journal_mode = WAL",
+            "SELECTED",
+            "-- SELECT\nDROP TABLE t",
+            "/* SELECT */",
+            "",
+        ] {
+            assert!(!is_read_statement(write), "{write:?} should be a write");
+        }
+    }
+}
@@ -3,16 +3,19 @@ use std::sync::Arc;
 
 use spin_factors::wasmtime::component::Resource;
 use spin_factors::{anyhow, SelfInstanceBuilder};
+use spin_locked_app::locked::AccessMode;
 use spin_world::spin::sqlite::sqlite as v3;
 use spin_world::v1::sqlite as v1;
 use spin_world::v2::sqlite as v2;
 use tracing::field::Empty;
 use tracing::{instrument, Level};
 
-use crate::{Connection, ConnectionCreator};
+use crate::{Connection, ConnectionCreator, RestrictedConnection};
 
 pub struct InstanceState {
     allowed_databases: Arc<HashSet<String>>,
+    /// The access mode of allowed databases whose access is restricted.
+    database_access: Arc<HashMap<String, AccessMode>>,
     /// A resource table of connections.
     connections: spin_resource_table::Table<Box<dyn Connection>>,
     /// A map from database label to connection creators.
@@ -22,13 +25,16 @@ pub struct InstanceState {
 impl InstanceState {
     /// Create a new `InstanceState`
     ///
-    /// Takes the list of allowed databases, and a function for getting a connection creator given a database label.
+    /// Takes the list of allowed databases, the access mode of those which are restricted,
+    /// and a function for getting a connection creator given a database label.
     pub fn new(
         allowed_databases: Arc<HashSet<String>>,
+        database_access: Arc<HashMap<String, AccessMode>>,
         connection_creators: HashMap<String, Arc<dyn ConnectionCreator>>,
     ) -> Self {
         Self {
             allowed_databases,
+            database_access,
             connections: spin_resource_table::Table::new(256),
             connection_creators,
         }
@@ -55,6 +61,10 @@ impl InstanceState {
             .ok_or(v3::Error::NoSuchDatabase)?
             .create_connection(&database)
             .await?;
+        let conn = match self.database_access.get(&database) {
+            Some(&access) => RestrictedConnection::wrap(conn, access),
+            None => conn,
+        };
         tracing::Span::current().record(
             "sqlite.backend",
             conn.summary().as_deref().unwrap_or("unknown"),
@@ -1,3 +1,4 @@
+mod access;
 mod host;
 pub mod runtime_config;
 
@@ -8,11 +9,12 @@ use host::InstanceState;
 
 use async_trait::async_trait;
 use spin_factors::{anyhow, Factor, FactorData};
-use spin_locked_app::MetadataKey;
+use spin_locked_app::{locked::AccessMode, MetadataKey};
 use spin_world::spin::sqlite::sqlite as v3;
 use spin_world::v1::sqlite as v1;
 use spin_world::v2::sqlite as v2;
 
+pub use access::RestrictedConnection;
 pub use runtime_config::RuntimeConfig;
 
 #[derive(Default)]
@@ -69,7 +71,29 @@ impl Factor for SqliteFactor {
             connection_creators.contains_key(label)
         })?;
 
-        Ok(AppState::new(allowed_databases, connection_creators))
+        let database_access = ctx
+            .app()
+            .components()
+            .map(|component| {
+                let access = component
+                    .get_metadata(DATABASE_ACCESS_KEY)?
+                    .unwrap_or_default();
+                let allowed = &allowed_databases[component.id()];
+                if let Some(label) = access.keys().find(|label| !allowed.contains(*label)) {
+                    anyhow::bail!(
+                        "database_access label {label:?} for component {:?} is not in sqlite_databases",
+                        component.id()
+                    );
+                }
+                Ok((component.id().to_string(), Arc::new(access)))
+            })
+            .collect::<anyhow::Result<HashMap<_, _>>>()?;
+
+        Ok(AppState {
+            allowed_databases,
+            database_access,
+            connection_creators,
+        })
     }
 
     fn prepare<T: spin_factors::RuntimeFactors>(
@@ -82,8 +106,15 @@ impl Factor for SqliteFactor {
             .get(ctx.app_component().id())
             .cloned()
             .unwrap_or_default();
+        let database_access = ctx
+            .app_state()
+            .database_access
+            .get(ctx.app_component().id())
+            .cloned()
+            .unwrap_or_default();
         Ok(InstanceState::new(
             allowed_databases,
+            database_access,
             ctx.app_state().connection_creators.clone(),
         ))
     }
@@ -124,11 +155,18 @@ fn ensure_allowed_databases_are_configured(
 
 /// Metadata key for a list of allowed databases for a component.
 pub const ALLOWED_DATABASES_KEY: MetadataKey<Vec<String>> = MetadataKey::new("databases");
+/// Metadata key for the access mode of a component's databases whose access is restricted.
+pub const DATABASE_ACCESS_KEY: MetadataKey<HashMap<String, AccessMode>> =
+    MetadataKey::new("database_access");
 
 #[derive(Clone)]
 pub struct AppState {
     /// A map from component id to a set of allowed database labels.
     allowed_databases: HashMap<String, Arc<HashSet<String>>>,
+    /// A map from component id to the access mode of its restricted databases.
+    ///
+    /// Allowed databases which are absent allow both reads and writes.
+    database_access: HashMap<String, Arc<HashMap<String, AccessMode>>>,
     /// A mapping from database label to a connection creator.
     connection_creators: HashMap<String, Arc<dyn ConnectionCreator>>,
 }
@@ -141,6 +179,7 @@ impl AppState {
     ) -> Self {
         Self {
             allowed_databases,
+            database_access: HashMap::new(),
             connection_creators,
         }
     }
@@ -209,6 +248,16 @@ pub trait Connection: Send + Sync {
 
     async fn last_insert_rowid(&self) -> Result<i64, v3::Error>;
 
+    /// Returns true if `query` can't write to the database.
+    ///
+    /// This is how a read-only [`RestrictedConnection`] decides which statements to run.
+    /// Backends which can prepare a statement without running it should ask SQLite. The
+    /// default only treats statements starting with `SELECT`, `VALUES` or `EXPLAIN` as
+    /// reads.
+    async fn is_read_only(&self, query: &str) -> Result<bool, v3::Error> {
+        Ok(access::is_read_statement(query))
+    }
+
     /// A human-readable summary of the connection's configuration
     ///
     /// Example: "libSQL at libsql://example.com"
@@ -4,6 +4,7 @@ use std::{
 };
 
 use spin_factor_sqlite::{RuntimeConfig, SqliteFactor};
+use spin_factors::wasmtime::component::Resource;
 use spin_factors::{
     anyhow::{self, bail, Context as _},
     RuntimeFactors,
@@ -95,6 +96,59 @@ async fn it_works_when_database_is_configured() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn read_only_database_rejects_writes() -> anyhow::Result<()> {
+    let factors = TestFactors {
+        sqlite: SqliteFactor::new(),
+    };
+    let mut connection_creators = HashMap::new();
+    connection_creators.insert("foo".to_owned(), Arc::new(MockConnectionCreator) as _);
+    let runtime_config = TestFactorsRuntimeConfig {
+        sqlite: Some(RuntimeConfig {
+            connection_creators,
+        }),
+    };
+    let env = TestEnvironment::new(factors)
+        .extend_manifest(toml! {
+            [component.test-component]
+            source = "does-not-exist.wasm"
+            sqlite_databases = [{ label = "foo", access = "read" }]
+        })
+        .runtime_config(runtime_config)?;
+
+    let mut state = env
+        .build_instance_state()
+        .await
+        .context("build_instance_state failed")?;
+
+    let connection = state.sqlite.open("foo".into()).await?;
+    // Reads reach the (always failing) mock connection...
+    assert!(matches!(
+        state
+            .sqlite
+            .execute(
+                Resource::new_borrow(connection.rep()),
+                "SELECT * FROM t".into(),
+                vec![]
+            )
+            .await,
+        Err(v2::Error::Io(_))
+    ));
+    // ...but writes are rejected first.
+    assert!(matches!(
+        state
+            .sqlite
+            .execute(
+                Resource::new_borrow(connection.rep()),
+                "DELETE FROM t".into(),
+                vec![]
+            )
+            .await,
+        Err(v2::Error::AccessDenied)
+    ));
+    Ok(())
+}
+
 /// A connection creator that returns a mock connection.
 struct MockConnectionCreator;
 
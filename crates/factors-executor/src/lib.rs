@@ -1,4 +1,7 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
 use anyhow::Context;
 use spin_app::{App, AppComponent};
@@ -16,6 +19,8 @@ pub struct FactorsExecutor<T: RuntimeFactors, U: 'static = ()> {
     core_engine: spin_core::Engine<InstanceState<T::InstanceState, U>>,
     factors: T,
     hooks: Vec<Box<dyn ExecutorHooks<T, U>>>,
+    /// IDs of components whose Wasm isn't loaded, as they are never instantiated.
+    unloaded_components: HashSet<String>,
 }
 
 impl<T: RuntimeFactors, U: Send + 'static> FactorsExecutor<T, U> {
@@ -33,6 +38,7 @@ impl<T: RuntimeFactors, U: Send + 'static> FactorsExecutor<T, U> {
             factors,
             core_engine: core_engine_builder.build(),
             hooks: Default::default(),
+            unloaded_components: Default::default(),
         })
     }
 
@@ -47,6 +53,14 @@ impl<T: RuntimeFactors, U: Send + 'static> FactorsExecutor<T, U> {
         self.hooks.push(Box::new(hooks));
     }
 
+    /// Skips loading the Wasm of the given components when loading an app, for
+    /// components which are handled without ever being instantiated.
+    ///
+    /// [`FactorsExecutorApp::prepare`] fails for these components.
+    pub fn skip_loading_components(&mut self, component_ids: impl IntoIterator<Item = String>) {
+        self.unloaded_components.extend(component_ids);
+    }
+
     /// Loads a [`App`] with this executor.
     pub async fn load_app(
         self: Arc<Self>,
@@ -67,6 +81,9 @@ impl<T: RuntimeFactors, U: Send + 'static> FactorsExecutor<T, U> {
         let mut component_instance_pres = HashMap::with_capacity(components.len());
 
         for component in components {
+            if self.unloaded_components.contains(component.id()) {
+                continue;
+            }
             let instance_pre = component_loader
                 .load_instance_pre(&self.core_engine, &component)
                 .await?;
@@ -154,7 +171,7 @@ impl<T: RuntimeFactors, U: Send + 'static> FactorsExecutorApp<T, U> {
     pub fn get_instance_pre(&self, component_id: &str) -> anyhow::Result<&InstancePre<T, U>> {
         self.component_instance_pres
             .get(component_id)
-            .with_context(|| format!("no such component {component_id:?}"))
+            .with_context(|| format!("component {component_id:?} is not loaded"))
     }
 
     /// Returns an instance builder for the given component ID.
@@ -165,7 +182,7 @@ impl<T: RuntimeFactors, U: Send + 'static> FactorsExecutorApp<T, U> {
             .get_component(component_id)
             .with_context(|| format!("no such component {component_id:?}"))?;
 
-        let instance_pre = self.component_instance_pres.get(component_id).unwrap();
+        let instance_pre = self.get_instance_pre(component_id)?;
 
         let factor_builders = self
             .executor
@@ -352,6 +369,39 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn skipped_components_are_not_loaded() -> anyhow::Result<()> {
+        let factors = TestFactors {
+            wasi: WasiFactor::new(DummyFilesMounter),
+        };
+        let env = TestEnvironment::new(factors);
+        let locked = env.build_locked_app().await?;
+        let app = App::new("test-app", locked);
+
+        let engine_builder = spin_core::Engine::builder(&Default::default())?;
+        let mut executor = FactorsExecutor::new(engine_builder, env.factors)?;
+        executor.skip_loading_components(["empty".to_owned()]);
+
+        let factors_app = Arc::new(executor)
+            .load_app(app, Default::default(), &PanickingComponentLoader)
+            .await?;
+        assert!(factors_app.prepare("empty").is_err());
+        Ok(())
+    }
+
+    struct PanickingComponentLoader;
+
+    #[async_trait]
+    impl ComponentLoader<TestFactors, ()> for PanickingComponentLoader {
+        async fn load_component(
+            &self,
+            _engine: &spin_core::wasmtime::Engine,
+            component: &AppComponent,
+        ) -> anyhow::Result<Component> {
+            panic!("component {:?} should not be loaded", component.id())
+        }
+    }
+
     struct DummyComponentLoader;
 
     #[async_trait]
@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-use spin_http_routes::HttpTriggerRouteConfig;
+use spin_http_routes::{HttpTriggerRouteConfig, RouteMatchers};
 
 /// Configuration for the HTTP trigger
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
@@ -9,14 +9,136 @@ pub struct HttpTriggerConfig {
     pub component: String,
     /// HTTP route the component will be invoked for
     pub route: HttpTriggerRouteConfig,
+    /// HTTP methods the component will be invoked for. If empty, the component
+    /// is invoked for any method.
+    #[serde(default)]
+    pub methods: Vec<String>,
+    /// Host the component will be invoked for, e.g. `api.example.com`, or
+    /// `*.example.com` for any subdomain. If not set, the component is invoked
+    /// for any host.
+    #[serde(default)]
+    pub host: Option<String>,
+    /// Compression of the component's responses. If not set, responses are
+    /// sent as the component returned them.
+    #[serde(default)]
+    pub compression: Option<HttpCompressionConfig>,
+    /// The CORS policy enforced for the component's route. If not set, the
+    /// application's policy is used, if any.
+    #[serde(default)]
+    pub cors: Option<HttpCorsConfig>,
+    /// The maximum number of requests the component may handle at once. If
+    /// not set, the number is not limited.
+    #[serde(default)]
+    pub max_concurrency: Option<usize>,
+    /// The rate at which the component may be invoked. If not set, the rate
+    /// is not limited.
+    #[serde(default)]
+    pub rate_limit: Option<HttpRateLimitConfig>,
     /// The HTTP executor the component requires
     #[serde(default)]
     pub executor: Option<HttpExecutorType>,
 }
 
+impl HttpTriggerConfig {
+    /// The methods and host the trigger's route is restricted to.
+    pub fn route_matchers(&self) -> RouteMatchers {
+        RouteMatchers {
+            methods: self.methods.clone(),
+            host: self.host.clone(),
+        }
+    }
+}
+
+/// Response compression configuration for an HTTP trigger.
+///
+/// `compression = {}` compresses responses with the defaults.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpCompressionConfig {
+    /// Responses smaller than this many bytes are not compressed. Streamed
+    /// responses of unknown length are always compressed.
+    pub min_size: u64,
+    /// The media types of responses to compress. A pattern may use `*` for
+    /// the whole subtype (`text/*`) or before a structured syntax suffix
+    /// (`application/*+json`).
+    pub content_types: Vec<String>,
+}
+
+impl Default for HttpCompressionConfig {
+    fn default() -> Self {
+        const DEFAULT_MIN_SIZE: u64 = 1024;
+        const DEFAULT_CONTENT_TYPES: &[&str] = &[
+            "text/*",
+            "application/javascript",
+            "application/json",
+            "application/*+json",
+            "application/xml",
+            "application/*+xml",
+            "application/wasm",
+            "image/svg+xml",
+        ];
+
+        Self {
+            min_size: DEFAULT_MIN_SIZE,
+            content_types: DEFAULT_CONTENT_TYPES
+                .iter()
+                .map(|t| t.to_string())
+                .collect(),
+        }
+    }
+}
+
+/// A CORS policy, enforced by the HTTP trigger before requests reach the
+/// component.
+///
+/// Each list may contain `"*"` to allow any value.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpCorsConfig {
+    /// The origins allowed to make cross-origin requests, e.g.
+    /// `https://example.com`.
+    pub origins: Vec<String>,
+    /// The methods allowed in cross-origin requests. If empty, `GET`, `HEAD`
+    /// and `POST` are allowed.
+    pub methods: Vec<String>,
+    /// The request headers allowed in cross-origin requests, beyond those
+    /// which are always allowed.
+    pub headers: Vec<String>,
+    /// How long in seconds the client may cache the result of a preflight
+    /// request.
+    pub max_age: Option<u64>,
+}
+
+/// A token bucket rate limit for an HTTP trigger.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct HttpRateLimitConfig {
+    /// The sustained number of requests allowed per second.
+    pub requests_per_second: f64,
+    /// The number of requests allowed in a burst above the sustained rate.
+    /// Defaults to one second's worth of requests.
+    #[serde(default)]
+    pub burst: Option<u32>,
+    /// What the limit applies to.
+    #[serde(default)]
+    pub key: HttpRateLimitKey,
+}
+
+/// What an [`HttpRateLimitConfig`] limits the request rate of.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HttpRateLimitKey {
+    /// All requests to the component share one limit.
+    #[default]
+    Global,
+    /// Each client IP address has its own limit.
+    ClientIp,
+}
+
 /// The executor for the HTTP component.
 /// The component can either implement the Spin HTTP interface,
-/// the `wasi-http` interface, or the Wagi CGI interface.
+/// the `wasi-http` interface, or the Wagi CGI interface, or the
+/// requests can be served from the component's files by the host.
 ///
 /// If an executor is not specified, the inferred default is `HttpExecutor::Spin`.
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
@@ -30,6 +152,9 @@ pub enum HttpExecutorType {
     Http,
     /// The component implements the Wagi CGI interface.
     Wagi(WagiTriggerConfig),
+    /// The host serves static files from the component's `files` mounts,
+    /// without running the component.
+    Static(StaticTriggerConfig),
 }
 
 /// Wagi specific configuration for the http executor.
@@ -65,6 +190,30 @@ impl Default for WagiTriggerConfig {
     }
 }
 
+/// Static file serving configuration for the http executor.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StaticTriggerConfig {
+    /// The directory to serve files from, as a path in the component's
+    /// `files` mounts. The part of the request path matched by the route's
+    /// trailing wildcard is resolved relative to this directory.
+    pub root: String,
+
+    /// The file to serve when a requested file does not exist, relative to
+    /// `root`. This supports single-page applications which do their own
+    /// routing, e.g. `fallback = "index.html"`.
+    pub fallback: Option<String>,
+}
+
+impl Default for StaticTriggerConfig {
+    fn default() -> Self {
+        Self {
+            root: "/".to_owned(),
+            fallback: None,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -78,4 +227,144 @@ mod tests {
         assert_eq!(config.entrypoint, "_start");
         assert_eq!(config.argv, "${SCRIPT_NAME} ${ARGS}");
     }
+
+    #[test]
+    fn static_config_smoke_test() {
+        let HttpExecutorType::Static(config) = toml::toml! { type = "static" }.try_into().unwrap()
+        else {
+            panic!("wrong type");
+        };
+        assert_eq!(config.root, "/");
+        assert_eq!(config.fallback, None);
+
+        let HttpExecutorType::Static(config) = toml::toml! {
+            type = "static"
+            root = "/dist"
+            fallback = "index.html"
+        }
+        .try_into()
+        .unwrap() else {
+            panic!("wrong type");
+        };
+        assert_eq!(config.root, "/dist");
+        assert_eq!(config.fallback.as_deref(), Some("index.html"));
+    }
+
+    #[test]
+    fn cors_config_smoke_test() {
+        let config: HttpTriggerConfig = toml::toml! {
+            component = "api"
+            route = "/..."
+        }
+        .try_into()
+        .unwrap();
+        assert!(config.cors.is_none());
+
+        let config: HttpTriggerConfig = toml::toml! {
+            component = "api"
+            route = "/..."
+            cors = { origins = ["https://example.com"], methods = ["GET", "PUT"], headers = ["content-type"], max_age = 600 }
+        }
+        .try_into()
+        .unwrap();
+        let cors = config.cors.unwrap();
+        assert_eq!(cors.origins, ["https://example.com"]);
+        assert_eq!(cors.methods, ["GET", "PUT"]);
+        assert_eq!(cors.headers, ["content-type"]);
+        assert_eq!(cors.max_age, Some(600));
+    }
+
+    #[test]
+    fn throttling_config_smoke_test() {
+        let config: HttpTriggerConfig = toml::toml! {
+            component = "api"
+            route = "/..."
+        }
+        .try_into()
+        .unwrap();
+        assert!(config.max_concurrency.is_none());
+        assert!(config.rate_limit.is_none());
+
+        let config: HttpTriggerConfig = toml::toml! {
+            component = "api"
+            route = "/..."
+            max_concurrency = 8
+            rate_limit = { requests_per_second = 2.5 }
+        }
+        .try_into()
+        .unwrap();
+        assert_eq!(config.max_concurrency, Some(8));
+        let rate_limit = config.rate_limit.unwrap();
+        assert_eq!(rate_limit.requests_per_second, 2.5);
+        assert_eq!(rate_limit.burst, None);
+        assert_eq!(rate_limit.key, HttpRateLimitKey::Global);
+
+        let config: HttpTriggerConfig = toml::toml! {
+            component = "api"
+            route = "/..."
+            rate_limit = { requests_per_second = 10, burst = 20, key = "client_ip" }
+        }
+        .try_into()
+        .unwrap();
+        let rate_limit = config.rate_limit.unwrap();
+        assert_eq!(rate_limit.requests_per_second, 10.0);
+        assert_eq!(rate_limit.burst, Some(20));
+        assert_eq!(rate_limit.key, HttpRateLimitKey::ClientIp);
+    }
+
+    #[test]
+    fn compression_config_smoke_test() {
+        let config: HttpTriggerConfig = toml::toml! {
+            component = "api"
+            route = "/..."
+        }
+        .try_into()
+        .unwrap();
+        assert!(config.compression.is_none());
+
+        let config: HttpTriggerConfig = toml::toml! {
+            component = "api"
+            route = "/..."
+            compression = {}
+        }
+        .try_into()
+        .unwrap();
+        let compression = config.compression.unwrap();
+        assert_eq!(compression.min_size, 1024);
+        assert!(compression.content_types.contains(&"text/*".to_owned()));
+
+        let config: HttpTriggerConfig = toml::toml! {
+            component = "api"
+            route = "/..."
+            compression = { min_size = 0, content_types = ["application/json"] }
+        }
+        .try_into()
+        .unwrap();
+        let compression = config.compression.unwrap();
+        assert_eq!(compression.min_size, 0);
+        assert_eq!(compression.content_types, ["application/json"]);
+    }
+
+    #[test]
+    fn route_matchers_are_optional() {
+        let config: HttpTriggerConfig = toml::toml! {
+            component = "api"
+            route = "/items/..."
+        }
+        .try_into()
+        .unwrap();
+        assert_eq!(config.route_matchers(), RouteMatchers::default());
+
+        let config: HttpTriggerConfig = toml::toml! {
+            component = "api"
+            route = "/items/..."
+            methods = ["GET", "POST"]
+            host = "*.example.com"
+        }
+        .try_into()
+        .unwrap();
+        let matchers = config.route_matchers();
+        assert_eq!(matchers.methods, ["GET", "POST"]);
+        assert_eq!(matchers.host.as_deref(), Some("*.example.com"));
+    }
 }
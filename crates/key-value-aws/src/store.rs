@@ -2,6 +2,7 @@ use core::str;
 use std::{
     collections::HashMap,
     sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use anyhow::Result;
@@ -11,7 +12,7 @@ use aws_sdk_dynamodb::{
     config::{ProvideCredentials, SharedCredentialsProvider},
     operation::{
         batch_get_item::BatchGetItemOutput, batch_write_item::BatchWriteItemOutput,
-        get_item::GetItemOutput,
+        get_item::GetItemOutput, scan::ScanOutput,
     },
     primitives::Blob,
     types::{
@@ -21,7 +22,7 @@ use aws_sdk_dynamodb::{
     Client,
 };
 use spin_core::async_trait;
-use spin_factor_key_value::{log_error, Cas, Error, Store, StoreManager, SwapError};
+use spin_factor_key_value::{log_error, Cas, Error, KeyPage, Store, StoreManager, SwapError};
 
 pub struct KeyValueAwsDynamo {
     /// AWS region
@@ -172,10 +173,54 @@ const PK: &str = "PK";
 const VAL: &str = "VAL";
 /// Version key in DynamoDB items used for atomic operations
 const VER: &str = "VER";
+/// Expiry key in DynamoDB items holding the epoch second after which the item is expired
+///
+/// Enabling DynamoDB's Time to Live on this attribute lets DynamoDB delete expired items,
+/// but as that happens lazily, expired items are also filtered out on every read.
+const TTL: &str = "TTL";
+
+/// Returns true if the item has a TTL attribute which is in the past.
+fn is_expired(item: &HashMap<String, AttributeValue>) -> bool {
+    let Some(AttributeValue::N(ttl)) = item.get(TTL) else {
+        return false;
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    ttl.parse::<u64>().map(|ttl| ttl <= now).unwrap_or(false)
+}
+
+/// The time the item has left to live, if it has a TTL attribute.
+fn remaining_ttl(item: &HashMap<String, AttributeValue>) -> Option<Duration> {
+    let Some(AttributeValue::N(ttl)) = item.get(TTL) else {
+        return None;
+    };
+    let expires_at = UNIX_EPOCH + Duration::from_secs(ttl.parse().ok()?);
+    Some(
+        expires_at
+            .duration_since(SystemTime::now())
+            .unwrap_or_default(),
+    )
+}
+
+/// The TTL attribute value for an item set now with the given time to live.
+fn ttl_attribute(ttl: Duration) -> AttributeValue {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    // DynamoDB TTLs have a granularity of seconds, so round up rather than expire early.
+    let expires_at = (now + ttl).as_secs() + u64::from((now + ttl).subsec_nanos() > 0);
+    AttributeValue::N(expires_at.to_string())
+}
 
 #[async_trait]
 impl Store for AwsDynamoStore {
     async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.get_with_ttl(key).await?.map(|(value, _)| value))
+    }
+
+    async fn get_with_ttl(&self, key: &str) -> Result<Option<(Vec<u8>, Option<Duration>)>, Error> {
         let response = self
             .client
             .get_item()
@@ -185,18 +230,22 @@ impl Store for AwsDynamoStore {
                 PK,
                 aws_sdk_dynamodb::types::AttributeValue::S(key.to_string()),
             )
-            .projection_expression(VAL)
+            .projection_expression("#VAL, #TTL")
+            .expression_attribute_names("#VAL", VAL)
+            .expression_attribute_names("#TTL", TTL)
             .send()
             .await
             .map_err(log_error)?;
 
-        let item = response.item.and_then(|mut item| {
-            if let Some(AttributeValue::B(val)) = item.remove(VAL) {
-                Some(val.into_inner())
-            } else {
-                None
-            }
-        });
+        let item = response
+            .item
+            .filter(|item| !is_expired(item))
+            .and_then(|mut item| {
+                let Some(AttributeValue::B(val)) = item.remove(VAL) else {
+                    return None;
+                };
+                Some((val.into_inner(), remaining_ttl(&item)))
+            });
 
         Ok(item)
     }
@@ -213,6 +262,19 @@ impl Store for AwsDynamoStore {
         Ok(())
     }
 
+    async fn set_with_ttl(&self, key: &str, value: &[u8], ttl: Duration) -> Result<(), Error> {
+        self.client
+            .put_item()
+            .table_name(self.table.as_str())
+            .item(PK, AttributeValue::S(key.to_string()))
+            .item(VAL, AttributeValue::B(Blob::new(value)))
+            .item(TTL, ttl_attribute(ttl))
+            .send()
+            .await
+            .map_err(log_error)?;
+        Ok(())
+    }
+
     async fn delete(&self, key: &str) -> Result<(), Error> {
         self.client
             .delete_item()
@@ -234,12 +296,16 @@ impl Store for AwsDynamoStore {
                 PK,
                 aws_sdk_dynamodb::types::AttributeValue::S(key.to_string()),
             )
-            .projection_expression(PK)
+            .projection_expression("#PK, #TTL")
+            .expression_attribute_names("#PK", PK)
+            .expression_attribute_names("#TTL", TTL)
             .send()
             .await
             .map_err(log_error)?;
 
-        Ok(item.map(|item| item.contains_key(PK)).unwrap_or(false))
+        Ok(item
+            .map(|item| item.contains_key(PK) && !is_expired(&item))
+            .unwrap_or(false))
     }
 
     async fn get_keys(&self) -> Result<Vec<String>, Error> {
@@ -249,7 +315,9 @@ impl Store for AwsDynamoStore {
             .client
             .scan()
             .table_name(self.table.as_str())
-            .projection_expression(PK)
+            .projection_expression("#PK, #TTL")
+            .expression_attribute_names("#PK", PK)
+            .expression_attribute_names("#TTL", TTL)
             .into_paginator()
             .send();
 
@@ -257,6 +325,9 @@ impl Store for AwsDynamoStore {
             let scan_output = output.map_err(log_error)?;
             if let Some(items) = scan_output.items {
                 for mut item in items {
+                    if is_expired(&item) {
+                        continue;
+                    }
                     if let Some(AttributeValue::S(pk)) = item.remove(PK) {
                         primary_keys.push(pk);
                     }
@@ -267,10 +338,63 @@ impl Store for AwsDynamoStore {
         Ok(primary_keys)
     }
 
+    /// Lists keys with a single `Scan` request, using the last evaluated key as the cursor.
+    ///
+    /// DynamoDB applies `limit` before filtering by prefix, so pages may be smaller than `limit`.
+    async fn list_keys(
+        &self,
+        prefix: Option<&str>,
+        cursor: Option<&str>,
+        limit: u32,
+    ) -> Result<KeyPage, Error> {
+        let mut scan = self
+            .client
+            .scan()
+            .table_name(self.table.as_str())
+            .consistent_read(self.consistent_read)
+            .limit(i32::try_from(limit.max(1)).unwrap_or(i32::MAX))
+            .projection_expression("#PK, #TTL")
+            .expression_attribute_names("#PK", PK)
+            .expression_attribute_names("#TTL", TTL);
+        if let Some(prefix) = prefix.filter(|p| !p.is_empty()) {
+            scan = scan
+                .filter_expression("begins_with(#PK, :prefix)")
+                .expression_attribute_values(":prefix", AttributeValue::S(prefix.to_owned()));
+        }
+        if let Some(cursor) = cursor {
+            scan = scan.exclusive_start_key(PK, AttributeValue::S(cursor.to_owned()));
+        }
+
+        let ScanOutput {
+            items,
+            last_evaluated_key,
+            ..
+        } = scan.send().await.map_err(log_error)?;
+
+        let keys = items
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|item| !is_expired(item))
+            .filter_map(|mut item| match item.remove(PK) {
+                Some(AttributeValue::S(pk)) => Some(pk),
+                _ => None,
+            })
+            .collect();
+        let cursor = last_evaluated_key.and_then(|mut key| match key.remove(PK) {
+            Some(AttributeValue::S(pk)) => Some(pk),
+            _ => None,
+        });
+
+        Ok(KeyPage { keys, cursor })
+    }
+
     async fn get_many(&self, keys: Vec<String>) -> Result<Vec<(String, Option<Vec<u8>>)>, Error> {
         let mut results = Vec::with_capacity(keys.len());
         let mut keys_and_attributes_builder = KeysAndAttributes::builder()
-            .projection_expression(format!("{PK},{VAL}"))
+            .projection_expression("#PK, #VAL, #TTL")
+            .expression_attribute_names("#PK", PK)
+            .expression_attribute_names("#VAL", VAL)
+            .expression_attribute_names("#TTL", TTL)
             .consistent_read(self.consistent_read);
         for key in keys {
             keys_and_attributes_builder = keys_and_attributes_builder.keys(HashMap::from_iter([(
@@ -300,6 +424,9 @@ impl Store for AwsDynamoStore {
                 responses.and_then(|mut responses| responses.remove(self.table.as_str()))
             {
                 for mut item in items {
+                    if is_expired(&item) {
+                        continue;
+                    }
                     match (item.remove(PK), item.remove(VAL)) {
                         (Some(AttributeValue::S(pk)), Some(AttributeValue::B(val))) => {
                             results.push((pk, Some(val.into_inner())));
@@ -394,12 +521,21 @@ impl Store for AwsDynamoStore {
             .consistent_read(true)
             .table_name(self.table.as_str())
             .key(PK, AttributeValue::S(key.clone()))
-            .projection_expression(VAL)
+            .projection_expression("#VAL, #TTL")
+            .expression_attribute_names("#VAL", VAL)
+            .expression_attribute_names("#TTL", TTL)
             .send()
             .await
             .map_err(log_error)?;
 
-        let old_val = match item {
+        // An expired counter is treated as missing, but the item still needs to be
+        // overwritten (and its expiry dropped) rather than created.
+        let expired_ttl = match &item {
+            Some(current_item) if is_expired(current_item) => current_item.get(TTL).cloned(),
+            _ => None,
+        };
+
+        let old_val = match item.filter(|_| expired_ttl.is_none()) {
             Some(mut current_item) => match current_item.remove(VAL) {
                 // We're expecting i64, so technically we could transmute but seems risky...
                 Some(AttributeValue::B(val)) => Some(
@@ -425,7 +561,13 @@ impl Store for AwsDynamoStore {
                 AttributeValue::B(Blob::new(new_val.to_string().as_bytes())),
             );
 
-        if let Some(old_val) = old_val {
+        if let Some(expired_ttl) = expired_ttl {
+            update = update
+                .update_expression("SET #VAL = :new_val REMOVE #TTL")
+                .condition_expression("#TTL = :old_ttl")
+                .expression_attribute_names("#TTL", TTL)
+                .expression_attribute_values(":old_ttl", expired_ttl)
+        } else if let Some(old_val) = old_val {
             update = update
                 .condition_expression("#VAL = :old_val")
                 .expression_attribute_values(
@@ -474,12 +616,19 @@ impl Cas for CompareAndSwap {
             .consistent_read(true)
             .table_name(self.table.as_str())
             .key(PK, AttributeValue::S(self.key.clone()))
-            .projection_expression(format!("{VAL},{VER}"))
+            .projection_expression("#VAL, #VER, #TTL")
+            .expression_attribute_names("#VAL", VAL)
+            .expression_attribute_names("#VER", VER)
+            .expression_attribute_names("#TTL", TTL)
             .send()
             .await
             .map_err(log_error)?;
 
-        match item {
+        // An expired item reads as missing, but the swap must still be conditioned on the
+        // item as it exists, so the recorded state comes from the expired item.
+        let expired = item.as_ref().map(is_expired).unwrap_or(false);
+
+        let value = match item {
             Some(mut current_item) => match (current_item.remove(VAL), current_item.remove(VER)) {
                 (Some(AttributeValue::B(val)), Some(AttributeValue::N(ver))) => {
                     self.state
@@ -487,7 +636,7 @@ impl Cas for CompareAndSwap {
                         .unwrap()
                         .clone_from(&CasState::Versioned(ver));
 
-                    Ok(Some(val.into_inner()))
+                    Some(val.into_inner())
                 }
                 (Some(AttributeValue::B(val)), _) => {
                     self.state
@@ -495,18 +644,20 @@ impl Cas for CompareAndSwap {
                         .unwrap()
                         .clone_from(&CasState::Unversioned(val.clone()));
 
-                    Ok(Some(val.into_inner()))
+                    Some(val.into_inner())
                 }
                 (_, _) => {
                     self.state.lock().unwrap().clone_from(&CasState::Unset);
-                    Ok(None)
+                    None
                 }
             },
             None => {
                 self.state.lock().unwrap().clone_from(&CasState::Unset);
-                Ok(None)
+                None
             }
-        }
+        };
+
+        Ok(value.filter(|_| !expired))
     }
 
     /// `swap` updates the value for the key -- if possible, using the version saved in the `current` function for
@@ -515,9 +666,10 @@ impl Cas for CompareAndSwap {
         let mut update = Update::builder()
             .table_name(self.table.as_str())
             .key(PK, AttributeValue::S(self.key.clone()))
-            .update_expression("SET #VAL = :val ADD #VER :increment")
+            .update_expression("SET #VAL = :val ADD #VER :increment REMOVE #TTL")
             .expression_attribute_names("#VAL", VAL)
             .expression_attribute_names("#VER", VER)
+            .expression_attribute_names("#TTL", TTL)
             .expression_attribute_values(":val", AttributeValue::B(Blob::new(value)))
             .expression_attribute_values(":increment", AttributeValue::N("1".to_owned()));
 
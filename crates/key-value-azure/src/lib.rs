@@ -33,6 +33,7 @@ pub struct AzureCosmosKeyValueRuntimeConfig {
     database: String,
     /// The Azure Cosmos DB container where data is stored.
     /// The CosmosDB container must be created with the default partition key, /id
+    /// Time to live must be enabled on the container for keys set with a TTL to expire.
     container: String,
 }
 
@@ -1,15 +1,22 @@
 use anyhow::{Context, Result};
 use async_trait::async_trait;
+use azure_core::headers::Header;
 use azure_data_cosmos::{
     prelude::{
-        AuthorizationToken, CollectionClient, CosmosClient, CosmosClientBuilder, Operation, Query,
+        AuthorizationToken, CollectionClient, CosmosClient, CosmosClientBuilder, Operation, Param,
+        Query,
     },
     CosmosEntity,
 };
 use futures::StreamExt;
 use serde::{Deserialize, Serialize};
-use spin_factor_key_value::{log_cas_error, log_error, Cas, Error, Store, StoreManager, SwapError};
-use std::sync::{Arc, Mutex};
+use spin_factor_key_value::{
+    log_cas_error, log_error, Cas, Error, KeyPage, Store, StoreManager, SwapError,
+};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 pub struct KeyValueAzureCosmos {
     client: CollectionClient,
@@ -153,27 +160,27 @@ impl Store for AzureCosmosStore {
         Ok(pair.map(|p| p.value))
     }
 
-    async fn set(&self, key: &str, value: &[u8]) -> Result<(), Error> {
-        let illegal_chars = ['/', '\\', '?', '#'];
+    async fn get_with_ttl(&self, key: &str) -> Result<Option<(Vec<u8>, Option<Duration>)>, Error> {
+        let pair = self.get_entity::<Pair>(key).await?;
+        Ok(pair.map(|p| {
+            let ttl = p.remaining_ttl();
+            (p.value, ttl)
+        }))
+    }
 
-        if key.contains(|c| illegal_chars.contains(&c)) {
-            return Err(Error::Other(format!(
-                "Key contains an illegal character. Keys must not include any of: {}",
-                illegal_chars.iter().collect::<String>()
-            )));
-        }
+    async fn set(&self, key: &str, value: &[u8]) -> Result<(), Error> {
+        self.upsert(key, value, None).await
+    }
 
-        let pair = Pair {
-            id: key.to_string(),
-            value: value.to_vec(),
-            store_id: self.store_id.clone(),
-        };
-        self.client
-            .create_document(pair)
-            .is_upsert(true)
-            .await
-            .map_err(log_error)?;
-        Ok(())
+    /// Sets the value with a per-item `ttl`.
+    ///
+    /// Cosmos DB only honours per-item TTLs when time to live is enabled on the
+    /// container (for example with a default TTL of `-1`, meaning "never expire").
+    async fn set_with_ttl(&self, key: &str, value: &[u8], ttl: Duration) -> Result<(), Error> {
+        // Cosmos DB TTLs have a granularity of seconds, so round up rather than expire early.
+        let secs = ttl.as_secs() + u64::from(ttl.subsec_nanos() > 0);
+        let ttl = i32::try_from(secs).unwrap_or(i32::MAX).max(1);
+        self.upsert(key, value, Some(ttl)).await
     }
 
     async fn delete(&self, key: &str) -> Result<(), Error> {
@@ -208,6 +215,35 @@ impl Store for AzureCosmosStore {
         self.get_keys().await
     }
 
+    /// Lists keys one query page at a time, using the Cosmos DB continuation token as the cursor.
+    async fn list_keys(
+        &self,
+        prefix: Option<&str>,
+        cursor: Option<&str>,
+        limit: u32,
+    ) -> Result<KeyPage, Error> {
+        let mut query = self
+            .client
+            .query_documents(self.list_keys_query(prefix))
+            .query_cross_partition(true)
+            .max_item_count(i32::try_from(limit.max(1)).unwrap_or(i32::MAX));
+        if let Some(cursor) = cursor {
+            query = query.continuation(cursor.to_owned());
+        }
+
+        let mut stream = query.into_stream::<Key>();
+        let Some(resp) = stream.next().await else {
+            return Ok(KeyPage::default());
+        };
+        let resp = resp.map_err(log_error)?;
+        Ok(KeyPage {
+            keys: resp.results.into_iter().map(|(key, _)| key.id).collect(),
+            cursor: resp
+                .continuation_token
+                .map(|token| token.value().as_str().to_owned()),
+        })
+    }
+
     async fn get_many(&self, keys: Vec<String>) -> Result<Vec<(String, Option<Vec<u8>>)>, Error> {
         let stmt = Query::new(self.get_in_query(keys));
         let query = self
@@ -374,6 +410,8 @@ impl Cas for CompareAndSwap {
             id: self.key.clone(),
             value,
             store_id: self.store_id.clone(),
+            ttl: None,
+            last_modified: None,
         };
 
         let doc_client = self
@@ -413,6 +451,31 @@ impl Cas for CompareAndSwap {
 }
 
 impl AzureCosmosStore {
+    async fn upsert(&self, key: &str, value: &[u8], ttl: Option<i32>) -> Result<(), Error> {
+        let illegal_chars = ['/', '\\', '?', '#'];
+
+        if key.contains(|c| illegal_chars.contains(&c)) {
+            return Err(Error::Other(format!(
+                "Key contains an illegal character. Keys must not include any of: {}",
+                illegal_chars.iter().collect::<String>()
+            )));
+        }
+
+        let pair = Pair {
+            id: key.to_string(),
+            value: value.to_vec(),
+            store_id: self.store_id.clone(),
+            ttl,
+            last_modified: None,
+        };
+        self.client
+            .create_document(pair)
+            .is_upsert(true)
+            .await
+            .map_err(log_error)?;
+        Ok(())
+    }
+
     async fn get_entity<F>(&self, key: &str) -> Result<Option<F>, Error>
     where
         F: CosmosEntity + Send + Sync + serde::de::DeserializeOwned + Clone,
@@ -469,6 +532,20 @@ impl AzureCosmosStore {
         query
     }
 
+    fn list_keys_query(&self, prefix: Option<&str>) -> Query {
+        let mut query = "SELECT c.id, c.store_id FROM c".to_owned();
+        let Some(prefix) = prefix.filter(|p| !p.is_empty()) else {
+            self.append_store_id(&mut query, false);
+            return Query::new(query);
+        };
+        query.push_str(" WHERE STARTSWITH(c.id, @prefix)");
+        self.append_store_id(&mut query, true);
+        Query::with_params(
+            query,
+            vec![Param::new("@prefix".to_owned(), prefix.to_owned())],
+        )
+    }
+
     fn get_in_query(&self, keys: Vec<String>) -> String {
         let in_clause: String = keys
             .into_iter()
@@ -511,6 +588,25 @@ pub struct Pair {
     pub value: Vec<u8>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub store_id: Option<String>,
+    /// Seconds after the last write at which Cosmos DB expires the item.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<i32>,
+    /// When the item was last written, in seconds since the Unix epoch, as set by Cosmos DB.
+    #[serde(rename = "_ts", default, skip_serializing)]
+    pub last_modified: Option<u64>,
+}
+
+impl Pair {
+    /// The time the item has left to live, if it was written with a TTL.
+    fn remaining_ttl(&self) -> Option<Duration> {
+        let ttl = u64::try_from(self.ttl?).ok()?;
+        let expires_at = UNIX_EPOCH + Duration::from_secs(self.last_modified? + ttl);
+        Some(
+            expires_at
+                .duration_since(SystemTime::now())
+                .unwrap_or_default(),
+        )
+    }
 }
 
 impl CosmosEntity for Pair {
@@ -1,8 +1,8 @@
 use anyhow::{Context, Result};
 use redis::{aio::ConnectionManager, parse_redis_url, AsyncCommands, Client, RedisError};
 use spin_core::async_trait;
-use spin_factor_key_value::{log_error, Cas, Error, Store, StoreManager, SwapError};
-use std::sync::Arc;
+use spin_factor_key_value::{log_error, Cas, Error, KeyPage, Store, StoreManager, SwapError};
+use std::{sync::Arc, time::Duration};
 use tokio::sync::OnceCell;
 use url::Url;
 
@@ -76,6 +76,19 @@ impl Store for RedisStore {
         self.connection.clone().get(key).await.map_err(log_error)
     }
 
+    async fn get_with_ttl(&self, key: &str) -> Result<Option<(Vec<u8>, Option<Duration>)>, Error> {
+        let (value, pttl): (Option<Vec<u8>>, i64) = redis::pipe()
+            .atomic()
+            .get(key)
+            .pttl(key)
+            .query_async(&mut self.connection.clone())
+            .await
+            .map_err(log_error)?;
+        // `PTTL` is negative for keys without an expiry.
+        let ttl = u64::try_from(pttl).ok().map(Duration::from_millis);
+        Ok(value.map(|value| (value, ttl)))
+    }
+
     async fn set(&self, key: &str, value: &[u8]) -> Result<(), Error> {
         self.connection
             .clone()
@@ -84,6 +97,16 @@ impl Store for RedisStore {
             .map_err(log_error)
     }
 
+    async fn set_with_ttl(&self, key: &str, value: &[u8], ttl: Duration) -> Result<(), Error> {
+        // Redis rejects an expiry of zero, so clamp sub-millisecond TTLs to the minimum.
+        let millis = ttl.as_millis().clamp(1, u64::MAX.into()) as u64;
+        self.connection
+            .clone()
+            .pset_ex(key, value, millis)
+            .await
+            .map_err(log_error)
+    }
+
     async fn delete(&self, key: &str) -> Result<(), Error> {
         self.connection.clone().del(key).await.map_err(log_error)
     }
@@ -96,6 +119,37 @@ impl Store for RedisStore {
         self.connection.clone().keys("*").await.map_err(log_error)
     }
 
+    /// Lists keys with `SCAN`, whose cursor doubles as the page cursor.
+    ///
+    /// `limit` is passed as the `COUNT` hint, so pages may be larger or smaller than `limit`.
+    async fn list_keys(
+        &self,
+        prefix: Option<&str>,
+        cursor: Option<&str>,
+        limit: u32,
+    ) -> Result<KeyPage, Error> {
+        let cursor = match cursor {
+            Some(cursor) => cursor
+                .parse::<u64>()
+                .map_err(|_| Error::Other(format!("invalid cursor {cursor:?}")))?,
+            None => 0,
+        };
+        let pattern = format!("{}*", escape_glob(prefix.unwrap_or_default()));
+        let (next, keys): (u64, Vec<String>) = redis::cmd("SCAN")
+            .arg(cursor)
+            .arg("MATCH")
+            .arg(pattern)
+            .arg("COUNT")
+            .arg(limit.max(1))
+            .query_async(&mut self.connection.clone())
+            .await
+            .map_err(log_error)?;
+        Ok(KeyPage {
+            keys,
+            cursor: (next != 0).then(|| next.to_string()),
+        })
+    }
+
     async fn get_many(&self, keys: Vec<String>) -> Result<Vec<(String, Option<Vec<u8>>)>, Error> {
         self.connection.clone().keys(keys).await.map_err(log_error)
     }
@@ -142,6 +196,18 @@ impl Store for RedisStore {
     }
 }
 
+/// Escapes the characters that `SCAN MATCH` treats as glob syntax.
+fn escape_glob(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
 #[async_trait]
 impl Cas for CompareAndSwap {
     /// current will initiate a transaction by WATCH'ing a key in Redis, and then returning the
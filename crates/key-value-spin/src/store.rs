@@ -1,15 +1,24 @@
 use anyhow::Result;
 use rusqlite::{named_params, Connection};
 use spin_core::async_trait;
-use spin_factor_key_value::{log_cas_error, log_error, Cas, Error, Store, StoreManager, SwapError};
+use spin_factor_key_value::{
+    log_cas_error, log_error, Cas, Error, KeyPage, Store, StoreManager, SwapError,
+};
 use std::rc::Rc;
 use std::{
     path::PathBuf,
     sync::OnceLock,
-    sync::{Arc, Mutex},
+    sync::{Arc, Mutex, Weak},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 use tokio::task;
 
+/// How often expired entries are removed from the database.
+///
+/// Expired entries are never returned by reads, so this only bounds how long
+/// they keep occupying space.
+const EXPIRY_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
 #[derive(Clone, Debug)]
 pub enum DatabaseLocation {
     InMemory,
@@ -47,6 +56,7 @@ impl KeyValueSqlite {
                            store TEXT NOT NULL,
                            key   TEXT NOT NULL,
                            value BLOB NOT NULL,
+                           expires_at INTEGER,
 
                            PRIMARY KEY (store, key)
                         )",
@@ -54,11 +64,96 @@ impl KeyValueSqlite {
             )
             .map_err(log_error)?;
 
+        // Databases created by older versions of Spin lack the expiry column.
+        let has_expiry_column = connection
+            .prepare("SELECT 1 FROM pragma_table_info('spin_key_value') WHERE name='expires_at'")
+            .map_err(log_error)?
+            .exists([])
+            .map_err(log_error)?;
+        if !has_expiry_column {
+            connection
+                .execute(
+                    "ALTER TABLE spin_key_value ADD COLUMN expires_at INTEGER",
+                    [],
+                )
+                .map_err(log_error)?;
+        }
+
+        connection
+            .execute(
+                "CREATE INDEX IF NOT EXISTS spin_key_value_expires_at
+                    ON spin_key_value (expires_at) WHERE expires_at IS NOT NULL",
+                [],
+            )
+            .map_err(log_error)?;
+
         // the array module is needed for `rarray` usage in queries.
         rusqlite::vtab::array::load_module(&connection).map_err(log_error)?;
 
-        Ok(Arc::new(Mutex::new(connection)))
+        let connection = Arc::new(Mutex::new(connection));
+        spawn_expiry_sweeper(Arc::downgrade(&connection));
+        Ok(connection)
+    }
+}
+
+/// Periodically deletes expired entries for as long as the connection is alive.
+fn spawn_expiry_sweeper(connection: Weak<Mutex<Connection>>) {
+    let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+        // Without a runtime we fall back to purely lazy expiry.
+        return;
+    };
+    runtime.spawn(async move {
+        let start = tokio::time::Instant::now() + EXPIRY_SWEEP_INTERVAL;
+        let mut interval = tokio::time::interval_at(start, EXPIRY_SWEEP_INTERVAL);
+        loop {
+            interval.tick().await;
+            let Some(connection) = connection.upgrade() else {
+                break;
+            };
+            let result = task::block_in_place(|| delete_expired(&connection.lock().unwrap()));
+            if let Err(e) = result {
+                tracing::warn!("failed to delete expired key-value entries: {e:?}");
+            }
+        }
+    });
+}
+
+/// Deletes all expired entries across every store, returning how many were removed.
+fn delete_expired(connection: &Connection) -> rusqlite::Result<usize> {
+    connection
+        .prepare_cached("DELETE FROM spin_key_value WHERE expires_at <= $1")?
+        .execute([now_millis()])
+}
+
+/// The current time in milliseconds since the Unix epoch, as stored in `expires_at`.
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+        .try_into()
+        .unwrap_or(i64::MAX)
+}
+
+/// The smallest string greater than every string starting with `prefix`, if there is one.
+///
+/// Keys are compared as UTF-8 bytes, which orders them by code point, so bumping the last
+/// character that can be bumped gives an exclusive upper bound for the prefix range.
+fn prefix_upper_bound(prefix: &str) -> Option<String> {
+    let mut chars: Vec<char> = prefix.chars().collect();
+    while let Some(last) = chars.pop() {
+        if let Some(next) = (u32::from(last) + 1..=u32::from(char::MAX)).find_map(char::from_u32) {
+            chars.push(next);
+            return Some(chars.into_iter().collect());
+        }
     }
+    None
+}
+
+/// The `expires_at` value for an entry set now with the given time to live.
+fn expires_at(ttl: Duration) -> i64 {
+    let ttl: i64 = ttl.as_millis().try_into().unwrap_or(i64::MAX);
+    now_millis().saturating_add(ttl)
 }
 
 #[async_trait]
@@ -104,9 +199,14 @@ impl Store for SqliteStore {
             self.connection
                 .lock()
                 .unwrap()
-                .prepare_cached("SELECT value FROM spin_key_value WHERE store=$1 AND key=$2")
+                .prepare_cached(
+                    "SELECT value FROM spin_key_value WHERE store=$1 AND key=$2
+                     AND (expires_at IS NULL OR expires_at > $3)",
+                )
                 .map_err(log_error)?
-                .query_map([&self.name, key], |row| row.get(0))
+                .query_map(rusqlite::params![&self.name, key, now_millis()], |row| {
+                    row.get(0)
+                })
                 .map_err(log_error)?
                 .next()
                 .transpose()
@@ -114,14 +214,41 @@ impl Store for SqliteStore {
         })
     }
 
+    async fn get_with_ttl(&self, key: &str) -> Result<Option<(Vec<u8>, Option<Duration>)>, Error> {
+        let now = now_millis();
+        let row: Option<(Vec<u8>, Option<i64>)> = task::block_in_place(|| {
+            self.connection
+                .lock()
+                .unwrap()
+                .prepare_cached(
+                    "SELECT value, expires_at FROM spin_key_value WHERE store=$1 AND key=$2
+                     AND (expires_at IS NULL OR expires_at > $3)",
+                )
+                .map_err(log_error)?
+                .query_map(rusqlite::params![&self.name, key, now], |row| {
+                    Ok((row.get(0)?, row.get(1)?))
+                })
+                .map_err(log_error)?
+                .next()
+                .transpose()
+                .map_err(log_error)
+        })?;
+        Ok(row.map(|(value, expires_at)| {
+            let ttl = expires_at.map(|expires_at| {
+                Duration::from_millis(expires_at.saturating_sub(now).try_into().unwrap_or(0))
+            });
+            (value, ttl)
+        }))
+    }
+
     async fn set(&self, key: &str, value: &[u8]) -> Result<(), Error> {
         task::block_in_place(|| {
             self.connection
                 .lock()
                 .unwrap()
                 .prepare_cached(
-                    "INSERT INTO spin_key_value (store, key, value) VALUES ($1, $2, $3)
-                     ON CONFLICT(store, key) DO UPDATE SET value=$3",
+                    "INSERT INTO spin_key_value (store, key, value, expires_at) VALUES ($1, $2, $3, NULL)
+                     ON CONFLICT(store, key) DO UPDATE SET value=$3, expires_at=NULL",
                 )
                 .map_err(log_error)?
                 .execute(rusqlite::params![&self.name, key, value])
@@ -130,6 +257,22 @@ impl Store for SqliteStore {
         })
     }
 
+    async fn set_with_ttl(&self, key: &str, value: &[u8], ttl: Duration) -> Result<(), Error> {
+        task::block_in_place(|| {
+            self.connection
+                .lock()
+                .unwrap()
+                .prepare_cached(
+                    "INSERT INTO spin_key_value (store, key, value, expires_at) VALUES ($1, $2, $3, $4)
+                     ON CONFLICT(store, key) DO UPDATE SET value=$3, expires_at=$4",
+                )
+                .map_err(log_error)?
+                .execute(rusqlite::params![&self.name, key, value, expires_at(ttl)])
+                .map_err(log_error)
+                .map(drop)
+        })
+    }
+
     async fn delete(&self, key: &str) -> Result<(), Error> {
         task::block_in_place(|| {
             self.connection
@@ -152,15 +295,78 @@ impl Store for SqliteStore {
             self.connection
                 .lock()
                 .unwrap()
-                .prepare_cached("SELECT key FROM spin_key_value WHERE store=$1")
+                .prepare_cached(
+                    "SELECT key FROM spin_key_value WHERE store=$1
+                     AND (expires_at IS NULL OR expires_at > $2)",
+                )
                 .map_err(log_error)?
-                .query_map([&self.name], |row| row.get(0))
+                .query_map(rusqlite::params![&self.name, now_millis()], |row| {
+                    row.get(0)
+                })
                 .map_err(log_error)?
                 .map(|r| r.map_err(log_error))
                 .collect()
         })
     }
 
+    async fn list_keys(
+        &self,
+        prefix: Option<&str>,
+        cursor: Option<&str>,
+        limit: u32,
+    ) -> Result<KeyPage, Error> {
+        let prefix = prefix.unwrap_or_default();
+        let limit = limit.max(1);
+        // A cursor is the last key of the previous page, so the next page starts after it.
+        let (start, start_op) = match cursor {
+            Some(cursor) if cursor >= prefix => (cursor, ">"),
+            _ => (prefix, ">="),
+        };
+        let end = prefix_upper_bound(prefix);
+
+        let mut sql = format!(
+            "SELECT key FROM spin_key_value WHERE store=:name AND key {start_op} :start
+             AND (expires_at IS NULL OR expires_at > :now)"
+        );
+        if end.is_some() {
+            sql.push_str(" AND key < :end");
+        }
+        // Fetch one extra key to find out whether there is another page.
+        sql.push_str(" ORDER BY key LIMIT :limit");
+
+        let now = now_millis();
+        let fetch = i64::from(limit) + 1;
+        let mut params: Vec<(&str, &dyn rusqlite::ToSql)> = vec![
+            (":name", &self.name),
+            (":start", &start),
+            (":now", &now),
+            (":limit", &fetch),
+        ];
+        if let Some(end) = &end {
+            params.push((":end", end));
+        }
+
+        let mut keys: Vec<String> = task::block_in_place(|| {
+            self.connection
+                .lock()
+                .unwrap()
+                .prepare_cached(&sql)
+                .map_err(log_error)?
+                .query_map(params.as_slice(), |row| row.get(0))
+                .map_err(log_error)?
+                .map(|r| r.map_err(log_error))
+                .collect::<Result<_, _>>()
+        })?;
+
+        let cursor = if keys.len() > limit as usize {
+            keys.truncate(limit as usize);
+            keys.last().cloned()
+        } else {
+            None
+        };
+        Ok(KeyPage { keys, cursor })
+    }
+
     async fn get_many(&self, keys: Vec<String>) -> Result<Vec<(String, Option<Vec<u8>>)>, Error> {
         task::block_in_place(|| {
             let sql_value_keys: Vec<rusqlite::types::Value> =
@@ -169,9 +375,9 @@ impl Store for SqliteStore {
             let row_iter: Vec<Result<(String, Option<Vec<u8>>), Error>> = self.connection
                 .lock()
                 .unwrap()
-                .prepare_cached("SELECT key, value FROM spin_key_value WHERE store=:name AND key IN rarray(:keys)")
+                .prepare_cached("SELECT key, value FROM spin_key_value WHERE store=:name AND key IN rarray(:keys) AND (expires_at IS NULL OR expires_at > :now)")
                 .map_err(log_error)?
-                .query_map(named_params! {":name": &self.name, ":keys": ptr}, |row| {
+                .query_map(named_params! {":name": &self.name, ":keys": ptr, ":now": now_millis()}, |row| {
                     <(String, Option<Vec<u8>>)>::try_from(row)
                 })
                 .map_err(log_error)?
@@ -193,8 +399,8 @@ impl Store for SqliteStore {
             let tx = binding.transaction().map_err(log_error)?;
             for kv in key_values {
                 tx.prepare_cached(
-                    "INSERT INTO spin_key_value (store, key, value) VALUES ($1, $2, $3)
-                     ON CONFLICT(store, key) DO UPDATE SET value=$3",
+                    "INSERT INTO spin_key_value (store, key, value, expires_at) VALUES ($1, $2, $3, NULL)
+                     ON CONFLICT(store, key) DO UPDATE SET value=$3, expires_at=NULL",
                 )
                 .map_err(log_error)?
                 .execute(rusqlite::params![&self.name, kv.0, kv.1])
@@ -232,28 +438,39 @@ impl Store for SqliteStore {
             let tx = binding.transaction().map_err(log_error)?;
 
             let value: Option<Vec<u8>> = tx
-                .prepare_cached("SELECT value FROM spin_key_value WHERE store=$1 AND key=$2")
+                .prepare_cached(
+                    "SELECT value FROM spin_key_value WHERE store=$1 AND key=$2
+                     AND (expires_at IS NULL OR expires_at > $3)",
+                )
                 .map_err(log_error)?
-                .query_map([&self.name, &key], |row| row.get(0))
+                .query_map(rusqlite::params![&self.name, &key, now_millis()], |row| {
+                    row.get(0)
+                })
                 .map_err(log_error)?
                 .next()
                 .transpose()
                 .map_err(log_error)?;
 
-            let numeric: i64 = match value {
-                Some(v) => i64::from_le_bytes(v.try_into().expect("incorrect length")),
-                None => 0,
+            // A live counter keeps its expiry; an expired one starts afresh without one.
+            let (numeric, upsert): (i64, &str) = match value {
+                Some(v) => (
+                    i64::from_le_bytes(v.try_into().expect("incorrect length")),
+                    "INSERT INTO spin_key_value (store, key, value) VALUES ($1, $2, $3)
+                     ON CONFLICT(store, key) DO UPDATE SET value=$3",
+                ),
+                None => (
+                    0,
+                    "INSERT INTO spin_key_value (store, key, value, expires_at) VALUES ($1, $2, $3, NULL)
+                     ON CONFLICT(store, key) DO UPDATE SET value=$3, expires_at=NULL",
+                ),
             };
 
             let new_value = numeric + delta;
-            tx.prepare_cached(
-                "INSERT INTO spin_key_value (store, key, value) VALUES ($1, $2, $3)
-                     ON CONFLICT(store, key) DO UPDATE SET value=$3",
-            )
-            .map_err(log_error)?
-            .execute(rusqlite::params![&self.name, key, new_value.to_le_bytes()])
-            .map_err(log_error)
-            .map(drop)?;
+            tx.prepare_cached(upsert)
+                .map_err(log_error)?
+                .execute(rusqlite::params![&self.name, key, new_value.to_le_bytes()])
+                .map_err(log_error)
+                .map(drop)?;
 
             tx.commit().map_err(log_error)?;
             Ok(new_value)
@@ -291,9 +508,15 @@ impl Cas for CompareAndSwap {
                 .connection
                 .lock()
                 .unwrap()
-                .prepare_cached("SELECT value FROM spin_key_value WHERE store=$1 AND key=$2")
+                .prepare_cached(
+                    "SELECT value FROM spin_key_value WHERE store=$1 AND key=$2
+                     AND (expires_at IS NULL OR expires_at > $3)",
+                )
                 .map_err(log_error)?
-                .query_map([&self.name, &self.key], |row| row.get(0))
+                .query_map(
+                    rusqlite::params![&self.name, &self.key, now_millis()],
+                    |row| row.get(0),
+                )
                 .map_err(log_error)?
                 .next()
                 .transpose()
@@ -312,7 +535,7 @@ impl Cas for CompareAndSwap {
                 Some(old_val) => {
                     conn
                         .prepare_cached(
-                             "UPDATE spin_key_value SET value=:new_value WHERE store=:name and key=:key and value=:old_value")
+                             "UPDATE spin_key_value SET value=:new_value, expires_at=NULL WHERE store=:name and key=:key and value=:old_value")
                         .map_err(log_cas_error)?
                         .execute(named_params! {
                             ":name": &self.name,
@@ -326,8 +549,8 @@ impl Cas for CompareAndSwap {
                     let tx = conn.transaction().map_err(log_cas_error)?;
                     let rows = tx
                         .prepare_cached(
-                            "INSERT INTO spin_key_value (store, key, value) VALUES ($1, $2, $3)
-                     ON CONFLICT(store, key) DO UPDATE SET value=$3",
+                            "INSERT INTO spin_key_value (store, key, value, expires_at) VALUES ($1, $2, $3, NULL)
+                     ON CONFLICT(store, key) DO UPDATE SET value=$3, expires_at=NULL",
                         )
                         .map_err(log_cas_error)?
                         .execute(rusqlite::params![&self.name, self.key, value])
@@ -498,6 +721,113 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn expiry() -> Result<()> {
+        let manager = KeyValueSqlite::new(DatabaseLocation::InMemory);
+        let store = manager.get("default").await?;
+
+        store.set_with_ttl("expired", b"a", Duration::ZERO).await?;
+        store
+            .set_with_ttl("live", b"b", Duration::from_secs(3600))
+            .await?;
+
+        assert_eq!(None, store.get("expired").await?);
+        assert!(!store.exists("expired").await?);
+        assert_eq!(Some(b"b" as &[_]), store.get("live").await?.as_deref());
+        assert_eq!(vec!["live".to_owned()], store.get_keys().await?);
+        assert_eq!(None, store.get_with_ttl("expired").await?);
+        let (value, ttl) = store.get_with_ttl("live").await?.unwrap();
+        assert_eq!(b"b" as &[_], value);
+        assert!(ttl.is_some_and(|ttl| ttl > Duration::from_secs(3500)));
+        assert_eq!(
+            vec![("live".to_owned(), Some(b"b".to_vec()))],
+            store
+                .get_many(vec!["expired".to_owned(), "live".to_owned()])
+                .await?
+        );
+
+        // An expired counter starts again from zero.
+        store
+            .set_with_ttl("counter", &5i64.to_le_bytes(), Duration::ZERO)
+            .await?;
+        assert_eq!(1, store.increment("counter".to_owned(), 1).await?);
+
+        // Setting without a TTL clears any previous expiry.
+        store.set_with_ttl("expired", b"a", Duration::ZERO).await?;
+        store.set("expired", b"c").await?;
+        assert_eq!(Some(b"c" as &[_]), store.get("expired").await?.as_deref());
+        assert_eq!(
+            Some((b"c".to_vec(), None)),
+            store.get_with_ttl("expired").await?
+        );
+
+        store.set_with_ttl("swept", b"d", Duration::ZERO).await?;
+        let connection = manager.connection.get().unwrap();
+        assert_eq!(1, delete_expired(&connection.lock().unwrap())?);
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn list_keys() -> Result<()> {
+        let manager = KeyValueSqlite::new(DatabaseLocation::InMemory);
+        let store = manager.get("default").await?;
+        let other = manager.get("other").await?;
+
+        for key in ["a", "b/1", "b/2", "b/3", "b0", "c"] {
+            store.set(key, b"x").await?;
+        }
+        store.set_with_ttl("b/4", b"x", Duration::ZERO).await?;
+        other.set("b/5", b"x").await?;
+
+        let page = store.list_keys(Some("b/"), None, 2).await?;
+        assert_eq!(vec!["b/1", "b/2"], page.keys);
+        let page = store
+            .list_keys(Some("b/"), page.cursor.as_deref(), 2)
+            .await?;
+        assert_eq!(
+            KeyPage {
+                keys: vec!["b/3".to_owned()],
+                cursor: None
+            },
+            page
+        );
+
+        let mut all = vec![];
+        let mut cursor = None;
+        loop {
+            let page = store.list_keys(None, cursor.as_deref(), 4).await?;
+            all.extend(page.keys);
+            cursor = page.cursor;
+            if cursor.is_none() {
+                break;
+            }
+        }
+        assert_eq!(vec!["a", "b/1", "b/2", "b/3", "b0", "c"], all);
+
+        // A zero limit still makes progress, as with the other backends.
+        let page = store.list_keys(None, None, 0).await?;
+        assert_eq!(vec!["a"], page.keys);
+        assert_eq!(Some("a"), page.cursor.as_deref());
+
+        assert_eq!(
+            KeyPage::default(),
+            store.list_keys(Some("z"), None, 10).await?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn prefix_upper_bound_is_exclusive() {
+        assert_eq!(None, prefix_upper_bound(""));
+        assert_eq!(Some("b".to_owned()), prefix_upper_bound("a"));
+        assert_eq!(Some("a0".to_owned()), prefix_upper_bound("a/"));
+        assert_eq!(Some("\u{e000}".to_owned()), prefix_upper_bound("\u{d7ff}"));
+        assert_eq!(Some("b".to_owned()), prefix_upper_bound("a\u{10ffff}"));
+        assert_eq!(None, prefix_upper_bound("\u{10ffff}"));
+    }
+
     async fn cas_failed(kv: &mut KeyValueDispatch, rep: u32) -> Result<()> {
         let cas_key = "fail".to_owned();
         let cas_orig_value = b"baz".to_vec();
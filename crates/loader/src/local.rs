@@ -7,8 +7,8 @@ use spin_common::{paths::parent_dir, sloth, ui::quoted_path};
 use spin_expressions::Resolver;
 use spin_locked_app::{
     locked::{
-        self, ContentPath, ContentRef, LockedApp, LockedComponent, LockedComponentDependency,
-        LockedComponentSource, LockedTrigger,
+        self, AccessMode, ContentPath, ContentRef, LockedApp, LockedComponent,
+        LockedComponentDependency, LockedComponentSource, LockedTrigger,
     },
     values::{ValuesMap, ValuesMapBuilder},
 };
@@ -155,11 +155,22 @@ impl LocalLoader {
 
         let component_requires_service_chaining = requires_service_chaining(&component);
 
+        let key_value_store_access = restricted_access(&component.key_value_stores);
+        let database_access = restricted_access(&component.sqlite_databases);
+
         let metadata = ValuesMapBuilder::new()
             .string("description", component.description)
             .string_array("allowed_outbound_hosts", allowed_outbound_hosts)
-            .string_array("key_value_stores", component.key_value_stores)
-            .string_array("databases", component.sqlite_databases)
+            .string_array(
+                "key_value_stores",
+                component.key_value_stores.into_iter().map(|g| g.label),
+            )
+            .string_array(
+                "databases",
+                component.sqlite_databases.into_iter().map(|g| g.label),
+            )
+            .serializable("key_value_store_access", key_value_store_access)?
+            .serializable("database_access", database_access)?
             .string_array("ai_models", component.ai_models)
             .serializable("build", component.build)?
             .take();
@@ -605,6 +616,23 @@ fn locked_metadata(
     Ok(builder.build())
 }
 
+/// Maps the labels of grants with restricted access to their access mode, or
+/// returns `None` if every grant is unrestricted.
+fn restricted_access(grants: &[v2::StoreGrant]) -> Option<BTreeMap<String, AccessMode>> {
+    let restricted = grants
+        .iter()
+        .filter_map(|grant| {
+            let access = match grant.access {
+                v2::StoreAccess::ReadWrite => return None,
+                v2::StoreAccess::Read => AccessMode::Read,
+                v2::StoreAccess::Write => AccessMode::Write,
+            };
+            Some((grant.label.clone(), access))
+        })
+        .collect::<BTreeMap<_, _>>();
+    (!restricted.is_empty()).then_some(restricted)
+}
+
 fn locked_variable(variable: v2::Variable) -> Result<locked::Variable> {
     ensure!(
         variable.required ^ variable.default.is_some(),
@@ -283,6 +283,32 @@ impl InheritConfiguration {
     }
 }
 
+/// AccessMode specifies the operations a component may perform on a labeled
+/// resource such as a key-value store or SQLite database.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AccessMode {
+    /// Reads and writes are allowed.
+    #[default]
+    ReadWrite,
+    /// Only reads are allowed.
+    Read,
+    /// Only writes are allowed.
+    Write,
+}
+
+impl AccessMode {
+    /// Returns true if reads are allowed.
+    pub fn can_read(self) -> bool {
+        matches!(self, Self::ReadWrite | Self::Read)
+    }
+
+    /// Returns true if writes are allowed.
+    pub fn can_write(self) -> bool {
+        matches!(self, Self::ReadWrite | Self::Write)
+    }
+}
+
 /// A LockedComponentSource specifies a Wasm source.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct LockedComponentSource {
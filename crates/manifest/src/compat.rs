@@ -69,8 +69,16 @@ pub fn v1_to_v2_app(manifest: v1::AppManifestV1) -> Result<v2::AppManifest, Erro
                 environment: component.environment,
                 files: component.files,
                 exclude_files: component.exclude_files,
-                key_value_stores: component.key_value_stores,
-                sqlite_databases: component.sqlite_databases,
+                key_value_stores: component
+                    .key_value_stores
+                    .into_iter()
+                    .map(Into::into)
+                    .collect(),
+                sqlite_databases: component
+                    .sqlite_databases
+                    .into_iter()
+                    .map(Into::into)
+                    .collect(),
                 ai_models,
                 build: component.build,
                 tool: Default::default(),
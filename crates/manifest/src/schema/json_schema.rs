@@ -13,6 +13,15 @@ pub struct TriggerSchema {
     /// Redis triggers
     #[schemars(default)]
     redis: Vec<RedisTriggerSchema>,
+    /// MQTT triggers
+    #[schemars(default)]
+    mqtt: Vec<MqttTriggerSchema>,
+    /// Cron triggers
+    #[schemars(default)]
+    cron: Vec<CronTriggerSchema>,
+    /// Command triggers
+    #[schemars(default)]
+    command: Vec<CommandTriggerSchema>,
 }
 
 #[allow(dead_code)]
@@ -30,9 +39,27 @@ pub struct HttpTriggerSchema {
     pub components: Map<String, OneOrManyComponentSpecs>,
     /// `route = "/user/:name/..."`
     route: HttpRouteSchema,
-    /// `executor = { type = "wagi" }
+    /// `methods = ["GET", "POST"]`
+    #[schemars(default)]
+    methods: Vec<String>,
+    /// `host = "api.example.com"` or `host = "*.example.com"`
+    #[schemars(default)]
+    host: Option<String>,
+    /// `executor = { type = "wagi" }` or `executor = { type = "static", root = "/", fallback = "index.html" }`
     #[schemars(default, schema_with = "toml_table")]
     executor: Option<toml::Table>,
+    /// `compression = { min_size = 1024, content_types = ["text/*", "application/json"] }`
+    #[schemars(default, schema_with = "toml_table")]
+    compression: Option<toml::Table>,
+    /// `cors = { origins = ["https://example.com"], methods = ["GET", "POST"], headers = ["content-type"], max_age = 600 }`
+    #[schemars(default, schema_with = "toml_table")]
+    cors: Option<toml::Table>,
+    /// The maximum number of requests the component may handle at once. Further requests receive a 503 response.
+    #[schemars(default)]
+    max_concurrency: Option<usize>,
+    /// `rate_limit = { requests_per_second = 10, burst = 20, key = "client_ip" }`. Requests over the limit receive a 429 response.
+    #[schemars(default, schema_with = "toml_table")]
+    rate_limit: Option<toml::Table>,
 }
 
 #[allow(dead_code)]
@@ -44,6 +71,8 @@ pub enum HttpRouteSchema {
     ///
     /// - Any number of single-segment wildcards, using the syntax `:name`. It matches only a single segment of a path, and allows further matching on segments beyond it.
     ///
+    /// - Constraints on single-segment wildcards, using the syntax `:name<int>`. The constraint is one of `int`, `uint`, `uuid`, `alpha` or `alnum`, or a regular expression which must match the whole segment. If the constraint is not met, the next matching route is tried.
+    ///
     /// - A trailing wildcard, using the syntax `/...`. This matches the given route and any route under it.
     ///
     /// In particular, the route `/...` matches _all_ paths.
@@ -83,24 +112,139 @@ pub struct RedisTriggerSchema {
     #[serde(default, skip_serializing_if = "Map::is_empty")]
     pub components: Map<String, OneOrManyComponentSpecs>,
     /// `channel = "my-messages"`
-    channel: String,
+    #[schemars(default)]
+    channel: Option<String>,
+    /// `stream = "orders"`
+    #[schemars(default)]
+    stream: Option<String>,
+    /// `group = "order-processors"`
+    #[schemars(default)]
+    group: Option<String>,
+    /// `consumer = "worker-1"`
+    #[schemars(default)]
+    consumer: Option<String>,
+    /// `field = "payload"`
+    #[schemars(default)]
+    field: Option<String>,
+    /// `max_deliveries = 3`
+    #[schemars(default)]
+    max_deliveries: Option<usize>,
+    /// `dead_letter_stream = "orders-dead"`
+    #[schemars(default)]
+    dead_letter_stream: Option<String>,
+    /// `claim_idle_secs = 60`
+    #[schemars(default)]
+    claim_idle_secs: Option<u64>,
     /// `address = "redis://redis.example.com:6379"`
     #[serde(default, skip_serializing_if = "Option::is_none")]
     address: Option<String>,
 }
 
+#[allow(dead_code)]
+#[derive(JsonSchema)]
+#[schemars(deny_unknown_fields)]
+pub struct MqttTriggerSchema {
+    /// `id = "trigger-id"`
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub id: String,
+    /// `component = ...`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub component: Option<ComponentSpec>,
+    /// `components = { ... }`
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub components: Map<String, OneOrManyComponentSpecs>,
+    /// `topic = "sensors/+/temperature"`. The topic filter may contain `+` (one level) and `#` (all remaining levels) wildcards.
+    topic: String,
+    /// `qos = 1`. The maximum quality of service with which to receive messages: 0 (at most once), 1 (at least once, the default) or 2 (exactly once).
+    #[schemars(default)]
+    qos: Option<u8>,
+    /// `shared_group = "workers"`. Share the subscription with other subscribers in the group, so that each message is delivered to only one of them.
+    #[schemars(default)]
+    shared_group: Option<String>,
+    /// `address = "mqtt://mqtt.example.com:1883"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    address: Option<String>,
+    /// `username = "{{ mqtt_username }}"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    username: Option<String>,
+    /// `password = "{{ mqtt_password }}"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    password: Option<String>,
+    /// `keep_alive_interval_secs = 30`
+    #[schemars(default)]
+    keep_alive_interval_secs: Option<u64>,
+}
+
+#[allow(dead_code)]
+#[derive(JsonSchema)]
+#[schemars(deny_unknown_fields)]
+pub struct CronTriggerSchema {
+    /// `id = "trigger-id"`
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub id: String,
+    /// `component = ...`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub component: Option<ComponentSpec>,
+    /// `components = { ... }`
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub components: Map<String, OneOrManyComponentSpecs>,
+    /// `schedule = "*/15 * * * *"`. A cron expression, evaluated in UTC. It may have five fields, or six or seven with seconds first and years last. Exactly one of `schedule` and `interval_secs` must be set.
+    #[schemars(default)]
+    schedule: Option<String>,
+    /// `interval_secs = 300`. Run at a fixed interval from when the application starts.
+    #[schemars(default)]
+    interval_secs: Option<u64>,
+    /// `jitter_secs = 10`. Delay each run by a random time up to this many seconds.
+    #[schemars(default)]
+    jitter_secs: Option<u64>,
+    /// `overlap = "skip"`. What to do when a run is due while the previous run is in progress: "skip" it (the default), "queue" it until the previous run finishes, or "allow" it to run alongside.
+    #[schemars(default)]
+    overlap: Option<CronOverlapPolicySchema>,
+}
+
+#[allow(dead_code)]
+#[derive(JsonSchema)]
+#[schemars(rename_all = "lowercase")]
+pub enum CronOverlapPolicySchema {
+    Skip,
+    Queue,
+    Allow,
+}
+
+#[allow(dead_code)]
+#[derive(JsonSchema)]
+#[schemars(deny_unknown_fields)]
+pub struct CommandTriggerSchema {
+    /// `id = "trigger-id"`
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub id: String,
+    /// `component = ...`. The component must export `wasi:cli/run`. It is run once, with the arguments passed to `spin up`, and Spin exits with its exit status.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub component: Option<ComponentSpec>,
+    /// `components = { ... }`
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub components: Map<String, OneOrManyComponentSpecs>,
+}
+
 /// The SQLite databases which the component is allowed to access. Databases are identified
 /// by label e.g. "default" or "analytics". Databases other than "default" must be mapped
 /// to a backing store in the runtime config. Use "spin up --sqlite" to run database setup scripts.
 ///
 /// Example: `sqlite_databases = ["default", "my-database"]`
 ///
+/// Access may be restricted to reads or writes: `sqlite_databases = [{ label = "reports", access = "read" }]`
+///
 /// Learn more: https://spinframework.dev/sqlite-api-guide#preparing-an-sqlite-database
 #[allow(dead_code)]
 #[derive(JsonSchema)]
 #[serde(untagged)]
 pub enum SqliteDatabase {
     Label(String),
+    Grant {
+        label: String,
+        #[serde(default)]
+        access: super::v2::StoreAccess,
+    },
 }
 
 /// The key-value stores which the component is allowed to access. Stores are identified
@@ -109,12 +253,19 @@ pub enum SqliteDatabase {
 ///
 /// Example: `key_value_stores = ["default", "my-store"]`
 ///
+/// Access may be restricted to reads or writes: `key_value_stores = [{ label = "config", access = "read" }]`
+///
 /// Learn more: https://spinframework.dev/kv-store-api-guide#custom-key-value-stores
 #[allow(dead_code)]
 #[derive(JsonSchema)]
 #[serde(untagged)]
 pub enum KeyValueStore {
     Label(String),
+    Grant {
+        label: String,
+        #[serde(default)]
+        access: super::v2::StoreAccess,
+    },
 }
 
 /// The network destinations which the component is allowed to access.
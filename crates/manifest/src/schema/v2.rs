@@ -107,8 +107,8 @@ pub struct AppDetails {
 /// a Spin component.
 ///
 /// The trigger manifest contains additional fields which depend on the trigger
-/// type. For the `http` type, these additional fields are `route` (required) and
-/// `executor` (optional). For the `redis` type, the additional fields are
+/// type. For the `http` type, these additional fields are `route` (required),
+/// and `executor`, `methods` and `host` (optional). For the `redis` type, the additional fields are
 /// `channel` (required) and `address` (optional). For other types, see the trigger
 /// documentation.
 ///
@@ -342,6 +342,8 @@ pub struct Component {
     ///
     /// Example: `key_value_stores = ["default", "my-store"]`
     ///
+    /// Access may be restricted to reads or writes: `key_value_stores = [{ label = "config", access = "read" }]`
+    ///
     /// Learn more: https://spinframework.dev/kv-store-api-guide#custom-key-value-stores
     #[serde(
         default,
@@ -349,13 +351,15 @@ pub struct Component {
         skip_serializing_if = "Vec::is_empty"
     )]
     #[schemars(with = "Vec<json_schema::KeyValueStore>")]
-    pub key_value_stores: Vec<String>,
+    pub key_value_stores: Vec<StoreGrant>,
     /// The SQLite databases which the component is allowed to access. Databases are identified
     /// by label e.g. "default" or "analytics". Databases other than "default" must be mapped
     /// to a backing store in the runtime config. Use "spin up --sqlite" to run database setup scripts.
     ///
     /// Example: `sqlite_databases = ["default", "my-database"]`
     ///
+    /// Access may be restricted to reads or writes: `sqlite_databases = [{ label = "reports", access = "read" }]`
+    ///
     /// Learn more: https://spinframework.dev/sqlite-api-guide#preparing-an-sqlite-database
     #[serde(
         default,
@@ -363,7 +367,7 @@ pub struct Component {
         skip_serializing_if = "Vec::is_empty"
     )]
     #[schemars(with = "Vec<json_schema::SqliteDatabase>")]
-    pub sqlite_databases: Vec<String>,
+    pub sqlite_databases: Vec<StoreGrant>,
     /// The AI models which the component is allowed to access. For local execution, you must
     /// download all models; for hosted execution, you should check which models are available
     /// in your target environment.
@@ -397,6 +401,77 @@ pub struct Component {
     pub dependencies: ComponentDependencies,
 }
 
+/// A component's access to a labeled key-value store or SQLite database.
+///
+/// Serialized as a bare label when access is unrestricted, and as a
+/// `{ label = "...", access = "..." }` table otherwise.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "StoreGrantRepr", into = "StoreGrantRepr")]
+pub struct StoreGrant {
+    /// The label of the store or database.
+    pub label: String,
+    /// The operations the component may perform.
+    pub access: StoreAccess,
+}
+
+impl From<String> for StoreGrant {
+    fn from(label: String) -> Self {
+        Self {
+            label,
+            access: StoreAccess::ReadWrite,
+        }
+    }
+}
+
+/// The operations a component may perform on a store or database.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum StoreAccess {
+    /// Reads and writes are allowed.
+    #[default]
+    ReadWrite,
+    /// Only reads are allowed.
+    Read,
+    /// Only writes are allowed.
+    Write,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+enum StoreGrantRepr {
+    Label(String),
+    Grant(StoreGrantTable),
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StoreGrantTable {
+    label: String,
+    #[serde(default)]
+    access: StoreAccess,
+}
+
+impl From<StoreGrantRepr> for StoreGrant {
+    fn from(repr: StoreGrantRepr) -> Self {
+        match repr {
+            StoreGrantRepr::Label(label) => label.into(),
+            StoreGrantRepr::Grant(StoreGrantTable { label, access }) => Self { label, access },
+        }
+    }
+}
+
+impl From<StoreGrant> for StoreGrantRepr {
+    fn from(grant: StoreGrant) -> Self {
+        match grant.access {
+            StoreAccess::ReadWrite => Self::Label(grant.label),
+            access => Self::Grant(StoreGrantTable {
+                label: grant.label,
+                access,
+            }),
+        }
+    }
+}
+
 /// Component dependencies
 #[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
 #[serde(transparent)]
@@ -554,15 +629,19 @@ pub enum TargetEnvironmentRef {
 }
 
 mod kebab_or_snake_case {
+    use super::StoreGrant;
     use serde::{Deserialize, Serialize};
     pub use spin_serde::{KebabId, SnakeId};
-    pub fn serialize<S>(value: &[String], serializer: S) -> Result<S::Ok, S::Error>
+
+    fn is_kebab_or_snake_case(label: &str) -> bool {
+        KebabId::try_from(label.to_owned()).is_ok() || SnakeId::try_from(label.to_owned()).is_ok()
+    }
+
+    pub fn serialize<S>(value: &[StoreGrant], serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::ser::Serializer,
     {
-        if value.iter().all(|s| {
-            KebabId::try_from(s.clone()).is_ok() || SnakeId::try_from(s.to_owned()).is_ok()
-        }) {
+        if value.iter().all(|g| is_kebab_or_snake_case(&g.label)) {
             value.serialize(serializer)
         } else {
             Err(serde::ser::Error::custom(
@@ -571,15 +650,13 @@ mod kebab_or_snake_case {
         }
     }
 
-    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<StoreGrant>, D::Error>
     where
         D: serde::Deserializer<'de>,
     {
         let value = toml::Value::deserialize(deserializer)?;
-        let list: Vec<String> = Vec::deserialize(value).map_err(serde::de::Error::custom)?;
-        if list.iter().all(|s| {
-            KebabId::try_from(s.clone()).is_ok() || SnakeId::try_from(s.to_owned()).is_ok()
-        }) {
+        let list: Vec<StoreGrant> = Vec::deserialize(value).map_err(serde::de::Error::custom)?;
+        if list.iter().all(|g| is_kebab_or_snake_case(&g.label)) {
             Ok(list)
         } else {
             Err(serde::de::Error::custom(
@@ -732,6 +809,68 @@ mod tests {
         .unwrap();
     }
 
+    #[test]
+    fn deserializing_store_access() {
+        let manifest = AppManifest::deserialize(toml! {
+            spin_manifest_version = 2
+            [application]
+            name = "trigger-configs"
+            [[trigger.fake]]
+            something = "something else"
+            [component.fake]
+            source = "dummy"
+            key_value_stores = ["default", { label = "config", access = "read" }]
+            sqlite_databases = [{ label = "audit", access = "write" }, { label = "db" }]
+        })
+        .unwrap();
+        let fake_id: KebabId = "fake".to_owned().try_into().unwrap();
+        let component = &manifest.components[&fake_id];
+        assert_eq!(
+            component.key_value_stores,
+            [
+                StoreGrant::from("default".to_owned()),
+                StoreGrant {
+                    label: "config".to_owned(),
+                    access: StoreAccess::Read
+                }
+            ]
+        );
+        assert_eq!(
+            component.sqlite_databases,
+            [
+                StoreGrant {
+                    label: "audit".to_owned(),
+                    access: StoreAccess::Write
+                },
+                StoreGrant::from("db".to_owned())
+            ]
+        );
+
+        assert!(AppManifest::deserialize(toml! {
+            spin_manifest_version = 2
+            [application]
+            name = "trigger-configs"
+            [[trigger.fake]]
+            something = "something else"
+            [component.fake]
+            source = "dummy"
+            key_value_stores = [{ label = "config", access = "none" }]
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn serialize_store_access() {
+        let mut component = get_test_component_with_labels(vec!["default".to_owned()]);
+        component.key_value_stores.push(StoreGrant {
+            label: "config".to_owned(),
+            access: StoreAccess::Read,
+        });
+        let serialized = toml::to_string(&component).unwrap();
+        let deserialized = toml::from_str::<Component>(&serialized).unwrap();
+        assert_eq!(deserialized.key_value_stores, component.key_value_stores);
+    }
+
     #[test]
     fn deserializing_labels_fails_for_non_kebab_or_snake() {
         assert!(AppManifest::deserialize(toml! {
@@ -748,6 +887,7 @@ mod tests {
     }
 
     fn get_test_component_with_labels(labels: Vec<String>) -> Component {
+        let labels: Vec<StoreGrant> = labels.into_iter().map(StoreGrant::from).collect();
         #[allow(deprecated)]
         Component {
             source: ComponentSource::Local("dummy".to_string()),
@@ -778,7 +918,12 @@ mod tests {
         let component = get_test_component_with_labels(stores.clone());
         let serialized = toml::to_string(&component).unwrap();
         let deserialized = toml::from_str::<Component>(&serialized).unwrap();
-        assert_eq!(deserialized.key_value_stores, stores);
+        let labels: Vec<_> = deserialized
+            .key_value_stores
+            .into_iter()
+            .map(|g| g.label)
+            .collect();
+        assert_eq!(labels, stores);
     }
 
     #[test]
@@ -0,0 +1,113 @@
+//! Constraints on named route parameters, e.g. `/users/:id<int>`.
+
+use regex::Regex;
+
+/// A constraint on the value of a named parameter.
+#[derive(Clone, Debug)]
+pub(crate) struct ParamConstraint {
+    /// The name of the parameter.
+    name: String,
+    /// What the value of the parameter must look like.
+    kind: ConstraintKind,
+}
+
+#[derive(Clone, Debug)]
+enum ConstraintKind {
+    /// An optionally signed decimal integer, e.g. `-42`.
+    Int,
+    /// An unsigned decimal integer, e.g. `42`.
+    Uint,
+    /// A hyphenated UUID, e.g. `67e55044-10b1-426f-9247-bb680e5fe0c8`.
+    Uuid,
+    /// ASCII letters only.
+    Alpha,
+    /// ASCII letters and digits only.
+    Alnum,
+    /// A regular expression which must match the whole value.
+    Regex(Regex),
+}
+
+impl ParamConstraint {
+    /// Removes any constraints from the named parameters in a route, returning the
+    /// route without them and the constraints. For example, `/users/:id<int>/...`
+    /// becomes `/users/:id/...` with an `int` constraint on `id`.
+    ///
+    /// A constraint is either the name of a built-in type (`int`, `uint`, `uuid`,
+    /// `alpha` or `alnum`) or a regular expression which must match the whole
+    /// parameter value. As parameters match a single path segment, the regular
+    /// expression cannot contain `/`.
+    pub(crate) fn strip_from_route(route: &str) -> Result<(String, Vec<Self>), String> {
+        let mut constraints = Vec::new();
+        let segments = route
+            .split('/')
+            .map(|segment| {
+                let Some(open) = segment.find('<') else {
+                    if segment.contains('>') {
+                        return Err(format!("unexpected '>' in route segment '{segment}'"));
+                    }
+                    return Ok(segment);
+                };
+                let (param, constraint) = segment.split_at(open);
+                let Some(name) = param.strip_prefix(':').filter(|name| !name.is_empty()) else {
+                    return Err(format!(
+                        "constraint in route segment '{segment}' must follow a named parameter, e.g. ':id<int>'"
+                    ));
+                };
+                let Some(constraint) = constraint[1..].strip_suffix('>') else {
+                    return Err(format!(
+                        "constraint in route segment '{segment}' must end the segment with '>'"
+                    ));
+                };
+                constraints.push(Self {
+                    name: name.to_owned(),
+                    kind: ConstraintKind::parse(constraint)?,
+                });
+                Ok(param)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok((segments.join("/"), constraints))
+    }
+
+    /// Whether the value captured for the parameter satisfies the constraint.
+    pub(crate) fn accepts(&self, captures: &routefinder::Captures) -> bool {
+        captures
+            .get(&self.name)
+            .is_some_and(|value| self.kind.accepts(value))
+    }
+}
+
+impl ConstraintKind {
+    fn parse(constraint: &str) -> Result<Self, String> {
+        Ok(match constraint {
+            "" => return Err("route parameter constraint must not be empty".to_owned()),
+            "int" => Self::Int,
+            "uint" => Self::Uint,
+            "uuid" => Self::Uuid,
+            "alpha" => Self::Alpha,
+            "alnum" => Self::Alnum,
+            regex => {
+                let regex = Regex::new(&format!("^(?:{regex})$"))
+                    .map_err(|e| format!("invalid route parameter constraint '{regex}': {e}"))?;
+                Self::Regex(regex)
+            }
+        })
+    }
+
+    fn accepts(&self, value: &str) -> bool {
+        let digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+        match self {
+            Self::Int => digits(value.strip_prefix('-').unwrap_or(value)),
+            Self::Uint => digits(value),
+            Self::Uuid => {
+                value.len() == 36
+                    && value.char_indices().all(|(i, c)| match i {
+                        8 | 13 | 18 | 23 => c == '-',
+                        _ => c.is_ascii_hexdigit(),
+                    })
+            }
+            Self::Alpha => !value.is_empty() && value.bytes().all(|b| b.is_ascii_alphabetic()),
+            Self::Alnum => !value.is_empty() && value.bytes().all(|b| b.is_ascii_alphanumeric()),
+            Self::Regex(regex) => regex.is_match(value),
+        }
+    }
+}
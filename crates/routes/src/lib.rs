@@ -2,7 +2,10 @@
 
 #![deny(missing_docs)]
 
+mod constraint;
+
 use anyhow::{anyhow, Result};
+use constraint::ParamConstraint;
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 use std::{borrow::Cow, collections::HashMap, fmt};
@@ -14,8 +17,31 @@ pub const WELL_KNOWN_PREFIX: &str = "/.well-known/spin/";
 #[derive(Clone, Debug)]
 pub struct Router {
     /// Resolves paths to routing information - specifically component IDs
-    /// but also recording about the original route.
-    router: std::sync::Arc<routefinder::Router<RouteHandler>>,
+    /// but also recording about the original route. There is one table per
+    /// host pattern, in the order they should be consulted.
+    tables: std::sync::Arc<Vec<RouteTable>>,
+}
+
+/// The routes which apply to requests for a particular host.
+#[derive(Debug)]
+struct RouteTable {
+    /// The host the routes apply to, or `None` if they apply to any host.
+    host: Option<HostPattern>,
+    /// The routes, each mapping to the handlers for that route. Handlers which
+    /// accept only specific methods come before those which accept any method.
+    router: routefinder::Router<Vec<RouteHandler>>,
+}
+
+impl RouteTable {
+    /// Orders tables so that the most specific host is consulted first: exact hosts,
+    /// then wildcards with the longest suffix, then routes for any host.
+    fn precedence(&self) -> (u8, std::cmp::Reverse<usize>) {
+        match &self.host {
+            Some(HostPattern::Exact(_)) => (0, std::cmp::Reverse(0)),
+            Some(HostPattern::Subdomains(suffix)) => (1, std::cmp::Reverse(suffix.len())),
+            None => (2, std::cmp::Reverse(0)),
+        }
+    }
 }
 
 /// What a route maps to
@@ -30,6 +56,10 @@ struct RouteHandler {
     /// The route, including any application base and capturing information about whether it has a trailing wildcard.
     /// (This avoids re-parsing the route string.)
     parsed_based_route: ParsedRoute,
+    /// Constraints on the values of the route's named parameters.
+    constraints: Vec<ParamConstraint>,
+    /// The methods and host the route is restricted to.
+    matchers: RouteMatchers,
 }
 
 /// A detected duplicate route.
@@ -43,6 +73,146 @@ pub struct DuplicateRoute {
     pub effective_id: String,
 }
 
+/// Restricts a route to requests with particular methods or for a particular host.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RouteMatchers {
+    /// The HTTP methods the route accepts. If empty, the route accepts any method.
+    pub methods: Vec<String>,
+    /// The host the route accepts, either a host name such as `api.example.com`
+    /// or a wildcard such as `*.example.com` which matches any subdomain. If `None`,
+    /// the route accepts any host.
+    pub host: Option<String>,
+}
+
+impl RouteMatchers {
+    /// Checks and normalizes the matchers: methods are upper-cased and
+    /// deduplicated, and the host is lower-cased.
+    fn normalize(self) -> Result<Self, String> {
+        let mut methods = Vec::with_capacity(self.methods.len());
+        for method in self.methods {
+            if method.is_empty()
+                || !method
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-')
+            {
+                return Err(format!("invalid HTTP method '{method}'"));
+            }
+            let method = method.to_ascii_uppercase();
+            if !methods.contains(&method) {
+                methods.push(method);
+            }
+        }
+        let host = self
+            .host
+            .map(|host| HostPattern::parse(&host).map(|_| host.to_ascii_lowercase()))
+            .transpose()?;
+        Ok(Self { methods, host })
+    }
+
+    /// Whether the route accepts the given method. `None` matches any method.
+    fn accepts_method(&self, method: Option<&str>) -> bool {
+        match method {
+            Some(method) if !self.methods.is_empty() => {
+                self.methods.iter().any(|m| m.eq_ignore_ascii_case(method))
+            }
+            _ => true,
+        }
+    }
+}
+
+impl fmt::Display for RouteMatchers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let methods = (!self.methods.is_empty()).then(|| self.methods.join(", "));
+        match (methods, &self.host) {
+            (Some(methods), Some(host)) => write!(f, "{methods} on {host}"),
+            (Some(methods), None) => write!(f, "{methods}"),
+            (None, Some(host)) => write!(f, "on {host}"),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+/// A host a route applies to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum HostPattern {
+    /// A single host, e.g. `api.example.com`.
+    Exact(String),
+    /// Any subdomain of a host, e.g. `*.example.com`. Holds the suffix,
+    /// including the leading dot, e.g. `.example.com`.
+    Subdomains(String),
+}
+
+impl HostPattern {
+    fn parse(pattern: &str) -> Result<Self, String> {
+        let pattern = pattern.to_ascii_lowercase();
+        let (host, wildcard) = match pattern.strip_prefix("*.") {
+            Some(suffix) => (suffix, true),
+            None => (pattern.as_str(), false),
+        };
+        let valid = !host.is_empty()
+            && host.split('.').all(|label| {
+                !label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            });
+        if !valid {
+            return Err(format!(
+                "invalid host '{pattern}': expected a host name such as 'api.example.com' or a wildcard such as '*.example.com'"
+            ));
+        }
+        Ok(if wildcard {
+            Self::Subdomains(format!(".{host}"))
+        } else {
+            Self::Exact(host.to_owned())
+        })
+    }
+
+    /// Whether the pattern matches a normalized host name.
+    fn matches(&self, host: &str) -> bool {
+        match self {
+            Self::Exact(exact) => host == exact,
+            Self::Subdomains(suffix) => {
+                host.len() > suffix.len() && host.ends_with(suffix.as_str())
+            }
+        }
+    }
+}
+
+/// Normalizes the host of a request (from the URI authority or the `Host` header)
+/// by removing any port and trailing dot, and lower-casing it.
+fn normalize_host(host: &str) -> String {
+    let host = if host.starts_with('[') {
+        // An IPv6 literal, possibly followed by a port.
+        host.split_inclusive(']').next().unwrap_or(host)
+    } else {
+        host.rsplit_once(':').map_or(host, |(host, _port)| host)
+    };
+    host.trim_end_matches('.').to_ascii_lowercase()
+}
+
+/// Why a request could not be routed.
+#[derive(Debug, PartialEq, Eq)]
+pub enum RouteError {
+    /// No route matches the request's path and host.
+    NotFound,
+    /// At least one route matches the request's path and host, but none of them
+    /// accepts the request's method. Holds the methods those routes accept.
+    MethodNotAllowed(Vec<String>),
+}
+
+impl fmt::Display for RouteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "no route matches the request"),
+            Self::MethodNotAllowed(allowed) => write!(
+                f,
+                "no route accepts the request method (allowed: {})",
+                allowed.join(", ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RouteError {}
+
 impl Router {
     /// Builds a router based on application configuration.
     ///
@@ -51,6 +221,26 @@ impl Router {
     pub fn build<'a>(
         base: &str,
         component_routes: impl IntoIterator<Item = (&'a str, &'a HttpTriggerRouteConfig)>,
+        duplicate_routes: Option<&mut Vec<DuplicateRoute>>,
+    ) -> Result<Self> {
+        Self::build_with_matchers(
+            base,
+            component_routes
+                .into_iter()
+                .map(|(component_id, route)| (component_id, route, RouteMatchers::default())),
+            duplicate_routes,
+        )
+    }
+
+    /// Builds a router based on application configuration, where routes may be
+    /// restricted to particular methods or hosts.
+    ///
+    /// Two routes for the same path and host are duplicates if they accept the
+    /// same methods. If their methods only partly overlap, the later route takes
+    /// the overlapping methods. `duplicate_routes` is populated as for [`Router::build`].
+    pub fn build_with_matchers<'a>(
+        base: &str,
+        component_routes: impl IntoIterator<Item = (&'a str, &'a HttpTriggerRouteConfig, RouteMatchers)>,
         mut duplicate_routes: Option<&mut Vec<DuplicateRoute>>,
     ) -> Result<Self> {
         // Some information we need to carry between stages of the builder.
@@ -58,18 +248,21 @@ impl Router {
             based_route: String,
             raw_route: &'a str,
             component_id: &'a str,
+            matchers: RouteMatchers,
         }
 
-        let mut routes = IndexMap::new();
+        let mut routes: IndexMap<(Option<String>, &str), Vec<RoutingEntry>> = IndexMap::new();
 
         // Filter out private endpoints and capture the routes.
         let routes_iter = component_routes
             .into_iter()
-            .filter_map(|(component_id, route)| {
+            .filter_map(|(component_id, route, matchers)| {
                 match route {
                     HttpTriggerRouteConfig::Route(raw_route) => {
                         let based_route = sanitize_with_base(base, raw_route);
-                        Some(Ok(RoutingEntry { based_route, raw_route, component_id }))
+                        Some(matchers.normalize()
+                            .map(|matchers| RoutingEntry { based_route, raw_route, component_id, matchers })
+                            .map_err(|e| anyhow!("Error in route {raw_route} associated with component {component_id}: {e}")))
                     }
                     HttpTriggerRouteConfig::Private(endpoint) => if endpoint.private {
                         None
@@ -82,85 +275,170 @@ impl Router {
         // Remove duplicates.
         for re in routes_iter {
             let re = re?;
-            if let Some(replaced) = routes.insert(re.raw_route, re) {
-                if let Some(duplicate_routes) = &mut duplicate_routes {
-                    let effective_id = routes
-                        .get(replaced.raw_route)
-                        .unwrap() // Safe because we just inserted it
-                        .component_id
-                        .to_owned();
+            let entries = routes
+                .entry((re.matchers.host.clone(), re.raw_route))
+                .or_default();
+            let mut kept = Vec::with_capacity(entries.len() + 1);
+            for mut existing in std::mem::take(entries) {
+                // A route for specific methods and a route for any method coexist,
+                // with the specific methods taking precedence.
+                let replaced = match (
+                    existing.matchers.methods.is_empty(),
+                    re.matchers.methods.is_empty(),
+                ) {
+                    (true, true) => true,
+                    (false, false) => {
+                        existing
+                            .matchers
+                            .methods
+                            .retain(|m| !re.matchers.methods.contains(m));
+                        existing.matchers.methods.is_empty()
+                    }
+                    _ => false,
+                };
+                if !replaced {
+                    kept.push(existing);
+                } else if let Some(duplicate_routes) = &mut duplicate_routes {
                     duplicate_routes.push(DuplicateRoute {
-                        route: replaced.based_route,
-                        replaced_id: replaced.component_id.to_owned(),
-                        effective_id,
+                        route: existing.based_route,
+                        replaced_id: existing.component_id.to_owned(),
+                        effective_id: re.component_id.to_owned(),
                     });
                 }
             }
+            kept.push(re);
+            kept.sort_by_key(|re| re.matchers.methods.is_empty());
+            *entries = kept;
         }
 
-        // Build a `routefinder` from the remaining routes.
+        // Parse the remaining routes.
+
+        let mut parsed_routes = Vec::with_capacity(routes.len());
+
+        for ((host, _), entries) in routes {
+            let host = host
+                .map(|host| HostPattern::parse(&host))
+                .transpose()
+                .map_err(|e| anyhow!(e))?;
+
+            let first = &entries[0]; // Safe because every entry holds at least one route
+            let (rfroute, parsed, constraints) =
+                Self::parse_route(&first.based_route).map_err(|e| {
+                    anyhow!(
+                        "Error parsing route {} associated with component {}: {e}",
+                        first.based_route,
+                        first.component_id
+                    )
+                })?;
+
+            let handlers: Vec<_> = entries
+                .into_iter()
+                .map(|re| RouteHandler {
+                    component_id: re.component_id.to_string(),
+                    based_route: re.based_route.into(),
+                    raw_route: re.raw_route.to_string().into(),
+                    parsed_based_route: parsed.clone(),
+                    constraints: constraints.clone(),
+                    matchers: re.matchers,
+                })
+                .collect();
+
+            parsed_routes.push((host, rfroute, handlers));
+        }
 
-        let mut rf = routefinder::Router::new();
+        // Build a `routefinder` for each host. Among routes of equal precedence, `routefinder`
+        // tries those added first, so add routes with parameter constraints first so they
+        // take precedence over the same route without constraints.
 
-        for re in routes.into_values() {
-            let (rfroute, parsed) = Self::parse_route(&re.based_route).map_err(|e| {
-                anyhow!(
-                    "Error parsing route {} associated with component {}: {e}",
-                    re.based_route,
-                    re.component_id
-                )
-            })?;
+        parsed_routes.sort_by_key(|(_, _, handlers)| handlers[0].constraints.is_empty());
 
-            let handler = RouteHandler {
-                component_id: re.component_id.to_string(),
-                based_route: re.based_route.into(),
-                raw_route: re.raw_route.to_string().into(),
-                parsed_based_route: parsed,
+        let mut tables: Vec<RouteTable> = Vec::new();
+
+        for (host, rfroute, handlers) in parsed_routes {
+            let table = match tables.iter().position(|table| table.host == host) {
+                Some(index) => &mut tables[index],
+                None => {
+                    tables.push(RouteTable {
+                        host,
+                        router: routefinder::Router::new(),
+                    });
+                    tables.last_mut().unwrap() // Safe because we just pushed it
+                }
             };
 
-            rf.add(rfroute, handler).map_err(|e| anyhow!("{e}"))?;
+            table
+                .router
+                .add(rfroute, handlers)
+                .map_err(|e| anyhow!("{e}"))?;
         }
 
+        tables.sort_by_key(RouteTable::precedence);
+
         let router = Self {
-            router: std::sync::Arc::new(rf),
+            tables: std::sync::Arc::new(tables),
         };
 
         Ok(router)
     }
 
-    fn parse_route(based_route: &str) -> Result<(routefinder::RouteSpec, ParsedRoute), String> {
-        if let Some(wild_suffixed) = based_route.strip_suffix("/...") {
+    fn parse_route(
+        based_route: &str,
+    ) -> Result<(routefinder::RouteSpec, ParsedRoute, Vec<ParamConstraint>), String> {
+        // `routefinder` doesn't understand constraints, so route on the parameters alone
+        // and check the constraints on the captured values.
+        let (unconstrained_route, constraints) = ParamConstraint::strip_from_route(based_route)?;
+        let (rs, parsed) = if let Some(wild_suffixed) = unconstrained_route.strip_suffix("/...") {
             let rs = format!("{wild_suffixed}/*").try_into()?;
-            let parsed = ParsedRoute::trailing_wildcard(wild_suffixed);
-            Ok((rs, parsed))
-        } else if let Some(wild_suffixed) = based_route.strip_suffix("/*") {
-            let rs = based_route.try_into()?;
-            let parsed = ParsedRoute::trailing_wildcard(wild_suffixed);
-            Ok((rs, parsed))
+            let parsed = ParsedRoute::trailing_wildcard(based_route.strip_suffix("/...").unwrap());
+            (rs, parsed)
+        } else if unconstrained_route.ends_with("/*") {
+            let rs = unconstrained_route.as_str().try_into()?;
+            let parsed = ParsedRoute::trailing_wildcard(based_route.strip_suffix("/*").unwrap());
+            (rs, parsed)
         } else {
-            let rs = based_route.try_into()?;
+            let rs = unconstrained_route.as_str().try_into()?;
             let parsed = ParsedRoute::exact(based_route);
-            Ok((rs, parsed))
-        }
+            (rs, parsed)
+        };
+        Ok((rs, parsed, constraints))
     }
 
     /// Returns the constructed routes.
     pub fn routes(&self) -> impl Iterator<Item = (&(impl fmt::Display + fmt::Debug), &String)> {
-        self.router
+        self.routes_with_matchers()
+            .map(|(route, _matchers, component_id)| (route, component_id))
+    }
+
+    /// Returns the constructed routes, with the methods and host each is restricted to.
+    pub fn routes_with_matchers(
+        &self,
+    ) -> impl Iterator<Item = (&(impl fmt::Display + fmt::Debug), &RouteMatchers, &String)> {
+        self.handlers().map(|handler| {
+            (
+                &handler.parsed_based_route,
+                &handler.matchers,
+                &handler.component_id,
+            )
+        })
+    }
+
+    fn handlers(&self) -> impl Iterator<Item = &RouteHandler> {
+        self.tables
             .iter()
-            .map(|(_spec, handler)| (&handler.parsed_based_route, &handler.component_id))
+            .flat_map(|table| table.router.iter())
+            .flat_map(|(_spec, handlers)| handlers)
     }
 
     /// true if one or more routes is under the reserved `/.well-known/spin/*`
     /// prefix; otherwise false.
     pub fn contains_reserved_route(&self) -> bool {
-        self.router
-            .iter()
-            .any(|(_spec, handler)| handler.based_route.starts_with(crate::WELL_KNOWN_PREFIX))
+        self.handlers()
+            .any(|handler| handler.based_route.starts_with(crate::WELL_KNOWN_PREFIX))
     }
 
     /// This returns the component ID that should handle the given path, or an error
-    /// if no component matches.
+    /// if no component matches. Routes restricted to a host are not considered, and
+    /// routes restricted to methods are considered to accept any method.
     ///
     /// If multiple components could potentially handle the same request based on their
     /// defined routes, components with matching exact routes take precedence followed
@@ -169,21 +447,82 @@ impl Router {
         &'router self,
         path: &'path str,
     ) -> Result<RouteMatch<'router, 'path>> {
-        let best_match = self
-            .router
-            .best_match(path)
-            .ok_or_else(|| anyhow!("Cannot match route for path {path}"))?;
-
-        let route_handler = best_match.handler();
-        let captures = best_match.captures();
-
-        Ok(RouteMatch {
-            inner: RouteMatchKind::Real {
-                route_handler,
-                captures,
-                path,
-            },
-        })
+        self.route_request(None, None, path)
+            .map_err(|_| anyhow!("Cannot match route for path {path}"))
+    }
+
+    /// This returns the component ID that should handle a request with the given
+    /// method, host and path. A `None` method matches routes for any method, and a
+    /// `None` host matches only routes which are not restricted to a host.
+    ///
+    /// Routes for the most specific host take precedence: routes for an exact host,
+    /// then for the wildcard host with the longest suffix, then routes for any host.
+    /// Among those, paths take precedence as for [`Router::route`], and routes for
+    /// specific methods take precedence over routes for any method. If some route
+    /// matches the host and path but none accepts the method, this returns
+    /// [`RouteError::MethodNotAllowed`].
+    pub fn route_request<'path, 'router: 'path>(
+        &'router self,
+        method: Option<&str>,
+        host: Option<&str>,
+        path: &'path str,
+    ) -> Result<RouteMatch<'router, 'path>, RouteError> {
+        let host = host.map(normalize_host);
+        let mut allowed: Vec<String> = Vec::new();
+
+        for table in self.tables.iter() {
+            let host_matches = match (&table.host, &host) {
+                (None, _) => true,
+                (Some(pattern), Some(host)) => pattern.matches(host),
+                (Some(_), None) => false,
+            };
+            if !host_matches {
+                continue;
+            }
+
+            for path_match in table.router.match_iter(path) {
+                let captures = path_match.captures();
+                let handlers = path_match
+                    .handler()
+                    .iter()
+                    .filter(|handler| {
+                        // If a parameter constraint fails, fall through to the next candidate.
+                        handler
+                            .constraints
+                            .iter()
+                            .all(|constraint| constraint.accepts(&captures))
+                    })
+                    .collect::<Vec<_>>();
+                match handlers
+                    .iter()
+                    .find(|handler| handler.matchers.accepts_method(method))
+                {
+                    Some(route_handler) => {
+                        return Ok(RouteMatch {
+                            inner: RouteMatchKind::Real {
+                                route_handler,
+                                captures,
+                                path,
+                            },
+                        });
+                    }
+                    None => {
+                        let methods = handlers.iter().flat_map(|h| &h.matchers.methods);
+                        for method in methods {
+                            if !allowed.contains(method) {
+                                allowed.push(method.clone());
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        if allowed.is_empty() {
+            Err(RouteError::NotFound)
+        } else {
+            Err(RouteError::MethodNotAllowed(allowed))
+        }
     }
 }
 
@@ -239,6 +578,8 @@ impl RouteMatch<'_, '_> {
                     based_route: "/...".into(),
                     raw_route: "/...".into(),
                     parsed_based_route: ParsedRoute::TrailingWildcard(String::new()),
+                    constraints: Vec::new(),
+                    matchers: RouteMatchers::default(),
                 },
                 trailing_wildcard: path,
             },
@@ -738,4 +1079,326 @@ mod route_tests {
         let routes = Router::build("/", vec![("comp", &"/.well-known/spin".into())], None).unwrap();
         assert!(!routes.contains_reserved_route());
     }
+
+    fn matchers(methods: &[&str], host: Option<&str>) -> RouteMatchers {
+        RouteMatchers {
+            methods: methods.iter().map(|m| m.to_string()).collect(),
+            host: host.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn routes_can_be_restricted_to_methods() -> Result<()> {
+        let r = Router::build_with_matchers(
+            "/",
+            [
+                ("read", &"/items".into(), matchers(&["GET", "head"], None)),
+                ("write", &"/items".into(), matchers(&["POST"], None)),
+            ],
+            None,
+        )?;
+
+        let route = |method| r.route_request(Some(method), None, "/items");
+        assert_eq!(route("GET").unwrap().component_id(), "read");
+        assert_eq!(route("HEAD").unwrap().component_id(), "read");
+        assert_eq!(route("POST").unwrap().component_id(), "write");
+        assert_eq!(
+            route("DELETE").err(),
+            Some(RouteError::MethodNotAllowed(vec![
+                "GET".into(),
+                "HEAD".into(),
+                "POST".into()
+            ]))
+        );
+        assert_eq!(
+            r.route_request(Some("GET"), None, "/other").err(),
+            Some(RouteError::NotFound)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn method_mismatch_falls_back_to_less_specific_routes() -> Result<()> {
+        let r = Router::build_with_matchers(
+            "/",
+            [
+                ("api", &"/api/...".into(), matchers(&["GET"], None)),
+                ("fallback", &"/...".into(), matchers(&[], None)),
+            ],
+            None,
+        )?;
+
+        let route = |method| r.route_request(Some(method), None, "/api/items");
+        assert_eq!(route("GET").unwrap().component_id(), "api");
+        assert_eq!(route("POST").unwrap().component_id(), "fallback");
+        Ok(())
+    }
+
+    #[test]
+    fn specific_methods_beat_any_method() -> Result<()> {
+        let r = Router::build_with_matchers(
+            "/",
+            [
+                ("any", &"/items".into(), matchers(&[], None)),
+                ("post", &"/items".into(), matchers(&["POST"], None)),
+            ],
+            None,
+        )?;
+
+        let route = |method| r.route_request(Some(method), None, "/items");
+        assert_eq!(route("POST").unwrap().component_id(), "post");
+        assert_eq!(route("GET").unwrap().component_id(), "any");
+        assert_eq!(2, r.routes().count());
+        Ok(())
+    }
+
+    #[test]
+    fn overlapping_methods_are_taken_by_the_later_route() -> Result<()> {
+        let mut duplicates = Vec::new();
+        let r = Router::build_with_matchers(
+            "/",
+            [
+                ("first", &"/items".into(), matchers(&["GET", "POST"], None)),
+                ("second", &"/items".into(), matchers(&["POST"], None)),
+                ("third", &"/other".into(), matchers(&["GET"], None)),
+                ("fourth", &"/other".into(), matchers(&["get"], None)),
+            ],
+            Some(&mut duplicates),
+        )?;
+
+        let route = |method, path| r.route_request(Some(method), None, path);
+        assert_eq!(route("GET", "/items").unwrap().component_id(), "first");
+        assert_eq!(route("POST", "/items").unwrap().component_id(), "second");
+        assert_eq!(route("GET", "/other").unwrap().component_id(), "fourth");
+
+        assert_eq!(1, duplicates.len());
+        assert_eq!("third", duplicates[0].replaced_id);
+        assert_eq!("fourth", duplicates[0].effective_id);
+        Ok(())
+    }
+
+    #[test]
+    fn routes_can_be_restricted_to_hosts() -> Result<()> {
+        let r = Router::build_with_matchers(
+            "/",
+            [
+                ("any", &"/...".into(), matchers(&[], None)),
+                (
+                    "api",
+                    &"/...".into(),
+                    matchers(&[], Some("api.example.com")),
+                ),
+                (
+                    "tenant",
+                    &"/...".into(),
+                    matchers(&[], Some("*.example.com")),
+                ),
+                (
+                    "eu",
+                    &"/...".into(),
+                    matchers(&[], Some("*.eu.example.com")),
+                ),
+            ],
+            None,
+        )?;
+
+        let route = |host| r.route_request(Some("GET"), host, "/foo").unwrap();
+        assert_eq!(route(Some("api.example.com")).component_id(), "api");
+        assert_eq!(route(Some("API.Example.com:3000")).component_id(), "api");
+        assert_eq!(route(Some("acme.example.com")).component_id(), "tenant");
+        assert_eq!(route(Some("a.b.example.com")).component_id(), "tenant");
+        assert_eq!(route(Some("acme.eu.example.com")).component_id(), "eu");
+        assert_eq!(route(Some("example.com")).component_id(), "any");
+        assert_eq!(route(Some("example.org")).component_id(), "any");
+        assert_eq!(route(None).component_id(), "any");
+        Ok(())
+    }
+
+    #[test]
+    fn host_routes_take_precedence_over_path_specificity() -> Result<()> {
+        let r = Router::build_with_matchers(
+            "/",
+            [
+                ("exact-path", &"/foo".into(), matchers(&[], None)),
+                (
+                    "api",
+                    &"/...".into(),
+                    matchers(&[], Some("api.example.com")),
+                ),
+            ],
+            None,
+        )?;
+
+        let route = |host| r.route_request(None, Some(host), "/foo").unwrap();
+        assert_eq!(route("api.example.com").component_id(), "api");
+        assert_eq!(route("www.example.com").component_id(), "exact-path");
+        Ok(())
+    }
+
+    #[test]
+    fn same_route_on_different_hosts_is_not_a_duplicate() -> Result<()> {
+        let mut duplicates = Vec::new();
+        let r = Router::build_with_matchers(
+            "/",
+            [
+                ("a", &"/foo".into(), matchers(&[], Some("a.example.com"))),
+                ("b", &"/foo".into(), matchers(&[], Some("b.example.com"))),
+                ("any", &"/foo".into(), matchers(&[], None)),
+            ],
+            Some(&mut duplicates),
+        )?;
+
+        assert_eq!(3, r.routes().count());
+        assert!(duplicates.is_empty());
+        assert!(r
+            .route_request(None, Some("c.example.com"), "/bar")
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_matchers_are_rejected() {
+        for (methods, host) in [
+            (&["GET POST"][..], None),
+            (&[""][..], None),
+            (&[][..], Some("")),
+            (&[][..], Some("api.*.com")),
+            (&[][..], Some("example.com:3000")),
+            (&[][..], Some("*")),
+        ] {
+            let e = Router::build_with_matchers(
+                "/",
+                [("bad-comp", &"/foo".into(), matchers(methods, host))],
+                None,
+            )
+            .expect_err("should have rejected invalid matchers");
+            assert!(e.to_string().contains("bad-comp"), "{e}");
+        }
+    }
+
+    #[test]
+    fn route_ignores_methods_and_excludes_host_routes() -> Result<()> {
+        let r = Router::build_with_matchers(
+            "/",
+            [
+                ("post", &"/foo".into(), matchers(&["POST"], None)),
+                ("host", &"/bar".into(), matchers(&[], Some("example.com"))),
+            ],
+            None,
+        )?;
+
+        assert_eq!(r.route("/foo")?.component_id(), "post");
+        assert!(r.route("/bar").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn constrained_parameters_fall_through_when_unsatisfied() -> Result<()> {
+        let r = Router::build(
+            "/",
+            [
+                ("by-name", &"/users/:name".into()),
+                ("by-id", &"/users/:id<int>".into()),
+                ("by-uuid", &"/users/:id<uuid>".into()),
+            ],
+            None,
+        )?;
+
+        let m = r.route("/users/42")?;
+        assert_eq!(m.component_id(), "by-id");
+        assert_eq!("42", m.named_wildcards()["id"]);
+
+        let m = r.route("/users/67e55044-10b1-426f-9247-bb680e5fe0c8")?;
+        assert_eq!(m.component_id(), "by-uuid");
+
+        let m = r.route("/users/alice")?;
+        assert_eq!(m.component_id(), "by-name");
+        assert_eq!("alice", m.named_wildcards()["name"]);
+        Ok(())
+    }
+
+    #[test]
+    fn constrained_parameters_fall_through_to_wildcards() -> Result<()> {
+        let r = Router::build(
+            "/",
+            [
+                ("catch-all", &"/...".into()),
+                ("item", &"/items/:id<uint>/...".into()),
+            ],
+            None,
+        )?;
+
+        let m = r.route("/items/7/reviews")?;
+        assert_eq!(m.component_id(), "item");
+        assert_eq!("/reviews", m.trailing_wildcard());
+        assert_eq!(r.route("/items/-7/reviews")?.component_id(), "catch-all");
+        Ok(())
+    }
+
+    #[test]
+    fn regex_constraints_match_whole_values() -> Result<()> {
+        let r = Router::build(
+            "/",
+            [("code", &"/countries/:code<[A-Z]{2}>/:year<\\d{4}>".into())],
+            None,
+        )?;
+
+        let m = r.route("/countries/GB/2024")?;
+        assert_eq!("GB", m.named_wildcards()["code"]);
+        assert_eq!("2024", m.named_wildcards()["year"]);
+        assert!(r.route("/countries/GBR/2024").is_err());
+        assert!(r.route("/countries/gb/2024").is_err());
+        assert!(r.route("/countries/GB/24").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn builtin_constraints_are_checked() -> Result<()> {
+        let cases = [
+            ("int", "-12", true),
+            ("int", "12a", false),
+            ("int", "-", false),
+            ("uint", "12", true),
+            ("uint", "-12", false),
+            ("alpha", "abcXYZ", true),
+            ("alpha", "abc1", false),
+            ("alnum", "abc1", true),
+            ("alnum", "abc-1", false),
+            ("uuid", "67E55044-10b1-426f-9247-bb680e5fe0c8", true),
+            ("uuid", "67e5504410b1426f9247bb680e5fe0c8", false),
+        ];
+        for (constraint, value, expected) in cases {
+            let route = format!("/:value<{constraint}>");
+            let r = Router::build("/", [("comp", &route.into())], None)?;
+            assert_eq!(
+                expected,
+                r.route(&format!("/{value}")).is_ok(),
+                "{constraint} with {value}"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn constrained_routes_display_their_constraints() {
+        let r = Router::build("/", [("comp", &"/users/:id<int>/...".into())], None).unwrap();
+        let (route, _) = r.routes().next().unwrap();
+        assert_eq!("/users/:id<int> (wildcard)", format!("{route}"));
+    }
+
+    #[test]
+    fn invalid_constraints_are_rejected() {
+        for route in [
+            "/users/:id<int",
+            "/users/id<int>",
+            "/users/:<int>",
+            "/users/:id<>",
+            "/users/:id<[a-z>",
+            "/users/:id>",
+        ] {
+            let e = Router::build("/", [("bad-comp", &route.into())], None)
+                .expect_err("should have rejected invalid constraint");
+            assert!(e.to_string().contains("bad-comp"), "{route}: {e}");
+        }
+    }
 }
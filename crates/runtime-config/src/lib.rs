@@ -21,7 +21,7 @@ use spin_factors::{
 };
 use spin_key_value_spin::{SpinKeyValueRuntimeConfig, SpinKeyValueStore};
 use spin_sqlite as sqlite;
-use spin_trigger::cli::UserProvidedPath;
+use spin_trigger::cli::{parse_log_age, parse_log_size, LogRotation, UserProvidedPath};
 use toml::Value;
 
 pub mod variables;
@@ -47,6 +47,8 @@ pub struct ResolvedRuntimeConfig<T> {
     ///
     /// `None` is used for an "unset" log directory.
     pub log_dir: Option<PathBuf>,
+    /// Rotation of log files, if configured.
+    pub log_rotation: Option<LogRotation>,
     /// The maximum memory allocation limit.
     pub max_instance_memory: Option<usize>,
     /// The input TOML, for informational summaries.
@@ -102,23 +104,7 @@ where
         provided_state_dir: UserProvidedPath,
         provided_log_dir: UserProvidedPath,
     ) -> anyhow::Result<Self> {
-        let toml = match runtime_config_path {
-            Some(runtime_config_path) => {
-                let file = std::fs::read_to_string(runtime_config_path).with_context(|| {
-                    format!(
-                        "failed to read runtime config file '{}'",
-                        runtime_config_path.display()
-                    )
-                })?;
-                toml::from_str(&file).with_context(|| {
-                    format!(
-                        "failed to parse runtime config file '{}' as toml",
-                        runtime_config_path.display()
-                    )
-                })?
-            }
-            None => Default::default(),
-        };
+        let toml = read_runtime_config_file(runtime_config_path)?;
         let toml_resolver =
             TomlResolver::new(&toml, local_app_dir, provided_state_dir, provided_log_dir);
 
@@ -143,6 +129,7 @@ where
 
         let toml = toml_resolver.toml();
         let log_dir = toml_resolver.log_dir()?;
+        let log_rotation = toml_resolver.log_rotation()?;
         let max_instance_memory = toml_resolver.max_instance_memory()?;
 
         let source = TomlRuntimeConfigSource::new(
@@ -163,6 +150,7 @@ where
             sqlite_resolver,
             state_dir,
             log_dir,
+            log_rotation,
             max_instance_memory,
             toml,
         })
@@ -178,12 +166,58 @@ where
         self.log_dir.clone()
     }
 
+    /// Rotation of log files, if configured.
+    pub fn log_rotation(&self) -> Option<LogRotation> {
+        self.log_rotation.clone()
+    }
+
     /// The maximum memory allocation limit.
     pub fn max_instance_memory(&self) -> Option<usize> {
         self.max_instance_memory
     }
 }
 
+/// Resolves only the key-value stores from a runtime config source TOML file.
+///
+/// This resolves stores exactly as a running app would, for tools (such as
+/// `spin kv`) which need to access an app's stores from outside the app.
+pub fn key_value_runtime_config_from_file(
+    runtime_config_path: Option<&Path>,
+    local_app_dir: Option<PathBuf>,
+    provided_state_dir: UserProvidedPath,
+) -> anyhow::Result<spin_factor_key_value::RuntimeConfig> {
+    let toml = read_runtime_config_file(runtime_config_path)?;
+    let toml_resolver = TomlResolver::new(
+        &toml,
+        local_app_dir,
+        provided_state_dir,
+        UserProvidedPath::Unset,
+    );
+    let runtime_config_dir = runtime_config_path
+        .and_then(Path::parent)
+        .map(ToOwned::to_owned);
+    let state_dir = toml_resolver.state_dir()?;
+    key_value_config_resolver(runtime_config_dir, state_dir).resolve(Some(&toml))
+}
+
+fn read_runtime_config_file(runtime_config_path: Option<&Path>) -> anyhow::Result<toml::Table> {
+    let Some(runtime_config_path) = runtime_config_path else {
+        return Ok(Default::default());
+    };
+    let file = std::fs::read_to_string(runtime_config_path).with_context(|| {
+        format!(
+            "failed to read runtime config file '{}'",
+            runtime_config_path.display()
+        )
+    })?;
+    toml::from_str(&file).with_context(|| {
+        format!(
+            "failed to parse runtime config file '{}' as toml",
+            runtime_config_path.display()
+        )
+    })
+}
+
 #[derive(Clone, Debug)]
 /// Resolves runtime configuration from a TOML file.
 pub struct TomlResolver<'a> {
@@ -276,6 +310,70 @@ impl<'a> TomlResolver<'a> {
         }
     }
 
+    /// Get the configured rotation of log files from the `[log_rotation]` table.
+    ///
+    /// Rotation is configured by `max_size` (bytes, or a string such as `"10M"`)
+    /// and/or `max_age` (e.g. `"1d"`), refined by `max_files` and `compress`.
+    pub fn log_rotation(&self) -> anyhow::Result<Option<LogRotation>> {
+        let Some(value) = self.table.get("log_rotation") else {
+            return Ok(None);
+        };
+        let table = value.as_table().context("`log_rotation` must be a table")?;
+        if let Some(unknown) = table
+            .keys()
+            .find(|key| !["max_size", "max_age", "max_files", "compress"].contains(&key.as_str()))
+        {
+            anyhow::bail!("unknown `log_rotation` key '{unknown}'");
+        }
+
+        let max_size = match table.get("max_size") {
+            None => None,
+            Some(Value::String(size)) => {
+                Some(parse_log_size(size).context("invalid `log_rotation.max_size`")?)
+            }
+            Some(Value::Integer(size)) if *size > 0 => Some(*size as u64),
+            Some(_) => anyhow::bail!("`log_rotation.max_size` must be a positive size"),
+        };
+        let max_age = table
+            .get("max_age")
+            .map(|age| {
+                let age = age
+                    .as_str()
+                    .context("`log_rotation.max_age` must be a string such as \"1d\"")?;
+                parse_log_age(age).context("invalid `log_rotation.max_age`")
+            })
+            .transpose()?;
+        if max_size.is_none() && max_age.is_none() {
+            anyhow::bail!("`log_rotation` must set `max_size` and/or `max_age`");
+        }
+        let max_files = table
+            .get("max_files")
+            .map(|files| {
+                files
+                    .as_integer()
+                    .and_then(|files| usize::try_from(files).ok())
+                    .context("`log_rotation.max_files` must be a non-negative integer")
+            })
+            .transpose()?
+            .unwrap_or(LogRotation::DEFAULT_MAX_FILES);
+        let compress = table
+            .get("compress")
+            .map(|compress| {
+                compress
+                    .as_bool()
+                    .context("`log_rotation.compress` must be a boolean")
+            })
+            .transpose()?
+            .unwrap_or(false);
+
+        Ok(Some(LogRotation {
+            max_size,
+            max_age,
+            max_files,
+            compress,
+        }))
+    }
+
     /// Get the configured maximum memory allocation limit.
     pub fn max_instance_memory(&self) -> anyhow::Result<Option<usize>> {
         self.table
@@ -635,6 +733,52 @@ mod tests {
         resolve_toml(toml, "config.toml").unwrap();
     }
 
+    #[test]
+    fn log_rotation_is_resolved() {
+        let toml = toml::Table::new();
+        assert_eq!(toml_resolver(&toml).log_rotation().unwrap(), None);
+
+        let toml = toml::toml! {
+            [log_rotation]
+            max_size = "10M"
+            max_age = "1d"
+            max_files = 3
+            compress = true
+        };
+        assert_eq!(
+            toml_resolver(&toml).log_rotation().unwrap(),
+            Some(LogRotation {
+                max_size: Some(10 * 1024 * 1024),
+                max_age: Some(std::time::Duration::from_secs(24 * 60 * 60)),
+                max_files: 3,
+                compress: true,
+            })
+        );
+
+        let toml = toml::toml! {
+            [log_rotation]
+            max_size = 4096
+        };
+        assert_eq!(
+            toml_resolver(&toml).log_rotation().unwrap(),
+            Some(LogRotation {
+                max_size: Some(4096),
+                max_age: None,
+                max_files: LogRotation::DEFAULT_MAX_FILES,
+                compress: false,
+            })
+        );
+
+        for invalid in [
+            toml::toml! { log_rotation = { compress = true } },
+            toml::toml! { log_rotation = { max_size = "big" } },
+            toml::toml! { log_rotation = { max_age = 86400 } },
+            toml::toml! { log_rotation = { max_size = 1024, keep = 2 } },
+        ] {
+            toml_resolver(&invalid).log_rotation().unwrap_err();
+        }
+    }
+
     #[test]
     fn fails_to_resolve_with_unused_key() {
         define_test_factor!(sqlite: SqliteFactor);
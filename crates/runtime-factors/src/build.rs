@@ -6,9 +6,9 @@ use anyhow::Context as _;
 use spin_factors_executor::FactorsExecutor;
 use spin_runtime_config::ResolvedRuntimeConfig;
 use spin_trigger::cli::{
-    FactorsConfig, InitialKvSetterHook, KeyValueDefaultStoreSummaryHook, MaxInstanceMemoryHook,
-    RuntimeFactorsBuilder, SqlStatementExecutorHook, SqliteDefaultStoreSummaryHook,
-    StdioLoggingExecutorHooks,
+    FactorsConfig, InitialKvSetterHook, KeyValueDefaultStoreSummaryHook, LogConfig,
+    MaxInstanceMemoryHook, RuntimeFactorsBuilder, SqlStatementExecutorHook,
+    SqliteDefaultStoreSummaryHook, StdioLoggingExecutorHooks,
 };
 use spin_variables_static::StaticVariablesProvider;
 
@@ -55,16 +55,29 @@ impl RuntimeFactorsBuilder for FactorsBuilder {
         Ok((factors, runtime_config))
     }
 
+    fn log_config(
+        config: &FactorsConfig,
+        runtime_config: &Self::RuntimeConfig,
+    ) -> anyhow::Result<LogConfig> {
+        Ok(LogConfig {
+            log_dir: runtime_config.log_dir(),
+            log_rotation: config.log_rotation.apply(runtime_config.log_rotation())?,
+        })
+    }
+
     fn configure_app<U: Send + 'static>(
         executor: &mut FactorsExecutor<Self::Factors, U>,
         runtime_config: &Self::RuntimeConfig,
         config: &FactorsConfig,
         args: &Self::CliArgs,
     ) -> anyhow::Result<()> {
+        let logs = Self::log_config(config, runtime_config)?;
         executor.add_hooks(StdioLoggingExecutorHooks::new(
             config.follow_components.clone(),
-            runtime_config.log_dir(),
+            logs.log_dir,
             config.truncate_logs,
+            logs.log_rotation,
+            config.log_format,
         ));
         executor.add_hooks(SqlStatementExecutorHook::new(
             args.sqlite_statements.clone(),
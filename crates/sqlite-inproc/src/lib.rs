@@ -118,6 +118,16 @@ impl Connection for InProcConnection {
         Ok(conn.last_insert_rowid())
     }
 
+    /// Asks SQLite whether the statement can write, by preparing it.
+    async fn is_read_only(&self, query: &str) -> Result<bool, sqlite::Error> {
+        let connection = self.db_connection()?;
+        let conn = connection.lock().unwrap();
+        let statement = conn
+            .prepare_cached(query)
+            .map_err(|e| sqlite::Error::Io(e.to_string()))?;
+        Ok(statement.readonly())
+    }
+
     fn summary(&self) -> Option<String> {
         Some(match &self.location {
             InProcDatabaseLocation::InMemory => "a temporary in-memory database".to_string(),
@@ -194,3 +204,43 @@ impl rusqlite::types::FromSql for ValueWrapper {
         Ok(ValueWrapper(value))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use spin_factor_sqlite::RestrictedConnection;
+    use spin_locked_app::locked::AccessMode;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn read_only_connection_classifies_statements_by_effect() -> anyhow::Result<()> {
+        let connection = InProcConnection::new(InProcDatabaseLocation::InMemory)?;
+        connection.execute_batch("CREATE TABLE t (x)").await?;
+        let connection = RestrictedConnection::wrap(Box::new(connection), AccessMode::Read);
+
+        for read in [
+            "SELECT * FROM t",
+            "WITH c AS (SELECT x FROM t) SELECT * FROM c",
+            "VALUES (1)",
+        ] {
+            assert!(
+                connection.query(read, vec![]).await.is_ok(),
+                "{read:?} should be allowed"
+            );
+        }
+        for write in [
+            "INSERT INTO t VALUES (1)",
+            "WITH c AS (SELECT 1) DELETE FROM t",
+        ] {
+            assert!(
+                connection.query(write, vec![]).await.is_err(),
+                "{write:?} should be rejected"
+            );
+        }
+        assert!(matches!(
+            connection.query("DELETE FROM t", vec![]).await,
+            Err(sqlite::Error::AccessDenied)
+        ));
+        Ok(())
+    }
+}
@@ -1,5 +1,8 @@
 use anyhow::bail;
-use opentelemetry::{global, trace::TracerProvider};
+use opentelemetry::{
+    global,
+    trace::{TraceContextExt as _, TracerProvider},
+};
 use opentelemetry_sdk::{
     resource::{EnvResourceDetector, ResourceDetector, TelemetryResourceDetector},
     Resource,
@@ -91,3 +94,17 @@ pub fn mark_as_error<E: std::fmt::Display>(err: &E, blame: Option<Blame>) {
         current_span.set_attribute("error.blame", blame.as_str());
     }
 }
+
+/// Returns the trace ID and span ID of the current span, as hex strings, if it
+/// is part of a trace.
+pub fn current_span_ids() -> Option<(String, String)> {
+    let context = tracing::Span::current().context();
+    let span = context.span();
+    let span_context = span.span_context();
+    span_context.is_valid().then(|| {
+        (
+            span_context.trace_id().to_string(),
+            span_context.span_id().to_string(),
+        )
+    })
+}
@@ -0,0 +1,229 @@
+use std::fmt;
+
+use anyhow::{bail, Context};
+use clap::Args;
+use serde::Deserialize;
+use spin_factor_wasi::WasiFactor;
+use spin_factors::RuntimeFactors;
+use spin_trigger::{App, Trigger, TriggerApp};
+use tracing::{instrument, Level};
+use wasmtime_wasi::{p2::bindings::CommandIndices, I32Exit};
+
+/// Runs a `wasi:cli/run` component once, passing it the trigger's arguments
+/// and the host's stdin and stdout, and exits with its status.
+pub struct CommandTrigger {
+    args: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct CliArgs {
+    /// Arguments to pass to the command component. Precede arguments beginning with `-` with `--`
+    #[clap(
+        value_name = "ARGS",
+        allow_hyphen_values = true,
+        multiple_values = true
+    )]
+    pub args: Vec<String>,
+}
+
+/// Command trigger configuration.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TriggerConfig {
+    /// Component ID to invoke
+    component: String,
+}
+
+/// The error returned when a command component exits with a non-zero
+/// status. The component has reported any errors itself, so the host should
+/// exit with the same status without reporting one.
+#[derive(Debug)]
+pub struct CommandExit {
+    code: i32,
+}
+
+impl CommandExit {
+    pub fn code(&self) -> i32 {
+        self.code
+    }
+}
+
+impl fmt::Display for CommandExit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "command component exited with status {}", self.code)
+    }
+}
+
+impl std::error::Error for CommandExit {}
+
+impl<F: RuntimeFactors> Trigger<F> for CommandTrigger {
+    const TYPE: &'static str = "command";
+
+    type CliArgs = CliArgs;
+
+    type InstanceState = ();
+
+    fn new(cli_args: Self::CliArgs, _app: &App) -> anyhow::Result<Self> {
+        Ok(Self {
+            args: cli_args.args,
+        })
+    }
+
+    async fn run(self, trigger_app: TriggerApp<Self, F>) -> anyhow::Result<()> {
+        let trigger_type = <Self as Trigger<F>>::TYPE;
+        let components = trigger_app
+            .app()
+            .trigger_configs::<TriggerConfig>(trigger_type)?
+            .into_iter()
+            .map(|(_, config)| config.component)
+            .collect::<Vec<_>>();
+        let Some(component_id) = command_component(&components)? else {
+            return Ok(());
+        };
+
+        match self.execute(&trigger_app, component_id).await? {
+            0 => Ok(()),
+            code => Err(CommandExit { code }.into()),
+        }
+    }
+}
+
+impl CommandTrigger {
+    /// Runs the component, returning its exit status.
+    #[instrument(name = "spin_trigger_command.execute", skip_all, err(level = Level::INFO), fields(
+        otel.name = format!("command {component_id}"),
+    ))]
+    async fn execute<F: RuntimeFactors>(
+        &self,
+        trigger_app: &TriggerApp<Self, F>,
+        component_id: &str,
+    ) -> anyhow::Result<i32> {
+        tracing::trace!("Executing command component {component_id}");
+        spin_telemetry::metrics::monotonic_counter!(
+            spin.request_count = 1,
+            trigger_type = "command",
+            app_id = trigger_app.app().id(),
+            component_id = component_id
+        );
+
+        let mut instance_builder = trigger_app.prepare(component_id)?;
+        let wasi_builder = instance_builder
+            .factor_builder::<WasiFactor>()
+            .context("The command trigger was configured without the required wasi support")?;
+        // By convention, the first argument is the program name.
+        wasi_builder
+            .args(std::iter::once(component_id).chain(self.args.iter().map(String::as_str)));
+        // The command's output is the host's, rather than being logged like
+        // other components' output.
+        wasi_builder.stdin(wasmtime_wasi::cli::stdin());
+        wasi_builder.stdout(wasmtime_wasi::cli::stdout());
+
+        let (instance, mut store) = instance_builder.instantiate(()).await?;
+
+        let pre = instance.instance_pre(&store);
+        let indices = CommandIndices::new(&pre)
+            .context("the component does not export the wasi:cli/run interface")?;
+        let command = indices.load(&mut store, &instance)?;
+
+        exit_status(command.wasi_cli_run().call_run(&mut store).await)
+    }
+}
+
+/// The component to run, given the components with command triggers, or
+/// `None` if there are none.
+fn command_component(components: &[String]) -> anyhow::Result<Option<&str>> {
+    match components {
+        [] => Ok(None),
+        [component_id] => Ok(Some(component_id)),
+        _ => bail!(
+            "the application has command triggers for components {}; choose which to run with `spin up --component-id`",
+            components.join(", ")
+        ),
+    }
+}
+
+/// The exit status of a command whose `run` function returned `result`:
+/// either the status it returned, or that with which it called `exit`.
+fn exit_status(result: anyhow::Result<Result<(), ()>>) -> anyhow::Result<i32> {
+    match result {
+        Ok(Ok(())) => Ok(0),
+        Ok(Err(())) => Ok(1),
+        Err(err) => match err.root_cause().downcast_ref::<I32Exit>() {
+            Some(exit) => Ok(exit.0),
+            None => Err(err),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_statuses_are_passed_on() {
+        assert_eq!(exit_status(Ok(Ok(()))).unwrap(), 0);
+        assert_eq!(exit_status(Ok(Err(()))).unwrap(), 1);
+
+        let exited = anyhow::Error::new(I32Exit(3)).context("error while executing");
+        assert_eq!(exit_status(Err(exited)).unwrap(), 3);
+        let exited = anyhow::Error::new(I32Exit(0));
+        assert_eq!(exit_status(Err(exited)).unwrap(), 0);
+
+        let trapped = anyhow::anyhow!("wasm trap: unreachable");
+        exit_status(Err(trapped)).unwrap_err();
+    }
+
+    #[test]
+    fn a_single_command_component_is_run() {
+        let components = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(command_component(&components(&[])).unwrap(), None);
+        assert_eq!(
+            command_component(&components(&["migrate"])).unwrap(),
+            Some("migrate")
+        );
+        let err = command_component(&components(&["migrate", "seed"])).unwrap_err();
+        assert!(err.to_string().contains("migrate, seed"), "{err}");
+        assert!(err.to_string().contains("--component-id"), "{err}");
+    }
+
+    #[test]
+    fn trigger_configs_are_validated() {
+        let config: TriggerConfig =
+            serde_json::from_value(serde_json::json!({ "component": "migrate" })).unwrap();
+        assert_eq!(config.component, "migrate");
+
+        serde_json::from_value::<TriggerConfig>(serde_json::json!({})).unwrap_err();
+        serde_json::from_value::<TriggerConfig>(
+            serde_json::json!({ "component": "migrate", "args": ["up"] }),
+        )
+        .unwrap_err();
+    }
+
+    #[test]
+    fn args_may_begin_with_hyphens() {
+        use clap::Parser;
+
+        #[derive(Parser)]
+        struct Command {
+            #[clap(long)]
+            quiet: bool,
+            #[clap(flatten)]
+            args: CliArgs,
+        }
+
+        let parse = |args: &[&str]| {
+            Command::try_parse_from(std::iter::once("spin").chain(args.iter().copied()))
+                .map(|command| (command.quiet, command.args.args))
+        };
+        assert_eq!(
+            parse(&["--quiet", "migrate", "--dry-run"]).unwrap(),
+            (true, vec!["migrate".to_owned(), "--dry-run".to_owned()])
+        );
+        assert_eq!(
+            parse(&["--", "--dry-run"]).unwrap(),
+            (false, vec!["--dry-run".to_owned()])
+        );
+        parse(&["--dry-run"]).unwrap_err();
+    }
+}
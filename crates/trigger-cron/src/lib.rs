@@ -0,0 +1,473 @@
+use std::{collections::VecDeque, future::Future, sync::Arc, time::Duration};
+
+use anyhow::{bail, ensure, Context};
+use chrono::{DateTime, Utc};
+use clap::Args;
+use serde::Deserialize;
+use spin_factors::RuntimeFactors;
+use spin_trigger::{App, ShutdownToken, Trigger, TriggerApp};
+use spin_world::exports::spin::cron::inbound_cron;
+use tokio::{task::JoinSet, time::Instant};
+use tracing::{instrument, Level};
+
+mod schedule;
+
+use schedule::{OverlapPolicy, Schedule};
+
+/// The maximum number of runs waiting for a previous run to finish under the
+/// `queue` overlap policy. Further runs are skipped.
+const MAX_QUEUED_RUNS: usize = 16;
+
+pub struct CronTrigger {
+    run_once: Option<String>,
+    shutdown: ShutdownToken,
+}
+
+#[derive(Args)]
+pub struct CliArgs {
+    /// Run the cron trigger with this ID once, immediately, and exit. Exits with an
+    /// error if the component fails
+    #[clap(long = "run-once", value_name = "TRIGGER_ID")]
+    pub run_once: Option<String>,
+}
+
+/// Cron trigger configuration.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TriggerConfig {
+    /// Component ID to invoke
+    component: String,
+    /// Cron expression for when to run, in UTC. `cron_expression`, as used by
+    /// the cron trigger plugin, is accepted too.
+    #[serde(alias = "cron_expression")]
+    schedule: Option<String>,
+    /// Interval at which to run, instead of a cron expression
+    interval_secs: Option<u64>,
+    /// Maximum random delay added to each run
+    #[serde(default)]
+    jitter_secs: u64,
+    /// What to do when a run is due while the previous run is in progress
+    #[serde(default)]
+    overlap: OverlapPolicy,
+}
+
+impl<F: RuntimeFactors> Trigger<F> for CronTrigger {
+    const TYPE: &'static str = "cron";
+
+    type CliArgs = CliArgs;
+
+    type InstanceState = ();
+
+    fn new(cli_args: Self::CliArgs, _app: &App) -> anyhow::Result<Self> {
+        Ok(Self {
+            run_once: cli_args.run_once,
+            shutdown: ShutdownToken::new(),
+        })
+    }
+
+    fn enable_graceful_shutdown(&mut self, token: ShutdownToken) -> bool {
+        self.shutdown = token;
+        true
+    }
+
+    async fn run(self, trigger_app: TriggerApp<Self, F>) -> anyhow::Result<()> {
+        let trigger_type = <Self as Trigger<F>>::TYPE;
+        let mut jobs = Vec::new();
+        for (trigger_id, config) in trigger_app
+            .app()
+            .trigger_configs::<TriggerConfig>(trigger_type)?
+        {
+            let component_id = config.component.clone();
+            let job = Job::new(trigger_id.to_owned(), config).with_context(|| {
+                format!("invalid cron trigger {trigger_id} for component {component_id}")
+            })?;
+            jobs.push(job);
+        }
+
+        let dispatcher = Arc::new(Dispatcher {
+            trigger_app: Arc::new(trigger_app),
+        });
+
+        if let Some(trigger_id) = self.run_once {
+            let Some(job) = jobs.iter().find(|job| job.trigger_id == trigger_id) else {
+                let trigger_ids: Vec<_> = jobs.iter().map(|job| job.trigger_id.as_str()).collect();
+                bail!(
+                    "no cron trigger with ID {trigger_id:?}; the application's cron triggers are: {}",
+                    trigger_ids.join(", ")
+                );
+            };
+            println!("Running cron trigger {trigger_id}: [{}]", job.component_id);
+            return dispatcher
+                .handle(&job.trigger_id, &job.component_id, Utc::now())
+                .await;
+        }
+
+        // Start scheduler(s)
+        let mut scheduler_tasks = Vec::new();
+        for job in jobs {
+            let task = tokio::spawn(job.run(dispatcher.clone(), self.shutdown.clone(), Utc::now));
+            scheduler_tasks.push(task);
+        }
+        if scheduler_tasks.is_empty() {
+            return Ok(());
+        }
+
+        // Wait for any task to complete, or for all to finish their in-flight
+        // runs on shutdown
+        let (res, _, rest) = futures::future::select_all(scheduler_tasks).await;
+        if self.shutdown.is_shutdown() {
+            futures::future::join_all(rest).await;
+        }
+        res?
+    }
+}
+
+/// A component's schedule.
+#[derive(Debug)]
+struct Job {
+    trigger_id: String,
+    component_id: String,
+    schedule: Schedule,
+    jitter: Duration,
+    overlap: OverlapPolicy,
+}
+
+impl Job {
+    fn new(trigger_id: String, config: TriggerConfig) -> anyhow::Result<Self> {
+        let schedule = match (&config.schedule, config.interval_secs) {
+            (Some(expr), None) => Schedule::cron(expr)?,
+            (None, Some(secs)) => Schedule::interval(Duration::from_secs(secs))?,
+            (Some(_), Some(_)) => bail!("a trigger can't set both `schedule` and `interval_secs`"),
+            (None, None) => bail!("a trigger must set either `schedule` or `interval_secs`"),
+        };
+        // Jitter as long as the time between runs could reorder them.
+        let jitter = Duration::from_secs(config.jitter_secs);
+        match (&schedule, schedule.shortest_period(Utc::now())) {
+            (Schedule::Interval(interval), _) => ensure!(
+                jitter < *interval,
+                "`jitter_secs` must be less than `interval_secs`"
+            ),
+            (Schedule::Cron { .. }, Some(period)) => ensure!(
+                jitter < period,
+                "`jitter_secs` must be less than the shortest time between scheduled runs, {}s",
+                period.as_secs()
+            ),
+            (Schedule::Cron { .. }, None) => {}
+        }
+        Ok(Self {
+            trigger_id,
+            component_id: config.component,
+            schedule,
+            jitter,
+            overlap: config.overlap,
+        })
+    }
+
+    /// Runs the component on schedule until shutdown, using `now` to tell the
+    /// time.
+    ///
+    /// Runs which are missed entirely, for example because the host was
+    /// suspended, are skipped rather than caught up on.
+    async fn run(
+        self,
+        handler: Arc<impl EventHandler>,
+        shutdown: ShutdownToken,
+        now: impl Fn() -> DateTime<Utc> + Send,
+    ) -> anyhow::Result<()> {
+        println!(
+            "Scheduled cron trigger {} {}: [{}]",
+            self.trigger_id, self.schedule, self.component_id
+        );
+        let mut next = self.next_run(now(), &now);
+        let mut queued = VecDeque::new();
+        let mut in_flight = JoinSet::new();
+        let mut shutting_down = false;
+        loop {
+            if in_flight.is_empty() && (shutting_down || next.is_none()) {
+                tracing::info!(
+                    "Shutting down: no longer running cron trigger {}",
+                    self.trigger_id
+                );
+                return Ok(());
+            }
+            let due = async {
+                match next {
+                    Some((_, at)) if !shutting_down => tokio::time::sleep_until(at).await,
+                    _ => std::future::pending().await,
+                }
+            };
+            tokio::select! {
+                () = due => {
+                    let (scheduled, _) = next.take().unwrap();
+                    self.start_or_queue(scheduled, &mut in_flight, &mut queued, &handler);
+                    next = self.next_run(scheduled, &now);
+                }
+                Some(_) = in_flight.join_next() => {
+                    if let Some(scheduled) = queued.pop_front() {
+                        self.start(scheduled, &mut in_flight, &handler);
+                    }
+                }
+                () = shutdown.wait(), if !shutting_down => {
+                    shutting_down = true;
+                    queued.clear();
+                }
+            }
+        }
+    }
+
+    /// The time of the run after `previous`, and the instant to start it,
+    /// including jitter.
+    fn next_run(
+        &self,
+        previous: DateTime<Utc>,
+        now: impl Fn() -> DateTime<Utc>,
+    ) -> Option<(DateTime<Utc>, Instant)> {
+        let current = now();
+        let mut scheduled = self.schedule.next_after(previous)?;
+        if scheduled < current {
+            tracing::warn!(
+                "Cron trigger {} missed runs scheduled since {scheduled}",
+                self.trigger_id
+            );
+            scheduled = self.schedule.next_after(current)?;
+        }
+        let jitter_millis = rand::random_range(0..=self.jitter.as_millis() as u64);
+        let delay = (scheduled - current).to_std().unwrap_or_default()
+            + Duration::from_millis(jitter_millis);
+        Some((scheduled, Instant::now() + delay))
+    }
+
+    /// Starts the run scheduled for `scheduled`, subject to the overlap policy.
+    fn start_or_queue(
+        &self,
+        scheduled: DateTime<Utc>,
+        in_flight: &mut JoinSet<()>,
+        queued: &mut VecDeque<DateTime<Utc>>,
+        handler: &Arc<impl EventHandler>,
+    ) {
+        let trigger_id = &self.trigger_id;
+        match self.overlap {
+            OverlapPolicy::Allow => self.start(scheduled, in_flight, handler),
+            _ if in_flight.is_empty() => self.start(scheduled, in_flight, handler),
+            OverlapPolicy::Skip => {
+                tracing::warn!(
+                    "Skipping cron trigger {trigger_id} run scheduled for {scheduled}: the previous run is still in progress"
+                );
+            }
+            OverlapPolicy::Queue if queued.len() < MAX_QUEUED_RUNS => queued.push_back(scheduled),
+            OverlapPolicy::Queue => {
+                tracing::warn!(
+                    "Skipping cron trigger {trigger_id} run scheduled for {scheduled}: {MAX_QUEUED_RUNS} runs are already queued"
+                );
+            }
+        }
+    }
+
+    fn start(
+        &self,
+        scheduled: DateTime<Utc>,
+        in_flight: &mut JoinSet<()>,
+        handler: &Arc<impl EventHandler>,
+    ) {
+        let handler = handler.clone();
+        let trigger_id = self.trigger_id.clone();
+        let component_id = self.component_id.clone();
+        in_flight.spawn(async move {
+            if let Err(err) = handler.handle(&trigger_id, &component_id, scheduled).await {
+                tracing::info!("Component {component_id} handler failed: {err}");
+            }
+        });
+    }
+}
+
+/// Handles runs scheduled by a [`Job`].
+trait EventHandler: Send + Sync + 'static {
+    fn handle(
+        &self,
+        trigger_id: &str,
+        component_id: &str,
+        scheduled: DateTime<Utc>,
+    ) -> impl Future<Output = anyhow::Result<()>> + Send;
+}
+
+/// Dispatches scheduled runs to components.
+struct Dispatcher<F: RuntimeFactors> {
+    trigger_app: Arc<TriggerApp<CronTrigger, F>>,
+}
+
+impl<F: RuntimeFactors> EventHandler for Dispatcher<F> {
+    #[instrument(name = "spin_trigger_cron.handle_cron_event", skip_all, err(level = Level::INFO), fields(
+        otel.name = format!("cron {trigger_id}"),
+        spin.trigger_id = trigger_id,
+        spin.scheduled_time = %scheduled,
+    ))]
+    async fn handle(
+        &self,
+        trigger_id: &str,
+        component_id: &str,
+        scheduled: DateTime<Utc>,
+    ) -> anyhow::Result<()> {
+        tracing::trace!(%scheduled, "Executing cron component {component_id}");
+        spin_telemetry::metrics::monotonic_counter!(
+            spin.request_count = 1,
+            trigger_type = "cron",
+            app_id = self.trigger_app.app().id(),
+            component_id = component_id
+        );
+
+        let (instance, mut store) = self
+            .trigger_app
+            .prepare(component_id)?
+            .instantiate(())
+            .await?;
+
+        let pre = instance.instance_pre(&store);
+        let guest_indices = inbound_cron::GuestIndices::new(&pre)?;
+        let guest = guest_indices.load(&mut store, &instance)?;
+
+        let metadata = inbound_cron::Metadata {
+            trigger_id: trigger_id.to_owned(),
+            scheduled_time: scheduled
+                .timestamp_millis()
+                .try_into()
+                .context("scheduled time is before the Unix epoch")?,
+        };
+
+        guest
+            .call_handle_cron_event(&mut store, &metadata)
+            .await?
+            .map_err(|inbound_cron::Error::Other(err)| anyhow::anyhow!(err))
+            .context("cron handler returned an error")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    fn job(config: TriggerConfig) -> anyhow::Result<Job> {
+        Job::new(
+            "trigger".into(),
+            TriggerConfig {
+                component: "component".into(),
+                ..config
+            },
+        )
+    }
+
+    #[test]
+    fn configs_are_validated() {
+        let cron = job(TriggerConfig {
+            schedule: Some("0 * * * *".into()),
+            jitter_secs: 1800,
+            overlap: OverlapPolicy::Queue,
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(matches!(cron.schedule, Schedule::Cron { .. }));
+        assert_eq!(cron.jitter, Duration::from_secs(1800));
+        assert_eq!(cron.overlap, OverlapPolicy::Queue);
+
+        let interval = job(TriggerConfig {
+            interval_secs: Some(60),
+            jitter_secs: 59,
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(matches!(interval.schedule, Schedule::Interval(_)));
+        assert_eq!(interval.overlap, OverlapPolicy::Skip);
+
+        for (schedule, interval_secs, jitter_secs) in [
+            (None, None, 0),
+            (Some("0 * * * *"), Some(60), 0),
+            (Some("every minute"), None, 0),
+            (None, Some(0), 0),
+            (None, Some(60), 60),
+            (Some("0 * * * *"), None, 3600),
+            (Some("0 9,10 * * *"), None, 7200),
+        ] {
+            job(TriggerConfig {
+                schedule: schedule.map(Into::into),
+                interval_secs,
+                jitter_secs,
+                ..Default::default()
+            })
+            .unwrap_err();
+        }
+    }
+
+    #[test]
+    fn plugin_configuration_is_accepted() {
+        let config: TriggerConfig = toml::toml! {
+            component = "component"
+            cron_expression = "1/2 * * * * *"
+        }
+        .try_into()
+        .unwrap();
+        assert_eq!(config.schedule.as_deref(), Some("1/2 * * * * *"));
+        job(config).unwrap();
+    }
+
+    /// Records when the runs it handles were scheduled, taking `duration` to
+    /// handle each.
+    struct SlowHandler {
+        duration: Duration,
+        started: Mutex<Vec<DateTime<Utc>>>,
+    }
+
+    impl EventHandler for SlowHandler {
+        async fn handle(
+            &self,
+            _trigger_id: &str,
+            _component_id: &str,
+            scheduled: DateTime<Utc>,
+        ) -> anyhow::Result<()> {
+            self.started.lock().unwrap().push(scheduled);
+            tokio::time::sleep(self.duration).await;
+            Ok(())
+        }
+    }
+
+    /// Runs a job every 10s for 55s with runs taking 25s, returning the
+    /// scheduled times of the runs started as seconds from the start.
+    async fn run_overlapping(overlap: OverlapPolicy) -> Vec<i64> {
+        let job = job(TriggerConfig {
+            interval_secs: Some(10),
+            overlap,
+            ..Default::default()
+        })
+        .unwrap();
+        let handler = Arc::new(SlowHandler {
+            duration: Duration::from_secs(25),
+            started: Default::default(),
+        });
+        let shutdown = ShutdownToken::new();
+
+        // Tell the time by tokio's paused clock.
+        let (start_instant, start_time) = (Instant::now(), Utc::now());
+        let now = move || start_time + (Instant::now() - start_instant);
+        let task = tokio::spawn(job.run(handler.clone(), shutdown.clone(), now));
+
+        tokio::time::sleep(Duration::from_secs(55)).await;
+        shutdown.shutdown();
+        task.await.unwrap().unwrap();
+
+        let started = handler.started.lock().unwrap();
+        started
+            .iter()
+            .map(|scheduled| (*scheduled - start_time).num_seconds())
+            .collect()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn overlapping_runs_follow_the_policy() {
+        assert_eq!(run_overlapping(OverlapPolicy::Skip).await, [10, 40]);
+        assert_eq!(run_overlapping(OverlapPolicy::Queue).await, [10, 20]);
+        assert_eq!(
+            run_overlapping(OverlapPolicy::Allow).await,
+            [10, 20, 30, 40, 50]
+        );
+    }
+}
@@ -0,0 +1,154 @@
+//! When cron triggers run.
+
+use std::{fmt, str::FromStr, time::Duration};
+
+use anyhow::{ensure, Context};
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::Deserialize;
+
+/// The number of upcoming runs of a cron schedule looked at to find the
+/// shortest time between runs.
+const PERIOD_SAMPLE_RUNS: usize = 1000;
+
+/// What a trigger does when a run is due while a previous run is still in
+/// progress.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum OverlapPolicy {
+    /// Skip the run.
+    #[default]
+    Skip,
+    /// Start the run once the previous run has finished.
+    Queue,
+    /// Start the run alongside the previous run.
+    Allow,
+}
+
+/// When a trigger runs.
+#[derive(Debug)]
+pub(crate) enum Schedule {
+    /// At the times matching a cron expression, in UTC.
+    Cron {
+        expr: String,
+        schedule: Box<cron::Schedule>,
+    },
+    /// At a fixed interval from when the trigger starts.
+    Interval(Duration),
+}
+
+impl Schedule {
+    /// Parses a cron expression. Expressions have six or seven fields, the
+    /// first being seconds and the optional last being years, or five fields
+    /// to run at the start of the matching minutes.
+    pub fn cron(expr: &str) -> anyhow::Result<Self> {
+        let full_expr = if expr.split_whitespace().count() == 5 {
+            format!("0 {expr}")
+        } else {
+            expr.to_owned()
+        };
+        let schedule = cron::Schedule::from_str(&full_expr)
+            .with_context(|| format!("invalid cron schedule {expr:?}"))?;
+        Ok(Self::Cron {
+            expr: expr.to_owned(),
+            schedule: Box::new(schedule),
+        })
+    }
+
+    pub fn interval(interval: Duration) -> anyhow::Result<Self> {
+        ensure!(
+            !interval.is_zero(),
+            "`interval_secs` must be greater than zero"
+        );
+        Ok(Self::Interval(interval))
+    }
+
+    /// The first time the trigger runs after `after`, or `None` if it never
+    /// runs again.
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Self::Cron { schedule, .. } => schedule.after(&after).next(),
+            Self::Interval(interval) => {
+                after.checked_add_signed(TimeDelta::from_std(*interval).ok()?)
+            }
+        }
+    }
+}
+
+impl Schedule {
+    /// The shortest time between runs, or `None` if the trigger runs at most
+    /// once more. Cron schedules are judged by their upcoming runs after `after`.
+    pub fn shortest_period(&self, after: DateTime<Utc>) -> Option<Duration> {
+        match self {
+            Self::Cron { schedule, .. } => {
+                let runs: Vec<_> = schedule.after(&after).take(PERIOD_SAMPLE_RUNS).collect();
+                runs.windows(2)
+                    .filter_map(|runs| (runs[1] - runs[0]).to_std().ok())
+                    .min()
+            }
+            Self::Interval(interval) => Some(*interval),
+        }
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Cron { expr, .. } => write!(f, "on schedule {expr:?}"),
+            Self::Interval(interval) => write!(f, "every {}s", interval.as_secs()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn at(h: u32, m: u32, s: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2025, 6, 2, h, m, s).unwrap()
+    }
+
+    #[test]
+    fn cron_schedules_may_omit_seconds() {
+        let schedule = Schedule::cron("*/15 * * * *").unwrap();
+        assert_eq!(schedule.next_after(at(12, 7, 30)), Some(at(12, 15, 0)));
+        assert_eq!(schedule.next_after(at(12, 15, 0)), Some(at(12, 30, 0)));
+        assert_eq!(schedule.to_string(), r#"on schedule "*/15 * * * *""#);
+
+        let schedule = Schedule::cron("30 0 9 * * Mon-Fri").unwrap();
+        assert_eq!(
+            schedule.next_after(at(12, 0, 0)),
+            Some(at(9, 0, 30) + TimeDelta::days(1))
+        );
+
+        let schedule = Schedule::cron("@hourly").unwrap();
+        assert_eq!(schedule.next_after(at(12, 7, 30)), Some(at(13, 0, 0)));
+
+        Schedule::cron("* * *").unwrap_err();
+        Schedule::cron("0 61 * * *").unwrap_err();
+    }
+
+    #[test]
+    fn periods_are_the_shortest_time_between_runs() {
+        let period = |expr: &str| Schedule::cron(expr).unwrap().shortest_period(at(12, 0, 0));
+        assert_eq!(period("*/15 * * * *"), Some(Duration::from_secs(900)));
+        assert_eq!(period("0 9,10 * * *"), Some(Duration::from_secs(3600)));
+        assert_eq!(period("0 0 0 1 1 * 2025"), None);
+
+        let schedule = Schedule::interval(Duration::from_secs(90)).unwrap();
+        assert_eq!(
+            schedule.shortest_period(at(12, 0, 0)),
+            Some(Duration::from_secs(90))
+        );
+    }
+
+    #[test]
+    fn intervals_are_relative() {
+        let schedule = Schedule::interval(Duration::from_secs(90)).unwrap();
+        assert_eq!(schedule.next_after(at(12, 0, 0)), Some(at(12, 1, 30)));
+        assert_eq!(schedule.to_string(), "every 90s");
+
+        Schedule::interval(Duration::ZERO).unwrap_err();
+    }
+}
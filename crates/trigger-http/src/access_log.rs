@@ -0,0 +1,435 @@
+//! Access logs of the requests handled by the HTTP trigger.
+
+use std::{
+    io::{self, Write},
+    net::SocketAddr,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    task::{ready, Context, Poll},
+};
+
+use anyhow::Context as _;
+use chrono::{DateTime, Local, SecondsFormat};
+use clap::ValueEnum;
+use http::{header, Request, Response, Version};
+use http_body_util::BodyExt;
+use hyper::body::{Body as HttpBody, Bytes, Frame, SizeHint};
+use serde::Serialize;
+use spin_common::ui::quoted_path;
+use spin_trigger::cli::{LogConfig, RotatingLogFile};
+use tokio::time::Instant;
+use wasmtime_wasi_http::bindings::http::types::ErrorCode;
+
+use crate::{
+    instrument::{MatchedComponent, MatchedRoute},
+    Body,
+};
+
+/// The name of the access log file in the log directory.
+const ACCESS_LOG_FILE: &str = "http_access.log";
+
+/// The format of access log entries.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum AccessLogFormat {
+    /// The Common Log Format, as used by many web servers.
+    Common,
+    /// One JSON object per line.
+    Json,
+}
+
+/// Writes an entry to the access log for each request.
+pub(crate) struct AccessLog {
+    format: AccessLogFormat,
+    writer: Mutex<Box<dyn Write + Send>>,
+}
+
+impl AccessLog {
+    /// Opens the access log in the log directory, or logs to stderr if there
+    /// is no log directory. The access log is rotated as configured, or when
+    /// it reaches 10 MiB if no rotation is configured.
+    pub fn open(format: AccessLogFormat, logs: &LogConfig) -> anyhow::Result<Self> {
+        let writer: Box<dyn Write + Send> = match logs.log_dir.as_deref() {
+            Some(log_dir) => {
+                std::fs::create_dir_all(log_dir).with_context(|| {
+                    format!("Failed to create log dir {}", quoted_path(log_dir))
+                })?;
+                let path = log_dir.join(ACCESS_LOG_FILE);
+                let file =
+                    RotatingLogFile::open(&path, logs.log_rotation.clone().unwrap_or_default())
+                        .with_context(|| {
+                            format!("Failed to open access log {}", quoted_path(&path))
+                        })?;
+                println!("Logging HTTP requests to {}", quoted_path(&path));
+                Box::new(file)
+            }
+            None => Box::new(io::stderr()),
+        };
+        Ok(Self::new(format, writer))
+    }
+
+    fn new(format: AccessLogFormat, writer: Box<dyn Write + Send>) -> Self {
+        Self {
+            format,
+            writer: Mutex::new(writer),
+        }
+    }
+
+    /// Starts an entry for the request, counting the bytes of its body as it
+    /// is read.
+    pub fn start(
+        self: &Arc<Self>,
+        req: Request<Body>,
+        client_addr: SocketAddr,
+    ) -> (Request<Body>, PendingEntry) {
+        let header = |name: header::HeaderName| {
+            req.headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_owned)
+        };
+        let bytes_in = Arc::new(AtomicU64::new(0));
+        let entry = PendingEntry {
+            log: self.clone(),
+            started: Instant::now(),
+            time: Local::now(),
+            client_addr,
+            method: req.method().to_string(),
+            target: req
+                .uri()
+                .path_and_query()
+                .map(|target| target.as_str())
+                .unwrap_or("/")
+                .to_owned(),
+            version: req.version(),
+            user_agent: header(header::USER_AGENT),
+            referer: header(header::REFERER),
+            bytes_in: bytes_in.clone(),
+            status: 0,
+            route: None,
+            component: None,
+        };
+        let req = req.map(|inner| {
+            CountedBody {
+                inner,
+                count: bytes_in,
+            }
+            .boxed()
+        });
+        (req, entry)
+    }
+
+    fn write(&self, entry: &Entry) {
+        let mut line = match self.format {
+            AccessLogFormat::Common => entry.common_log_format(),
+            AccessLogFormat::Json => match serde_json::to_string(entry) {
+                Ok(json) => json,
+                Err(err) => {
+                    tracing::warn!("Unable to serialize access log entry: {err}");
+                    return;
+                }
+            },
+        };
+        line.push('\n');
+        let mut writer = self.writer.lock().unwrap();
+        if let Err(err) = writer
+            .write_all(line.as_bytes())
+            .and_then(|()| writer.flush())
+        {
+            tracing::warn!("Unable to write access log entry: {err}");
+        }
+    }
+}
+
+/// The access log entry for a request which is in progress.
+pub(crate) struct PendingEntry {
+    log: Arc<AccessLog>,
+    started: Instant,
+    time: DateTime<Local>,
+    client_addr: SocketAddr,
+    method: String,
+    target: String,
+    version: Version,
+    user_agent: Option<String>,
+    referer: Option<String>,
+    bytes_in: Arc<AtomicU64>,
+    status: u16,
+    route: Option<String>,
+    component: Option<String>,
+}
+
+impl PendingEntry {
+    /// Completes the entry with the response. The entry is written once the
+    /// response body has been sent, or dropped if the client went away.
+    pub fn finish(mut self, res: anyhow::Result<Response<Body>>) -> anyhow::Result<Response<Body>> {
+        match res {
+            Ok(res) => {
+                let extensions = res.extensions();
+                self.status = res.status().as_u16();
+                self.route = extensions
+                    .get::<MatchedRoute>()
+                    .map(|matched| matched.route.clone());
+                self.component = extensions
+                    .get::<MatchedComponent>()
+                    .map(|matched| matched.component_id.clone());
+                Ok(res.map(|inner| {
+                    LoggedBody {
+                        inner,
+                        entry: Some(self),
+                        bytes_out: 0,
+                    }
+                    .boxed()
+                }))
+            }
+            Err(err) => {
+                self.status = 500;
+                self.write(0);
+                Err(err)
+            }
+        }
+    }
+
+    fn write(self, bytes_out: u64) {
+        let entry = Entry {
+            time: self.time,
+            client_addr: self.client_addr,
+            method: self.method,
+            target: self.target,
+            version: format!("{:?}", self.version),
+            status: self.status,
+            route: self.route,
+            component: self.component,
+            user_agent: self.user_agent,
+            referer: self.referer,
+            bytes_in: self.bytes_in.load(Ordering::Relaxed),
+            bytes_out,
+            latency_ms: self.started.elapsed().as_secs_f64() * 1000.0,
+        };
+        self.log.write(&entry);
+    }
+}
+
+/// An access log entry.
+#[derive(Serialize)]
+struct Entry {
+    #[serde(serialize_with = "serialize_time")]
+    time: DateTime<Local>,
+    client_addr: SocketAddr,
+    method: String,
+    target: String,
+    version: String,
+    status: u16,
+    route: Option<String>,
+    component: Option<String>,
+    user_agent: Option<String>,
+    referer: Option<String>,
+    bytes_in: u64,
+    bytes_out: u64,
+    latency_ms: f64,
+}
+
+impl Entry {
+    /// Formats the entry in the Common Log Format:
+    /// `host ident authuser [date] "request line" status bytes`.
+    fn common_log_format(&self) -> String {
+        let bytes_out = match self.bytes_out {
+            0 => "-".to_owned(),
+            bytes => bytes.to_string(),
+        };
+        format!(
+            "{} - - [{}] \"{} {} {}\" {} {}",
+            self.client_addr.ip(),
+            self.time.format("%d/%b/%Y:%H:%M:%S %z"),
+            self.method,
+            self.target,
+            self.version,
+            self.status,
+            bytes_out,
+        )
+    }
+}
+
+fn serialize_time<S: serde::Serializer>(time: &DateTime<Local>, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(&time.to_rfc3339_opts(SecondsFormat::Millis, false))
+}
+
+/// A request body which counts the bytes read from it.
+struct CountedBody {
+    inner: Body,
+    count: Arc<AtomicU64>,
+}
+
+impl HttpBody for CountedBody {
+    type Data = Bytes;
+    type Error = ErrorCode;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let frame = ready!(Pin::new(&mut self.inner).poll_frame(cx));
+        if let Some(Ok(frame)) = &frame {
+            let len = frame.data_ref().map_or(0, Bytes::len) as u64;
+            self.count.fetch_add(len, Ordering::Relaxed);
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// A response body which counts the bytes sent, and writes the access log
+/// entry for the request once it has been sent or dropped.
+struct LoggedBody {
+    inner: Body,
+    entry: Option<PendingEntry>,
+    bytes_out: u64,
+}
+
+impl LoggedBody {
+    fn write_entry(&mut self) {
+        if let Some(entry) = self.entry.take() {
+            entry.write(self.bytes_out);
+        }
+    }
+}
+
+impl HttpBody for LoggedBody {
+    type Data = Bytes;
+    type Error = ErrorCode;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let frame = ready!(Pin::new(&mut self.inner).poll_frame(cx));
+        match &frame {
+            Some(Ok(frame)) => {
+                self.bytes_out += frame.data_ref().map_or(0, Bytes::len) as u64;
+            }
+            // The body is finished, or failed.
+            _ => self.write_entry(),
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for LoggedBody {
+    fn drop(&mut self) {
+        self.write_entry();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use spin_http::body;
+
+    use super::*;
+
+    /// A writer which can be inspected after being given to an [`AccessLog`].
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuffer {
+        fn contents(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    async fn log_request(format: AccessLogFormat) -> String {
+        let buffer = SharedBuffer::default();
+        let log = Arc::new(AccessLog::new(format, Box::new(buffer.clone())));
+        let req = Request::post("/api/items?page=2")
+            .header(header::USER_AGENT, "test-agent")
+            .body(body::full(Bytes::from_static(b"request")))
+            .unwrap();
+        let (req, entry) = log.start(req, "10.0.0.7:4567".parse().unwrap());
+
+        // The handler reads the request body and responds.
+        let read = req.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(read, "request");
+        let mut res = Response::builder()
+            .status(201)
+            .body(body::full(Bytes::from_static(b"created!")))
+            .unwrap();
+        MatchedRoute::set_response_extension(&mut res, "/api/...");
+        MatchedComponent::set_response_extension(&mut res, "api");
+        let res = entry.finish(Ok(res)).unwrap();
+        assert!(
+            buffer.contents().is_empty(),
+            "written before the body was sent"
+        );
+
+        let sent = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(sent, "created!");
+        buffer.contents()
+    }
+
+    #[tokio::test]
+    async fn common_log_format_entries() {
+        let line = log_request(AccessLogFormat::Common).await;
+        assert!(line.starts_with("10.0.0.7 - - ["), "{line}");
+        assert!(
+            line.ends_with("] \"POST /api/items?page=2 HTTP/1.1\" 201 8\n"),
+            "{line}"
+        );
+    }
+
+    #[tokio::test]
+    async fn json_entries() {
+        let line = log_request(AccessLogFormat::Json).await;
+        assert_eq!(line.lines().count(), 1);
+        let entry: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(entry["client_addr"], "10.0.0.7:4567");
+        assert_eq!(entry["method"], "POST");
+        assert_eq!(entry["target"], "/api/items?page=2");
+        assert_eq!(entry["status"], 201);
+        assert_eq!(entry["route"], "/api/...");
+        assert_eq!(entry["component"], "api");
+        assert_eq!(entry["user_agent"], "test-agent");
+        assert_eq!(entry["referer"], serde_json::Value::Null);
+        assert_eq!(entry["bytes_in"], 7);
+        assert_eq!(entry["bytes_out"], 8);
+        assert!(entry["latency_ms"].as_f64().unwrap() >= 0.0);
+        assert!(entry["time"].is_string());
+    }
+
+    #[tokio::test]
+    async fn failed_requests_are_logged() {
+        let buffer = SharedBuffer::default();
+        let log = Arc::new(AccessLog::new(
+            AccessLogFormat::Common,
+            Box::new(buffer.clone()),
+        ));
+        let req = Request::get("/").body(body::empty()).unwrap();
+        let (_req, entry) = log.start(req, "127.0.0.1:1234".parse().unwrap());
+        entry.finish(Err(anyhow::anyhow!("boom"))).unwrap_err();
+        assert!(buffer.contents().ends_with("\"GET / HTTP/1.1\" 500 -\n"));
+    }
+}
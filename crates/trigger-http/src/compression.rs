@@ -0,0 +1,536 @@
+//! Content-negotiated compression of component responses.
+
+use std::{
+    io::{self, Write},
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use http::{
+    header::{self, HeaderMap, HeaderValue},
+    Method, Request, Response, StatusCode,
+};
+use http_body_util::BodyExt;
+use hyper::body::{Body as HttpBody, Bytes, Frame, SizeHint};
+use spin_http::config::HttpCompressionConfig;
+use wasmtime_wasi_http::bindings::http::types::ErrorCode;
+
+use crate::{headers::append_vary, Body};
+
+/// The compression quality for brotli, trading ratio for speed as responses
+/// are compressed on the fly.
+const BROTLI_QUALITY: u32 = 4;
+/// The brotli window size, as a power of two.
+const BROTLI_WINDOW_BITS: u32 = 22;
+/// The compression level for zstd.
+const ZSTD_LEVEL: i32 = 3;
+
+/// A content coding the server can compress responses with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Encoding {
+    Brotli,
+    Zstd,
+    Gzip,
+}
+
+impl Encoding {
+    /// The encodings in order of preference when the client accepts several equally.
+    const PREFERENCE: [Self; 3] = [Self::Brotli, Self::Zstd, Self::Gzip];
+
+    /// The name of the encoding in `Accept-Encoding` and `Content-Encoding` headers.
+    fn name(self) -> &'static str {
+        match self {
+            Self::Brotli => "br",
+            Self::Zstd => "zstd",
+            Self::Gzip => "gzip",
+        }
+    }
+}
+
+/// Compresses responses for a trigger according to its configuration.
+pub(crate) struct ResponseCompression<'a> {
+    config: &'a HttpCompressionConfig,
+    /// The encoding negotiated with the client, if any.
+    encoding: Option<Encoding>,
+}
+
+impl<'a> ResponseCompression<'a> {
+    /// Negotiates the encoding for the response to the request.
+    pub fn negotiate<B>(config: &'a HttpCompressionConfig, req: &Request<B>) -> Self {
+        let encoding = if req.method() == Method::HEAD {
+            None
+        } else {
+            preferred_encoding(req.headers())
+        };
+        Self { config, encoding }
+    }
+
+    /// Compresses the response if it is eligible and the client accepts a
+    /// supported encoding.
+    pub fn apply(&self, mut res: Response<Body>) -> Response<Body> {
+        if !self.is_eligible(&res) {
+            return res;
+        }
+        // The response depends on `Accept-Encoding` whether or not this client
+        // accepted an encoding.
+        append_vary(res.headers_mut(), "Accept-Encoding");
+        let Some(encoding) = self.encoding else {
+            return res;
+        };
+        let encoder = match Encoder::new(encoding) {
+            Ok(encoder) => encoder,
+            Err(err) => {
+                tracing::warn!("Unable to compress response: {err}");
+                return res;
+            }
+        };
+
+        let (mut parts, body) = res.into_parts();
+        parts.headers.remove(header::CONTENT_LENGTH);
+        parts.headers.insert(
+            header::CONTENT_ENCODING,
+            HeaderValue::from_static(encoding.name()),
+        );
+        // The compressed representation is not byte-for-byte the same as the
+        // original, so a strong validator must be weakened.
+        if let Some(etag) = parts.headers.get(header::ETAG) {
+            if !etag.as_bytes().starts_with(b"W/") {
+                let mut weak = b"W/".to_vec();
+                weak.extend_from_slice(etag.as_bytes());
+                if let Ok(weak) = HeaderValue::from_bytes(&weak) {
+                    parts.headers.insert(header::ETAG, weak);
+                }
+            }
+        }
+        Response::from_parts(parts, CompressedBody::new(body, encoder).boxed())
+    }
+
+    fn is_eligible(&self, res: &Response<Body>) -> bool {
+        let headers = res.headers();
+        let status = res.status();
+        if status.is_informational()
+            || status == StatusCode::NO_CONTENT
+            || status == StatusCode::NOT_MODIFIED
+            || status == StatusCode::PARTIAL_CONTENT
+            || headers.contains_key(header::CONTENT_ENCODING)
+            || headers.contains_key(header::CONTENT_RANGE)
+        {
+            return false;
+        }
+        let no_transform = headers
+            .get_all(header::CACHE_CONTROL)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|directive| directive.trim().eq_ignore_ascii_case("no-transform"));
+        if no_transform {
+            return false;
+        }
+
+        let content_type = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok());
+        let Some(content_type) = content_type else {
+            return false;
+        };
+        if !self
+            .config
+            .content_types
+            .iter()
+            .any(|pattern| media_type_matches(pattern, content_type))
+        {
+            return false;
+        }
+
+        // Streamed bodies of unknown length are compressed regardless of size.
+        let len = headers
+            .get(header::CONTENT_LENGTH)
+            .and_then(|len| len.to_str().ok()?.parse::<u64>().ok())
+            .or_else(|| res.body().size_hint().exact());
+        len.is_none_or(|len| len >= self.config.min_size)
+    }
+}
+
+/// Whether a media type matches a pattern such as `text/*` or `application/*+json`.
+fn media_type_matches(pattern: &str, media_type: &str) -> bool {
+    let essence = media_type.split(';').next().unwrap_or_default().trim();
+    let (Some((pattern_type, pattern_subtype)), Some((type_, subtype))) =
+        (pattern.split_once('/'), essence.split_once('/'))
+    else {
+        return false;
+    };
+    if !pattern_type.eq_ignore_ascii_case(type_) {
+        return false;
+    }
+    match pattern_subtype.strip_prefix('*') {
+        Some("") => true,
+        Some(suffix) => {
+            subtype.len() > suffix.len()
+                && subtype[subtype.len() - suffix.len()..].eq_ignore_ascii_case(suffix)
+        }
+        None => pattern_subtype.eq_ignore_ascii_case(subtype),
+    }
+}
+
+/// The `Accept-Encoding` quality value for an encoding, or `None` if the header
+/// doesn't list the encoding.
+fn encoding_quality(headers: &HeaderMap, encoding: &str) -> Option<f32> {
+    headers
+        .get_all(header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .find_map(|item| {
+            let mut params = item.split(';').map(str::trim);
+            let name = params.next().unwrap_or_default();
+            if !name.eq_ignore_ascii_case(encoding) {
+                return None;
+            }
+            let quality = params
+                .find_map(|param| param.strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.trim().parse::<f32>().ok())?;
+            Some(quality)
+        })
+}
+
+/// Whether the `Accept-Encoding` header explicitly accepts the encoding.
+pub(crate) fn accepts_encoding(headers: &HeaderMap, encoding: &str) -> bool {
+    encoding_quality(headers, encoding).is_some_and(|q| q > 0.0)
+}
+
+/// The supported encoding the client most prefers, if it accepts any.
+fn preferred_encoding(headers: &HeaderMap) -> Option<Encoding> {
+    let mut preferred = None;
+    for encoding in Encoding::PREFERENCE {
+        let quality = encoding_quality(headers, encoding.name())
+            .or_else(|| encoding_quality(headers, "*"))
+            .unwrap_or(0.0);
+        if quality > 0.0 && preferred.is_none_or(|(_, best)| quality > best) {
+            preferred = Some((encoding, quality));
+        }
+    }
+    preferred.map(|(encoding, _)| encoding)
+}
+
+/// Compresses into an in-memory buffer.
+enum Encoder {
+    Brotli(Box<brotli::CompressorWriter<Vec<u8>>>),
+    Zstd(zstd::stream::write::Encoder<'static, Vec<u8>>),
+    Gzip(flate2::write::GzEncoder<Vec<u8>>),
+}
+
+impl Encoder {
+    fn new(encoding: Encoding) -> io::Result<Self> {
+        Ok(match encoding {
+            Encoding::Brotli => Self::Brotli(Box::new(brotli::CompressorWriter::new(
+                Vec::new(),
+                0,
+                BROTLI_QUALITY,
+                BROTLI_WINDOW_BITS,
+            ))),
+            Encoding::Zstd => {
+                Self::Zstd(zstd::stream::write::Encoder::new(Vec::new(), ZSTD_LEVEL)?)
+            }
+            Encoding::Gzip => Self::Gzip(flate2::write::GzEncoder::new(
+                Vec::new(),
+                flate2::Compression::default(),
+            )),
+        })
+    }
+
+    /// Compresses a chunk, flushing it so that streamed responses aren't held
+    /// back waiting for more data.
+    fn encode(&mut self, data: &[u8]) -> io::Result<Bytes> {
+        let writer: &mut dyn Write = match self {
+            Self::Brotli(encoder) => encoder.as_mut(),
+            Self::Zstd(encoder) => encoder,
+            Self::Gzip(encoder) => encoder,
+        };
+        writer.write_all(data)?;
+        writer.flush()?;
+        let output = match self {
+            Self::Brotli(encoder) => encoder.get_mut(),
+            Self::Zstd(encoder) => encoder.get_mut(),
+            Self::Gzip(encoder) => encoder.get_mut(),
+        };
+        Ok(std::mem::take(output).into())
+    }
+
+    /// Finishes the compressed stream.
+    fn finish(self) -> io::Result<Bytes> {
+        Ok(match self {
+            Self::Brotli(encoder) => encoder.into_inner(),
+            Self::Zstd(encoder) => encoder.finish()?,
+            Self::Gzip(encoder) => encoder.finish()?,
+        }
+        .into())
+    }
+}
+
+/// A body compressed as it is sent.
+struct CompressedBody {
+    inner: Body,
+    /// The encoder, until the compressed stream is finished.
+    encoder: Option<Encoder>,
+    /// Trailers to send after the compressed stream is finished.
+    trailers: Option<HeaderMap>,
+}
+
+impl CompressedBody {
+    fn new(inner: Body, encoder: Encoder) -> Self {
+        Self {
+            inner,
+            encoder: Some(encoder),
+            trailers: None,
+        }
+    }
+}
+
+impl HttpBody for CompressedBody {
+    type Data = Bytes;
+    type Error = ErrorCode;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, ErrorCode>>> {
+        let to_error = |e: io::Error| ErrorCode::InternalError(Some(e.to_string()));
+        let Self {
+            inner,
+            encoder,
+            trailers,
+        } = &mut *self;
+        loop {
+            let Some(active) = encoder.as_mut() else {
+                return Poll::Ready(trailers.take().map(|t| Ok(Frame::trailers(t))));
+            };
+            let finished = match ready!(Pin::new(&mut *inner).poll_frame(cx)) {
+                Some(Ok(frame)) => match frame.into_data() {
+                    Ok(data) => {
+                        let compressed = active.encode(&data).map_err(to_error)?;
+                        if !compressed.is_empty() {
+                            return Poll::Ready(Some(Ok(Frame::data(compressed))));
+                        }
+                        continue;
+                    }
+                    Err(frame) => {
+                        *trailers = frame.into_trailers().ok();
+                        encoder.take()
+                    }
+                },
+                Some(Err(err)) => return Poll::Ready(Some(Err(err))),
+                None => encoder.take(),
+            };
+            let compressed = finished.unwrap().finish().map_err(to_error)?; // Safe because the encoder was active
+            return Poll::Ready(Some(Ok(Frame::data(compressed))));
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.encoder.is_none() && self.trailers.is_none()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use spin_http::body;
+
+    use super::*;
+
+    fn request(accept_encoding: &str) -> Request<()> {
+        Request::get("/")
+            .header(header::ACCEPT_ENCODING, accept_encoding)
+            .body(())
+            .unwrap()
+    }
+
+    fn response(content_type: &str, body: &'static str) -> Response<Body> {
+        Response::builder()
+            .header(header::CONTENT_TYPE, content_type)
+            .header(header::CONTENT_LENGTH, body.len())
+            .body(body::full(Bytes::from_static(body.as_bytes())))
+            .unwrap()
+    }
+
+    fn config(min_size: u64) -> HttpCompressionConfig {
+        HttpCompressionConfig {
+            min_size,
+            ..Default::default()
+        }
+    }
+
+    fn decompress(encoding: &str, compressed: &[u8]) -> String {
+        let mut decompressed = String::new();
+        match encoding {
+            "br" => brotli::Decompressor::new(compressed, 4096)
+                .read_to_string(&mut decompressed)
+                .unwrap(),
+            "zstd" => zstd::stream::read::Decoder::new(compressed)
+                .unwrap()
+                .read_to_string(&mut decompressed)
+                .unwrap(),
+            "gzip" => flate2::read::GzDecoder::new(compressed)
+                .read_to_string(&mut decompressed)
+                .unwrap(),
+            other => panic!("unexpected encoding {other}"),
+        };
+        decompressed
+    }
+
+    #[test]
+    fn preferred_encoding_follows_quality_then_server_preference() {
+        let preferred = |accept: &str| preferred_encoding(request(accept).headers());
+        assert_eq!(preferred("gzip, deflate, br, zstd"), Some(Encoding::Brotli));
+        assert_eq!(preferred("gzip, zstd"), Some(Encoding::Zstd));
+        assert_eq!(preferred("gzip;q=1, br;q=0.5"), Some(Encoding::Gzip));
+        assert_eq!(preferred("br;q=0, gzip"), Some(Encoding::Gzip));
+        assert_eq!(preferred("*"), Some(Encoding::Brotli));
+        assert_eq!(preferred("*, br;q=0"), Some(Encoding::Zstd));
+        assert_eq!(preferred("identity"), None);
+        assert_eq!(preferred(""), None);
+    }
+
+    #[test]
+    fn media_types_match_patterns() {
+        assert!(media_type_matches("text/*", "text/html; charset=utf-8"));
+        assert!(media_type_matches("application/json", "Application/JSON"));
+        assert!(media_type_matches(
+            "application/*+json",
+            "application/problem+json"
+        ));
+        assert!(!media_type_matches(
+            "application/*+json",
+            "application/json"
+        ));
+        assert!(!media_type_matches("text/*", "image/png"));
+        assert!(!media_type_matches("application/json", "application/jsonx"));
+    }
+
+    #[tokio::test]
+    async fn eligible_responses_are_compressed_with_each_encoding() {
+        let text = "hello, compressed world! ".repeat(100);
+        let text: &'static str = text.leak();
+        for encoding in ["br", "zstd", "gzip"] {
+            let config = config(0);
+            let compression = ResponseCompression::negotiate(&config, &request(encoding));
+            let mut res = response("text/plain", text);
+            res.headers_mut()
+                .insert(header::ETAG, HeaderValue::from_static("\"v1\""));
+            let res = compression.apply(res);
+
+            assert_eq!(res.headers()[header::CONTENT_ENCODING], encoding);
+            assert_eq!(res.headers()[header::VARY], "Accept-Encoding");
+            assert_eq!(res.headers()[header::ETAG], "W/\"v1\"");
+            assert!(!res.headers().contains_key(header::CONTENT_LENGTH));
+
+            let compressed = res.into_body().collect().await.unwrap().to_bytes();
+            assert!(compressed.len() < text.len());
+            assert_eq!(decompress(encoding, &compressed), text);
+        }
+    }
+
+    #[tokio::test]
+    async fn ineligible_responses_are_unchanged() {
+        let config = config(10);
+        let compression = ResponseCompression::negotiate(&config, &request("gzip"));
+
+        // Too small
+        let res = compression.apply(response("text/plain", "tiny"));
+        assert!(!res.headers().contains_key(header::CONTENT_ENCODING));
+        assert!(!res.headers().contains_key(header::VARY));
+
+        // Not a compressible type
+        let res = compression.apply(response("image/png", "not really a png"));
+        assert!(!res.headers().contains_key(header::CONTENT_ENCODING));
+
+        // Already encoded
+        let mut res = response("text/plain", "already compressed");
+        res.headers_mut()
+            .insert(header::CONTENT_ENCODING, HeaderValue::from_static("br"));
+        let res = compression.apply(res);
+        assert_eq!(res.headers()[header::CONTENT_ENCODING], "br");
+
+        // Partial content
+        let mut res = response("text/plain", "0123456789abcdef");
+        *res.status_mut() = StatusCode::PARTIAL_CONTENT;
+        let res = compression.apply(res);
+        assert!(!res.headers().contains_key(header::CONTENT_ENCODING));
+
+        // Opted out with `no-transform`
+        let mut res = response("text/plain", "please leave me alone");
+        res.headers_mut().insert(
+            header::CACHE_CONTROL,
+            HeaderValue::from_static("public, no-transform"),
+        );
+        let res = compression.apply(res);
+        assert!(!res.headers().contains_key(header::CONTENT_ENCODING));
+    }
+
+    #[tokio::test]
+    async fn eligible_responses_vary_even_when_not_compressed() {
+        let config = config(0);
+        let compression = ResponseCompression::negotiate(&config, &request("identity"));
+        let res = compression.apply(response("text/plain", "hello"));
+        assert!(!res.headers().contains_key(header::CONTENT_ENCODING));
+        assert_eq!(res.headers()[header::VARY], "Accept-Encoding");
+
+        let head = Request::head("/")
+            .header(header::ACCEPT_ENCODING, "gzip")
+            .body(())
+            .unwrap();
+        let compression = ResponseCompression::negotiate(&config, &head);
+        let res = compression.apply(response("text/plain", "hello"));
+        assert!(!res.headers().contains_key(header::CONTENT_ENCODING));
+    }
+
+    #[tokio::test]
+    async fn streamed_bodies_are_compressed_frame_by_frame() {
+        let (tx, rx) = tokio::sync::mpsc::channel::<Result<Frame<Bytes>, ErrorCode>>(4);
+        let stream = futures::stream::unfold(rx, |mut rx| async move {
+            rx.recv().await.map(|frame| (frame, rx))
+        });
+        let res = Response::builder()
+            .header(header::CONTENT_TYPE, "text/event-stream")
+            .body(http_body_util::StreamBody::new(stream).boxed())
+            .unwrap();
+
+        let config = config(1024);
+        let compression = ResponseCompression::negotiate(&config, &request("gzip"));
+        let mut body = compression.apply(res).into_body();
+
+        // Each chunk is sent as soon as it is compressed, without waiting for the
+        // end of the stream.
+        let mut compressed = Vec::new();
+        for event in ["data: one\n\n", "data: two\n\n"] {
+            tx.send(Ok(Frame::data(Bytes::from_static(event.as_bytes()))))
+                .await
+                .unwrap();
+            let frame = body.frame().await.unwrap().unwrap();
+            let data = frame.into_data().unwrap();
+            assert!(!data.is_empty());
+            compressed.extend_from_slice(&data);
+        }
+
+        let mut trailers = HeaderMap::new();
+        trailers.insert("x-done", HeaderValue::from_static("yes"));
+        tx.send(Ok(Frame::trailers(trailers))).await.unwrap();
+        drop(tx);
+
+        let frame = body.frame().await.unwrap().unwrap();
+        compressed.extend_from_slice(&frame.into_data().unwrap());
+        let frame = body.frame().await.unwrap().unwrap();
+        assert_eq!(frame.into_trailers().unwrap()["x-done"], "yes");
+        assert!(body.frame().await.is_none());
+
+        assert_eq!(
+            decompress("gzip", &compressed),
+            "data: one\n\ndata: two\n\n"
+        );
+    }
+}
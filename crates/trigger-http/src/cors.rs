@@ -0,0 +1,394 @@
+//! Enforcement of declarative CORS policies.
+
+use anyhow::{bail, Context};
+use http::{
+    header::{self, HeaderName},
+    Method, Request, Response, StatusCode,
+};
+use spin_http::{body, config::HttpCorsConfig};
+
+use crate::{
+    headers::{append_headers, append_vary},
+    Body,
+};
+
+/// The methods allowed when a policy doesn't list any.
+const DEFAULT_METHODS: [Method; 3] = [Method::GET, Method::HEAD, Method::POST];
+
+/// A CORS policy for a trigger, validated from its configuration.
+#[derive(Clone, Debug)]
+pub(crate) struct CorsPolicy {
+    origins: Allowed<String>,
+    methods: Allowed<Method>,
+    headers: Allowed<HeaderName>,
+    max_age: Option<u64>,
+}
+
+/// The values of a request property a policy allows.
+#[derive(Clone, Debug)]
+enum Allowed<T> {
+    Any,
+    List(Vec<T>),
+}
+
+impl<T> Allowed<T> {
+    fn parse(values: &[String], parse: impl Fn(&str) -> anyhow::Result<T>) -> anyhow::Result<Self> {
+        if values.iter().any(|value| value == "*") {
+            return Ok(Self::Any);
+        }
+        values
+            .iter()
+            .map(|value| parse(value))
+            .collect::<anyhow::Result<_>>()
+            .map(Self::List)
+    }
+}
+
+impl CorsPolicy {
+    /// Validates a CORS configuration.
+    pub fn new(config: &HttpCorsConfig) -> anyhow::Result<Self> {
+        if config.origins.is_empty() {
+            bail!("CORS policy must allow at least one origin");
+        }
+        let origins = Allowed::parse(&config.origins, |origin| {
+            let origin = origin.trim_end_matches('/').to_ascii_lowercase();
+            let valid = origin.split_once("://").is_some_and(|(scheme, authority)| {
+                !scheme.is_empty()
+                    && !authority.contains('/')
+                    && authority.parse::<http::uri::Authority>().is_ok()
+            });
+            if !valid {
+                bail!("invalid CORS origin '{origin}': expected e.g. 'https://example.com'");
+            }
+            Ok(origin)
+        })?;
+        let methods = if config.methods.is_empty() {
+            Allowed::List(DEFAULT_METHODS.to_vec())
+        } else {
+            Allowed::parse(&config.methods, |method| {
+                Method::from_bytes(method.to_ascii_uppercase().as_bytes())
+                    .with_context(|| format!("invalid CORS method '{method}'"))
+            })?
+        };
+        let headers = Allowed::parse(&config.headers, |name| {
+            HeaderName::from_bytes(name.as_bytes())
+                .with_context(|| format!("invalid CORS header '{name}'"))
+        })?;
+        Ok(Self {
+            origins,
+            methods,
+            headers,
+            max_age: config.max_age,
+        })
+    }
+
+    /// Whether the request is a CORS preflight request.
+    pub fn is_preflight<B>(req: &Request<B>) -> bool {
+        req.method() == Method::OPTIONS
+            && req.headers().contains_key(header::ORIGIN)
+            && req
+                .headers()
+                .contains_key(header::ACCESS_CONTROL_REQUEST_METHOD)
+    }
+
+    /// The method the client intends to use, as stated in a preflight request.
+    pub fn requested_method<B>(req: &Request<B>) -> Option<&str> {
+        req.headers()
+            .get(header::ACCESS_CONTROL_REQUEST_METHOD)?
+            .to_str()
+            .ok()
+    }
+
+    /// Answers a preflight request. A preflight the policy doesn't allow is
+    /// rejected without CORS headers, so the client won't send the request.
+    pub fn preflight_response<B>(&self, req: &Request<B>) -> anyhow::Result<Response<Body>> {
+        let mut res = Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .body(body::empty())?;
+        let headers = res.headers_mut();
+        append_vary(headers, "Origin");
+        append_vary(headers, "Access-Control-Request-Method");
+        append_vary(headers, "Access-Control-Request-Headers");
+
+        let method =
+            Self::requested_method(req).and_then(|m| Method::from_bytes(m.as_bytes()).ok());
+        let requested_headers = req
+            .headers()
+            .get_all(header::ACCESS_CONTROL_REQUEST_HEADERS)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(|name| name.trim().to_ascii_lowercase())
+            .filter(|name| !name.is_empty())
+            .collect::<Vec<_>>();
+
+        let method_allowed = method.as_ref().is_some_and(|method| match &self.methods {
+            Allowed::Any => true,
+            Allowed::List(methods) => methods.contains(method),
+        });
+        let headers_allowed = match &self.headers {
+            Allowed::Any => true,
+            Allowed::List(allowed) => requested_headers
+                .iter()
+                .all(|name| allowed.iter().any(|allowed| allowed.as_str() == name)),
+        };
+        let Some(allow_origin) = self
+            .allow_origin(req)
+            .filter(|_| method_allowed && headers_allowed)
+        else {
+            tracing::info!("Rejecting CORS preflight request not allowed by policy");
+            *res.status_mut() = StatusCode::FORBIDDEN;
+            return Ok(res);
+        };
+
+        let allow_methods = match &self.methods {
+            // Echo the method, as clients ignore `*` for credentialed requests.
+            Allowed::Any => method.map(|m| m.to_string()).unwrap_or_default(),
+            Allowed::List(methods) => join(methods.iter().map(Method::as_str)),
+        };
+        let allow_headers = match &self.headers {
+            Allowed::Any => requested_headers.join(", "),
+            Allowed::List(headers) => join(headers.iter().map(HeaderName::as_str)),
+        };
+        let mut cors_headers = vec![
+            (
+                header::ACCESS_CONTROL_ALLOW_ORIGIN.to_string(),
+                allow_origin,
+            ),
+            (
+                header::ACCESS_CONTROL_ALLOW_METHODS.to_string(),
+                allow_methods,
+            ),
+        ];
+        if !allow_headers.is_empty() {
+            cors_headers.push((
+                header::ACCESS_CONTROL_ALLOW_HEADERS.to_string(),
+                allow_headers,
+            ));
+        }
+        if let Some(max_age) = self.max_age {
+            cors_headers.push((
+                header::ACCESS_CONTROL_MAX_AGE.to_string(),
+                max_age.to_string(),
+            ));
+        }
+        append_headers(res.headers_mut(), Some(cors_headers))?;
+        Ok(res)
+    }
+
+    /// Prepares the CORS headers for the response to an actual (non-preflight)
+    /// request.
+    pub fn for_request<B>(&self, req: &Request<B>) -> CorsHeaders<'_> {
+        CorsHeaders {
+            policy: self,
+            allow_origin: self.allow_origin(req),
+        }
+    }
+
+    /// The value of `Access-Control-Allow-Origin` for the request, if its
+    /// origin is allowed.
+    fn allow_origin<B>(&self, req: &Request<B>) -> Option<String> {
+        let origin = req.headers().get(header::ORIGIN)?.to_str().ok()?;
+        match &self.origins {
+            Allowed::Any => Some("*".to_owned()),
+            Allowed::List(origins) => origins
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(origin))
+                .then(|| origin.to_owned()),
+        }
+    }
+}
+
+/// The CORS headers for the response to a request.
+pub(crate) struct CorsHeaders<'a> {
+    policy: &'a CorsPolicy,
+    /// The value of `Access-Control-Allow-Origin`, if the request was a
+    /// cross-origin request the policy allows.
+    allow_origin: Option<String>,
+}
+
+impl CorsHeaders<'_> {
+    /// Adds the CORS headers to the response, replacing any set by the component.
+    pub fn apply(self, res: &mut Response<Body>) -> anyhow::Result<()> {
+        if let Allowed::List(_) = self.policy.origins {
+            // The response depends on the origin whether or not it was allowed.
+            append_vary(res.headers_mut(), "Origin");
+        }
+        let cors_headers = self.allow_origin.map(|allow_origin| {
+            vec![(
+                header::ACCESS_CONTROL_ALLOW_ORIGIN.to_string(),
+                allow_origin,
+            )]
+        });
+        append_headers(res.headers_mut(), cors_headers)
+    }
+}
+
+fn join<'a>(values: impl Iterator<Item = &'a str>) -> String {
+    values.collect::<Vec<_>>().join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(origins: &[&str], methods: &[&str], headers: &[&str]) -> CorsPolicy {
+        let strings = |values: &[&str]| values.iter().map(|v| v.to_string()).collect();
+        CorsPolicy::new(&HttpCorsConfig {
+            origins: strings(origins),
+            methods: strings(methods),
+            headers: strings(headers),
+            max_age: Some(600),
+        })
+        .unwrap()
+    }
+
+    fn preflight(origin: &str, method: &str, headers: &str) -> Request<()> {
+        let mut req = Request::options("/api")
+            .header(header::ORIGIN, origin)
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, method);
+        if !headers.is_empty() {
+            req = req.header(header::ACCESS_CONTROL_REQUEST_HEADERS, headers);
+        }
+        req.body(()).unwrap()
+    }
+
+    #[test]
+    fn invalid_policies_are_rejected() {
+        let config = |origins: &[&str], methods: &[&str]| HttpCorsConfig {
+            origins: origins.iter().map(|o| o.to_string()).collect(),
+            methods: methods.iter().map(|m| m.to_string()).collect(),
+            ..Default::default()
+        };
+        CorsPolicy::new(&config(&[], &[])).unwrap_err();
+        CorsPolicy::new(&config(&["example.com"], &[])).unwrap_err();
+        CorsPolicy::new(&config(&["https://example.com/path"], &[])).unwrap_err();
+        CorsPolicy::new(&config(&["https://example.com"], &["NOT A METHOD"])).unwrap_err();
+        CorsPolicy::new(&config(&["https://example.com/"], &["get"])).unwrap();
+        CorsPolicy::new(&config(&["*"], &[])).unwrap();
+    }
+
+    #[test]
+    fn preflights_are_recognized() {
+        assert!(CorsPolicy::is_preflight(&preflight(
+            "https://example.com",
+            "PUT",
+            ""
+        )));
+        let options = Request::options("/api")
+            .header(header::ORIGIN, "https://example.com")
+            .body(())
+            .unwrap();
+        assert!(!CorsPolicy::is_preflight(&options));
+    }
+
+    #[test]
+    fn allowed_preflight_is_answered() {
+        let policy = policy(
+            &["https://Example.com/"],
+            &["get", "put"],
+            &["Content-Type", "x-api-key"],
+        );
+        let res = policy
+            .preflight_response(&preflight(
+                "https://example.com",
+                "PUT",
+                "content-type, X-Api-Key",
+            ))
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        let headers = res.headers();
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://example.com"
+        );
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_METHODS], "GET, PUT");
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_HEADERS],
+            "content-type, x-api-key"
+        );
+        assert_eq!(headers[header::ACCESS_CONTROL_MAX_AGE], "600");
+        let vary = headers
+            .get_all(header::VARY)
+            .iter()
+            .map(|v| v.to_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vary,
+            [
+                "Origin",
+                "Access-Control-Request-Method",
+                "Access-Control-Request-Headers"
+            ]
+        );
+    }
+
+    #[test]
+    fn disallowed_preflight_is_rejected() {
+        let policy = policy(&["https://example.com"], &[], &["content-type"]);
+        for req in [
+            preflight("https://evil.example", "GET", ""),
+            preflight("https://example.com", "DELETE", ""),
+            preflight("https://example.com", "POST", "content-type, x-secret"),
+        ] {
+            let res = policy.preflight_response(&req).unwrap();
+            assert_eq!(res.status(), StatusCode::FORBIDDEN);
+            assert!(!res
+                .headers()
+                .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+        }
+    }
+
+    #[test]
+    fn wildcards_allow_anything_requested() {
+        let policy = policy(&["*"], &["*"], &["*"]);
+        let res = policy
+            .preflight_response(&preflight("https://any.example", "PURGE", "x-custom"))
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        let headers = res.headers();
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_METHODS], "PURGE");
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_HEADERS], "x-custom");
+    }
+
+    #[test]
+    fn actual_responses_get_cors_headers() {
+        let policy = policy(&["https://example.com"], &[], &[]);
+        let response = || {
+            Response::builder()
+                .header(
+                    header::ACCESS_CONTROL_ALLOW_ORIGIN,
+                    "https://set-by-component",
+                )
+                .body(body::empty())
+                .unwrap()
+        };
+
+        let req = Request::get("/api")
+            .header(header::ORIGIN, "https://example.com")
+            .body(())
+            .unwrap();
+        let mut res = response();
+        policy.for_request(&req).apply(&mut res).unwrap();
+        assert_eq!(
+            res.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://example.com"
+        );
+        assert_eq!(res.headers()[header::VARY], "Origin");
+
+        // A disallowed origin gets no CORS headers, but the response still varies by origin
+        let req = Request::get("/api")
+            .header(header::ORIGIN, "https://evil.example")
+            .body(())
+            .unwrap();
+        let mut res = response();
+        res.headers_mut()
+            .remove(header::ACCESS_CONTROL_ALLOW_ORIGIN);
+        policy.for_request(&req).apply(&mut res).unwrap();
+        assert!(!res
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+        assert_eq!(res.headers()[header::VARY], "Origin");
+    }
+}
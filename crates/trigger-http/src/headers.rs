@@ -10,7 +10,7 @@ use hyper::Request;
 use spin_factor_outbound_networking::config::allowed_hosts::is_service_chaining_host;
 use spin_http::routes::RouteMatch;
 
-use crate::Body;
+use crate::{tls::ClientCertificate, Body};
 
 // We need to make the following pieces of information available to both executors.
 // While the values we set are identical, the way they are passed to the
@@ -24,6 +24,7 @@ pub const COMPONENT_ROUTE: [&str; 2] = ["SPIN_COMPONENT_ROUTE", "X_COMPONENT_ROU
 pub const RAW_COMPONENT_ROUTE: [&str; 2] = ["SPIN_RAW_COMPONENT_ROUTE", "X_RAW_COMPONENT_ROUTE"];
 pub const BASE_PATH: [&str; 2] = ["SPIN_BASE_PATH", "X_BASE_PATH"];
 pub const CLIENT_ADDR: [&str; 2] = ["SPIN_CLIENT_ADDR", "X_CLIENT_ADDR"];
+pub const CLIENT_CERT_SUBJECT: [&str; 2] = ["SPIN_CLIENT_CERT_SUBJECT", "X_CLIENT_CERT_SUBJECT"];
 
 // Header key/value pairs that use copy on write to avoid allocation
 pub type HeaderPair<'a> = ([Cow<'static, str>; 2], Cow<'a, str>);
@@ -34,6 +35,7 @@ pub fn compute_default_headers<'a>(
     host: &str,
     route_match: &'a RouteMatch,
     client_addr: SocketAddr,
+    client_cert: Option<&'a ClientCertificate>,
 ) -> anyhow::Result<Vec<HeaderPair<'a>>> {
     fn owned(strs: &[&'static str; 2]) -> [Cow<'static, str>; 2] {
         [strs[0].into(), strs[1].into()]
@@ -70,6 +72,12 @@ pub fn compute_default_headers<'a>(
         route_match.raw_route_or_prefix().into(),
     ));
     res.push((owned_client_addr, client_addr.to_string().into()));
+    if let Some(client_cert) = client_cert {
+        res.push((
+            owned(&CLIENT_CERT_SUBJECT),
+            client_cert.subject.as_str().into(),
+        ));
+    }
 
     for (wild_name, wild_value) in route_match.named_wildcards() {
         let wild_header = format!("SPIN_PATH_MATCH_{}", wild_name.to_ascii_uppercase()).into();
@@ -96,10 +104,14 @@ pub fn prepare_request_headers(
     route_match: &RouteMatch,
     client_addr: SocketAddr,
 ) -> Result<Vec<(String, String)>> {
+    // Only Spin may set the client certificate header, so drop any sent by the client.
+    let client_cert_header = prepare_header_key(CLIENT_CERT_SUBJECT[0]);
+
     let mut res = Vec::new();
     for (name, value) in req
         .headers()
         .iter()
+        .filter(|(name, _)| name.as_str() != client_cert_header)
         .map(|(name, value)| (name.to_string(), std::str::from_utf8(value.as_bytes())))
     {
         let value = value?.to_string();
@@ -117,7 +129,10 @@ pub fn prepare_request_headers(
     // Set the environment information (path info, base path, etc) as headers.
     // In the future, we might want to have this information in a context
     // object as opposed to headers.
-    for (keys, val) in compute_default_headers(req.uri(), host, route_match, client_addr)? {
+    let client_cert = req.extensions().get::<ClientCertificate>();
+    for (keys, val) in
+        compute_default_headers(req.uri(), host, route_match, client_addr, client_cert)?
+    {
         res.push((prepare_header_key(&keys[0]), val.into_owned()));
     }
 
@@ -140,6 +155,25 @@ pub fn append_headers(
     Ok(())
 }
 
+/// Adds `name` to the response's `Vary` header unless it is already listed.
+pub fn append_vary(map: &mut http::HeaderMap, name: &'static str) {
+    let varies = map
+        .get_all(http::header::VARY)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|listed| {
+            let listed = listed.trim();
+            listed == "*" || listed.eq_ignore_ascii_case(name)
+        });
+    if !varies {
+        map.append(
+            http::header::VARY,
+            http::header::HeaderValue::from_static(name),
+        );
+    }
+}
+
 fn prepare_header_key(key: &str) -> String {
     key.replace('_', "-").to_ascii_lowercase()
 }
@@ -190,7 +224,8 @@ mod tests {
         let router = Router::build("/", [("DUMMY", &trigger_route.into())], None)?;
         let route_match = router.route("/foo/bar")?;
 
-        let default_headers = compute_default_headers(req.uri(), host, &route_match, client_addr)?;
+        let default_headers =
+            compute_default_headers(req.uri(), host, &route_match, client_addr, None)?;
 
         assert_eq!(
             search(&FULL_URL, &default_headers).unwrap(),
@@ -220,6 +255,36 @@ mod tests {
             search(&CLIENT_ADDR, &default_headers).unwrap(),
             "127.0.0.1:8777".to_string()
         );
+        assert_eq!(search(&CLIENT_CERT_SUBJECT, &default_headers), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_client_cert_subject_header() -> Result<()> {
+        let client_addr: SocketAddr = "127.0.0.1:8777".parse().unwrap();
+        let router = Router::build("/", [("DUMMY", &"/...".into())], None)?;
+        let route_match = router.route("/foo")?;
+
+        let mut req = http::Request::builder()
+            .uri("https://fermyon.dev/foo")
+            .header("spin-client-cert-subject", "CN=spoofed")
+            .body(spin_http::body::empty())?;
+        let headers = prepare_request_headers(&req, &route_match, client_addr)?;
+        assert!(!headers
+            .iter()
+            .any(|(name, _)| name == "spin-client-cert-subject"));
+
+        req.extensions_mut().insert(ClientCertificate {
+            subject: "CN=client".to_string(),
+        });
+        let headers = prepare_request_headers(&req, &route_match, client_addr)?;
+        let subjects: Vec<_> = headers
+            .iter()
+            .filter(|(name, _)| name == "spin-client-cert-subject")
+            .map(|(_, value)| value.as_str())
+            .collect();
+        assert_eq!(subjects, ["CN=client"]);
 
         Ok(())
     }
@@ -246,7 +311,8 @@ mod tests {
         let router = Router::build("/", [("DUMMY", &trigger_route.into())], None)?;
         let route_match = router.route("/foo/42/bar")?;
 
-        let default_headers = compute_default_headers(req.uri(), host, &route_match, client_addr)?;
+        let default_headers =
+            compute_default_headers(req.uri(), host, &route_match, client_addr, None)?;
 
         assert_eq!(
             search(&FULL_URL, &default_headers).unwrap(),
@@ -289,6 +355,36 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_default_headers_with_constrained_named_wildcards() -> Result<()> {
+        let host = "fermyon.dev";
+        let client_addr: SocketAddr = "127.0.0.1:8777".parse().unwrap();
+        let req = http::Request::builder()
+            .uri("https://fermyon.dev/foo/42/bar")
+            .body("")?;
+
+        let router = Router::build("/", [("DUMMY", &"/foo/:userid<int>/...".into())], None)?;
+        let route_match = router.route("/foo/42/bar")?;
+
+        let default_headers =
+            compute_default_headers(req.uri(), host, &route_match, client_addr, None)?;
+
+        assert_eq!(
+            search(&RAW_COMPONENT_ROUTE, &default_headers).unwrap(),
+            "/foo/:userid<int>/...".to_string()
+        );
+        assert_eq!(
+            search(
+                &["SPIN_PATH_MATCH_USERID", "X_PATH_MATCH_USERID"],
+                &default_headers
+            )
+            .unwrap(),
+            "42".to_string()
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn forbidden_headers_are_removed() {
         let mut req = Request::get("http://test.spin.internal")
@@ -94,3 +94,18 @@ impl MatchedRoute {
         resp
     }
 }
+
+/// MatchedComponent is used as a response extension to track the component which handled a
+/// request, for access logs.
+#[derive(Clone)]
+pub struct MatchedComponent {
+    pub component_id: String,
+}
+
+impl MatchedComponent {
+    pub fn set_response_extension(resp: &mut Response<Body>, component_id: impl Into<String>) {
+        resp.extensions_mut().insert(MatchedComponent {
+            component_id: component_id.into(),
+        });
+    }
+}
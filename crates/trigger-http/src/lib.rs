@@ -1,32 +1,45 @@
 //! Implementation for the Spin HTTP engine.
 
+mod access_log;
+mod compression;
+mod cors;
 mod headers;
 mod instrument;
+mod limits;
 mod outbound_http;
+mod protocol;
 mod server;
 mod spin;
+mod static_files;
+mod throttle;
 mod tls;
 mod wagi;
 mod wasi;
 
 use std::{
+    collections::HashSet,
     error::Error,
     net::{Ipv4Addr, SocketAddr, ToSocketAddrs},
     path::PathBuf,
     sync::Arc,
+    time::Duration,
 };
 
 use anyhow::{bail, Context};
-use clap::Args;
+use clap::{Args, ValueEnum};
 use serde::Deserialize;
-use spin_app::App;
 use spin_factors::RuntimeFactors;
-use spin_trigger::Trigger;
+use spin_http::config::{HttpCorsConfig, HttpExecutorType, HttpTriggerConfig};
+use spin_trigger::{cli::LogConfig, ShutdownToken, Trigger};
 use wasmtime_wasi_http::bindings::http::types::ErrorCode;
 
+use access_log::AccessLog;
+pub use access_log::AccessLogFormat;
+pub use limits::HttpLimits;
+pub use protocol::{HttpProtocols, HttpVersion};
 pub use server::HttpServer;
 
-pub use tls::TlsConfig;
+pub use tls::{ClientAuthConfig, TlsConfig};
 
 pub(crate) use wasmtime_wasi_http::body::HyperIncomingBody as Body;
 
@@ -51,21 +64,125 @@ pub struct CliArgs {
     #[clap(long, env = "SPIN_TLS_KEY", requires = "tls-cert")]
     pub tls_key: Option<PathBuf>,
 
+    /// A directory of certificates to use for https, selected by the client's SNI hostname.
+    /// Each certificate `<hostname>.crt` is paired with the key `<hostname>.key`, and a certificate
+    /// `_wildcard.<domain>.crt` matches any subdomain. Requests matching no hostname use `--tls-cert`.
+    /// Certificates are reloaded when they change on disk
+    #[clap(long, env = "SPIN_TLS_CERT_DIR")]
+    pub tls_cert_dir: Option<PathBuf>,
+
+    /// The path to the CA certificates, in PEM format, used to verify client certificates. The
+    /// verified certificate's subject is passed to components in the `spin-client-cert-subject`
+    /// header
+    #[clap(long, env = "SPIN_TLS_CLIENT_CA")]
+    pub tls_client_ca: Option<PathBuf>,
+
+    /// Whether clients must present a certificate signed by `--tls-client-ca`. Defaults to
+    /// `required`
+    #[clap(
+        long,
+        env = "SPIN_TLS_CLIENT_AUTH",
+        value_enum,
+        requires = "tls-client-ca"
+    )]
+    pub tls_client_auth: Option<ClientAuthMode>,
+
     #[clap(long = "find-free-port")]
     pub find_free_port: bool,
+
+    /// The HTTP versions to serve. `auto` serves HTTP/1.1 and HTTP/2, `http1` only HTTP/1.1 and
+    /// `http2` only HTTP/2. Over cleartext, HTTP/2 clients must use prior knowledge (h2c); over
+    /// TLS, the version is negotiated with ALPN
+    #[clap(long, env = "SPIN_HTTP_PROTOCOL", value_enum, default_value = "auto")]
+    pub http_protocol: HttpVersion,
+
+    /// Also serve HTTP/3 over QUIC, on the same port as HTTPS. Requires TLS
+    #[cfg(feature = "http3")]
+    #[clap(long, env = "SPIN_EXPERIMENTAL_HTTP3")]
+    pub experimental_http3: bool,
+
+    /// The maximum size of a request body in bytes. Larger requests receive a 413 response.
+    /// Overrides `max_request_body_bytes` in the manifest's `[application.trigger.http]`
+    #[clap(long, env = "SPIN_HTTP_MAX_REQUEST_BODY_BYTES")]
+    pub max_request_body_bytes: Option<u64>,
+
+    /// The maximum total size of request header names and values in bytes. Larger requests
+    /// receive a 431 response. Overrides `max_request_header_bytes` in the manifest's
+    /// `[application.trigger.http]`
+    #[clap(long, env = "SPIN_HTTP_MAX_REQUEST_HEADER_BYTES")]
+    pub max_request_header_bytes: Option<usize>,
+
+    /// The maximum time in seconds a component may take to start responding to a request.
+    /// Slower requests receive a 504 response. Overrides `request_timeout` in the manifest's
+    /// `[application.trigger.http]`
+    #[clap(long, env = "SPIN_HTTP_REQUEST_TIMEOUT", value_parser = limits::parse_seconds)]
+    pub request_timeout: Option<Duration>,
+
+    /// The time in seconds after which a connection with no request in progress is closed.
+    /// Overrides `idle_timeout` in the manifest's `[application.trigger.http]`
+    #[clap(long, env = "SPIN_HTTP_IDLE_TIMEOUT", value_parser = limits::parse_seconds)]
+    pub idle_timeout: Option<Duration>,
+
+    /// Log each request in the given format to `http_access.log` in the log directory, or to
+    /// stderr if logging to disk is disabled
+    #[clap(long, env = "SPIN_HTTP_ACCESS_LOG", value_enum)]
+    pub access_log: Option<AccessLogFormat>,
 }
 
 impl CliArgs {
-    fn into_tls_config(self) -> Option<TlsConfig> {
-        match (self.tls_cert, self.tls_key) {
-            (Some(cert_path), Some(key_path)) => Some(TlsConfig {
-                cert_path,
-                key_path,
-            }),
-            (None, None) => None,
-            _ => unreachable!(),
+    fn protocols(&self) -> HttpProtocols {
+        HttpProtocols {
+            version: self.http_protocol,
+            #[cfg(feature = "http3")]
+            http3: self.experimental_http3,
+        }
+    }
+
+    fn limits(&self) -> HttpLimits {
+        HttpLimits {
+            max_request_body_bytes: self.max_request_body_bytes,
+            max_request_header_bytes: self.max_request_header_bytes,
+            request_timeout: self.request_timeout,
+            idle_timeout: self.idle_timeout,
         }
     }
+
+    fn into_tls_config(self) -> anyhow::Result<Option<TlsConfig>> {
+        let client_auth = self.tls_client_ca.map(|ca_path| ClientAuthConfig {
+            ca_path,
+            required: self.tls_client_auth != Some(ClientAuthMode::Optional),
+        });
+        let config = match (self.tls_cert, self.tls_key, self.tls_cert_dir) {
+            (None, None, None) => {
+                if client_auth.is_some() {
+                    bail!("--tls-client-ca requires a TLS certificate");
+                }
+                return Ok(None);
+            }
+            (Some(cert_path), Some(key_path), cert_dir) => {
+                let config = TlsConfig::new(cert_path, key_path);
+                match cert_dir {
+                    Some(cert_dir) => config.with_cert_dir(cert_dir),
+                    None => config,
+                }
+            }
+            (None, None, Some(cert_dir)) => TlsConfig::from_cert_dir(cert_dir),
+            _ => bail!("--tls-cert and --tls-key must be provided together"),
+        };
+        Ok(Some(match client_auth {
+            Some(client_auth) => config.with_client_auth(client_auth),
+            None => config,
+        }))
+    }
+}
+
+/// Whether clients must present a certificate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ClientAuthMode {
+    /// Clients may present a certificate, which is verified if present.
+    Optional,
+    /// Clients must present a valid certificate.
+    Required,
 }
 
 /// The Spin HTTP trigger.
@@ -77,6 +194,49 @@ pub struct HttpTrigger {
     listen_addr: SocketAddr,
     tls_config: Option<TlsConfig>,
     find_free_port: bool,
+    limits: HttpLimits,
+    protocols: HttpProtocols,
+    /// The app-wide CORS policy.
+    cors: Option<HttpCorsConfig>,
+    /// The format of the access log, if enabled.
+    access_log: Option<AccessLogFormat>,
+    /// Where and how logs are written.
+    logs: LogConfig,
+    shutdown: ShutdownToken,
+}
+
+/// The HTTP trigger's global configuration, from `[application.trigger.http]`.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TriggerMetadata {
+    base: Option<String>,
+    max_request_body_bytes: Option<u64>,
+    max_request_header_bytes: Option<usize>,
+    /// In seconds.
+    request_timeout: Option<f64>,
+    /// In seconds.
+    idle_timeout: Option<f64>,
+    /// The CORS policy for triggers which don't set their own.
+    cors: Option<HttpCorsConfig>,
+}
+
+impl TriggerMetadata {
+    fn limits(&self) -> anyhow::Result<HttpLimits> {
+        Ok(HttpLimits {
+            max_request_body_bytes: self.max_request_body_bytes,
+            max_request_header_bytes: self.max_request_header_bytes,
+            request_timeout: self
+                .request_timeout
+                .map(limits::seconds)
+                .transpose()
+                .context("invalid `request_timeout` in `[application.trigger.http]`")?,
+            idle_timeout: self
+                .idle_timeout
+                .map(limits::seconds)
+                .transpose()
+                .context("invalid `idle_timeout` in `[application.trigger.http]`")?,
+        })
+    }
 }
 
 impl<F: RuntimeFactors> Trigger<F> for HttpTrigger {
@@ -87,13 +247,49 @@ impl<F: RuntimeFactors> Trigger<F> for HttpTrigger {
 
     fn new(cli_args: Self::CliArgs, app: &spin_app::App) -> anyhow::Result<Self> {
         let find_free_port = cli_args.find_free_port;
+        let address = cli_args.address;
+        let limits = cli_args.limits();
+        let protocols = cli_args.protocols();
+        let access_log = cli_args.access_log;
+        let tls_config = cli_args.into_tls_config()?;
+
+        #[cfg(feature = "http3")]
+        if protocols.http3 && tls_config.is_none() {
+            bail!("--experimental-http3 requires a TLS certificate");
+        }
 
-        Self::new(
-            app,
-            cli_args.address,
-            cli_args.into_tls_config(),
-            find_free_port,
-        )
+        let mut trigger = Self::new(app, address, tls_config, find_free_port)?;
+        trigger.limits = limits.or(trigger.limits);
+        trigger.protocols = protocols;
+        trigger.access_log = access_log;
+        Ok(trigger)
+    }
+
+    fn configure_logs(&mut self, logs: &LogConfig) -> anyhow::Result<()> {
+        self.logs = logs.clone();
+        Ok(())
+    }
+
+    fn enable_graceful_shutdown(&mut self, token: ShutdownToken) -> bool {
+        self.shutdown = token;
+        true
+    }
+
+    /// Components whose every route is served by the static file executor.
+    fn components_not_run(&self, app: &spin_app::App) -> anyhow::Result<Vec<String>> {
+        let mut static_components = HashSet::new();
+        let mut run_components = HashSet::new();
+        for (_, config) in app.trigger_configs::<HttpTriggerConfig>("http")? {
+            if matches!(config.executor, Some(HttpExecutorType::Static(_))) {
+                static_components.insert(config.component);
+            } else {
+                run_components.insert(config.component);
+            }
+        }
+        Ok(static_components
+            .difference(&run_components)
+            .cloned()
+            .collect())
     }
 
     async fn run(self, trigger_app: TriggerApp<F>) -> anyhow::Result<()> {
@@ -117,12 +313,21 @@ impl HttpTrigger {
         tls_config: Option<TlsConfig>,
         find_free_port: bool,
     ) -> anyhow::Result<Self> {
-        Self::validate_app(app)?;
+        let metadata = app
+            .get_trigger_metadata::<TriggerMetadata>("http")?
+            .unwrap_or_default();
+        Self::validate_metadata(&metadata)?;
 
         Ok(Self {
             listen_addr,
             tls_config,
             find_free_port,
+            limits: metadata.limits()?,
+            protocols: HttpProtocols::default(),
+            cors: metadata.cors,
+            access_log: None,
+            logs: LogConfig::default(),
+            shutdown: ShutdownToken::new(),
         })
     }
 
@@ -135,23 +340,31 @@ impl HttpTrigger {
             listen_addr,
             tls_config,
             find_free_port,
+            limits,
+            protocols,
+            cors,
+            access_log,
+            logs,
+            shutdown,
         } = self;
-        let server = Arc::new(HttpServer::new(
+        let mut server = HttpServer::new(
             listen_addr,
             tls_config,
             find_free_port,
+            limits,
+            protocols,
+            cors,
+            shutdown,
             trigger_app,
-        )?);
-        Ok(server)
+        )?;
+        if let Some(format) = access_log {
+            server.set_access_log(AccessLog::open(format, &logs)?);
+        }
+        Ok(Arc::new(server))
     }
 
-    fn validate_app(app: &App) -> anyhow::Result<()> {
-        #[derive(Deserialize)]
-        #[serde(deny_unknown_fields)]
-        struct TriggerMetadata {
-            base: Option<String>,
-        }
-        if let Some(TriggerMetadata { base: Some(base) }) = app.get_trigger_metadata("http")? {
+    fn validate_metadata(metadata: &TriggerMetadata) -> anyhow::Result<()> {
+        if let Some(base) = &metadata.base {
             if base == "/" {
                 tracing::warn!("This application has the deprecated trigger 'base' set to the default value '/'. This may be an error in the future!");
             } else {
@@ -0,0 +1,237 @@
+use std::{
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use anyhow::{bail, Context as _};
+use http::{HeaderMap, Response, StatusCode};
+use hyper::body::{Body as HttpBody, Bytes, Frame, SizeHint};
+use spin_http::body;
+use wasmtime_wasi_http::bindings::http::types::ErrorCode;
+
+use crate::Body;
+
+/// Limits applied to requests received by the HTTP trigger.
+///
+/// Each limit is unset by default.
+#[derive(Clone, Debug, Default)]
+pub struct HttpLimits {
+    /// The maximum size of a request body, in bytes.
+    pub max_request_body_bytes: Option<u64>,
+    /// The maximum total size of a request's header names and values, in bytes.
+    pub max_request_header_bytes: Option<usize>,
+    /// The maximum time a handler may take to start responding to a request.
+    pub request_timeout: Option<Duration>,
+    /// How long a connection may stay open with no request in progress.
+    pub idle_timeout: Option<Duration>,
+}
+
+impl HttpLimits {
+    /// Returns these limits, using `fallback` for any that are unset.
+    pub fn or(self, fallback: Self) -> Self {
+        Self {
+            max_request_body_bytes: self
+                .max_request_body_bytes
+                .or(fallback.max_request_body_bytes),
+            max_request_header_bytes: self
+                .max_request_header_bytes
+                .or(fallback.max_request_header_bytes),
+            request_timeout: self.request_timeout.or(fallback.request_timeout),
+            idle_timeout: self.idle_timeout.or(fallback.idle_timeout),
+        }
+    }
+
+    /// Checks the request head against the size limits, returning the reason it
+    /// must be rejected, if any.
+    pub(crate) fn check_head(&self, headers: &HeaderMap) -> Option<LimitExceeded> {
+        if let Some(max) = self.max_request_header_bytes {
+            if header_bytes(headers) > max {
+                return Some(LimitExceeded::HeaderSize);
+            }
+        }
+        if let Some(max) = self.max_request_body_bytes {
+            let content_length = headers
+                .get(http::header::CONTENT_LENGTH)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse::<u64>().ok());
+            if content_length.is_some_and(|len| len > max) {
+                return Some(LimitExceeded::BodySize);
+            }
+        }
+        None
+    }
+}
+
+/// The reason a request was rejected for exceeding an [`HttpLimits`] limit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum LimitExceeded {
+    BodySize,
+    HeaderSize,
+    Timeout,
+}
+
+impl LimitExceeded {
+    /// The value recorded as the request span's `error.type`.
+    pub fn error_type(self) -> &'static str {
+        match self {
+            Self::BodySize => "request_body_too_large",
+            Self::HeaderSize => "request_header_too_large",
+            Self::Timeout => "request_timeout",
+        }
+    }
+
+    fn status(self) -> StatusCode {
+        match self {
+            Self::BodySize => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::HeaderSize => StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+            Self::Timeout => StatusCode::GATEWAY_TIMEOUT,
+        }
+    }
+
+    /// Creates the response for a rejected request.
+    pub fn response(self) -> anyhow::Result<Response<Body>> {
+        Ok(Response::builder()
+            .status(self.status())
+            .body(body::empty())?)
+    }
+}
+
+/// The total size of the header names and values.
+fn header_bytes(headers: &HeaderMap) -> usize {
+    headers
+        .iter()
+        .map(|(name, value)| name.as_str().len() + value.len())
+        .sum()
+}
+
+/// A request body which fails once more than a maximum number of bytes have been read.
+///
+/// The flag returned by [`LimitedBody::new`] is set if the limit was exceeded, so that
+/// the server can respond with a 413 whatever the handler made of the error.
+pub(crate) struct LimitedBody {
+    inner: Body,
+    remaining: u64,
+    max: u64,
+    exceeded: Arc<AtomicBool>,
+}
+
+impl LimitedBody {
+    pub fn new(inner: Body, max: u64) -> (Self, Arc<AtomicBool>) {
+        let exceeded = Arc::new(AtomicBool::new(false));
+        let body = Self {
+            inner,
+            remaining: max,
+            max,
+            exceeded: exceeded.clone(),
+        };
+        (body, exceeded)
+    }
+}
+
+impl HttpBody for LimitedBody {
+    type Data = Bytes;
+    type Error = ErrorCode;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, ErrorCode>>> {
+        let frame = match Pin::new(&mut self.inner).poll_frame(cx) {
+            Poll::Ready(Some(Ok(frame))) => frame,
+            other => return other,
+        };
+        if let Some(data) = frame.data_ref() {
+            let len = data.len() as u64;
+            if len > self.remaining {
+                self.exceeded.store(true, Ordering::Relaxed);
+                return Poll::Ready(Some(Err(ErrorCode::HttpRequestBodySize(Some(self.max)))));
+            }
+            self.remaining -= len;
+        }
+        Poll::Ready(Some(Ok(frame)))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        let mut hint = self.inner.size_hint();
+        if hint.lower() > self.remaining {
+            return hint;
+        }
+        if hint.upper().is_none_or(|upper| upper > self.remaining) {
+            hint.set_upper(self.remaining);
+        }
+        hint
+    }
+}
+
+/// Parses a number of seconds, which may be fractional, into a [`Duration`].
+pub(crate) fn parse_seconds(secs: &str) -> anyhow::Result<Duration> {
+    let secs: f64 = secs
+        .parse()
+        .with_context(|| format!("invalid number of seconds {secs:?}"))?;
+    seconds(secs)
+}
+
+/// Converts a number of seconds, which may be fractional, into a [`Duration`].
+pub(crate) fn seconds(secs: f64) -> anyhow::Result<Duration> {
+    if secs <= 0.0 {
+        bail!("duration must be greater than zero, got {secs}");
+    }
+    Duration::try_from_secs_f64(secs).with_context(|| format!("invalid duration {secs}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use http_body_util::BodyExt;
+
+    use super::*;
+
+    #[test]
+    fn check_head_enforces_header_and_declared_body_size() {
+        let limits = HttpLimits {
+            max_request_body_bytes: Some(10),
+            max_request_header_bytes: Some(32),
+            ..Default::default()
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert("content-length", "10".parse().unwrap());
+        assert_eq!(limits.check_head(&headers), None);
+
+        headers.insert("content-length", "11".parse().unwrap());
+        assert_eq!(limits.check_head(&headers), Some(LimitExceeded::BodySize));
+
+        headers.insert("content-length", "1".parse().unwrap());
+        headers.insert("x-long", "a".repeat(20).parse().unwrap());
+        assert_eq!(limits.check_head(&headers), Some(LimitExceeded::HeaderSize));
+    }
+
+    #[tokio::test]
+    async fn limited_body_fails_past_the_limit() {
+        let (body, exceeded) = LimitedBody::new(body::full(Bytes::from_static(b"12345")), 5);
+        assert_eq!(body.collect().await.unwrap().to_bytes().as_ref(), b"12345");
+        assert!(!exceeded.load(Ordering::Relaxed));
+
+        let (body, exceeded) = LimitedBody::new(body::full(Bytes::from_static(b"123456")), 5);
+        assert!(matches!(
+            body.collect().await,
+            Err(ErrorCode::HttpRequestBodySize(Some(5)))
+        ));
+        assert!(exceeded.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn parses_seconds() {
+        assert_eq!(parse_seconds("1.5").unwrap(), Duration::from_millis(1500));
+        assert!(parse_seconds("0").is_err());
+        assert!(parse_seconds("-1").is_err());
+        assert!(parse_seconds("soon").is_err());
+    }
+}
@@ -0,0 +1,47 @@
+use clap::ValueEnum;
+use hyper_util::{rt::TokioExecutor, server::conn::auto::Builder};
+
+/// The protocols served by the HTTP trigger.
+#[derive(Clone, Debug, Default)]
+pub struct HttpProtocols {
+    /// The HTTP versions served over TCP.
+    pub version: HttpVersion,
+    /// Whether to also serve HTTP/3 over QUIC, on the same port as HTTPS.
+    #[cfg(feature = "http3")]
+    pub http3: bool,
+}
+
+/// The HTTP versions served over TCP.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum HttpVersion {
+    /// HTTP/1.1 and HTTP/2. Over cleartext, HTTP/2 requires prior knowledge (h2c); over TLS,
+    /// it is negotiated with ALPN.
+    #[default]
+    Auto,
+    /// HTTP/1.1 only.
+    Http1,
+    /// HTTP/2 only. Over cleartext, clients must use prior knowledge (h2c).
+    Http2,
+}
+
+impl HttpVersion {
+    /// Creates a connection builder which serves these versions.
+    pub(crate) fn connection_builder(self) -> Builder<TokioExecutor> {
+        let builder = Builder::new(TokioExecutor::new());
+        match self {
+            Self::Auto => builder,
+            Self::Http1 => builder.http1_only(),
+            Self::Http2 => builder.http2_only(),
+        }
+    }
+
+    /// The protocols to offer in TLS application-layer protocol negotiation (ALPN).
+    pub(crate) fn alpn_protocols(self) -> Vec<Vec<u8>> {
+        let protocols: &[&[u8]] = match self {
+            Self::Auto => &[b"h2", b"http/1.1"],
+            Self::Http1 => &[b"http/1.1"],
+            Self::Http2 => &[b"h2"],
+        };
+        protocols.iter().map(|p| p.to_vec()).collect()
+    }
+}
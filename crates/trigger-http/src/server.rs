@@ -1,9 +1,16 @@
+#[cfg(feature = "http3")]
+mod http3;
+
 use std::{
     collections::HashMap,
     future::Future,
     io::{ErrorKind, IsTerminal},
     net::SocketAddr,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
 };
 
 use anyhow::{bail, Context};
@@ -16,34 +23,42 @@ use hyper::{
     body::{Bytes, Incoming},
     service::service_fn,
 };
-use hyper_util::{
-    rt::{TokioExecutor, TokioIo},
-    server::conn::auto::Builder,
-};
+use hyper_util::rt::TokioIo;
 use spin_app::{APP_DESCRIPTION_KEY, APP_NAME_KEY};
 use spin_factor_outbound_http::{OutboundHttpFactor, SelfRequestOrigin};
 use spin_factors::RuntimeFactors;
 use spin_http::{
     app_info::AppInfo,
     body,
-    config::{HttpExecutorType, HttpTriggerConfig},
-    routes::{RouteMatch, Router},
+    config::{HttpCorsConfig, HttpExecutorType, HttpTriggerConfig},
+    routes::{RouteError, RouteMatch, RouteMatchers, Router},
     trigger::HandlerType,
 };
+use spin_trigger::ShutdownToken;
 use tokio::{
     io::{AsyncRead, AsyncWrite},
     net::TcpListener,
-    task,
+    sync::Notify,
+    task::{self, JoinSet},
+    time::{self, Instant},
 };
 use tracing::Instrument;
 use wasmtime_wasi::p2::bindings::CommandIndices;
 use wasmtime_wasi_http::body::HyperOutgoingBody;
 
 use crate::{
+    access_log::AccessLog,
+    compression::ResponseCompression,
+    cors::{CorsHeaders, CorsPolicy},
     headers::strip_forbidden_headers,
-    instrument::{finalize_http_span, http_span, instrument_error, MatchedRoute},
+    instrument::{finalize_http_span, http_span, instrument_error, MatchedComponent, MatchedRoute},
+    limits::{HttpLimits, LimitExceeded, LimitedBody},
     outbound_http::OutboundHttpInterceptor,
+    protocol::HttpProtocols,
     spin::SpinHttpExecutor,
+    static_files::StaticFileExecutor,
+    throttle::{Admission, Throttle},
+    tls::ClientCertificate,
     wagi::WagiHttpExecutor,
     wasi::WasiHttpExecutor,
     Body, NotFoundRouteKind, TlsConfig, TriggerApp, TriggerInstanceBuilder,
@@ -59,6 +74,12 @@ pub struct HttpServer<F: RuntimeFactors> {
     tls_config: Option<TlsConfig>,
     /// Whether to find a free port if the specified port is already in use.
     find_free_port: bool,
+    /// Limits applied to incoming requests.
+    limits: HttpLimits,
+    /// The protocols to serve.
+    protocols: HttpProtocols,
+    /// Signalled when the server should stop accepting connections and drain.
+    shutdown: ShutdownToken,
     /// Request router.
     router: Router,
     /// The app being triggered.
@@ -67,14 +88,27 @@ pub struct HttpServer<F: RuntimeFactors> {
     component_trigger_configs: HashMap<String, HttpTriggerConfig>,
     // Component ID -> handler type
     component_handler_types: HashMap<String, HandlerType>,
+    // Component ID -> static file executor, for components using the static executor
+    component_static_executors: HashMap<String, StaticFileExecutor>,
+    // Component ID -> CORS policy, for components with a policy of their own or app-wide
+    component_cors_policies: HashMap<String, CorsPolicy>,
+    // Component ID -> throttle, for components with concurrency or rate limits
+    component_throttles: HashMap<String, Throttle>,
+    /// The access log, if enabled.
+    access_log: Option<Arc<AccessLog>>,
 }
 
 impl<F: RuntimeFactors> HttpServer<F> {
     /// Create a new [`HttpServer`].
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         listen_addr: SocketAddr,
         tls_config: Option<TlsConfig>,
         find_free_port: bool,
+        limits: HttpLimits,
+        protocols: HttpProtocols,
+        cors: Option<HttpCorsConfig>,
+        shutdown: ShutdownToken,
         trigger_app: TriggerApp<F>,
     ) -> anyhow::Result<Self> {
         // This needs to be a vec before building the router to handle duplicate routes
@@ -89,9 +123,16 @@ impl<F: RuntimeFactors> HttpServer<F> {
         // Build router
         let component_routes = component_trigger_configs
             .iter()
-            .map(|(component_id, config)| (component_id.as_str(), &config.route));
+            .map(|(component_id, config)| {
+                (
+                    component_id.as_str(),
+                    &config.route,
+                    config.route_matchers(),
+                )
+            });
         let mut duplicate_routes = Vec::new();
-        let router = Router::build("/", component_routes, Some(&mut duplicate_routes))?;
+        let router =
+            Router::build_with_matchers("/", component_routes, Some(&mut duplicate_routes))?;
         if !duplicate_routes.is_empty() {
             tracing::error!(
                 "The following component routes are duplicates and will never be used:"
@@ -119,38 +160,94 @@ impl<F: RuntimeFactors> HttpServer<F> {
         // Now that router is built we can merge duplicate routes by component
         let component_trigger_configs = HashMap::from_iter(component_trigger_configs);
 
-        let component_handler_types = component_trigger_configs
-            .iter()
-            .map(|(component_id, trigger_config)| {
-                let pre = trigger_app.get_instance_pre(component_id)?;
-                let handler_type = match &trigger_config.executor {
-                    None | Some(HttpExecutorType::Http) => {
-                        HandlerType::from_instance_pre(pre)?
-                    }
-                    Some(HttpExecutorType::Wagi(wagi_config)) => {
-                        anyhow::ensure!(
-                            wagi_config.entrypoint == "_start",
-                            "Wagi component '{component_id}' cannot use deprecated 'entrypoint' field"
-                        );
-                        HandlerType::Wagi(CommandIndices::new(pre)
-                            .context("failed to find wasi command interface for wagi executor")?)
-                    }
-                };
-                Ok((component_id.clone(), handler_type))
-            })
-            .collect::<anyhow::Result<_>>()?;
+        let mut component_handler_types = HashMap::new();
+        let mut component_static_executors = HashMap::new();
+        for (component_id, trigger_config) in &component_trigger_configs {
+            let handler_type = match &trigger_config.executor {
+                None | Some(HttpExecutorType::Http) => {
+                    HandlerType::from_instance_pre(trigger_app.get_instance_pre(component_id)?)?
+                }
+                Some(HttpExecutorType::Wagi(wagi_config)) => {
+                    anyhow::ensure!(
+                        wagi_config.entrypoint == "_start",
+                        "Wagi component '{component_id}' cannot use deprecated 'entrypoint' field"
+                    );
+                    let pre = trigger_app.get_instance_pre(component_id)?;
+                    HandlerType::Wagi(
+                        CommandIndices::new(pre)
+                            .context("failed to find wasi command interface for wagi executor")?,
+                    )
+                }
+                Some(HttpExecutorType::Static(static_config)) => {
+                    // Static files are served by the host, so the component isn't run.
+                    let component = trigger_app
+                        .app()
+                        .get_component(component_id)
+                        .with_context(|| format!("unknown component '{component_id}'"))?;
+                    let executor = StaticFileExecutor::new(&component, static_config)?;
+                    component_static_executors.insert(component_id.clone(), executor);
+                    continue;
+                }
+            };
+            component_handler_types.insert(component_id.clone(), handler_type);
+        }
+
+        let app_cors_policy = cors
+            .as_ref()
+            .map(CorsPolicy::new)
+            .transpose()
+            .context("invalid `cors` in `[application.trigger.http]`")?;
+        let mut component_cors_policies = HashMap::new();
+        for (component_id, trigger_config) in &component_trigger_configs {
+            let policy = match &trigger_config.cors {
+                Some(config) => CorsPolicy::new(config)
+                    .with_context(|| format!("invalid `cors` for component '{component_id}'"))?,
+                None => match &app_cors_policy {
+                    Some(policy) => policy.clone(),
+                    None => continue,
+                },
+            };
+            component_cors_policies.insert(component_id.clone(), policy);
+        }
+
+        let mut component_throttles = HashMap::new();
+        for (component_id, trigger_config) in &component_trigger_configs {
+            let throttle = Throttle::new(
+                trigger_config.max_concurrency,
+                trigger_config.rate_limit.as_ref(),
+            )
+            .with_context(|| format!("invalid limits for component '{component_id}'"))?;
+            if let Some(throttle) = throttle {
+                component_throttles.insert(component_id.clone(), throttle);
+            }
+        }
         Ok(Self {
             listen_addr,
             tls_config,
             find_free_port,
+            limits,
+            protocols,
+            shutdown,
             router,
             trigger_app,
             component_trigger_configs,
             component_handler_types,
+            component_static_executors,
+            component_cors_policies,
+            component_throttles,
+            access_log: None,
         })
     }
 
+    /// Writes an entry to the access log for each request received from the network.
+    pub(crate) fn set_access_log(&mut self, access_log: AccessLog) {
+        self.access_log = Some(Arc::new(access_log));
+    }
+
     /// Serve incoming requests over the provided [`TcpListener`].
+    ///
+    /// Once the server's [`ShutdownToken`] is signalled, stops accepting connections and
+    /// returns when the requests in progress have finished.
     pub async fn serve(self: Arc<Self>) -> anyhow::Result<()> {
         let listener: TcpListener = if self.find_free_port {
             self.search_for_free_port().await?
@@ -165,6 +262,14 @@ impl<F: RuntimeFactors> HttpServer<F> {
         };
 
         if let Some(tls_config) = self.tls_config.clone() {
+            #[cfg(feature = "http3")]
+            if self.protocols.http3 {
+                let addr = listener.local_addr()?;
+                let https = self.clone().serve_https(listener, tls_config.clone());
+                let http3 = self.serve_http3(addr, tls_config);
+                tokio::try_join!(https, http3)?;
+                return Ok(());
+            }
             self.serve_https(listener, tls_config).await?;
         } else {
             self.serve_http(listener).await?;
@@ -205,11 +310,22 @@ impl<F: RuntimeFactors> HttpServer<F> {
 
     async fn serve_http(self: Arc<Self>, listener: TcpListener) -> anyhow::Result<()> {
         self.print_startup_msgs("http", &listener)?;
+        let mut connections = JoinSet::new();
         loop {
-            let (stream, client_addr) = listener.accept().await?;
-            self.clone()
-                .serve_connection(stream, Scheme::HTTP, client_addr);
+            let (stream, client_addr) = tokio::select! {
+                accepted = listener.accept() => accepted?,
+                Some(_) = connections.join_next() => continue,
+                () = self.shutdown.wait() => break,
+            };
+            connections.spawn(self.clone().serve_connection(
+                stream,
+                Scheme::HTTP,
+                client_addr,
+                None,
+            ));
         }
+        drain(connections).await;
+        Ok(())
     }
 
     async fn serve_https(
@@ -218,16 +334,32 @@ impl<F: RuntimeFactors> HttpServer<F> {
         tls_config: TlsConfig,
     ) -> anyhow::Result<()> {
         self.print_startup_msgs("https", &listener)?;
-        let acceptor = tls_config.server_config()?;
+        let alpn_protocols = self.protocols.version.alpn_protocols();
+        let (acceptor, reload_certs) = tls_config.acceptor(alpn_protocols)?;
+        let reload_certs = task::spawn(reload_certs);
+        let mut connections = JoinSet::new();
         loop {
-            let (stream, client_addr) = listener.accept().await?;
+            let (stream, client_addr) = tokio::select! {
+                accepted = listener.accept() => accepted?,
+                Some(_) = connections.join_next() => continue,
+                () = self.shutdown.wait() => break,
+            };
             match acceptor.accept(stream).await {
-                Ok(stream) => self
-                    .clone()
-                    .serve_connection(stream, Scheme::HTTPS, client_addr),
+                Ok(stream) => {
+                    let client_cert = ClientCertificate::from_connection(stream.get_ref().1);
+                    connections.spawn(self.clone().serve_connection(
+                        stream,
+                        Scheme::HTTPS,
+                        client_addr,
+                        client_cert,
+                    ));
+                }
                 Err(err) => tracing::error!(?err, "Failed to start TLS session"),
             }
         }
+        reload_certs.abort();
+        drain(connections).await;
+        Ok(())
     }
 
     /// Handles incoming requests using an HTTP executor.
@@ -260,12 +392,44 @@ impl<F: RuntimeFactors> HttpServer<F> {
             };
         }
 
-        match self.router.route(&path) {
+        let host = request_host(&req);
+
+        // Answer CORS preflights for the route the actual request would take
+        if CorsPolicy::is_preflight(&req) {
+            let requested_method = CorsPolicy::requested_method(&req).unwrap_or_default();
+            if let Ok(route_match) =
+                self.router
+                    .route_request(Some(requested_method), host.as_deref(), &path)
+            {
+                let component_id = route_match.component_id();
+                if let Some(policy) = self.component_cors_policies.get(component_id) {
+                    let mut res = policy.preflight_response(&req)?;
+                    MatchedComponent::set_response_extension(&mut res, component_id);
+                    return Ok(MatchedRoute::with_response_extension(
+                        res,
+                        route_match.raw_route(),
+                    ));
+                }
+            }
+        }
+
+        let method = req.method().as_str().to_owned();
+        match self
+            .router
+            .route_request(Some(&method), host.as_deref(), &path)
+        {
             Ok(route_match) => {
-                self.handle_trigger_route(req, route_match, server_scheme, client_addr)
-                    .await
+                let component_id = route_match.component_id().to_owned();
+                let mut res = self
+                    .handle_trigger_route(req, route_match, server_scheme, client_addr)
+                    .await?;
+                MatchedComponent::set_response_extension(&mut res, component_id);
+                Ok(res)
+            }
+            Err(RouteError::MethodNotAllowed(allowed)) => Self::method_not_allowed(&allowed),
+            Err(RouteError::NotFound) => {
+                Self::not_found(NotFoundRouteKind::Normal(path.to_string()))
             }
-            Err(_) => Self::not_found(NotFoundRouteKind::Normal(path.to_string())),
         }
     }
 
@@ -293,6 +457,45 @@ impl<F: RuntimeFactors> HttpServer<F> {
             component_id = component_id
         );
 
+        let admission = match self
+            .component_throttles
+            .get(component_id)
+            .map(|throttle| throttle.admit(client_addr.ip()))
+            .transpose()
+        {
+            Ok(admission) => admission,
+            Err(throttled) => {
+                spin_telemetry::metrics::monotonic_counter!(
+                    spin.request_throttled = 1,
+                    trigger_type = "http",
+                    app_id = app_id,
+                    component_id = component_id,
+                    reason = throttled.error_type()
+                );
+                tracing::info!("Throttling request: {}", throttled.error_type());
+                tracing::Span::current().record("error.type", throttled.error_type());
+                return Ok(MatchedRoute::with_response_extension(
+                    throttled.response()?,
+                    route_match.raw_route(),
+                ));
+            }
+        };
+
+        let trigger_config = self.component_trigger_configs.get(component_id).unwrap();
+        let compression = trigger_config
+            .compression
+            .as_ref()
+            .map(|config| ResponseCompression::negotiate(config, &req));
+        let cors = self
+            .component_cors_policies
+            .get(component_id)
+            .map(|policy| policy.for_request(&req));
+
+        if let Some(executor) = self.component_static_executors.get(component_id) {
+            let res = executor.execute(&route_match, req).await;
+            return Self::matched_response(res, &route_match, admission, cors, compression);
+        }
+
         let mut instance_builder = self.trigger_app.prepare(component_id)?;
 
         // Set up outbound HTTP request origin and service chaining
@@ -309,7 +512,6 @@ impl<F: RuntimeFactors> HttpServer<F> {
         outbound_http.set_request_interceptor(OutboundHttpInterceptor::new(self.clone()))?;
 
         // Prepare HTTP executor
-        let trigger_config = self.component_trigger_configs.get(component_id).unwrap();
         let handler_type = self.component_handler_types.get(component_id).unwrap();
         let executor = trigger_config
             .executor
@@ -345,12 +547,37 @@ impl<F: RuntimeFactors> HttpServer<F> {
                     .execute(instance_builder, &route_match, req, client_addr)
                     .await
             }
+            HttpExecutorType::Static(_) => unreachable!(),
         };
+        Self::matched_response(res, &route_match, admission, cors, compression)
+    }
+
+    /// Converts the result of an executor into the response for a matched route,
+    /// adding CORS headers and compressing it if the trigger is configured to.
+    /// The request's admission is held until the response has been sent.
+    fn matched_response(
+        res: anyhow::Result<Response<Body>>,
+        route_match: &RouteMatch<'_, '_>,
+        admission: Option<Admission>,
+        cors: Option<CorsHeaders>,
+        compression: Option<ResponseCompression>,
+    ) -> anyhow::Result<Response<Body>> {
         match res {
-            Ok(res) => Ok(MatchedRoute::with_response_extension(
-                res,
-                route_match.raw_route(),
-            )),
+            Ok(mut res) => {
+                if let Some(cors) = cors {
+                    cors.apply(&mut res)?;
+                }
+                if let Some(compression) = compression {
+                    res = compression.apply(res);
+                }
+                if let Some(admission) = admission {
+                    res = admission.hold_until_sent(res);
+                }
+                Ok(MatchedRoute::with_response_extension(
+                    res,
+                    route_match.raw_route(),
+                ))
+            }
             Err(err) => {
                 tracing::error!("Error processing request: {err:?}");
                 instrument_error(&err);
@@ -405,56 +632,141 @@ impl<F: RuntimeFactors> HttpServer<F> {
             .body(body::empty())?)
     }
 
-    fn serve_connection<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
+    /// Creates an HTTP 405 response listing the methods which are allowed.
+    fn method_not_allowed(allowed: &[String]) -> anyhow::Result<Response<Body>> {
+        Ok(Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header(http::header::ALLOW, allowed.join(", "))
+            .body(body::empty())?)
+    }
+
+    async fn serve_connection<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
         self: Arc<Self>,
         stream: S,
         server_scheme: Scheme,
         client_addr: SocketAddr,
+        client_cert: Option<ClientCertificate>,
     ) {
-        task::spawn(async move {
-            if let Err(err) = Builder::new(TokioExecutor::new())
-                .serve_connection(
-                    TokioIo::new(stream),
-                    service_fn(move |request| {
-                        self.clone().instrumented_service_fn(
-                            server_scheme.clone(),
-                            client_addr,
-                            request,
-                        )
-                    }),
-                )
-                .await
-            {
-                tracing::warn!("Error serving HTTP connection: {err:?}");
+        let idle_timeout = self.limits.idle_timeout;
+        let shutdown = self.shutdown.clone();
+        let activity = Arc::new(ConnectionActivity::new());
+        let service_activity = activity.clone();
+        let mut builder = self.protocols.version.connection_builder();
+        if let Some(max) = self.limits.max_request_header_bytes {
+            builder
+                .http2()
+                .max_header_list_size(max.try_into().unwrap_or(u32::MAX));
+        }
+        let conn = builder.serve_connection(
+            TokioIo::new(stream),
+            service_fn(move |mut request: Request<Incoming>| {
+                let guard = service_activity.start_request();
+                if let Some(client_cert) = &client_cert {
+                    request.extensions_mut().insert(client_cert.clone());
+                }
+                let server = self.clone();
+                let scheme = server_scheme.clone();
+                async move {
+                    let _guard = guard;
+                    let request = request.map(|body: Incoming| {
+                        body.map_err(wasmtime_wasi_http::hyper_response_error)
+                            .boxed()
+                    });
+                    server
+                        .instrumented_service_fn(scheme, client_addr, request)
+                        .await
+                }
+            }),
+        );
+        tokio::pin!(conn);
+
+        let idle = async {
+            match idle_timeout {
+                Some(timeout) => activity.idle_for(timeout).await,
+                None => std::future::pending().await,
             }
-        });
+        };
+        let result = tokio::select! {
+            result = conn.as_mut() => result,
+            () = shutdown.wait() => {
+                conn.as_mut().graceful_shutdown();
+                conn.await
+            }
+            () = idle => {
+                tracing::debug!("Closing idle HTTP connection from {client_addr}");
+                conn.as_mut().graceful_shutdown();
+                conn.await
+            }
+        };
+        if let Err(err) = result {
+            tracing::warn!("Error serving HTTP connection: {err:?}");
+        }
     }
 
-    async fn instrumented_service_fn(
+    pub(crate) async fn instrumented_service_fn(
         self: Arc<Self>,
         server_scheme: Scheme,
         client_addr: SocketAddr,
-        request: Request<Incoming>,
+        request: Request<Body>,
     ) -> anyhow::Result<Response<HyperOutgoingBody>> {
         let span = http_span!(request, client_addr);
         let method = request.method().to_string();
         async {
+            let (request, access_log_entry) = match &self.access_log {
+                Some(access_log) => {
+                    let (request, entry) = access_log.start(request, client_addr);
+                    (request, Some(entry))
+                }
+                None => (request, None),
+            };
             let result = self
-                .handle(
-                    request.map(|body: Incoming| {
-                        body.map_err(wasmtime_wasi_http::hyper_response_error)
-                            .boxed()
-                    }),
-                    server_scheme,
-                    client_addr,
-                )
+                .handle_within_limits(request, server_scheme, client_addr)
                 .await;
-            finalize_http_span(result, method)
+            let result = finalize_http_span(result, method);
+            match access_log_entry {
+                Some(entry) => entry.finish(result),
+                None => result,
+            }
         }
         .instrument(span)
         .await
     }
 
+    /// Handles a request from the network, enforcing the server's [`HttpLimits`].
+    async fn handle_within_limits(
+        self: &Arc<Self>,
+        req: Request<Body>,
+        server_scheme: Scheme,
+        client_addr: SocketAddr,
+    ) -> anyhow::Result<Response<Body>> {
+        if let Some(exceeded) = self.limits.check_head(req.headers()) {
+            return reject(exceeded);
+        }
+
+        let (req, body_exceeded) = match self.limits.max_request_body_bytes {
+            Some(max) => {
+                let (parts, body) = req.into_parts();
+                let (body, exceeded) = LimitedBody::new(body, max);
+                (Request::from_parts(parts, body.boxed()), Some(exceeded))
+            }
+            None => (req, None),
+        };
+
+        let handled = self.handle(req, server_scheme, client_addr);
+        let result = match self.limits.request_timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, handled).await {
+                Ok(result) => result,
+                Err(_) => return reject(LimitExceeded::Timeout),
+            },
+            None => handled.await,
+        };
+
+        if body_exceeded.is_some_and(|exceeded| exceeded.load(Ordering::Relaxed)) {
+            return reject(LimitExceeded::BodySize);
+        }
+        result
+    }
+
     fn print_startup_msgs(&self, scheme: &str, listener: &TcpListener) -> anyhow::Result<()> {
         let local_addr = listener.local_addr()?;
         let base_url = format!("{scheme}://{local_addr:?}");
@@ -462,8 +774,12 @@ impl<F: RuntimeFactors> HttpServer<F> {
         tracing::info!("Serving {base_url}");
 
         println!("Available Routes:");
-        for (route, component_id) in self.router.routes() {
-            println!("  {component_id}: {base_url}{route}");
+        for (route, matchers, component_id) in self.router.routes_with_matchers() {
+            if *matchers == RouteMatchers::default() {
+                println!("  {component_id}: {base_url}{route}");
+            } else {
+                println!("  {component_id}: {base_url}{route} ({matchers})");
+            }
             if let Some(component) = self.trigger_app.app().get_component(component_id) {
                 if let Some(description) = component.get_metadata(APP_DESCRIPTION_KEY)? {
                     println!("    {description}");
@@ -474,6 +790,88 @@ impl<F: RuntimeFactors> HttpServer<F> {
     }
 }
 
+/// The host a request is for, from the URI (HTTP/2 and HTTP/3) or the `Host` header.
+fn request_host<B>(req: &Request<B>) -> Option<String> {
+    req.uri().host().map(str::to_owned).or_else(|| {
+        req.headers()
+            .get(http::header::HOST)
+            .and_then(|host| host.to_str().ok())
+            .map(str::to_owned)
+    })
+}
+
+/// Waits for the connections in progress to finish.
+pub(crate) async fn drain(mut connections: JoinSet<()>) {
+    if !connections.is_empty() {
+        tracing::info!(
+            "Shutting down: waiting for {} open connection(s) to finish",
+            connections.len()
+        );
+    }
+    while connections.join_next().await.is_some() {}
+}
+
+/// Responds to a request which exceeded a limit, recording the reason on the request span.
+fn reject(exceeded: LimitExceeded) -> anyhow::Result<Response<Body>> {
+    tracing::info!("Rejecting request: {}", exceeded.error_type());
+    tracing::Span::current().record("error.type", exceeded.error_type());
+    exceeded.response()
+}
+
+/// Tracks the requests in progress on a connection, to detect when it is idle.
+struct ConnectionActivity {
+    in_flight: AtomicUsize,
+    last_active: Mutex<Instant>,
+    /// Notified when the last request in progress finishes.
+    requests_finished: Notify,
+}
+
+impl ConnectionActivity {
+    fn new() -> Self {
+        Self {
+            in_flight: AtomicUsize::new(0),
+            last_active: Mutex::new(Instant::now()),
+            requests_finished: Notify::new(),
+        }
+    }
+
+    /// Marks a request as in progress until the returned guard is dropped.
+    fn start_request(self: &Arc<Self>) -> RequestGuard {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        RequestGuard(self.clone())
+    }
+
+    /// Waits until no request has been in progress for `idle_timeout`.
+    async fn idle_for(&self, idle_timeout: Duration) {
+        loop {
+            // A request can take longer than `idle_timeout`, so the timer only
+            // runs while none is in progress.
+            if self.in_flight.load(Ordering::SeqCst) > 0 {
+                self.requests_finished.notified().await;
+                continue;
+            }
+            let deadline = *self.last_active.lock().unwrap() + idle_timeout;
+            if Instant::now() >= deadline {
+                return;
+            }
+            time::sleep_until(deadline).await;
+        }
+    }
+}
+
+struct RequestGuard(Arc<ConnectionActivity>);
+
+impl Drop for RequestGuard {
+    fn drop(&mut self) {
+        *self.0.last_active.lock().unwrap() = Instant::now();
+        if self.0.in_flight.fetch_sub(1, Ordering::SeqCst) == 1 {
+            // Stores a permit if `idle_for` isn't waiting yet, so that it
+            // can't miss the notification.
+            self.0.requests_finished.notify_one();
+        }
+    }
+}
+
 /// The incoming request's scheme and authority
 ///
 /// The incoming request's URI is relative to the server, so we need to set the scheme and authority.
@@ -526,3 +924,41 @@ pub(crate) trait HttpExecutor {
         client_addr: SocketAddr,
     ) -> impl Future<Output = anyhow::Result<Response<Body>>>;
 }
+
+#[cfg(test)]
+mod tests {
+    use std::future::poll_fn;
+    use std::pin::pin;
+
+    use super::*;
+
+    const IDLE_TIMEOUT: Duration = Duration::from_millis(50);
+
+    #[tokio::test]
+    async fn requests_slower_than_the_idle_timeout_keep_connections_open() {
+        let activity = Arc::new(ConnectionActivity::new());
+        let request = activity.start_request();
+        let mut idle = pin!(activity.idle_for(IDLE_TIMEOUT));
+
+        let mut polls = 0;
+        let waiting = poll_fn(|cx| {
+            polls += 1;
+            idle.as_mut().poll(cx)
+        });
+        let res = time::timeout(IDLE_TIMEOUT * 5, waiting).await;
+        assert!(res.is_err(), "connection timed out during a request");
+        // Were the timer running during the request, it would be polled
+        // over and over once the timeout had passed.
+        assert!(
+            polls <= 2,
+            "idle timer polled {polls} times during a request"
+        );
+
+        drop(request);
+        let finished = Instant::now();
+        time::timeout(IDLE_TIMEOUT * 5, idle)
+            .await
+            .expect("connection should time out once idle");
+        assert!(finished.elapsed() >= IDLE_TIMEOUT);
+    }
+}
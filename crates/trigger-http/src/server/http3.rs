@@ -0,0 +1,386 @@
+//! An experimental HTTP/3 listener.
+
+use std::{
+    future::Future,
+    net::SocketAddr,
+    pin::Pin,
+    sync::Arc,
+    task::{Context as TaskContext, Poll},
+};
+
+use anyhow::Context;
+use http::{uri::Scheme, Request, Response, StatusCode};
+use http_body_util::BodyExt;
+use hyper::body::{Body as HttpBody, Buf, Bytes, Frame};
+use rustls_pki_types::CertificateDer;
+use spin_factors::RuntimeFactors;
+use spin_http::body;
+use tokio::{
+    sync::mpsc,
+    task::{self, JoinSet},
+};
+use wasmtime_wasi_http::bindings::http::types::ErrorCode;
+
+use super::HttpServer;
+use crate::{tls::ClientCertificate, Body, TlsConfig};
+
+type RequestStream<S> = h3::server::RequestStream<S, Bytes>;
+
+impl<F: RuntimeFactors> HttpServer<F> {
+    /// Serves HTTP/3 over QUIC on the given UDP address until shutdown.
+    pub(super) async fn serve_http3(
+        self: Arc<Self>,
+        addr: SocketAddr,
+        tls_config: TlsConfig,
+    ) -> anyhow::Result<()> {
+        let (mut server_config, reload_certs) = tls_config.server_config()?;
+        server_config.alpn_protocols = vec![b"h3".to_vec()];
+        let crypto = quinn::crypto::rustls::QuicServerConfig::try_from(server_config)
+            .context("the TLS configuration is not supported by HTTP/3")?;
+        let mut quic_config = quinn::ServerConfig::with_crypto(Arc::new(crypto));
+        if let Some(idle_timeout) = self.limits.idle_timeout {
+            let mut transport = quinn::TransportConfig::default();
+            transport.max_idle_timeout(Some(idle_timeout.try_into()?));
+            quic_config.transport_config(Arc::new(transport));
+        }
+        let endpoint = quinn::Endpoint::server(quic_config, addr)
+            .with_context(|| format!("Unable to listen for HTTP/3 on {addr}"))?;
+
+        terminal::step!("Serving", "HTTP/3 on {addr} (experimental)");
+        tracing::info!("Serving HTTP/3 on {addr}");
+
+        let reload_certs = task::spawn(reload_certs);
+        let mut connections = JoinSet::new();
+        loop {
+            let incoming = tokio::select! {
+                incoming = endpoint.accept() => match incoming {
+                    Some(incoming) => incoming,
+                    None => break,
+                },
+                Some(_) = connections.join_next() => continue,
+                () = self.shutdown.wait() => break,
+            };
+            connections.spawn(self.clone().serve_http3_connection(incoming));
+        }
+        reload_certs.abort();
+        super::drain(connections).await;
+        endpoint.wait_idle().await;
+        Ok(())
+    }
+
+    async fn serve_http3_connection(self: Arc<Self>, incoming: quinn::Incoming) {
+        let client_addr = incoming.remote_address();
+        if let Err(err) = self.try_serve_http3_connection(incoming, client_addr).await {
+            tracing::warn!("Error serving HTTP/3 connection: {err:?}");
+        }
+    }
+
+    async fn try_serve_http3_connection(
+        self: Arc<Self>,
+        incoming: quinn::Incoming,
+        client_addr: SocketAddr,
+    ) -> anyhow::Result<()> {
+        let conn = incoming.await?;
+        let client_cert = conn
+            .peer_identity()
+            .and_then(|identity| identity.downcast::<Vec<CertificateDer<'static>>>().ok())
+            .and_then(|chain| ClientCertificate::from_chain(&chain));
+
+        let mut builder = h3::server::builder();
+        if let Some(max) = self.limits.max_request_header_bytes {
+            builder.max_field_section_size(max as u64);
+        }
+        let mut h3_conn = builder
+            .build::<_, Bytes>(h3_quinn::Connection::new(conn))
+            .await?;
+
+        let mut requests = JoinSet::new();
+        loop {
+            let accepted = tokio::select! {
+                accepted = h3_conn.accept() => accepted,
+                Some(_) = requests.join_next() => continue,
+                () = self.shutdown.wait() => {
+                    h3_conn.shutdown(0).await?;
+                    break;
+                }
+            };
+            match accepted {
+                Ok(Some((request, stream))) => {
+                    requests.spawn(self.clone().serve_http3_request(
+                        request,
+                        stream,
+                        client_addr,
+                        client_cert.clone(),
+                    ));
+                }
+                Ok(None) => break,
+                Err(err) => match err.get_error_level() {
+                    h3::error::ErrorLevel::ConnectionError => return Err(err.into()),
+                    h3::error::ErrorLevel::StreamError => continue,
+                },
+            }
+        }
+        while requests.join_next().await.is_some() {}
+        Ok(())
+    }
+
+    async fn serve_http3_request<S>(
+        self: Arc<Self>,
+        request: Request<()>,
+        stream: RequestStream<S>,
+        client_addr: SocketAddr,
+        client_cert: Option<ClientCertificate>,
+    ) where
+        S: h3::quic::BidiStream<Bytes> + Send + 'static,
+        S::RecvStream: Send + 'static,
+        S::SendStream: Send,
+    {
+        serve_request(request, stream, |mut request| async move {
+            if let Some(client_cert) = client_cert {
+                request.extensions_mut().insert(client_cert);
+            }
+            self.instrumented_service_fn(Scheme::HTTPS, client_addr, request)
+                .await
+        })
+        .await
+    }
+}
+
+/// Serves a request received on an HTTP/3 stream with `service`.
+///
+/// The request body keeps streaming after `service` returns the response head, until
+/// the body is dropped.
+async fn serve_request<S, Fut>(
+    request: Request<()>,
+    stream: RequestStream<S>,
+    service: impl FnOnce(Request<Body>) -> Fut,
+) where
+    S: h3::quic::BidiStream<Bytes> + Send + 'static,
+    S::RecvStream: Send + 'static,
+    S::SendStream: Send,
+    Fut: Future<Output = anyhow::Result<Response<Body>>>,
+{
+    let (mut send, recv) = stream.split();
+    let (tx, rx) = mpsc::channel(1);
+    task::spawn(receive_body(recv, tx));
+
+    let request = request.map(|()| ChannelBody(rx).boxed());
+    let response = match service(request).await {
+        Ok(response) => response,
+        Err(err) => {
+            tracing::error!("Error processing HTTP/3 request: {err:?}");
+            Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(body::empty())
+                .unwrap()
+        }
+    };
+
+    if let Err(err) = send_response(&mut send, response).await {
+        tracing::warn!("Error sending HTTP/3 response: {err:?}");
+    }
+}
+
+/// Forwards the request body from an HTTP/3 stream to a [`ChannelBody`], until the
+/// body ends or the [`ChannelBody`] is dropped.
+async fn receive_body<S: h3::quic::RecvStream>(
+    mut recv: RequestStream<S>,
+    tx: mpsc::Sender<Result<Frame<Bytes>, ErrorCode>>,
+) {
+    loop {
+        let data = tokio::select! {
+            data = recv.recv_data() => data,
+            () = tx.closed() => return,
+        };
+        let frame = match data {
+            Ok(Some(mut data)) => Ok(Frame::data(data.copy_to_bytes(data.remaining()))),
+            Ok(None) => match recv.recv_trailers().await {
+                Ok(Some(trailers)) => Ok(Frame::trailers(trailers)),
+                Ok(None) => return,
+                Err(err) => Err(ErrorCode::InternalError(Some(err.to_string()))),
+            },
+            Err(err) => Err(ErrorCode::InternalError(Some(err.to_string()))),
+        };
+        let done = frame.as_ref().map_or(true, |frame| frame.is_trailers());
+        if tx.send(frame).await.is_err() || done {
+            return;
+        }
+    }
+}
+
+async fn send_response<S: h3::quic::SendStream<Bytes>>(
+    send: &mut RequestStream<S>,
+    response: Response<Body>,
+) -> anyhow::Result<()> {
+    let (parts, mut body) = response.into_parts();
+    send.send_response(Response::from_parts(parts, ())).await?;
+    while let Some(frame) = body.frame().await {
+        match frame?.into_data() {
+            Ok(data) => send.send_data(data).await?,
+            Err(frame) => {
+                if let Ok(trailers) = frame.into_trailers() {
+                    send.send_trailers(trailers).await?;
+                }
+            }
+        }
+    }
+    send.finish().await?;
+    Ok(())
+}
+
+/// A request body received from another task.
+struct ChannelBody(mpsc::Receiver<Result<Frame<Bytes>, ErrorCode>>);
+
+impl HttpBody for ChannelBody {
+    type Data = Bytes;
+    type Error = ErrorCode;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, ErrorCode>>> {
+        self.0.poll_recv(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, time::Duration};
+
+    use rustls::{
+        client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+        crypto::CryptoProvider,
+        pki_types::{ServerName, UnixTime},
+        DigitallySignedStruct, SignatureScheme,
+    };
+
+    use super::*;
+
+    const TESTDATA_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata");
+
+    #[tokio::test]
+    async fn request_body_streams_after_response_head() -> anyhow::Result<()> {
+        let server = server_endpoint()?;
+        let addr = server.local_addr()?;
+        let server_task = task::spawn(async move {
+            let conn = server.accept().await.unwrap().await.unwrap();
+            let mut h3_conn =
+                h3::server::Connection::<_, Bytes>::new(h3_quinn::Connection::new(conn))
+                    .await
+                    .unwrap();
+            let (request, stream) = h3_conn.accept().await.unwrap().unwrap();
+            // Like a wasi-http handler, return the response head at once and echo the
+            // request body into the response body as it arrives.
+            serve_request(request, stream, |request| async move {
+                let (tx, rx) = mpsc::channel(1);
+                task::spawn(async move {
+                    let mut body = request.into_body();
+                    while let Some(frame) = body.frame().await {
+                        if tx.send(frame).await.is_err() {
+                            return;
+                        }
+                    }
+                });
+                Ok(Response::new(ChannelBody(rx).boxed()))
+            })
+            .await;
+            // Keep the connection open until the client has read the response.
+            let _ = h3_conn.accept().await;
+        });
+
+        let client = client_endpoint()?;
+        let conn = client.connect(addr, "localhost")?.await?;
+        let (mut driver, mut send_request) =
+            h3::client::new(h3_quinn::Connection::new(conn)).await?;
+        let driver = task::spawn(async move {
+            let _ = std::future::poll_fn(|cx| driver.poll_close(cx)).await;
+        });
+
+        let request = Request::post("https://localhost/").body(())?;
+        let mut stream = send_request.send_request(request).await?;
+        stream.send_data(Bytes::from_static(b"hello ")).await?;
+        let response = stream.recv_response().await?;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // The rest of the body is sent only once the response head has been returned.
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        stream.send_data(Bytes::from_static(b"world")).await?;
+        stream.finish().await?;
+
+        let mut echoed = vec![];
+        while let Some(mut data) = stream.recv_data().await? {
+            echoed.extend_from_slice(&data.copy_to_bytes(data.remaining()));
+        }
+        assert_eq!(echoed, b"hello world");
+
+        drop(send_request);
+        driver.abort();
+        server_task.abort();
+        Ok(())
+    }
+
+    fn server_endpoint() -> anyhow::Result<quinn::Endpoint> {
+        let testdata = Path::new(TESTDATA_DIR);
+        let tls_config = TlsConfig::new(
+            testdata.join("valid-cert.pem"),
+            testdata.join("valid-private-key.pem"),
+        );
+        let (mut server_config, _reload_certs) = tls_config.server_config()?;
+        server_config.alpn_protocols = vec![b"h3".to_vec()];
+        let crypto = quinn::crypto::rustls::QuicServerConfig::try_from(server_config)?;
+        let config = quinn::ServerConfig::with_crypto(Arc::new(crypto));
+        Ok(quinn::Endpoint::server(config, ([127, 0, 0, 1], 0).into())?)
+    }
+
+    fn client_endpoint() -> anyhow::Result<quinn::Endpoint> {
+        let provider = rustls::ClientConfig::builder().crypto_provider().clone();
+        let mut tls_config = rustls::ClientConfig::builder()
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(AcceptAnyServerCert(provider)))
+            .with_no_client_auth();
+        tls_config.alpn_protocols = vec![b"h3".to_vec()];
+        let crypto = quinn::crypto::rustls::QuicClientConfig::try_from(tls_config)?;
+        let mut endpoint = quinn::Endpoint::client(([127, 0, 0, 1], 0).into())?;
+        endpoint.set_default_client_config(quinn::ClientConfig::new(Arc::new(crypto)));
+        Ok(endpoint)
+    }
+
+    /// Trusts the test server's certificate, which isn't issued for `localhost`.
+    #[derive(Debug)]
+    struct AcceptAnyServerCert(Arc<CryptoProvider>);
+
+    impl ServerCertVerifier for AcceptAnyServerCert {
+        fn verify_server_cert(
+            &self,
+            _end_entity: &CertificateDer<'_>,
+            _intermediates: &[CertificateDer<'_>],
+            _server_name: &ServerName<'_>,
+            _ocsp_response: &[u8],
+            _now: UnixTime,
+        ) -> Result<ServerCertVerified, rustls::Error> {
+            Ok(ServerCertVerified::assertion())
+        }
+
+        fn verify_tls12_signature(
+            &self,
+            _message: &[u8],
+            _cert: &CertificateDer<'_>,
+            _dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            Ok(HandshakeSignatureValid::assertion())
+        }
+
+        fn verify_tls13_signature(
+            &self,
+            _message: &[u8],
+            _cert: &CertificateDer<'_>,
+            _dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            Ok(HandshakeSignatureValid::assertion())
+        }
+
+        fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+            self.0.signature_verification_algorithms.supported_schemes()
+        }
+    }
+}
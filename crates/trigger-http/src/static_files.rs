@@ -0,0 +1,665 @@
+//! Serves static files from a component's `files` mounts on the host, without
+//! running the component.
+
+use std::{
+    fs::Metadata,
+    path::{Component, Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use anyhow::{ensure, Context, Result};
+use futures::TryStreamExt;
+use http::{
+    header::{self, HeaderMap, HeaderValue},
+    Method, Request, Response, StatusCode,
+};
+use http_body_util::{BodyExt, StreamBody};
+use hyper::body::{Bytes, Frame};
+use spin_app::AppComponent;
+use spin_common::url::parse_file_url;
+use spin_http::{body, config::StaticTriggerConfig, routes::RouteMatch};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tracing::{instrument, Level};
+use wasmtime_wasi_http::bindings::http::types::ErrorCode;
+
+use crate::{compression::accepts_encoding, Body};
+
+/// The file served for requests to a directory.
+const INDEX_FILE: &str = "index.html";
+
+/// Precompressed variants, in order of preference: the file extension and the
+/// corresponding `Content-Encoding`.
+const PRECOMPRESSED: &[(&str, &str)] = &[("br", "br"), ("gz", "gzip")];
+
+/// The size of the chunks in which file contents are sent.
+const CHUNK_SIZE: u64 = 64 * 1024;
+
+/// Serves files for the `static` HTTP executor.
+pub struct StaticFileExecutor {
+    /// The component's files mounts, as guest paths and the host directories
+    /// mounted there, with the most specific guest path first.
+    mounts: Vec<(PathBuf, PathBuf)>,
+    /// The guest directory files are served from.
+    root: PathBuf,
+    /// The file to serve if the requested file does not exist, relative to `root`.
+    fallback: Option<PathBuf>,
+}
+
+impl StaticFileExecutor {
+    /// Creates an executor serving files from the given component's `files` mounts.
+    pub fn new(component: &AppComponent, config: &StaticTriggerConfig) -> Result<Self> {
+        let mounts = component
+            .files()
+            .map(|content_dir| {
+                let source_uri =
+                    content_dir.content.source.as_deref().with_context(|| {
+                        format!("Missing 'source' on files mount {content_dir:?}")
+                    })?;
+                Ok((content_dir.path.clone(), parse_file_url(source_uri)?))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Self::from_mounts(mounts, config)
+            .with_context(|| format!("Invalid static executor for component '{}'", component.id()))
+    }
+
+    fn from_mounts(
+        mounts: impl IntoIterator<Item = (PathBuf, PathBuf)>,
+        config: &StaticTriggerConfig,
+    ) -> Result<Self> {
+        let mut mounts = mounts
+            .into_iter()
+            .map(|(guest_path, host_path)| {
+                ensure!(
+                    host_path.is_dir(),
+                    "the static executor only supports directory mounts; {} is not a directory",
+                    host_path.display()
+                );
+                // Canonicalize so that requests can be checked against the mount
+                // after following symlinks.
+                Ok((guest_path, host_path.canonicalize()?))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        ensure!(
+            !mounts.is_empty(),
+            "the static executor requires the component to have `files` to serve"
+        );
+        mounts.sort_by_key(|(guest_path, _)| std::cmp::Reverse(guest_path.components().count()));
+
+        let root = PathBuf::from(&config.root);
+        ensure!(
+            root.is_absolute(),
+            "static executor `root` must be an absolute path, but was '{}'",
+            config.root
+        );
+        let fallback = config
+            .fallback
+            .as_deref()
+            .map(|fallback| {
+                relative_path(fallback.split('/')).with_context(|| {
+                    format!("static executor `fallback` must be a path within `root`, but was '{fallback}'")
+                })
+            })
+            .transpose()?;
+
+        Ok(Self {
+            mounts,
+            root,
+            fallback,
+        })
+    }
+
+    /// Serves the file for the part of the request path matched by the route's
+    /// trailing wildcard.
+    #[instrument(name = "spin_trigger_http.serve_static", skip_all, err(level = Level::INFO), fields(otel.name = format!("serve_static {}", route_match.component_id())))]
+    pub async fn execute(
+        &self,
+        route_match: &RouteMatch<'_, '_>,
+        req: Request<Body>,
+    ) -> Result<Response<Body>> {
+        if req.method() != Method::GET && req.method() != Method::HEAD {
+            return Ok(Response::builder()
+                .status(StatusCode::METHOD_NOT_ALLOWED)
+                .header(header::ALLOW, "GET, HEAD")
+                .body(body::empty())?);
+        }
+
+        let requested = route_match.trailing_wildcard();
+        let segments = requested
+            .split('/')
+            .map(|segment| percent_encoding::percent_decode_str(segment).decode_utf8());
+        let file = match segments.collect::<Result<Vec<_>, _>>() {
+            Ok(segments) => match relative_path(segments.iter().map(|s| s.as_ref())) {
+                Some(path) => self.find_file(&self.root.join(path)).await,
+                None => None,
+            },
+            Err(_) => None,
+        };
+        let file = match (file, &self.fallback) {
+            (Some(file), _) => Some(file),
+            (None, Some(fallback)) => self.find_file(&self.root.join(fallback)).await,
+            (None, None) => None,
+        };
+        let Some((path, metadata, mount_host_path)) = file else {
+            return Ok(Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(body::empty())?);
+        };
+
+        serve_file(
+            &path,
+            metadata,
+            mount_host_path,
+            req.method(),
+            req.headers(),
+        )
+        .await
+    }
+
+    /// Finds the file at a guest path, or the index file if the path is a directory,
+    /// along with the host directory of the mount it is in.
+    async fn find_file(&self, guest_path: &Path) -> Option<(PathBuf, Metadata, &Path)> {
+        let (mount_guest_path, mount_host_path) = self
+            .mounts
+            .iter()
+            .find(|(mount_guest_path, _)| guest_path.starts_with(mount_guest_path))?;
+        let mut host_path = mount_host_path.join(guest_path.strip_prefix(mount_guest_path).ok()?);
+
+        let mut metadata = tokio::fs::metadata(&host_path).await.ok()?;
+        if metadata.is_dir() {
+            host_path.push(INDEX_FILE);
+            metadata = tokio::fs::metadata(&host_path).await.ok()?;
+        }
+        if !metadata.is_file() {
+            return None;
+        }
+
+        let host_path = within_mount(&host_path, mount_host_path).await?;
+        Some((host_path, metadata, mount_host_path))
+    }
+}
+
+/// Canonicalizes a host path, or returns `None` if that leaves the mount, so that
+/// symlinks are not followed out of it.
+async fn within_mount(host_path: &Path, mount_host_path: &Path) -> Option<PathBuf> {
+    let host_path = tokio::fs::canonicalize(host_path).await.ok()?;
+    host_path.starts_with(mount_host_path).then_some(host_path)
+}
+
+/// Joins path segments into a relative path, or returns `None` if any segment
+/// would escape the directory it is relative to.
+fn relative_path<'a>(segments: impl IntoIterator<Item = &'a str>) -> Option<PathBuf> {
+    let mut path = PathBuf::new();
+    for segment in segments {
+        if segment.is_empty() || segment == "." {
+            continue;
+        }
+        if segment.contains(['/', '\\', '\0']) {
+            return None;
+        }
+        match Path::new(segment).components().next() {
+            Some(Component::Normal(_)) => path.push(segment),
+            _ => return None,
+        }
+    }
+    Some(path)
+}
+
+/// Serves a file, or a precompressed variant of it, honouring conditional and
+/// range requests.
+async fn serve_file(
+    path: &Path,
+    metadata: Metadata,
+    mount_host_path: &Path,
+    method: &Method,
+    headers: &HeaderMap,
+) -> Result<Response<Body>> {
+    let content_type = mime_guess::from_path(path).first_or_octet_stream();
+
+    let mut variant = None;
+    for (extension, encoding) in PRECOMPRESSED {
+        if !accepts_encoding(headers, encoding) {
+            continue;
+        }
+        let mut variant_path = path.as_os_str().to_owned();
+        variant_path.push(format!(".{extension}"));
+        let Some(variant_path) = within_mount(Path::new(&variant_path), mount_host_path).await
+        else {
+            continue;
+        };
+        if let Ok(variant_metadata) = tokio::fs::metadata(&variant_path).await {
+            if variant_metadata.is_file() {
+                variant = Some((variant_path, variant_metadata, *encoding));
+                break;
+            }
+        }
+    }
+    let (path, metadata, encoding) = match &variant {
+        Some((path, metadata, encoding)) => (path.as_path(), metadata, Some(*encoding)),
+        None => (path, &metadata, None),
+    };
+
+    let etag = etag(metadata, encoding);
+    let mut builder = Response::builder()
+        .header(header::ETAG, &etag)
+        .header(header::VARY, "Accept-Encoding");
+
+    if let Some(if_none_match) = headers.get(header::IF_NONE_MATCH) {
+        if etag_matches(if_none_match, &etag) {
+            return Ok(builder
+                .status(StatusCode::NOT_MODIFIED)
+                .body(body::empty())?);
+        }
+    }
+
+    builder = builder
+        .header(header::CONTENT_TYPE, content_type.as_ref())
+        .header(header::ACCEPT_RANGES, "bytes");
+    if let Some(encoding) = encoding {
+        builder = builder.header(header::CONTENT_ENCODING, encoding);
+    }
+
+    let len = metadata.len();
+    let if_range_matches = headers
+        .get(header::IF_RANGE)
+        .is_none_or(|if_range| if_range.as_bytes() == etag.as_bytes());
+    let range = headers
+        .get(header::RANGE)
+        .and_then(|range| range.to_str().ok())
+        .filter(|_| if_range_matches)
+        .and_then(|range| parse_range(range, len));
+    let (start, end) = match range {
+        None => (0, len),
+        Some(ByteRange::Satisfiable { start, end }) => {
+            builder = builder.status(StatusCode::PARTIAL_CONTENT).header(
+                header::CONTENT_RANGE,
+                format!("bytes {start}-{}/{len}", end - 1),
+            );
+            (start, end)
+        }
+        Some(ByteRange::Unsatisfiable) => {
+            return Ok(builder
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(header::CONTENT_RANGE, format!("bytes */{len}"))
+                .body(body::empty())?);
+        }
+    };
+
+    builder = builder.header(header::CONTENT_LENGTH, end - start);
+    if method == Method::HEAD {
+        return Ok(builder.body(body::empty())?);
+    }
+    let mut file = tokio::fs::File::open(path).await?;
+    file.seek(std::io::SeekFrom::Start(start)).await?;
+    Ok(builder.body(file_body(file, end - start))?)
+}
+
+/// Streams `len` bytes from the file's current position.
+fn file_body(file: tokio::fs::File, len: u64) -> Body {
+    let frames = futures::stream::try_unfold((file, len), |(mut file, remaining)| async move {
+        if remaining == 0 {
+            return Ok(None);
+        }
+        let mut chunk = vec![0; remaining.min(CHUNK_SIZE) as usize];
+        file.read_exact(&mut chunk).await?;
+        let remaining = remaining - chunk.len() as u64;
+        Ok::<_, std::io::Error>(Some((Frame::data(Bytes::from(chunk)), (file, remaining))))
+    })
+    .map_err(|e| ErrorCode::InternalError(Some(e.to_string())));
+    StreamBody::new(frames).boxed()
+}
+
+/// An entity tag derived from the file's size and modification time.
+fn etag(metadata: &Metadata, encoding: Option<&str>) -> String {
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .unwrap_or_default();
+    let len = metadata.len();
+    let nanos = modified.as_nanos();
+    match encoding {
+        Some(encoding) => format!("\"{nanos:x}-{len:x}-{encoding}\""),
+        None => format!("\"{nanos:x}-{len:x}\""),
+    }
+}
+
+/// Whether an `If-None-Match` header matches the entity tag, using weak comparison.
+fn etag_matches(if_none_match: &HeaderValue, etag: &str) -> bool {
+    let Ok(if_none_match) = if_none_match.to_str() else {
+        return false;
+    };
+    if_none_match.split(',').map(str::trim).any(|candidate| {
+        candidate == "*" || candidate.strip_prefix("W/").unwrap_or(candidate) == etag
+    })
+}
+
+/// A range of bytes requested by a `Range` header.
+#[derive(Debug, PartialEq, Eq)]
+enum ByteRange {
+    /// The bytes from `start` up to, but excluding, `end`.
+    Satisfiable { start: u64, end: u64 },
+    /// The range doesn't overlap the file.
+    Unsatisfiable,
+}
+
+/// Parses a `Range` header for a file of the given length. Returns `None` if the
+/// header is invalid or requests multiple ranges, in which case the whole file
+/// is served.
+fn parse_range(range: &str, len: u64) -> Option<ByteRange> {
+    let range = range.trim().strip_prefix("bytes=")?;
+    if range.contains(',') {
+        return None;
+    }
+    let (start, end) = range.split_once('-')?;
+    let (start, end) = (start.trim(), end.trim());
+
+    if start.is_empty() {
+        // The last `suffix` bytes.
+        let suffix = end.parse::<u64>().ok()?;
+        if suffix == 0 || len == 0 {
+            return Some(ByteRange::Unsatisfiable);
+        }
+        return Some(ByteRange::Satisfiable {
+            start: len.saturating_sub(suffix),
+            end: len,
+        });
+    }
+
+    let start = start.parse::<u64>().ok()?;
+    let end = match end {
+        "" => len,
+        end => {
+            let last = end.parse::<u64>().ok()?;
+            if last < start {
+                return None;
+            }
+            last.saturating_add(1).min(len)
+        }
+    };
+    if start >= len {
+        return Some(ByteRange::Unsatisfiable);
+    }
+    Some(ByteRange::Satisfiable { start, end })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fixture {
+        dir: tempfile::TempDir,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let dir = tempfile::tempdir().unwrap();
+            std::fs::create_dir_all(dir.path().join("site/docs")).unwrap();
+            std::fs::create_dir_all(dir.path().join("images")).unwrap();
+            std::fs::write(dir.path().join("site/index.html"), "<h1>home</h1>").unwrap();
+            std::fs::write(dir.path().join("site/app.js"), "console.log(1)").unwrap();
+            std::fs::write(dir.path().join("site/app.js.br"), "brotli").unwrap();
+            std::fs::write(dir.path().join("site/app.js.gz"), "gzip").unwrap();
+            std::fs::write(dir.path().join("site/docs/index.html"), "<h1>docs</h1>").unwrap();
+            std::fs::write(dir.path().join("site/my file.txt"), "0123456789").unwrap();
+            std::fs::write(dir.path().join("images/logo.png"), "png").unwrap();
+            std::fs::write(dir.path().join("secret.txt"), "secret").unwrap();
+            Self { dir }
+        }
+
+        fn executor(&self, fallback: Option<&str>) -> StaticFileExecutor {
+            let mounts = [
+                ("/".into(), self.dir.path().join("site")),
+                ("/images".into(), self.dir.path().join("images")),
+            ];
+            let config = StaticTriggerConfig {
+                root: "/".into(),
+                fallback: fallback.map(str::to_owned),
+            };
+            StaticFileExecutor::from_mounts(mounts, &config).unwrap()
+        }
+    }
+
+    async fn get(
+        executor: &StaticFileExecutor,
+        path: &str,
+        headers: &[(&str, &str)],
+    ) -> (StatusCode, HeaderMap, String) {
+        let mut req = Request::get(format!("http://localhost{path}"));
+        for (name, value) in headers {
+            req = req.header(*name, *value);
+        }
+        let req = req.body(body::empty()).unwrap();
+        let route_match = RouteMatch::synthetic("static".into(), path.into());
+        let (parts, body) = executor
+            .execute(&route_match, req)
+            .await
+            .unwrap()
+            .into_parts();
+        let body = body.collect().await.unwrap().to_bytes();
+        (
+            parts.status,
+            parts.headers,
+            String::from_utf8(body.to_vec()).unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn serves_files_and_directory_indexes() {
+        let fixture = Fixture::new();
+        let executor = fixture.executor(None);
+
+        let (status, headers, body) = get(&executor, "/app.js", &[]).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "console.log(1)");
+        assert_eq!(headers[header::CONTENT_TYPE], "text/javascript");
+        assert_eq!(headers[header::CONTENT_LENGTH], "14");
+
+        let (status, headers, body) = get(&executor, "/", &[]).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "<h1>home</h1>");
+        assert_eq!(headers[header::CONTENT_TYPE], "text/html");
+
+        let (_, _, body) = get(&executor, "/docs/", &[]).await;
+        assert_eq!(body, "<h1>docs</h1>");
+
+        let (_, _, body) = get(&executor, "/my%20file.txt", &[]).await;
+        assert_eq!(body, "0123456789");
+
+        // Files are found in the most specific mount.
+        let (status, headers, body) = get(&executor, "/images/logo.png", &[]).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "png");
+        assert_eq!(headers[header::CONTENT_TYPE], "image/png");
+    }
+
+    #[tokio::test]
+    async fn missing_files_are_not_found_without_fallback() {
+        let fixture = Fixture::new();
+        let executor = fixture.executor(None);
+
+        let (status, _, _) = get(&executor, "/missing", &[]).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn missing_files_are_served_the_fallback() {
+        let fixture = Fixture::new();
+        let executor = fixture.executor(Some("index.html"));
+
+        let (status, _, body) = get(&executor, "/app/settings/profile", &[]).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "<h1>home</h1>");
+    }
+
+    #[tokio::test]
+    async fn paths_cannot_escape_the_mounts() {
+        let fixture = Fixture::new();
+        let executor = fixture.executor(None);
+
+        for path in ["/../secret.txt", "/%2e%2e/secret.txt", "/..%2fsecret.txt"] {
+            let (status, _, _) = get(&executor, path, &[]).await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{path}");
+        }
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(
+                fixture.dir.path().join("secret.txt"),
+                fixture.dir.path().join("site/link.txt"),
+            )
+            .unwrap();
+            let (status, _, _) = get(&executor, "/link.txt", &[]).await;
+            assert_eq!(status, StatusCode::NOT_FOUND);
+
+            // Precompressed variants are held to the same containment check.
+            std::fs::remove_file(fixture.dir.path().join("site/app.js.br")).unwrap();
+            std::os::unix::fs::symlink(
+                fixture.dir.path().join("secret.txt"),
+                fixture.dir.path().join("site/app.js.br"),
+            )
+            .unwrap();
+            let (_, headers, body) = get(&executor, "/app.js", &[("accept-encoding", "br")]).await;
+            assert_eq!(body, "console.log(1)");
+            assert!(!headers.contains_key(header::CONTENT_ENCODING));
+        }
+    }
+
+    #[tokio::test]
+    async fn only_get_and_head_are_allowed() {
+        let fixture = Fixture::new();
+        let executor = fixture.executor(None);
+
+        let req = Request::post("http://localhost/app.js")
+            .body(body::empty())
+            .unwrap();
+        let route_match = RouteMatch::synthetic("static".into(), "/app.js".into());
+        let res = executor.execute(&route_match, req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(res.headers()[header::ALLOW], "GET, HEAD");
+
+        let req = Request::head("http://localhost/app.js")
+            .body(body::empty())
+            .unwrap();
+        let res = executor.execute(&route_match, req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[header::CONTENT_LENGTH], "14");
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert!(body.is_empty());
+    }
+
+    #[tokio::test]
+    async fn etags_support_conditional_requests() {
+        let fixture = Fixture::new();
+        let executor = fixture.executor(None);
+
+        let (_, headers, _) = get(&executor, "/app.js", &[]).await;
+        let etag = headers[header::ETAG].to_str().unwrap().to_owned();
+
+        let (status, headers, body) = get(&executor, "/app.js", &[("if-none-match", &etag)]).await;
+        assert_eq!(status, StatusCode::NOT_MODIFIED);
+        assert_eq!(headers[header::ETAG], etag.as_str());
+        assert!(body.is_empty());
+
+        let weak = format!("\"other\", W/{etag}");
+        let (status, _, _) = get(&executor, "/app.js", &[("if-none-match", &weak)]).await;
+        assert_eq!(status, StatusCode::NOT_MODIFIED);
+
+        let (status, _, _) = get(&executor, "/app.js", &[("if-none-match", "\"other\"")]).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn precompressed_variants_are_preferred() {
+        let fixture = Fixture::new();
+        let executor = fixture.executor(None);
+
+        let (_, headers, body) =
+            get(&executor, "/app.js", &[("accept-encoding", "gzip, br")]).await;
+        assert_eq!(body, "brotli");
+        assert_eq!(headers[header::CONTENT_ENCODING], "br");
+        assert_eq!(headers[header::CONTENT_TYPE], "text/javascript");
+        assert_eq!(headers[header::VARY], "Accept-Encoding");
+
+        let (_, headers, body) =
+            get(&executor, "/app.js", &[("accept-encoding", "gzip, br;q=0")]).await;
+        assert_eq!(body, "gzip");
+        assert_eq!(headers[header::CONTENT_ENCODING], "gzip");
+
+        let (_, headers, body) = get(&executor, "/app.js", &[("accept-encoding", "deflate")]).await;
+        assert_eq!(body, "console.log(1)");
+        assert!(!headers.contains_key(header::CONTENT_ENCODING));
+
+        // Files without variants are served as they are.
+        let (_, headers, body) = get(&executor, "/", &[("accept-encoding", "br")]).await;
+        assert_eq!(body, "<h1>home</h1>");
+        assert!(!headers.contains_key(header::CONTENT_ENCODING));
+    }
+
+    #[tokio::test]
+    async fn range_requests_are_served_partially() {
+        let fixture = Fixture::new();
+        let executor = fixture.executor(None);
+        let path = "/my%20file.txt";
+
+        let (status, headers, body) = get(&executor, path, &[("range", "bytes=2-4")]).await;
+        assert_eq!(status, StatusCode::PARTIAL_CONTENT);
+        assert_eq!(body, "234");
+        assert_eq!(headers[header::CONTENT_RANGE], "bytes 2-4/10");
+        assert_eq!(headers[header::CONTENT_LENGTH], "3");
+
+        let (_, _, body) = get(&executor, path, &[("range", "bytes=7-")]).await;
+        assert_eq!(body, "789");
+
+        let (_, _, body) = get(&executor, path, &[("range", "bytes=-2")]).await;
+        assert_eq!(body, "89");
+
+        let (status, headers, _) = get(&executor, path, &[("range", "bytes=10-")]).await;
+        assert_eq!(status, StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(headers[header::CONTENT_RANGE], "bytes */10");
+
+        // A stale `If-Range` gets the whole file.
+        let (status, _, body) = get(
+            &executor,
+            path,
+            &[("range", "bytes=2-4"), ("if-range", "\"stale\"")],
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "0123456789");
+    }
+
+    #[test]
+    fn ranges_are_parsed() {
+        let satisfiable = |start, end| Some(ByteRange::Satisfiable { start, end });
+        assert_eq!(parse_range("bytes=0-0", 10), satisfiable(0, 1));
+        assert_eq!(parse_range("bytes=5-100", 10), satisfiable(5, 10));
+        assert_eq!(parse_range("bytes=-100", 10), satisfiable(0, 10));
+        assert_eq!(parse_range("bytes=-0", 10), Some(ByteRange::Unsatisfiable));
+        assert_eq!(parse_range("bytes=0-", 0), Some(ByteRange::Unsatisfiable));
+        assert_eq!(parse_range("bytes=4-2", 10), None);
+        assert_eq!(parse_range("bytes=0-1,4-5", 10), None);
+        assert_eq!(parse_range("items=0-1", 10), None);
+        assert_eq!(parse_range("bytes=a-b", 10), None);
+    }
+
+    #[test]
+    fn invalid_config_is_rejected() {
+        let fixture = Fixture::new();
+        let mounts = || [("/".into(), fixture.dir.path().join("site"))];
+        let config = |root: &str, fallback: Option<&str>| StaticTriggerConfig {
+            root: root.into(),
+            fallback: fallback.map(str::to_owned),
+        };
+
+        assert!(StaticFileExecutor::from_mounts(mounts(), &config("dist", None)).is_err());
+        assert!(
+            StaticFileExecutor::from_mounts(mounts(), &config("/", Some("../index.html"))).is_err()
+        );
+        assert!(StaticFileExecutor::from_mounts([], &config("/", None)).is_err());
+        assert!(StaticFileExecutor::from_mounts(
+            [("/".into(), fixture.dir.path().join("secret.txt"))],
+            &config("/", None)
+        )
+        .is_err());
+    }
+}
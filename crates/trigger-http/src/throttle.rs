@@ -0,0 +1,381 @@
+//! Per-trigger concurrency caps and rate limits.
+
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use anyhow::ensure;
+use http::{header, Response, StatusCode};
+use http_body_util::BodyExt;
+use hyper::body::{Body as HttpBody, Bytes, Frame, SizeHint};
+use spin_http::{
+    body,
+    config::{HttpRateLimitConfig, HttpRateLimitKey},
+};
+use tokio::{
+    sync::{OwnedSemaphorePermit, Semaphore},
+    time::Instant,
+};
+use wasmtime_wasi_http::bindings::http::types::ErrorCode;
+
+use crate::Body;
+
+/// How long clients are asked to wait before retrying when a component is at
+/// its concurrency limit.
+const CONCURRENCY_RETRY_AFTER: Duration = Duration::from_secs(1);
+
+/// Once a per-client rate limiter tracks this many clients, clients whose
+/// buckets have refilled are forgotten, and failing that, the client whose
+/// bucket was least recently used.
+const MAX_CLIENTS: usize = 10_000;
+
+/// Admits or turns away requests to a component according to its trigger's
+/// `max_concurrency` and `rate_limit`.
+pub(crate) struct Throttle {
+    concurrency: Option<Arc<Semaphore>>,
+    rate_limit: Option<RateLimiter>,
+}
+
+impl Throttle {
+    /// Creates a throttle for a trigger, or `None` if it has no limits.
+    pub fn new(
+        max_concurrency: Option<usize>,
+        rate_limit: Option<&HttpRateLimitConfig>,
+    ) -> anyhow::Result<Option<Self>> {
+        if max_concurrency.is_none() && rate_limit.is_none() {
+            return Ok(None);
+        }
+        let concurrency = max_concurrency
+            .map(|max| {
+                ensure!(max > 0, "`max_concurrency` must be greater than zero");
+                Ok(Arc::new(Semaphore::new(max)))
+            })
+            .transpose()?;
+        let rate_limit = rate_limit.map(RateLimiter::new).transpose()?;
+        Ok(Some(Self {
+            concurrency,
+            rate_limit,
+        }))
+    }
+
+    /// Admits a request from the client if it is within the limits, returning
+    /// the permit to hold while the request is in progress.
+    pub fn admit(&self, client_ip: IpAddr) -> Result<Admission, Throttled> {
+        // Check concurrency first so that requests turned away don't use up the rate.
+        let permit = match &self.concurrency {
+            Some(semaphore) => Some(
+                semaphore
+                    .clone()
+                    .try_acquire_owned()
+                    .map_err(|_| Throttled::AtCapacity)?,
+            ),
+            None => None,
+        };
+        if let Some(rate_limit) = &self.rate_limit {
+            rate_limit.acquire(client_ip, Instant::now())?;
+        }
+        Ok(Admission { permit })
+    }
+}
+
+/// A request admitted by a [`Throttle`].
+pub(crate) struct Admission {
+    permit: Option<OwnedSemaphorePermit>,
+}
+
+impl Admission {
+    /// Holds the admission until the response body has been sent, as the
+    /// component may still be running while it streams the body.
+    pub fn hold_until_sent(self, res: Response<Body>) -> Response<Body> {
+        match self.permit {
+            Some(permit) => res.map(|inner| {
+                PermitBody {
+                    inner,
+                    _permit: permit,
+                }
+                .boxed()
+            }),
+            None => res,
+        }
+    }
+}
+
+/// The reason a request was turned away by a [`Throttle`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Throttled {
+    /// The component is handling as many requests as it may.
+    AtCapacity,
+    /// The request rate limit was exceeded.
+    RateLimited {
+        /// When a request would next be admitted.
+        retry_after: Duration,
+    },
+}
+
+impl Throttled {
+    /// The value recorded as the request span's `error.type`, and as the
+    /// `reason` of the throttled request metric.
+    pub fn error_type(self) -> &'static str {
+        match self {
+            Self::AtCapacity => "concurrency_limit",
+            Self::RateLimited { .. } => "rate_limit",
+        }
+    }
+
+    /// Creates the response for a throttled request.
+    pub fn response(self) -> anyhow::Result<Response<Body>> {
+        let (status, retry_after) = match self {
+            Self::AtCapacity => (StatusCode::SERVICE_UNAVAILABLE, CONCURRENCY_RETRY_AFTER),
+            Self::RateLimited { retry_after } => (StatusCode::TOO_MANY_REQUESTS, retry_after),
+        };
+        // Retry-After is in whole seconds, so round up to avoid early retries.
+        let retry_after_secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+        Ok(Response::builder()
+            .status(status)
+            .header(header::RETRY_AFTER, retry_after_secs.max(1))
+            .body(body::empty())?)
+    }
+}
+
+/// A token bucket rate limiter, with a bucket per client IP address if
+/// configured.
+struct RateLimiter {
+    /// Tokens added per second.
+    rate: f64,
+    /// The maximum number of tokens in a bucket.
+    burst: f64,
+    key: HttpRateLimitKey,
+    buckets: Mutex<HashMap<Option<IpAddr>, Bucket>>,
+    /// The number of clients to track buckets for.
+    max_clients: usize,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    fn new(config: &HttpRateLimitConfig) -> anyhow::Result<Self> {
+        let rate = config.requests_per_second;
+        ensure!(
+            rate.is_finite() && rate > 0.0,
+            "`rate_limit.requests_per_second` must be greater than zero"
+        );
+        let burst = match config.burst {
+            Some(burst) => {
+                ensure!(burst > 0, "`rate_limit.burst` must be greater than zero");
+                f64::from(burst)
+            }
+            None => rate.ceil(),
+        };
+        Ok(Self {
+            rate,
+            burst,
+            key: config.key,
+            buckets: Default::default(),
+            max_clients: MAX_CLIENTS,
+        })
+    }
+
+    /// Takes a token from the client's bucket.
+    fn acquire(&self, client_ip: IpAddr, now: Instant) -> Result<(), Throttled> {
+        let key = match self.key {
+            HttpRateLimitKey::Global => None,
+            HttpRateLimitKey::ClientIp => Some(client_ip),
+        };
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= self.max_clients && !buckets.contains_key(&key) {
+            buckets.retain(|_, bucket| self.refilled(bucket, now) < self.burst);
+            // Forgetting a client gives it a full bucket should it return,
+            // which is better than tracking every address a client can use.
+            if buckets.len() >= self.max_clients {
+                let least_recent = buckets
+                    .iter()
+                    .min_by_key(|(_, bucket)| bucket.updated)
+                    .map(|(key, _)| *key);
+                if let Some(least_recent) = least_recent {
+                    buckets.remove(&least_recent);
+                }
+            }
+        }
+        let bucket = buckets.entry(key).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        bucket.tokens = self.refilled(bucket, now);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            let retry_after = Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate);
+            Err(Throttled::RateLimited { retry_after })
+        }
+    }
+
+    /// The number of tokens in the bucket at `now`.
+    fn refilled(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        (bucket.tokens + elapsed * self.rate).min(self.burst)
+    }
+}
+
+/// A response body which holds a concurrency permit until it is dropped.
+struct PermitBody {
+    inner: Body,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl HttpBody for PermitBody {
+    type Data = Bytes;
+    type Error = ErrorCode;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        Pin::new(&mut self.inner).poll_frame(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rate_limit(requests_per_second: f64, burst: Option<u32>) -> HttpRateLimitConfig {
+        HttpRateLimitConfig {
+            requests_per_second,
+            burst,
+            key: HttpRateLimitKey::Global,
+        }
+    }
+
+    #[test]
+    fn invalid_limits_are_rejected() {
+        assert!(Throttle::new(None, None).unwrap().is_none());
+        assert!(Throttle::new(Some(0), None).is_err());
+        assert!(Throttle::new(None, Some(&rate_limit(0.0, None))).is_err());
+        assert!(Throttle::new(None, Some(&rate_limit(f64::NAN, None))).is_err());
+        assert!(Throttle::new(None, Some(&rate_limit(1.0, Some(0)))).is_err());
+    }
+
+    #[tokio::test]
+    async fn concurrency_is_capped_until_responses_are_sent() {
+        let throttle = Throttle::new(Some(1), None).unwrap().unwrap();
+        let ip = IpAddr::from([127, 0, 0, 1]);
+
+        let admission = throttle.admit(ip).unwrap();
+        assert_eq!(throttle.admit(ip).err(), Some(Throttled::AtCapacity));
+
+        let res = admission.hold_until_sent(Response::new(body::full("hi".into())));
+        assert_eq!(throttle.admit(ip).err(), Some(Throttled::AtCapacity));
+
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "hi");
+        assert!(throttle.admit(ip).is_ok());
+    }
+
+    #[test]
+    fn tokens_refill_at_the_configured_rate() {
+        let limiter = RateLimiter::new(&rate_limit(2.0, Some(3))).unwrap();
+        let ip = IpAddr::from([127, 0, 0, 1]);
+        let start = Instant::now();
+
+        for _ in 0..3 {
+            limiter.acquire(ip, start).unwrap();
+        }
+        let Err(Throttled::RateLimited { retry_after }) = limiter.acquire(ip, start) else {
+            panic!("expected the burst to be exhausted");
+        };
+        assert_eq!(retry_after, Duration::from_millis(500));
+
+        limiter
+            .acquire(ip, start + Duration::from_millis(500))
+            .unwrap();
+        limiter
+            .acquire(ip, start + Duration::from_millis(500))
+            .unwrap_err();
+        // Refills don't exceed the burst size
+        let later = start + Duration::from_secs(60);
+        for _ in 0..3 {
+            limiter.acquire(ip, later).unwrap();
+        }
+        limiter.acquire(ip, later).unwrap_err();
+    }
+
+    #[test]
+    fn client_ip_limits_are_independent() {
+        let limiter = RateLimiter::new(&HttpRateLimitConfig {
+            key: HttpRateLimitKey::ClientIp,
+            ..rate_limit(1.0, None)
+        })
+        .unwrap();
+        let now = Instant::now();
+        let alice = IpAddr::from([10, 0, 0, 1]);
+        let bob = IpAddr::from([10, 0, 0, 2]);
+
+        limiter.acquire(alice, now).unwrap();
+        limiter.acquire(alice, now).unwrap_err();
+        limiter.acquire(bob, now).unwrap();
+    }
+
+    #[test]
+    fn tracked_clients_are_capped() {
+        let mut limiter = RateLimiter::new(&HttpRateLimitConfig {
+            key: HttpRateLimitKey::ClientIp,
+            ..rate_limit(1.0, None)
+        })
+        .unwrap();
+        limiter.max_clients = 2;
+        let start = Instant::now();
+        let client = |n: u8| IpAddr::from([10, 0, 0, n]);
+
+        // No client's bucket refills, so the least recently used is evicted.
+        for n in 0..100 {
+            let now = start + Duration::from_millis(n.into());
+            limiter.acquire(client(n), now).unwrap();
+            assert!(limiter.buckets.lock().unwrap().len() <= 2);
+        }
+        let now = start + Duration::from_millis(100);
+        limiter.acquire(client(99), now).unwrap_err();
+        limiter.acquire(client(98), now).unwrap_err();
+        // Having been evicted, a client starts over with a full bucket.
+        limiter.acquire(client(0), now).unwrap();
+        limiter.acquire(client(99), now).unwrap_err();
+        assert!(!limiter
+            .buckets
+            .lock()
+            .unwrap()
+            .contains_key(&Some(client(98))));
+    }
+
+    #[test]
+    fn throttled_responses_ask_clients_to_retry() {
+        let res = Throttled::AtCapacity.response().unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(res.headers()[header::RETRY_AFTER], "1");
+
+        let res = Throttled::RateLimited {
+            retry_after: Duration::from_millis(2100),
+        }
+        .response()
+        .unwrap();
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(res.headers()[header::RETRY_AFTER], "3");
+    }
+}
@@ -1,37 +1,349 @@
-use anyhow::Context;
-use rustls_pki_types::pem::PemObject;
+use anyhow::{bail, Context};
+use rustls_pki_types::{pem::PemObject, CertificateDer};
 use std::{
+    collections::HashMap,
+    future::Future,
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+use tokio_rustls::{
+    rustls::{
+        self,
+        crypto::CryptoProvider,
+        server::{ClientHello, ResolvesServerCert, ServerConnection, WebPkiClientVerifier},
+        sign::CertifiedKey,
+        InconsistentKeys, RootCertStore,
+    },
+    TlsAcceptor,
 };
-use tokio_rustls::{rustls, TlsAcceptor};
 
 // TODO: dedupe with spin-factor-outbound-networking (spin-tls crate?)
 
+/// How often certificate files are checked for changes.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The file extension of certificates in [`TlsConfig::cert_dir`].
+const CERT_EXTENSION: &str = "crt";
+/// The file extension of private keys in [`TlsConfig::cert_dir`].
+const KEY_EXTENSION: &str = "key";
+
+/// The file name prefix of wildcard certificates in [`TlsConfig::cert_dir`], standing in
+/// for the `*` label, which isn't allowed in file names on every platform.
+const WILDCARD_PREFIX: &str = "_wildcard.";
+
 /// TLS configuration for the server.
-#[derive(Clone)]
+///
+/// Certificates are reloaded when their files change on disk.
+#[derive(Clone, Debug)]
 pub struct TlsConfig {
-    /// Path to TLS certificate.
-    pub cert_path: PathBuf,
-    /// Path to TLS key.
-    pub key_path: PathBuf,
+    /// Paths to the default TLS certificate and key.
+    default_cert: Option<(PathBuf, PathBuf)>,
+    /// Directory of certificates selected by SNI hostname.
+    cert_dir: Option<PathBuf>,
+    /// Verification of client certificates. If unset, client certificates are not requested.
+    client_auth: Option<ClientAuthConfig>,
+}
+
+/// Configuration for verifying client certificates (mutual TLS).
+#[derive(Clone, Debug)]
+pub struct ClientAuthConfig {
+    /// Path to the CA certificates which client certificates must chain to.
+    pub ca_path: PathBuf,
+    /// Whether connections without a client certificate are rejected.
+    pub required: bool,
 }
 
 impl TlsConfig {
-    // Creates a TLS acceptor from server config.
-    pub(super) fn server_config(&self) -> anyhow::Result<TlsAcceptor> {
-        let certs = load_certs(&self.cert_path)?;
-        let private_key = load_key(&self.key_path)?;
+    /// Creates a configuration serving the given certificate and key.
+    pub fn new(cert_path: impl Into<PathBuf>, key_path: impl Into<PathBuf>) -> Self {
+        Self {
+            default_cert: Some((cert_path.into(), key_path.into())),
+            cert_dir: None,
+            client_auth: None,
+        }
+    }
+
+    /// Creates a configuration serving only the certificates in `cert_dir`, as described
+    /// in [`Self::with_cert_dir`].
+    pub fn from_cert_dir(cert_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            default_cert: None,
+            cert_dir: Some(cert_dir.into()),
+            client_auth: None,
+        }
+    }
+
+    /// Selects certificates from `cert_dir` by SNI hostname.
+    ///
+    /// Each certificate `<hostname>.crt` is paired with the private key `<hostname>.key`.
+    /// A certificate `_wildcard.example.com.crt` matches any single-label subdomain of
+    /// `example.com`. Connections whose hostname matches no certificate in the directory
+    /// use the default certificate.
+    pub fn with_cert_dir(mut self, cert_dir: impl Into<PathBuf>) -> Self {
+        self.cert_dir = Some(cert_dir.into());
+        self
+    }
+
+    /// Requests client certificates, verifying them as `client_auth` configures.
+    pub fn with_client_auth(mut self, client_auth: ClientAuthConfig) -> Self {
+        self.client_auth = Some(client_auth);
+        self
+    }
+
+    /// Creates a TLS acceptor from server config, and a future which reloads its
+    /// certificates whenever they change on disk.
+    pub(super) fn acceptor(
+        &self,
+        alpn_protocols: Vec<Vec<u8>>,
+    ) -> anyhow::Result<(TlsAcceptor, impl Future<Output = ()> + Send + 'static)> {
+        let (mut cfg, reload) = self.server_config()?;
+        cfg.alpn_protocols = alpn_protocols;
+        Ok((Arc::new(cfg).into(), reload))
+    }
+
+    /// Creates the server config, and a future which reloads its certificates whenever
+    /// they change on disk.
+    pub(super) fn server_config(
+        &self,
+    ) -> anyhow::Result<(
+        rustls::ServerConfig,
+        impl Future<Output = ()> + Send + 'static,
+    )> {
+        let builder = rustls::ServerConfig::builder();
+        let resolver = Arc::new(CertResolver {
+            certs: RwLock::new(Certificates::load(self, builder.crypto_provider())?),
+            provider: builder.crypto_provider().clone(),
+            config: self.clone(),
+        });
+        let builder = match &self.client_auth {
+            Some(client_auth) => {
+                let verifier = client_auth.verifier(builder.crypto_provider())?;
+                builder.with_client_cert_verifier(verifier)
+            }
+            None => builder.with_no_client_auth(),
+        };
+        let cfg = builder.with_cert_resolver(resolver.clone());
+        Ok((cfg, resolver.reload_on_change()))
+    }
+
+    /// The certificate files, and when they were last modified.
+    fn file_versions(&self) -> Vec<(PathBuf, Option<SystemTime>)> {
+        let dir_entries = self
+            .cert_dir
+            .iter()
+            .filter_map(|dir| std::fs::read_dir(dir).ok())
+            .flatten()
+            .filter_map(|entry| Some(entry.ok()?.path()));
+        let mut files: Vec<_> = self
+            .default_cert
+            .iter()
+            .flat_map(|(cert_path, key_path)| [cert_path.clone(), key_path.clone()])
+            .chain(dir_entries)
+            .map(|path| {
+                let modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok();
+                (path, modified)
+            })
+            .collect();
+        files.sort();
+        files
+    }
+}
+
+impl ClientAuthConfig {
+    fn verifier(
+        &self,
+        provider: &Arc<CryptoProvider>,
+    ) -> anyhow::Result<Arc<dyn rustls::server::danger::ClientCertVerifier>> {
+        let mut roots = RootCertStore::empty();
+        for cert in load_certs(&self.ca_path)? {
+            roots.add(cert).with_context(|| {
+                format!(
+                    "invalid client CA certificate in '{}'",
+                    self.ca_path.display()
+                )
+            })?;
+        }
+        let builder =
+            WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider.clone());
+        let builder = if self.required {
+            builder
+        } else {
+            builder.allow_unauthenticated()
+        };
+        builder
+            .build()
+            .context("failed to build client certificate verifier")
+    }
+}
+
+/// A verified client certificate, added to the extensions of requests received over
+/// the connection it was presented on.
+#[derive(Clone, Debug)]
+pub(crate) struct ClientCertificate {
+    /// The certificate's subject distinguished name, e.g. `CN=client, O=Example`.
+    pub subject: String,
+}
+
+impl ClientCertificate {
+    /// Returns the certificate the client presented on a connection, if any.
+    pub fn from_connection(conn: &ServerConnection) -> Option<Self> {
+        Self::from_chain(conn.peer_certificates()?)
+    }
 
-        let cfg = rustls::ServerConfig::builder()
-            .with_no_client_auth()
-            .with_single_cert(certs, private_key)
-            .map_err(|e| anyhow::anyhow!("{}", e))?;
+    /// Returns the end-entity certificate of a verified client certificate chain.
+    pub fn from_chain(chain: &[CertificateDer<'_>]) -> Option<Self> {
+        Self::from_der(chain.first()?)
+            .inspect_err(|err| tracing::warn!("Failed to parse client certificate: {err}"))
+            .ok()
+    }
 
-        Ok(Arc::new(cfg).into())
+    fn from_der(cert: &[u8]) -> anyhow::Result<Self> {
+        let (_, cert) = x509_parser::parse_x509_certificate(cert)?;
+        Ok(Self {
+            subject: cert.subject().to_string(),
+        })
     }
 }
 
+/// The certificates served by the server.
+#[derive(Debug, Default)]
+struct Certificates {
+    /// The certificate for connections which match no SNI certificate.
+    default: Option<Arc<CertifiedKey>>,
+    /// Lowercase hostname -> certificate
+    by_hostname: HashMap<String, Arc<CertifiedKey>>,
+}
+
+impl Certificates {
+    fn load(config: &TlsConfig, provider: &CryptoProvider) -> anyhow::Result<Self> {
+        let default = match &config.default_cert {
+            Some((cert_path, key_path)) => Some(load_certified_key(cert_path, key_path, provider)?),
+            None => None,
+        };
+
+        let mut by_hostname = HashMap::new();
+        if let Some(dir) = &config.cert_dir {
+            let entries = std::fs::read_dir(dir).with_context(|| {
+                format!("failed to read certificate directory '{}'", dir.display())
+            })?;
+            for entry in entries {
+                let cert_path = entry?.path();
+                if cert_path
+                    .extension()
+                    .is_none_or(|ext| ext != CERT_EXTENSION)
+                {
+                    continue;
+                }
+                let Some(stem) = cert_path.file_stem().and_then(|s| s.to_str()) else {
+                    continue;
+                };
+                let hostname = match stem.strip_prefix(WILDCARD_PREFIX) {
+                    Some(domain) => format!("*.{domain}"),
+                    None if stem.contains('*') => continue,
+                    None => stem.to_owned(),
+                };
+                let key_path = cert_path.with_extension(KEY_EXTENSION);
+                let certified_key = load_certified_key(&cert_path, &key_path, provider)?;
+                by_hostname.insert(hostname.to_ascii_lowercase(), certified_key);
+            }
+        }
+
+        if default.is_none() && by_hostname.is_empty() {
+            bail!("no TLS certificates were found");
+        }
+        Ok(Self {
+            default,
+            by_hostname,
+        })
+    }
+
+    /// Finds the certificate for a hostname, falling back to the default.
+    fn lookup(&self, hostname: Option<&str>) -> Option<Arc<CertifiedKey>> {
+        let from_sni = hostname.and_then(|hostname| {
+            let hostname = hostname.to_ascii_lowercase();
+            self.by_hostname.get(&hostname).or_else(|| {
+                let (_, parent) = hostname.split_once('.')?;
+                self.by_hostname.get(&format!("*.{parent}"))
+            })
+        });
+        from_sni.or(self.default.as_ref()).cloned()
+    }
+}
+
+/// Resolves certificates by SNI hostname, reloading them when their files change.
+#[derive(Debug)]
+struct CertResolver {
+    certs: RwLock<Certificates>,
+    provider: Arc<CryptoProvider>,
+    config: TlsConfig,
+}
+
+impl CertResolver {
+    /// Polls the certificate files for changes, reloading the certificates when they do.
+    ///
+    /// If the changed certificates fail to load, the previous ones remain in use.
+    async fn reload_on_change(self: Arc<Self>) {
+        let mut versions = self.config.file_versions();
+        let mut interval = tokio::time::interval(RELOAD_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            let current = self.config.file_versions();
+            if current == versions {
+                continue;
+            }
+            versions = current;
+            match Certificates::load(&self.config, &self.provider) {
+                Ok(certs) => {
+                    tracing::info!("Reloaded TLS certificates");
+                    *self.certs.write().unwrap() = certs;
+                }
+                Err(err) => {
+                    tracing::error!("Failed to reload TLS certificates: {err:?}");
+                }
+            }
+        }
+    }
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        self.certs
+            .read()
+            .unwrap()
+            .lookup(client_hello.server_name())
+    }
+}
+
+// load_certified_key loads a certificate chain and its private key
+fn load_certified_key(
+    cert_path: &Path,
+    key_path: &Path,
+    provider: &CryptoProvider,
+) -> anyhow::Result<Arc<CertifiedKey>> {
+    let certs = load_certs(cert_path)?;
+    let private_key = provider
+        .key_provider
+        .load_private_key(load_key(key_path)?)
+        .with_context(|| format!("unsupported private key '{}'", key_path.display()))?;
+    let certified_key = CertifiedKey::new(certs, private_key);
+    match certified_key.keys_match() {
+        // Don't treat unknown consistency as an error
+        Ok(()) | Err(rustls::Error::InconsistentKeys(InconsistentKeys::Unknown)) => (),
+        Err(err) => {
+            return Err(err).with_context(|| {
+                format!(
+                    "certificate '{}' does not match private key '{}'",
+                    cert_path.display(),
+                    key_path.display()
+                )
+            })
+        }
+    }
+    Ok(Arc::new(certified_key))
+}
+
 // load_certs parse and return the certs from the provided file
 fn load_certs(
     path: impl AsRef<Path>,
@@ -112,4 +424,77 @@ mod tests {
         let path = Path::new(TESTDATA_DIR).join("valid-private-key.pem");
         load_key(path).unwrap();
     }
+
+    fn write_cert_pair(dir: &Path, hostname: &str) {
+        let testdata = Path::new(TESTDATA_DIR);
+        std::fs::copy(
+            testdata.join("valid-cert.pem"),
+            dir.join(format!("{hostname}.crt")),
+        )
+        .unwrap();
+        std::fs::copy(
+            testdata.join("valid-private-key.pem"),
+            dir.join(format!("{hostname}.key")),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_lookup_by_sni_hostname() {
+        let dir = tempfile::tempdir().unwrap();
+        write_cert_pair(dir.path(), "example.com");
+        write_cert_pair(dir.path(), "_wildcard.example.org");
+        let config = TlsConfig::from_cert_dir(dir.path());
+        let provider = rustls::ServerConfig::builder().crypto_provider().clone();
+        let certs = Certificates::load(&config, &provider).unwrap();
+
+        assert!(certs.lookup(Some("EXAMPLE.com")).is_some());
+        assert!(certs.lookup(Some("api.example.org")).is_some());
+        assert!(certs.lookup(Some("example.org")).is_none());
+        assert!(certs.lookup(Some("a.b.example.org")).is_none());
+        assert!(certs.lookup(None).is_none());
+    }
+
+    #[test]
+    fn test_cert_dir_requires_matching_key() {
+        let dir = tempfile::tempdir().unwrap();
+        write_cert_pair(dir.path(), "example.com");
+        std::fs::remove_file(dir.path().join("example.com.key")).unwrap();
+        let config = TlsConfig::from_cert_dir(dir.path());
+        let provider = rustls::ServerConfig::builder().crypto_provider().clone();
+        Certificates::load(&config, &provider).unwrap_err();
+    }
+
+    #[test]
+    fn test_client_certificate_subject() {
+        let path = Path::new(TESTDATA_DIR).join("valid-cert.pem");
+        let cert = load_certs(path).unwrap().remove(0);
+        let client_cert = ClientCertificate::from_der(&cert).unwrap();
+        assert_eq!(client_cert.subject, "O=system:masters, CN=system:admin");
+    }
+
+    #[test]
+    fn test_client_auth_requires_valid_ca() {
+        let provider = rustls::ServerConfig::builder().crypto_provider().clone();
+        let client_auth = ClientAuthConfig {
+            ca_path: Path::new(TESTDATA_DIR).join("valid-cert.pem"),
+            required: true,
+        };
+        client_auth.verifier(&provider).unwrap();
+
+        let client_auth = ClientAuthConfig {
+            ca_path: Path::new(TESTDATA_DIR).join("invalid-cert.pem"),
+            required: false,
+        };
+        client_auth.verifier(&provider).unwrap_err();
+    }
+
+    #[test]
+    fn test_file_versions_track_new_certs() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = TlsConfig::from_cert_dir(dir.path());
+        let before = config.file_versions();
+        write_cert_pair(dir.path(), "example.com");
+        assert_ne!(before, config.file_versions());
+    }
 }
@@ -11,7 +11,10 @@ use wasmtime_wasi::p2::bindings::CommandIndices;
 use wasmtime_wasi::p2::pipe::MemoryOutputPipe;
 use wasmtime_wasi_http::body::HyperIncomingBody as Body;
 
-use crate::{headers::compute_default_headers, server::HttpExecutor, TriggerInstanceBuilder};
+use crate::{
+    headers::compute_default_headers, server::HttpExecutor, tls::ClientCertificate,
+    TriggerInstanceBuilder,
+};
 
 pub struct WagiHttpExecutor<'a> {
     pub wagi_config: &'a WagiTriggerConfig,
@@ -70,7 +73,10 @@ impl HttpExecutor for WagiHttpExecutor<'_> {
         // This sets the current environment variables Wagi expects (such as
         // `PATH_INFO`, or `X_FULL_URL`).
         // Note that this overrides any existing headers previously set by Wagi.
-        for (keys, val) in compute_default_headers(&parts.uri, host, route_match, client_addr)? {
+        let client_cert = parts.extensions.get::<ClientCertificate>();
+        for (keys, val) in
+            compute_default_headers(&parts.uri, host, route_match, client_addr, client_cert)?
+        {
             headers.insert(keys[1].to_string(), val.into_owned());
         }
 
@@ -0,0 +1,862 @@
+use std::{future::Future, str::FromStr, sync::Arc, time::Duration};
+
+use anyhow::{ensure, Context};
+use rumqttc::{AsyncClient, Event, MqttOptions, Packet, Publish, QoS};
+use serde::{Deserialize, Deserializer};
+use spin_factor_variables::VariablesFactor;
+use spin_factors::RuntimeFactors;
+use spin_trigger::{cli::NoCliArgs, App, ShutdownToken, Trigger, TriggerApp};
+use spin_world::exports::spin::mqtt::inbound_mqtt;
+use tokio::task::JoinSet;
+use tracing::{instrument, Level};
+
+/// The capacity of the channel between an MQTT client and its event loop.
+const MQTT_CHANNEL_CAP: usize = 10;
+
+/// How long to wait before reconnecting to a broker after losing the connection.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// The number of messages a subscription handles at once. Further messages
+/// are not received from the broker until a handler finishes.
+const MAX_IN_FLIGHT: usize = 64;
+
+pub struct MqttTrigger {
+    shutdown: ShutdownToken,
+}
+
+/// MQTT trigger metadata, which provides defaults for all MQTT triggers.
+///
+/// The `keep_alive_interval` name and string numbers of the MQTT trigger
+/// plugin are accepted too.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TriggerMetadata {
+    address: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+    #[serde(
+        alias = "keep_alive_interval",
+        default,
+        deserialize_with = "optional_number_or_string"
+    )]
+    keep_alive_interval_secs: Option<u64>,
+}
+
+/// MQTT trigger configuration.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TriggerConfig {
+    /// Component ID to invoke
+    component: String,
+    /// Topic filter to subscribe to, which may contain `+` and `#` wildcards
+    topic: String,
+    /// Maximum QoS with which to receive messages: 0, 1 or 2
+    #[serde(default = "default_qos", deserialize_with = "number_or_string")]
+    qos: u8,
+    /// Optionally share the subscription with other subscribers in the group
+    shared_group: Option<String>,
+    /// Optionally override address for trigger
+    address: Option<String>,
+    /// Optionally override username for trigger
+    username: Option<String>,
+    /// Optionally override password for trigger
+    password: Option<String>,
+    /// Optionally override keep alive interval for trigger
+    #[serde(
+        alias = "keep_alive_interval",
+        default,
+        deserialize_with = "optional_number_or_string"
+    )]
+    keep_alive_interval_secs: Option<u64>,
+}
+
+fn default_qos() -> u8 {
+    1
+}
+
+/// A number which may be written as a string, e.g. `qos = "1"`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NumberOrString {
+    Number(u64),
+    String(String),
+}
+
+fn number_or_string<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: TryFrom<u64> + FromStr,
+{
+    let invalid = || serde::de::Error::custom("expected a non-negative integer");
+    match NumberOrString::deserialize(deserializer)? {
+        NumberOrString::Number(n) => T::try_from(n).map_err(|_| invalid()),
+        NumberOrString::String(s) => s.trim().parse().map_err(|_| invalid()),
+    }
+}
+
+fn optional_number_or_string<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: TryFrom<u64> + FromStr,
+{
+    number_or_string(deserializer).map(Some)
+}
+
+impl<F: RuntimeFactors> Trigger<F> for MqttTrigger {
+    const TYPE: &'static str = "mqtt";
+
+    type CliArgs = NoCliArgs;
+
+    type InstanceState = ();
+
+    fn new(_cli_args: Self::CliArgs, _app: &App) -> anyhow::Result<Self> {
+        Ok(Self {
+            shutdown: ShutdownToken::new(),
+        })
+    }
+
+    fn enable_graceful_shutdown(&mut self, token: ShutdownToken) -> bool {
+        self.shutdown = token;
+        true
+    }
+
+    async fn run(self, trigger_app: TriggerApp<Self, F>) -> anyhow::Result<()> {
+        let app_variables = trigger_app
+            .configured_app()
+            .app_state::<VariablesFactor>()
+            .context("MqttTrigger depends on VariablesFactor")?;
+
+        let app = trigger_app.app();
+        let trigger_type = <Self as Trigger<F>>::TYPE;
+        let metadata = app
+            .get_trigger_metadata::<TriggerMetadata>(trigger_type)?
+            .unwrap_or_default();
+
+        // Resolve trigger configs before starting any subscribers
+        let mut subscriptions = Vec::new();
+        for (trigger_id, config) in app
+            .trigger_configs::<TriggerConfig>(trigger_type)?
+            .into_iter()
+            .collect::<Vec<_>>()
+        {
+            let component_id = &config.component;
+            let resolve = |field: &'static str, expr: Option<&String>| {
+                let expr = expr.cloned();
+                async move {
+                    let Some(expr) = expr else {
+                        return Ok(None);
+                    };
+                    app_variables
+                        .resolve_expression(expr.clone())
+                        .await
+                        .map(Some)
+                        .with_context(|| {
+                            format!(
+                                "failed to resolve mqtt trigger {field} {expr:?} for component {component_id}"
+                            )
+                        })
+                }
+            };
+
+            let address = resolve(
+                "address",
+                config.address.as_ref().or(metadata.address.as_ref()),
+            )
+            .await?
+            .with_context(|| {
+                format!("mqtt trigger for component {component_id} has no address, and there is no default address in [application.trigger.mqtt]")
+            })?;
+            let username = resolve(
+                "username",
+                config.username.as_ref().or(metadata.username.as_ref()),
+            )
+            .await?;
+            let password = resolve(
+                "password",
+                config.password.as_ref().or(metadata.password.as_ref()),
+            )
+            .await?;
+            let topic = resolve("topic", Some(&config.topic)).await?.unwrap();
+            let keep_alive = config
+                .keep_alive_interval_secs
+                .or(metadata.keep_alive_interval_secs)
+                .map(Duration::from_secs);
+
+            let options = mqtt_options(&address, username, password, keep_alive, trigger_id)
+                .with_context(|| {
+                    format!("invalid mqtt trigger address for component {component_id}")
+                })?;
+            let subscription = Subscription::new(
+                config.component.clone(),
+                topic,
+                config.qos,
+                config.shared_group.clone(),
+                options,
+            )
+            .with_context(|| format!("invalid mqtt trigger for component {component_id}"))?;
+            subscriptions.push(subscription);
+        }
+
+        // Start subscriber(s)
+        let dispatcher = Arc::new(Dispatcher {
+            trigger_app: Arc::new(trigger_app),
+        });
+        let mut subscriber_tasks = Vec::new();
+        for subscription in subscriptions {
+            let task = tokio::spawn(subscription.run(dispatcher.clone(), self.shutdown.clone()));
+            subscriber_tasks.push(task);
+        }
+        if subscriber_tasks.is_empty() {
+            return Ok(());
+        }
+
+        // Wait for any task to complete, or for all to finish their in-flight
+        // messages on shutdown
+        let (res, _, rest) = futures::future::select_all(subscriber_tasks).await;
+        if self.shutdown.is_shutdown() {
+            futures::future::join_all(rest).await;
+        }
+        res?
+    }
+}
+
+/// Builds the options for connecting to the broker at `address`, which is a
+/// URL such as `mqtt://broker.example.com:1883`.
+///
+/// Unless the address sets a `client_id`, a client ID unique to this trigger
+/// and process is used.
+fn mqtt_options(
+    address: &str,
+    username: Option<String>,
+    password: Option<String>,
+    keep_alive: Option<Duration>,
+    trigger_id: &str,
+) -> anyhow::Result<MqttOptions> {
+    let mut url = url::Url::parse(address)?;
+    if !url.query_pairs().any(|(key, _)| key == "client_id") {
+        let client_id = format!("spin-{trigger_id}-{:08x}", rand::random::<u32>());
+        url.query_pairs_mut().append_pair("client_id", &client_id);
+    }
+    let mut options = MqttOptions::try_from(url)?;
+    if let Some(username) = username {
+        options.set_credentials(username, password.unwrap_or_default());
+    }
+    if let Some(keep_alive) = keep_alive {
+        ensure!(
+            keep_alive >= Duration::from_secs(1),
+            "keep_alive_interval_secs must be at least 1"
+        );
+        options.set_keep_alive(keep_alive);
+    }
+    // Messages are acknowledged once they have been handled, successfully or
+    // not.
+    options.set_manual_acks(true);
+    Ok(options)
+}
+
+/// A component's subscription to a topic filter on a broker.
+#[derive(Debug)]
+struct Subscription {
+    component_id: String,
+    /// The filter subscribed to, including any shared subscription prefix.
+    filter: String,
+    qos: QoS,
+    options: MqttOptions,
+    /// The number of messages handled at once.
+    max_in_flight: usize,
+}
+
+impl Subscription {
+    fn new(
+        component_id: String,
+        topic: String,
+        qos: u8,
+        shared_group: Option<String>,
+        options: MqttOptions,
+    ) -> anyhow::Result<Self> {
+        ensure!(
+            rumqttc::valid_filter(&topic),
+            "invalid topic filter {topic:?}: wildcards must occupy a whole level, and '#' must be the last level"
+        );
+        let qos = rumqttc::qos(qos).map_err(|_| anyhow::anyhow!("qos must be 0, 1 or 2"))?;
+        let filter = match shared_group {
+            Some(group) => {
+                ensure!(
+                    !group.is_empty() && !group.contains(['/', '+', '#']),
+                    "invalid shared_group {group:?}: must be non-empty and not contain '/', '+' or '#'"
+                );
+                format!("$share/{group}/{topic}")
+            }
+            None => topic,
+        };
+        Ok(Self {
+            component_id,
+            filter,
+            qos,
+            options,
+            max_in_flight: MAX_IN_FLIGHT,
+        })
+    }
+
+    /// Connects to the broker and handles messages until shutdown,
+    /// reconnecting if the connection is lost.
+    async fn run(
+        self,
+        handler: Arc<impl MessageHandler>,
+        shutdown: ShutdownToken,
+    ) -> anyhow::Result<()> {
+        let (host, port) = self.options.broker_address();
+        let broker = format!("{host}:{port}");
+        let (client, mut event_loop) = AsyncClient::new(self.options.clone(), MQTT_CHANNEL_CAP);
+
+        tracing::info!("Connecting to MQTT broker at {broker}");
+        let mut subscribed = false;
+        let mut shutting_down = false;
+        let mut in_flight = JoinSet::new();
+        loop {
+            if shutting_down && in_flight.is_empty() {
+                tracing::info!("Shutting down: no longer receiving messages from {broker}");
+                return Ok(());
+            }
+            let event = tokio::select! {
+                event = event_loop.poll(), if in_flight.len() < self.max_in_flight => event,
+                Some(_) = in_flight.join_next() => continue,
+                () = shutdown.wait(), if !shutting_down => {
+                    shutting_down = true;
+                    continue;
+                }
+            };
+            match event {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    // Sessions are clean, so subscribe on every (re)connection.
+                    tracing::info!("Subscribing to {:?} on {broker}", self.filter);
+                    client
+                        .try_subscribe(&self.filter, self.qos)
+                        .context("MQTT trigger failed to subscribe")?;
+                }
+                Ok(Event::Incoming(Packet::SubAck(suback))) => {
+                    ensure!(
+                        !suback
+                            .return_codes
+                            .contains(&rumqttc::SubscribeReasonCode::Failure),
+                        "MQTT broker at {broker} rejected subscription to {:?}",
+                        self.filter
+                    );
+                    if !subscribed {
+                        subscribed = true;
+                        println!(
+                            "Subscribed to MQTT topic {:?} on {broker}: [{}]",
+                            self.filter, self.component_id
+                        );
+                    }
+                }
+                Ok(Event::Incoming(Packet::Publish(publish))) if !shutting_down => {
+                    let handler = handler.clone();
+                    let client = client.clone();
+                    let component_id = self.component_id.clone();
+                    in_flight.spawn(async move {
+                        // Sessions are clean, so the broker wouldn't redeliver
+                        // a message left unacknowledged, which would instead
+                        // hold one of its slots for messages in flight.
+                        if let Err(err) = handler.handle(&component_id, &publish).await {
+                            tracing::error!(
+                                "Component {component_id} failed to handle MQTT message on {:?}: {err}",
+                                publish.topic
+                            );
+                        }
+                        if let Err(err) = client.ack(&publish).await {
+                            tracing::warn!("Failed to acknowledge MQTT message: {err}");
+                        }
+                    });
+                }
+                Ok(_) => {}
+                // A broker which can't be reached at startup is most likely
+                // misconfigured, so fail rather than retry.
+                Err(err) if !subscribed => {
+                    return Err(err)
+                        .with_context(|| format!("MQTT trigger failed to connect to {broker}"));
+                }
+                Err(err) => {
+                    tracing::error!("Lost connection to MQTT broker at {broker}: {err}");
+                    if shutting_down {
+                        continue;
+                    }
+                    tokio::select! {
+                        () = tokio::time::sleep(RECONNECT_DELAY) => {}
+                        () = shutdown.wait() => shutting_down = true,
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Handles messages received by a [`Subscription`].
+trait MessageHandler: Send + Sync + 'static {
+    fn handle(
+        &self,
+        component_id: &str,
+        message: &Publish,
+    ) -> impl Future<Output = anyhow::Result<()>> + Send;
+}
+
+/// Dispatches messages to components.
+struct Dispatcher<F: RuntimeFactors> {
+    trigger_app: Arc<TriggerApp<MqttTrigger, F>>,
+}
+
+impl<F: RuntimeFactors> MessageHandler for Dispatcher<F> {
+    #[instrument(name = "spin_trigger_mqtt.handle_message", skip_all, err(level = Level::INFO), fields(
+        otel.name = format!("{} receive", message.topic),
+        otel.kind = "consumer",
+        messaging.operation = "receive",
+        messaging.system = "mqtt",
+        messaging.destination.name = %message.topic,
+    ))]
+    async fn handle(&self, component_id: &str, message: &Publish) -> anyhow::Result<()> {
+        tracing::trace!(topic = %message.topic, "Executing MQTT component {component_id}");
+        spin_telemetry::metrics::monotonic_counter!(
+            spin.request_count = 1,
+            trigger_type = "mqtt",
+            app_id = self.trigger_app.app().id(),
+            component_id = component_id
+        );
+
+        let (instance, mut store) = self
+            .trigger_app
+            .prepare(component_id)?
+            .instantiate(())
+            .await?;
+
+        let pre = instance.instance_pre(&store);
+        let guest_indices = inbound_mqtt::GuestIndices::new(&pre)?;
+        let guest = guest_indices.load(&mut store, &instance)?;
+
+        let message = inbound_mqtt::Message {
+            topic: message.topic.clone(),
+            payload: message.payload.to_vec(),
+            qos: match message.qos {
+                QoS::AtMostOnce => inbound_mqtt::Qos::AtMostOnce,
+                QoS::AtLeastOnce => inbound_mqtt::Qos::AtLeastOnce,
+                QoS::ExactlyOnce => inbound_mqtt::Qos::ExactlyOnce,
+            },
+            retain: message.retain,
+        };
+
+        guest
+            .call_handle_message(&mut store, &message)
+            .await?
+            .map_err(|inbound_mqtt::Error::Other(err)| anyhow::anyhow!(err))
+            .context("MQTT handler returned an error")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+    use rumqttc::{ConnAck, ConnectReturnCode, PubAck, SubAck, SubscribeReasonCode};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+        sync::mpsc,
+    };
+
+    use super::*;
+
+    /// Records the messages it handles, failing those with a `fail` payload.
+    struct RecordingHandler {
+        handled: mpsc::UnboundedSender<(String, String)>,
+    }
+
+    impl MessageHandler for RecordingHandler {
+        async fn handle(&self, component_id: &str, message: &Publish) -> anyhow::Result<()> {
+            self.handled
+                .send((component_id.to_owned(), message.topic.clone()))
+                .unwrap();
+            ensure!(message.payload != "fail", "handler failed");
+            Ok(())
+        }
+    }
+
+    /// Records the messages it starts handling, and finishes handling one for
+    /// each permit added to `finish`.
+    struct GatedHandler {
+        started: mpsc::UnboundedSender<String>,
+        finish: tokio::sync::Semaphore,
+    }
+
+    impl MessageHandler for GatedHandler {
+        async fn handle(&self, _component_id: &str, message: &Publish) -> anyhow::Result<()> {
+            self.started.send(message.topic.clone()).unwrap();
+            self.finish.acquire().await?.forget();
+            Ok(())
+        }
+    }
+
+    /// A stand-in for an MQTT broker, speaking MQTT 3.1.1 to a single client.
+    struct BrokerConnection {
+        stream: TcpStream,
+        buf: BytesMut,
+    }
+
+    impl BrokerConnection {
+        async fn accept(listener: &TcpListener) -> Self {
+            let (stream, _) = listener.accept().await.unwrap();
+            Self {
+                stream,
+                buf: BytesMut::new(),
+            }
+        }
+
+        async fn read(&mut self) -> Packet {
+            loop {
+                match rumqttc::read(&mut self.buf, 1024 * 1024) {
+                    Ok(packet) => return packet,
+                    Err(rumqttc::Error::InsufficientBytes(_)) => {
+                        let read = self.stream.read_buf(&mut self.buf).await.unwrap();
+                        assert_ne!(read, 0, "client disconnected");
+                    }
+                    Err(err) => panic!("invalid packet: {err}"),
+                }
+            }
+        }
+
+        async fn write(&mut self, write: impl FnOnce(&mut BytesMut)) {
+            let mut buf = BytesMut::new();
+            write(&mut buf);
+            self.stream.write_all(&buf).await.unwrap();
+        }
+
+        /// Accepts a connection and its subscription.
+        async fn accept_subscriber(listener: &TcpListener) -> Self {
+            let mut broker = Self::accept(listener).await;
+            assert!(matches!(broker.read().await, Packet::Connect(_)));
+            broker
+                .write(|buf| {
+                    ConnAck::new(ConnectReturnCode::Success, false)
+                        .write(buf)
+                        .unwrap();
+                })
+                .await;
+            let Packet::Subscribe(subscribe) = broker.read().await else {
+                panic!("expected a subscription");
+            };
+            broker
+                .write(|buf| {
+                    SubAck::new(
+                        subscribe.pkid,
+                        vec![SubscribeReasonCode::Success(QoS::AtLeastOnce)],
+                    )
+                    .write(buf)
+                    .unwrap();
+                })
+                .await;
+            broker
+        }
+
+        async fn publish(&mut self, topic: &str, pkid: u16, payload: &str) {
+            let qos = if pkid == 0 {
+                QoS::AtMostOnce
+            } else {
+                QoS::AtLeastOnce
+            };
+            let mut publish = Publish::new(topic, qos, payload);
+            publish.pkid = pkid;
+            self.write(|buf| _ = publish.write(buf).unwrap()).await;
+        }
+    }
+
+    async fn listen() -> (TcpListener, Subscription) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = format!("mqtt://{}", listener.local_addr().unwrap());
+        let options = mqtt_options(&address, None, None, None, "trigger").unwrap();
+        let subscription =
+            Subscription::new("sensors".into(), "sensors/#".into(), 1, None, options).unwrap();
+        (listener, subscription)
+    }
+
+    fn subscription(
+        topic: &str,
+        qos: u8,
+        shared_group: Option<&str>,
+    ) -> anyhow::Result<Subscription> {
+        let options = mqtt_options("mqtt://localhost:1883", None, None, None, "trigger")?;
+        Subscription::new(
+            "component".into(),
+            topic.into(),
+            qos,
+            shared_group.map(Into::into),
+            options,
+        )
+    }
+
+    #[test]
+    fn subscriptions_are_validated() {
+        let sub = subscription("sensors/+/temperature", 2, None).unwrap();
+        assert_eq!(sub.filter, "sensors/+/temperature");
+        assert_eq!(sub.qos, QoS::ExactlyOnce);
+
+        let sub = subscription("sensors/#", 0, Some("workers")).unwrap();
+        assert_eq!(sub.filter, "$share/workers/sensors/#");
+        assert_eq!(sub.qos, QoS::AtMostOnce);
+
+        for (topic, qos, group) in [
+            ("sensors/#/temperature", 1, None),
+            ("sensors+", 1, None),
+            ("", 1, None),
+            ("sensors", 3, None),
+            ("sensors", 1, Some("")),
+            ("sensors", 1, Some("a/b")),
+        ] {
+            subscription(topic, qos, group).unwrap_err();
+        }
+    }
+
+    #[test]
+    fn client_ids_are_unique_unless_set() {
+        let options = |address: &str| {
+            mqtt_options(
+                address,
+                Some("user".into()),
+                None,
+                Some(Duration::from_secs(30)),
+                "trigger",
+            )
+            .unwrap()
+        };
+
+        let first = options("mqtt://localhost:1883");
+        let second = options("mqtt://localhost:1883");
+        assert!(first.client_id().starts_with("spin-trigger-"));
+        assert_ne!(first.client_id(), second.client_id());
+        assert_eq!(first.credentials(), Some(("user".into(), "".into())));
+        assert_eq!(first.keep_alive(), Duration::from_secs(30));
+        assert!(first.manual_acks());
+
+        let options = options("mqtt://localhost:1883?client_id=mine");
+        assert_eq!(options.client_id(), "mine");
+
+        mqtt_options("redis://localhost", None, None, None, "trigger").unwrap_err();
+        mqtt_options(
+            "mqtt://localhost",
+            None,
+            None,
+            Some(Duration::ZERO),
+            "trigger",
+        )
+        .unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn messages_are_dispatched_and_acknowledged() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = format!("mqtt://{}", listener.local_addr().unwrap());
+        let options = mqtt_options(&address, None, None, None, "trigger").unwrap();
+        let subscription = Subscription::new(
+            "sensors".into(),
+            "sensors/+/temperature".into(),
+            1,
+            Some("workers".into()),
+            options,
+        )
+        .unwrap();
+
+        let (handled_tx, mut handled) = mpsc::unbounded_channel();
+        let handler = Arc::new(RecordingHandler {
+            handled: handled_tx,
+        });
+        let shutdown = ShutdownToken::new();
+        let subscriber = tokio::spawn(subscription.run(handler, shutdown.clone()));
+
+        let mut broker = BrokerConnection::accept(&listener).await;
+        assert!(matches!(broker.read().await, Packet::Connect(_)));
+        broker
+            .write(|buf| {
+                ConnAck::new(ConnectReturnCode::Success, false)
+                    .write(buf)
+                    .unwrap();
+            })
+            .await;
+
+        let Packet::Subscribe(subscribe) = broker.read().await else {
+            panic!("expected a subscription");
+        };
+        assert_eq!(subscribe.filters.len(), 1);
+        assert_eq!(
+            subscribe.filters[0].path,
+            "$share/workers/sensors/+/temperature"
+        );
+        assert_eq!(subscribe.filters[0].qos, QoS::AtLeastOnce);
+        broker
+            .write(|buf| {
+                SubAck::new(
+                    subscribe.pkid,
+                    vec![SubscribeReasonCode::Success(QoS::AtLeastOnce)],
+                )
+                .write(buf)
+                .unwrap();
+            })
+            .await;
+
+        let mut publish = Publish::new("sensors/kitchen/temperature", QoS::AtLeastOnce, "21.5");
+        publish.pkid = 7;
+        broker.write(|buf| _ = publish.write(buf).unwrap()).await;
+
+        assert_eq!(
+            handled.recv().await.unwrap(),
+            ("sensors".into(), "sensors/kitchen/temperature".into())
+        );
+        assert_eq!(broker.read().await, Packet::PubAck(PubAck::new(7)));
+
+        shutdown.shutdown();
+        subscriber.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn failed_messages_are_acknowledged() {
+        let (listener, subscription) = listen().await;
+        let (handled_tx, mut handled) = mpsc::unbounded_channel();
+        let handler = Arc::new(RecordingHandler {
+            handled: handled_tx,
+        });
+        let shutdown = ShutdownToken::new();
+        let subscriber = tokio::spawn(subscription.run(handler, shutdown.clone()));
+        let mut broker = BrokerConnection::accept_subscriber(&listener).await;
+
+        // More failures than a broker would typically allow in flight.
+        for pkid in 1..=30 {
+            broker.publish("sensors/broken", pkid, "fail").await;
+            handled.recv().await.unwrap();
+            assert_eq!(broker.read().await, Packet::PubAck(PubAck::new(pkid)));
+        }
+        broker.publish("sensors/working", 31, "21.5").await;
+        handled.recv().await.unwrap();
+        assert_eq!(broker.read().await, Packet::PubAck(PubAck::new(31)));
+
+        shutdown.shutdown();
+        subscriber.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn in_flight_messages_are_limited() {
+        let (listener, mut subscription) = listen().await;
+        subscription.max_in_flight = 2;
+        let (started_tx, mut started) = mpsc::unbounded_channel();
+        let handler = Arc::new(GatedHandler {
+            started: started_tx,
+            finish: tokio::sync::Semaphore::new(0),
+        });
+        let shutdown = ShutdownToken::new();
+        let subscriber = tokio::spawn(subscription.run(handler.clone(), shutdown.clone()));
+        let mut broker = BrokerConnection::accept_subscriber(&listener).await;
+
+        for topic in ["sensors/1", "sensors/2", "sensors/3"] {
+            broker.publish(topic, 0, "21.5").await;
+        }
+        assert_eq!(started.recv().await.unwrap(), "sensors/1");
+        assert_eq!(started.recv().await.unwrap(), "sensors/2");
+        tokio::time::timeout(Duration::from_millis(200), started.recv())
+            .await
+            .unwrap_err();
+
+        handler.finish.add_permits(1);
+        assert_eq!(started.recv().await.unwrap(), "sensors/3");
+
+        handler.finish.add_permits(2);
+        shutdown.shutdown();
+        subscriber.await.unwrap().unwrap();
+    }
+
+    #[test]
+    fn plugin_configuration_is_accepted() {
+        let metadata: TriggerMetadata = toml::toml! {
+            address = "mqtt://localhost:1883"
+            username = "admin"
+            password = "public"
+            keep_alive_interval = "30"
+        }
+        .try_into()
+        .unwrap();
+        assert_eq!(metadata.keep_alive_interval_secs, Some(30));
+
+        let config: TriggerConfig = toml::toml! {
+            component = "sensors"
+            topic = "sensors/#"
+            qos = "2"
+        }
+        .try_into()
+        .unwrap();
+        assert_eq!(config.qos, 2);
+
+        let config: TriggerConfig = toml::toml! {
+            component = "sensors"
+            topic = "sensors/#"
+            keep_alive_interval_secs = 30
+        }
+        .try_into()
+        .unwrap();
+        assert_eq!(config.qos, 1);
+        assert_eq!(config.keep_alive_interval_secs, Some(30));
+
+        toml::Value::try_into::<TriggerConfig>(
+            toml::toml! { component = "sensors" topic = "sensors/#" qos = "high" }.into(),
+        )
+        .unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn rejected_subscriptions_fail() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = format!("mqtt://{}", listener.local_addr().unwrap());
+        let options = mqtt_options(&address, None, None, None, "trigger").unwrap();
+        let subscription =
+            Subscription::new("sensors".into(), "sensors/#".into(), 0, None, options).unwrap();
+        let handler = Arc::new(RecordingHandler {
+            handled: mpsc::unbounded_channel().0,
+        });
+        let subscriber = tokio::spawn(subscription.run(handler, ShutdownToken::new()));
+
+        let mut broker = BrokerConnection::accept(&listener).await;
+        broker.read().await;
+        broker
+            .write(|buf| {
+                ConnAck::new(ConnectReturnCode::Success, false)
+                    .write(buf)
+                    .unwrap();
+            })
+            .await;
+        let Packet::Subscribe(subscribe) = broker.read().await else {
+            panic!("expected a subscription");
+        };
+        broker
+            .write(|buf| {
+                SubAck::new(subscribe.pkid, vec![SubscribeReasonCode::Failure])
+                    .write(buf)
+                    .unwrap();
+            })
+            .await;
+
+        let err = subscriber.await.unwrap().unwrap_err();
+        assert!(err.to_string().contains("rejected subscription"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn unreachable_brokers_fail() {
+        // Find a port with nothing listening on it
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = format!("mqtt://{}", listener.local_addr().unwrap());
+        drop(listener);
+
+        let options = mqtt_options(&address, None, None, None, "trigger").unwrap();
+        let subscription =
+            Subscription::new("sensors".into(), "sensors/#".into(), 0, None, options).unwrap();
+        let handler = Arc::new(RecordingHandler {
+            handled: mpsc::unbounded_channel().0,
+        });
+        let err = subscription
+            .run(handler, ShutdownToken::new())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("failed to connect"), "{err}");
+    }
+}
@@ -1,16 +1,22 @@
-use std::{collections::HashMap, sync::Arc};
+mod stream;
 
-use anyhow::Context;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use anyhow::{bail, Context};
 use futures::{StreamExt, TryFutureExt};
 use redis::{Client, Msg};
 use serde::Deserialize;
 use spin_factor_variables::VariablesFactor;
 use spin_factors::RuntimeFactors;
-use spin_trigger::{cli::NoCliArgs, App, Trigger, TriggerApp};
+use spin_trigger::{cli::NoCliArgs, App, ShutdownToken, Trigger, TriggerApp};
 use spin_world::exports::fermyon::spin::inbound_redis;
 use tracing::{instrument, Level};
 
-pub struct RedisTrigger;
+use crate::stream::{StreamConfig, StreamConsumer};
+
+pub struct RedisTrigger {
+    shutdown: ShutdownToken,
+}
 
 /// Redis trigger metadata.
 #[derive(Clone, Debug, Default, Deserialize)]
@@ -26,11 +32,90 @@ struct TriggerConfig {
     /// Component ID to invoke
     component: String,
     /// Channel to subscribe to
-    channel: String,
+    channel: Option<String>,
+    /// Stream to read as a member of `group`
+    stream: Option<String>,
+    /// Consumer group to read `stream` in
+    group: Option<String>,
+    /// Consumer name within `group`, `spin-<trigger ID>` by default
+    consumer: Option<String>,
+    /// Stream entry field holding the message payload
+    field: Option<String>,
+    /// Number of deliveries after which a failing entry is dead-lettered
+    max_deliveries: Option<usize>,
+    /// Stream to move entries which can't be handled to
+    dead_letter_stream: Option<String>,
+    /// How long an entry may be pending before it is reclaimed and retried
+    claim_idle_secs: Option<u64>,
     /// Optionally override address for trigger
     address: Option<String>,
 }
 
+/// What a trigger receives messages from.
+#[derive(Debug, PartialEq)]
+enum TriggerSource {
+    Channel(String),
+    Stream(StreamConfig),
+}
+
+impl TriggerConfig {
+    /// Determines where the trigger receives messages from, with the channel
+    /// or stream name still to be resolved.
+    fn source(&self, trigger_id: &str) -> anyhow::Result<TriggerSource> {
+        let stream_options = [
+            ("group", self.group.is_some()),
+            ("consumer", self.consumer.is_some()),
+            ("field", self.field.is_some()),
+            ("max_deliveries", self.max_deliveries.is_some()),
+            ("dead_letter_stream", self.dead_letter_stream.is_some()),
+            ("claim_idle_secs", self.claim_idle_secs.is_some()),
+        ];
+        match (&self.channel, &self.stream) {
+            (Some(channel), None) => {
+                if let Some((option, _)) = stream_options.iter().find(|(_, set)| *set) {
+                    bail!("`{option}` can only be set for a `stream` trigger");
+                }
+                Ok(TriggerSource::Channel(channel.clone()))
+            }
+            (None, Some(stream)) => {
+                let Some(group) = self.group.clone() else {
+                    bail!("a `stream` trigger must set the consumer `group`");
+                };
+                let max_deliveries = self
+                    .max_deliveries
+                    .unwrap_or(StreamConfig::DEFAULT_MAX_DELIVERIES);
+                if max_deliveries == 0 {
+                    bail!("`max_deliveries` must be greater than zero");
+                }
+                let claim_idle = self
+                    .claim_idle_secs
+                    .map(Duration::from_secs)
+                    .unwrap_or(StreamConfig::DEFAULT_CLAIM_IDLE);
+                if claim_idle.is_zero() {
+                    bail!("`claim_idle_secs` must be greater than zero");
+                }
+                Ok(TriggerSource::Stream(StreamConfig {
+                    stream: stream.clone(),
+                    group,
+                    consumer: self
+                        .consumer
+                        .clone()
+                        .unwrap_or_else(|| format!("spin-{trigger_id}")),
+                    field: self
+                        .field
+                        .clone()
+                        .unwrap_or_else(|| StreamConfig::DEFAULT_FIELD.to_owned()),
+                    max_deliveries,
+                    dead_letter_stream: self.dead_letter_stream.clone(),
+                    claim_idle,
+                }))
+            }
+            (Some(_), Some(_)) => bail!("a trigger can't set both `channel` and `stream`"),
+            (None, None) => bail!("a trigger must set either `channel` or `stream`"),
+        }
+    }
+}
+
 impl<F: RuntimeFactors> Trigger<F> for RedisTrigger {
     const TYPE: &'static str = "redis";
 
@@ -39,7 +124,14 @@ impl<F: RuntimeFactors> Trigger<F> for RedisTrigger {
     type InstanceState = ();
 
     fn new(_cli_args: Self::CliArgs, _app: &App) -> anyhow::Result<Self> {
-        Ok(Self)
+        Ok(Self {
+            shutdown: ShutdownToken::new(),
+        })
+    }
+
+    fn enable_graceful_shutdown(&mut self, token: ShutdownToken) -> bool {
+        self.shutdown = token;
+        true
     }
 
     async fn run(self, trigger_app: spin_trigger::TriggerApp<Self, F>) -> anyhow::Result<()> {
@@ -63,14 +155,19 @@ impl<F: RuntimeFactors> Trigger<F> for RedisTrigger {
 
         // Maps <server address> -> <channel> -> <component IDs>
         let mut server_channel_components: HashMap<String, ChannelComponents> = HashMap::new();
+        // <server address>, <component ID>, <stream config>
+        let mut stream_consumers = Vec::new();
 
         // Resolve trigger configs before starting any subscribers
-        for (_, config) in app
+        for (trigger_id, config) in app
             .trigger_configs::<TriggerConfig>(trigger_type)?
             .into_iter()
             .collect::<Vec<_>>()
         {
-            let component_id = config.component;
+            let component_id = config.component.clone();
+            let source = config
+                .source(trigger_id)
+                .with_context(|| format!("invalid redis trigger for component {component_id}"))?;
 
             let address_expr = config.address.as_ref().unwrap_or(&default_address);
             let address = app_variables
@@ -82,35 +179,69 @@ impl<F: RuntimeFactors> Trigger<F> for RedisTrigger {
                     )
                 })?;
 
-            let channel_expr = &config.channel;
-            let channel = app_variables
-                .resolve_expression(channel_expr.clone())
-                .await
-                .with_context(|| {
-                    format!(
-                        "failed to resolve redis trigger channel {channel_expr:?} for component {component_id}"
-                    )
-                })?;
-
-            server_channel_components
-                .entry(address)
-                .or_default()
-                .entry(channel)
-                .or_default()
-                .push(component_id);
+            match source {
+                TriggerSource::Channel(channel_expr) => {
+                    let channel = app_variables
+                        .resolve_expression(channel_expr.clone())
+                        .await
+                        .with_context(|| {
+                            format!(
+                                "failed to resolve redis trigger channel {channel_expr:?} for component {component_id}"
+                            )
+                        })?;
+
+                    server_channel_components
+                        .entry(address)
+                        .or_default()
+                        .entry(channel)
+                        .or_default()
+                        .push(component_id);
+                }
+                TriggerSource::Stream(mut stream_config) => {
+                    let stream_expr = &stream_config.stream;
+                    stream_config.stream = app_variables
+                        .resolve_expression(stream_expr.clone())
+                        .await
+                        .with_context(|| {
+                            format!(
+                                "failed to resolve redis trigger stream {stream_expr:?} for component {component_id}"
+                            )
+                        })?;
+                    stream_consumers.push((address, component_id, stream_config));
+                }
+            }
         }
 
         // Start subscriber(s)
         let trigger_app = Arc::new(trigger_app);
         let mut subscriber_tasks = Vec::new();
         for (address, channel_components) in server_channel_components {
-            let subscriber = Subscriber::new(address, trigger_app.clone(), channel_components)?;
+            let subscriber = Subscriber::new(
+                address,
+                trigger_app.clone(),
+                channel_components,
+                self.shutdown.clone(),
+            )?;
             let task = tokio::spawn(subscriber.run_listener());
             subscriber_tasks.push(task);
         }
+        for (address, component_id, stream_config) in stream_consumers {
+            let consumer = StreamConsumer::new(
+                address,
+                trigger_app.clone(),
+                component_id,
+                stream_config,
+                self.shutdown.clone(),
+            )?;
+            subscriber_tasks.push(tokio::spawn(consumer.run()));
+        }
 
-        // Wait for any task to complete
-        let (res, _, _) = futures::future::select_all(subscriber_tasks).await;
+        // Wait for any task to complete, or for all to finish their in-flight
+        // messages on shutdown
+        let (res, _, rest) = futures::future::select_all(subscriber_tasks).await;
+        if self.shutdown.is_shutdown() {
+            futures::future::join_all(rest).await;
+        }
         res?
     }
 }
@@ -123,6 +254,7 @@ struct Subscriber<F: RuntimeFactors> {
     client: Client,
     trigger_app: Arc<TriggerApp<RedisTrigger, F>>,
     channel_components: ChannelComponents,
+    shutdown: ShutdownToken,
 }
 
 impl<F: RuntimeFactors> Subscriber<F> {
@@ -130,12 +262,14 @@ impl<F: RuntimeFactors> Subscriber<F> {
         address: String,
         trigger_app: Arc<TriggerApp<RedisTrigger, F>>,
         channel_components: ChannelComponents,
+        shutdown: ShutdownToken,
     ) -> anyhow::Result<Self> {
         let client = Client::open(address)?;
         Ok(Self {
             client,
             trigger_app,
             channel_components,
+            shutdown,
         })
     }
 
@@ -161,7 +295,17 @@ impl<F: RuntimeFactors> Subscriber<F> {
         }
 
         let mut message_stream = pubsub.on_message();
-        while let Some(msg) = message_stream.next().await {
+        loop {
+            let msg = tokio::select! {
+                msg = message_stream.next() => msg,
+                () = self.shutdown.wait() => {
+                    tracing::info!("Shutting down: no longer receiving messages from {server_addr}");
+                    return Ok(());
+                }
+            };
+            let Some(msg) = msg else {
+                break;
+            };
             if let Err(err) = self.handle_message(msg).await {
                 tracing::error!("Error handling message from {server_addr}: {err}");
             }
@@ -197,28 +341,104 @@ impl<F: RuntimeFactors> Subscriber<F> {
     }
 
     async fn dispatch_handler(&self, msg: &Msg, component_id: &str) -> anyhow::Result<()> {
-        spin_telemetry::metrics::monotonic_counter!(
-            spin.request_count = 1,
-            trigger_type = "redis",
-            app_id = self.trigger_app.app().id(),
-            component_id = component_id
-        );
+        dispatch(&self.trigger_app, component_id, msg.get_payload_bytes()).await
+    }
+}
 
-        let (instance, mut store) = self
-            .trigger_app
-            .prepare(component_id)?
-            .instantiate(())
-            .await?;
+/// Invokes the component's Redis handler with the payload.
+async fn dispatch<F: RuntimeFactors>(
+    trigger_app: &TriggerApp<RedisTrigger, F>,
+    component_id: &str,
+    payload: &[u8],
+) -> anyhow::Result<()> {
+    spin_telemetry::metrics::monotonic_counter!(
+        spin.request_count = 1,
+        trigger_type = "redis",
+        app_id = trigger_app.app().id(),
+        component_id = component_id
+    );
+
+    let (instance, mut store) = trigger_app.prepare(component_id)?.instantiate(()).await?;
+
+    let pre = instance.instance_pre(&store);
+    let guest_indices = inbound_redis::GuestIndices::new(&pre)?;
+    let guest = guest_indices.load(&mut store, &instance)?;
+
+    guest
+        .call_handle_message(&mut store, &payload.to_vec())
+        .await?
+        .context("Redis handler returned an error")
+}
 
-        let pre = instance.instance_pre(&store);
-        let guest_indices = inbound_redis::GuestIndices::new(&pre)?;
-        let guest = guest_indices.load(&mut store, &instance)?;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        let payload = msg.get_payload_bytes().to_vec();
+    fn stream_config() -> TriggerConfig {
+        TriggerConfig {
+            component: "orders".into(),
+            stream: Some("orders".into()),
+            group: Some("processors".into()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn stream_options_have_defaults() {
+        let TriggerSource::Stream(config) = stream_config().source("orders").unwrap() else {
+            panic!("expected a stream source");
+        };
+        assert_eq!(config.stream, "orders");
+        assert_eq!(config.group, "processors");
+        assert_eq!(config.consumer, "spin-orders");
+        assert_eq!(config.field, StreamConfig::DEFAULT_FIELD);
+        assert_eq!(config.max_deliveries, StreamConfig::DEFAULT_MAX_DELIVERIES);
+        assert_eq!(config.dead_letter_stream, None);
+        assert_eq!(config.claim_idle, StreamConfig::DEFAULT_CLAIM_IDLE);
+    }
 
-        guest
-            .call_handle_message(&mut store, &payload)
-            .await?
-            .context("Redis handler returned an error")
+    #[test]
+    fn stream_options_are_validated() {
+        let err = |config: TriggerConfig| config.source("t").unwrap_err().to_string();
+
+        assert!(err(TriggerConfig::default()).contains("either `channel` or `stream`"));
+        assert!(err(TriggerConfig {
+            channel: Some("messages".into()),
+            ..stream_config()
+        })
+        .contains("both"));
+        assert!(err(TriggerConfig {
+            group: None,
+            ..stream_config()
+        })
+        .contains("`group`"));
+        assert!(err(TriggerConfig {
+            max_deliveries: Some(0),
+            ..stream_config()
+        })
+        .contains("`max_deliveries`"));
+        assert!(err(TriggerConfig {
+            claim_idle_secs: Some(0),
+            ..stream_config()
+        })
+        .contains("`claim_idle_secs`"));
+        assert!(err(TriggerConfig {
+            channel: Some("messages".into()),
+            dead_letter_stream: Some("dead".into()),
+            ..Default::default()
+        })
+        .contains("`dead_letter_stream`"));
+    }
+
+    #[test]
+    fn channel_triggers_are_unchanged() {
+        let config = TriggerConfig {
+            channel: Some("messages".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            config.source("t").unwrap(),
+            TriggerSource::Channel("messages".into())
+        );
     }
 }
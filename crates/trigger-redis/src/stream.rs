@@ -0,0 +1,898 @@
+//! Reading Redis Streams as a member of a consumer group.
+
+use std::{collections::HashMap, future::Future, sync::Arc, time::Duration};
+
+use anyhow::Context;
+use redis::{
+    aio::MultiplexedConnection,
+    streams::{
+        StreamClaimReply, StreamId, StreamPendingCountReply, StreamReadOptions, StreamReadReply,
+    },
+    AsyncCommands, Client,
+};
+use spin_factors::RuntimeFactors;
+use spin_trigger::{ShutdownToken, TriggerApp};
+use tokio::time::Instant;
+use tracing::instrument;
+
+use crate::RedisTrigger;
+
+/// The maximum number of entries read or reclaimed at once.
+const BATCH_SIZE: usize = 16;
+
+/// How long a read waits for new entries before checking for shutdown and
+/// entries to reclaim.
+const READ_BLOCK: Duration = Duration::from_secs(5);
+
+/// How long to wait before reconnecting to a server after a Redis error.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// How a trigger reads a stream.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct StreamConfig {
+    /// The stream to read.
+    pub stream: String,
+    /// The consumer group to read the stream in, which is created if it
+    /// doesn't exist.
+    pub group: String,
+    /// The name of this consumer within the group. Entries pending for a
+    /// consumer are delivered to it again when it restarts, so the name should
+    /// be stable, and distinct for each instance reading the stream.
+    pub consumer: String,
+    /// The entry field holding the message payload.
+    pub field: String,
+    /// The number of times an entry is delivered before it is dead-lettered.
+    pub max_deliveries: usize,
+    /// The stream to which entries which can't be handled are moved, or `None`
+    /// to drop them.
+    pub dead_letter_stream: Option<String>,
+    /// How long an entry may go unacknowledged before it is reclaimed from
+    /// its consumer and delivered again.
+    pub claim_idle: Duration,
+}
+
+impl StreamConfig {
+    pub const DEFAULT_FIELD: &'static str = "payload";
+    pub const DEFAULT_MAX_DELIVERIES: usize = 3;
+    pub const DEFAULT_CLAIM_IDLE: Duration = Duration::from_secs(60);
+}
+
+/// Handles the payloads of stream entries.
+pub(crate) trait EntryHandler: Send + Sync + 'static {
+    fn handle(
+        &self,
+        component_id: &str,
+        payload: &[u8],
+    ) -> impl Future<Output = anyhow::Result<()>> + Send;
+}
+
+impl<F: RuntimeFactors> EntryHandler for TriggerApp<RedisTrigger, F> {
+    async fn handle(&self, component_id: &str, payload: &[u8]) -> anyhow::Result<()> {
+        crate::dispatch(self, component_id, payload).await
+    }
+}
+
+/// Reads entries from a stream as a member of a consumer group, acknowledging
+/// each entry once the component has handled it.
+///
+/// Entries the component fails to handle are left pending, to be reclaimed
+/// and retried by any consumer in the group once they have been idle for
+/// `claim_idle`. After `max_deliveries`, they are dead-lettered instead.
+pub(crate) struct StreamConsumer<H> {
+    client: Client,
+    handler: Arc<H>,
+    component_id: String,
+    config: StreamConfig,
+    shutdown: ShutdownToken,
+    reconnect_delay: Duration,
+}
+
+impl<H: EntryHandler> StreamConsumer<H> {
+    pub fn new(
+        address: String,
+        handler: Arc<H>,
+        component_id: String,
+        config: StreamConfig,
+        shutdown: ShutdownToken,
+    ) -> anyhow::Result<Self> {
+        let client = Client::open(address)?;
+        Ok(Self {
+            client,
+            handler,
+            component_id,
+            config,
+            shutdown,
+            reconnect_delay: RECONNECT_DELAY,
+        })
+    }
+
+    /// Reads the stream until shutdown, reconnecting after any Redis error.
+    pub async fn run(self) -> anyhow::Result<()> {
+        let server_addr = &self.client.get_connection_info().addr;
+        let StreamConfig {
+            stream,
+            group,
+            consumer,
+            ..
+        } = &self.config;
+
+        // A server which can't be used at startup is most likely
+        // misconfigured, so fail rather than retry.
+        tracing::info!("Connecting to Redis server at {server_addr}");
+        let mut conn = self.connect().await?;
+        println!(
+            "Reading stream {server_addr}/{stream} as {group}/{consumer}: [{}]",
+            self.component_id
+        );
+
+        loop {
+            let Err(err) = self.read(&mut conn).await else {
+                tracing::info!(
+                    "Shutting down: no longer reading stream {stream:?} from {server_addr}"
+                );
+                return Ok(());
+            };
+            tracing::error!("Error reading stream {stream:?} from {server_addr}: {err:#}");
+            conn = loop {
+                tokio::select! {
+                    () = tokio::time::sleep(self.reconnect_delay) => {}
+                    () = self.shutdown.wait() => return Ok(()),
+                }
+                match self.connect().await {
+                    Ok(conn) => break conn,
+                    Err(err) => tracing::error!("{err:#}"),
+                }
+            };
+            tracing::info!("Reconnected to Redis server at {server_addr}");
+        }
+    }
+
+    /// Connects to the server, creating the consumer group if necessary.
+    async fn connect(&self) -> anyhow::Result<MultiplexedConnection> {
+        let server_addr = &self.client.get_connection_info().addr;
+        let StreamConfig { stream, group, .. } = &self.config;
+
+        let mut conn = self
+            .client
+            .get_multiplexed_async_connection()
+            .await
+            .with_context(|| format!("Redis trigger failed to connect to {server_addr}"))?;
+
+        // Start new groups from new entries, creating the stream if necessary.
+        let created: redis::RedisResult<()> = conn.xgroup_create_mkstream(stream, group, "$").await;
+        match created {
+            Err(err) if err.code() != Some("BUSYGROUP") => {
+                Err(err).with_context(|| {
+                    format!("Redis trigger failed to create consumer group {group:?} for stream {stream:?} on {server_addr}")
+                })
+            }
+            _ => Ok(conn),
+        }
+    }
+
+    /// Reads and handles entries until shutdown, or until a Redis error.
+    async fn read(&self, conn: &mut MultiplexedConnection) -> anyhow::Result<()> {
+        let StreamConfig {
+            stream,
+            group,
+            consumer,
+            ..
+        } = &self.config;
+
+        let read_options = StreamReadOptions::default()
+            .group(group, consumer)
+            .count(BATCH_SIZE)
+            .block(READ_BLOCK.as_millis() as usize);
+        let reclaim_interval = self.config.claim_idle / 2;
+        let mut last_reclaimed: Option<Instant> = None;
+        loop {
+            if self.shutdown.is_shutdown() {
+                return Ok(());
+            }
+
+            if last_reclaimed.is_none_or(|at| at.elapsed() >= reclaim_interval) {
+                self.reclaim(conn)
+                    .await
+                    .context("failed to reclaim pending entries")?;
+                last_reclaimed = Some(Instant::now());
+            }
+
+            let streams = [stream];
+            let read = tokio::select! {
+                read = conn.xread_options(&streams, &[">"], &read_options) => read,
+                () = self.shutdown.wait() => continue,
+            };
+            let reply: Option<StreamReadReply> = read.context("failed to read new entries")?;
+            for entry in reply.into_iter().flat_map(|r| r.keys).flat_map(|k| k.ids) {
+                self.handle_entry(conn, entry, 1).await?;
+            }
+        }
+    }
+
+    /// Claims entries which have been pending for longer than `claim_idle`,
+    /// including any this consumer failed to handle, and handles them again.
+    async fn reclaim(&self, conn: &mut MultiplexedConnection) -> anyhow::Result<()> {
+        let StreamConfig {
+            stream,
+            group,
+            consumer,
+            claim_idle,
+            ..
+        } = &self.config;
+        let min_idle_ms = claim_idle.as_millis() as usize;
+        loop {
+            let pending: StreamPendingCountReply = redis::cmd("XPENDING")
+                .arg(stream)
+                .arg(group)
+                .arg("IDLE")
+                .arg(min_idle_ms)
+                .arg("-")
+                .arg("+")
+                .arg(BATCH_SIZE)
+                .query_async(conn)
+                .await?;
+            if pending.ids.is_empty() {
+                return Ok(());
+            }
+            let deliveries: HashMap<_, _> = pending
+                .ids
+                .iter()
+                .map(|pending| (pending.id.clone(), pending.times_delivered))
+                .collect();
+            let ids: Vec<_> = deliveries.keys().collect();
+
+            // Another consumer may claim an entry first, in which case it
+            // isn't returned here.
+            let claimed: StreamClaimReply = conn
+                .xclaim(stream, group, consumer, min_idle_ms, &ids)
+                .await?;
+            if claimed.ids.is_empty() {
+                return Ok(());
+            }
+            for entry in claimed.ids {
+                let delivery = deliveries.get(&entry.id).copied().unwrap_or_default() + 1;
+                self.handle_entry(conn, entry, delivery).await?;
+            }
+        }
+    }
+
+    /// Handles an entry on its `delivery`th delivery, acknowledging it if the
+    /// component succeeds and dead-lettering it if it has no more deliveries.
+    ///
+    /// Only failures to talk to Redis are returned as errors.
+    #[instrument(name = "spin_trigger_redis.handle_stream_entry", skip_all, fields(
+        otel.name = format!("{} receive", self.config.stream),
+        otel.kind = "consumer",
+        messaging.operation = "receive",
+        messaging.system = "redis",
+        messaging.message.id = %entry.id,
+    ))]
+    async fn handle_entry(
+        &self,
+        conn: &mut MultiplexedConnection,
+        entry: StreamId,
+        delivery: usize,
+    ) -> anyhow::Result<()> {
+        let StreamConfig {
+            stream,
+            group,
+            field,
+            max_deliveries,
+            ..
+        } = &self.config;
+        let component_id = &self.component_id;
+        tracing::trace!(%stream, id = %entry.id, delivery, "Received entry");
+
+        let Some(payload) = entry.get::<Vec<u8>>(field) else {
+            // Retrying won't make the field appear.
+            let reason = format!("entry has no {field:?} field");
+            tracing::error!("Redis stream {stream:?} entry {}: {reason}", entry.id);
+            return self.dead_letter(conn, &entry, &reason).await;
+        };
+
+        match self.handler.handle(component_id, &payload).await {
+            Ok(()) => {
+                let _: () = conn.xack(stream, group, &[&entry.id]).await?;
+            }
+            Err(err) if delivery >= *max_deliveries => {
+                tracing::error!(
+                    "Component {component_id} failed to handle stream {stream:?} entry {} after {delivery} deliveries: {err:#}",
+                    entry.id
+                );
+                spin_telemetry::traces::mark_as_error(&err, None);
+                self.dead_letter(conn, &entry, &format!("{err:#}")).await?;
+            }
+            Err(err) => {
+                tracing::info!(
+                    "Component {component_id} handler failed (delivery {delivery} of {max_deliveries}), retrying after {}s: {err:#}",
+                    self.config.claim_idle.as_secs()
+                );
+                spin_telemetry::traces::mark_as_error(&err, None);
+            }
+        }
+        Ok(())
+    }
+
+    /// Acknowledges an entry which can't be handled, first copying it to the
+    /// dead-letter stream if there is one.
+    async fn dead_letter(
+        &self,
+        conn: &mut MultiplexedConnection,
+        entry: &StreamId,
+        reason: &str,
+    ) -> anyhow::Result<()> {
+        let StreamConfig {
+            stream,
+            group,
+            dead_letter_stream,
+            ..
+        } = &self.config;
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        if let Some(dead_letter_stream) = dead_letter_stream {
+            pipe.xadd(
+                dead_letter_stream,
+                "*",
+                &dead_letter_fields(stream, entry, reason),
+            )
+            .ignore();
+        } else {
+            tracing::warn!("Dropping Redis stream {stream:?} entry {}", entry.id);
+        }
+        pipe.xack(stream, group, &[&entry.id]).ignore();
+        let _: () = pipe.query_async(conn).await?;
+        Ok(())
+    }
+}
+
+/// The fields of a dead-lettered entry: the original entry's fields, and
+/// where it came from and why it was dead-lettered.
+fn dead_letter_fields(stream: &str, entry: &StreamId, reason: &str) -> Vec<(String, Vec<u8>)> {
+    let mut fields: Vec<_> = entry
+        .map
+        .iter()
+        .filter_map(|(field, value)| Some((field.clone(), redis::from_redis_value(value).ok()?)))
+        .collect();
+    fields.sort_by(|(a, _), (b, _)| a.cmp(b));
+    for (field, value) in [
+        ("spin_source_stream", stream),
+        ("spin_source_id", &entry.id),
+        ("spin_error", reason),
+    ] {
+        fields.push((field.to_owned(), value.as_bytes().to_vec()));
+    }
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, sync::Mutex};
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+        sync::mpsc,
+        task::AbortHandle,
+    };
+
+    use super::*;
+
+    /// Records the payloads it handles, failing those which are `fail`.
+    struct RecordingHandler {
+        handled: mpsc::UnboundedSender<String>,
+    }
+
+    impl EntryHandler for RecordingHandler {
+        async fn handle(&self, _component_id: &str, payload: &[u8]) -> anyhow::Result<()> {
+            self.handled
+                .send(String::from_utf8(payload.to_vec()).unwrap())
+                .unwrap();
+            anyhow::ensure!(payload != b"fail", "handler failed");
+            Ok(())
+        }
+    }
+
+    type Fields = Vec<(String, String)>;
+
+    /// An entry which has been delivered to a consumer but not acknowledged.
+    struct Pending {
+        consumer: String,
+        delivered_at: std::time::Instant,
+        deliveries: usize,
+    }
+
+    /// A consumer group of the stream being read.
+    #[derive(Default)]
+    struct Group {
+        /// The number of entries of the stream delivered to the group.
+        delivered: usize,
+        pending: BTreeMap<String, Pending>,
+    }
+
+    #[derive(Default)]
+    struct State {
+        streams: HashMap<String, Vec<(String, Fields)>>,
+        groups: HashMap<(String, String), Group>,
+        next_id: u64,
+        connections: Vec<AbortHandle>,
+    }
+
+    impl State {
+        fn add(&mut self, stream: &str, fields: Fields) -> String {
+            self.next_id += 1;
+            let id = format!("{}-0", self.next_id);
+            let entries = self.streams.entry(stream.to_owned()).or_default();
+            entries.push((id.clone(), fields));
+            id
+        }
+
+        fn entry(&self, stream: &str, id: &str) -> Reply {
+            let (id, fields) = self.streams[stream]
+                .iter()
+                .find(|(entry_id, _)| entry_id == id)
+                .unwrap();
+            entry_reply(id, fields)
+        }
+
+        fn group(&mut self, args: &[String]) -> &mut Group {
+            self.groups
+                .get_mut(&(args[0].clone(), args[1].clone()))
+                .expect("no such group")
+        }
+    }
+
+    enum Reply {
+        Ok,
+        Queued,
+        Error(String),
+        Int(usize),
+        Bulk(String),
+        Nil,
+        Array(Vec<Reply>),
+    }
+
+    impl Reply {
+        fn encode(&self, out: &mut Vec<u8>) {
+            match self {
+                Self::Ok => out.extend_from_slice(b"+OK\r\n"),
+                Self::Queued => out.extend_from_slice(b"+QUEUED\r\n"),
+                Self::Error(err) => out.extend_from_slice(format!("-{err}\r\n").as_bytes()),
+                Self::Int(n) => out.extend_from_slice(format!(":{n}\r\n").as_bytes()),
+                Self::Bulk(s) => {
+                    out.extend_from_slice(format!("${}\r\n{s}\r\n", s.len()).as_bytes())
+                }
+                Self::Nil => out.extend_from_slice(b"*-1\r\n"),
+                Self::Array(items) => {
+                    out.extend_from_slice(format!("*{}\r\n", items.len()).as_bytes());
+                    for item in items {
+                        item.encode(out);
+                    }
+                }
+            }
+        }
+    }
+
+    fn entry_reply(id: &str, fields: &Fields) -> Reply {
+        let fields = fields
+            .iter()
+            .flat_map(|(field, value)| [Reply::Bulk(field.clone()), Reply::Bulk(value.clone())]);
+        Reply::Array(vec![
+            Reply::Bulk(id.to_owned()),
+            Reply::Array(fields.collect()),
+        ])
+    }
+
+    /// A stand-in for a Redis server, implementing the stream commands used
+    /// by a consumer.
+    #[derive(Clone, Default)]
+    struct FakeRedis {
+        state: Arc<Mutex<State>>,
+    }
+
+    impl FakeRedis {
+        async fn start() -> (Self, String) {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let address = format!("redis://{}", listener.local_addr().unwrap());
+            let redis = Self::default();
+            let server = redis.clone();
+            tokio::spawn(async move {
+                loop {
+                    let (stream, _) = listener.accept().await.unwrap();
+                    let connection = tokio::spawn(server.clone().serve(stream));
+                    let mut state = server.state.lock().unwrap();
+                    state.connections.push(connection.abort_handle());
+                }
+            });
+            (redis, address)
+        }
+
+        fn state(&self) -> std::sync::MutexGuard<'_, State> {
+            self.state.lock().unwrap()
+        }
+
+        /// Drops all client connections.
+        fn disconnect(&self) {
+            for connection in self.state().connections.drain(..) {
+                connection.abort();
+            }
+        }
+
+        async fn serve(self, mut stream: TcpStream) {
+            let mut buf = Vec::new();
+            let mut transaction: Option<Vec<Vec<String>>> = None;
+            loop {
+                let Some((command, len)) = parse_command(&buf) else {
+                    if stream.read_buf(&mut buf).await.unwrap() == 0 {
+                        return;
+                    }
+                    continue;
+                };
+                buf.drain(..len);
+                let reply = match (command[0].to_ascii_uppercase().as_str(), &mut transaction) {
+                    ("MULTI", _) => {
+                        transaction = Some(Vec::new());
+                        Reply::Ok
+                    }
+                    ("EXEC", transaction) => {
+                        let commands = transaction.take().unwrap();
+                        let mut replies = Vec::new();
+                        for command in commands {
+                            replies.push(self.execute(&command));
+                        }
+                        Reply::Array(replies)
+                    }
+                    (_, Some(transaction)) => {
+                        transaction.push(command);
+                        Reply::Queued
+                    }
+                    (_, None) => self.execute(&command),
+                };
+                if matches!(reply, Reply::Nil) {
+                    // Block briefly, as if waiting for new entries.
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                }
+                let mut out = Vec::new();
+                reply.encode(&mut out);
+                stream.write_all(&out).await.unwrap();
+            }
+        }
+
+        fn execute(&self, command: &[String]) -> Reply {
+            let (name, args) = command.split_first().unwrap();
+            let arg_after = |name: &str| {
+                let i = args.iter().position(|arg| arg.eq_ignore_ascii_case(name));
+                &args[i.expect(name) + 1]
+            };
+            let mut state = self.state();
+            match name.to_ascii_uppercase().as_str() {
+                "CLIENT" => Reply::Ok,
+                "XGROUP" => {
+                    let (stream, group) = (&args[1], &args[2]);
+                    let key = (stream.clone(), group.clone());
+                    if state.groups.contains_key(&key) {
+                        return Reply::Error("BUSYGROUP Consumer Group name already exists".into());
+                    }
+                    let entries = state.streams.entry(stream.clone()).or_default();
+                    let delivered = entries.len();
+                    state.groups.insert(
+                        key,
+                        Group {
+                            delivered,
+                            ..Default::default()
+                        },
+                    );
+                    Reply::Ok
+                }
+                "XREADGROUP" => {
+                    let i = args.iter().position(|arg| arg == "GROUP").unwrap();
+                    let (group, consumer) = (&args[i + 1], &args[i + 2]);
+                    let count: usize = arg_after("COUNT").parse().unwrap();
+                    let stream = arg_after("STREAMS").clone();
+                    let entries = state.streams[&stream].clone();
+                    let group = state
+                        .groups
+                        .get_mut(&(stream.clone(), group.clone()))
+                        .unwrap();
+                    let new = &entries[group.delivered..entries.len().min(group.delivered + count)];
+                    if new.is_empty() {
+                        return Reply::Nil;
+                    }
+                    group.delivered += new.len();
+                    for (id, _) in new {
+                        group.pending.insert(
+                            id.clone(),
+                            Pending {
+                                consumer: consumer.clone(),
+                                delivered_at: std::time::Instant::now(),
+                                deliveries: 1,
+                            },
+                        );
+                    }
+                    let new = new.iter().map(|(id, fields)| entry_reply(id, fields));
+                    Reply::Array(vec![Reply::Array(vec![
+                        Reply::Bulk(stream),
+                        Reply::Array(new.collect()),
+                    ])])
+                }
+                "XACK" => {
+                    let group = state.group(args);
+                    let acked = args[2..]
+                        .iter()
+                        .filter(|id| group.pending.remove(*id).is_some())
+                        .count();
+                    Reply::Int(acked)
+                }
+                "XPENDING" => {
+                    let min_idle = Duration::from_millis(arg_after("IDLE").parse().unwrap());
+                    let count: usize = args.last().unwrap().parse().unwrap();
+                    let group = state.group(args);
+                    let pending = group
+                        .pending
+                        .iter()
+                        .filter(|(_, pending)| pending.delivered_at.elapsed() >= min_idle)
+                        .take(count)
+                        .map(|(id, pending)| {
+                            Reply::Array(vec![
+                                Reply::Bulk(id.clone()),
+                                Reply::Bulk(pending.consumer.clone()),
+                                Reply::Int(pending.delivered_at.elapsed().as_millis() as usize),
+                                Reply::Int(pending.deliveries),
+                            ])
+                        });
+                    Reply::Array(pending.collect())
+                }
+                "XCLAIM" => {
+                    let stream = &args[0];
+                    let consumer = &args[2];
+                    let min_idle = Duration::from_millis(args[3].parse().unwrap());
+                    let group = state.group(args);
+                    let mut claimed = Vec::new();
+                    for id in &args[4..] {
+                        let Some(pending) = group.pending.get_mut(id) else {
+                            continue;
+                        };
+                        if pending.delivered_at.elapsed() >= min_idle {
+                            pending.consumer = consumer.clone();
+                            pending.delivered_at = std::time::Instant::now();
+                            pending.deliveries += 1;
+                            claimed.push(id.clone());
+                        }
+                    }
+                    let claimed = claimed.iter().map(|id| state.entry(stream, id));
+                    Reply::Array(claimed.collect())
+                }
+                "XADD" => {
+                    let fields = args[2..]
+                        .chunks(2)
+                        .map(|pair| (pair[0].clone(), pair[1].clone()))
+                        .collect();
+                    Reply::Bulk(state.add(&args[0], fields))
+                }
+                _ => Reply::Error(format!("ERR unknown command '{name}'")),
+            }
+        }
+    }
+
+    /// Parses a command sent as an array of bulk strings, returning it and its
+    /// length, or `None` if the buffer doesn't hold a whole command yet.
+    fn parse_command(buf: &[u8]) -> Option<(Vec<String>, usize)> {
+        fn line(buf: &[u8], at: usize) -> Option<(&str, usize)> {
+            let len = buf.get(at..)?.windows(2).position(|w| w == b"\r\n")?;
+            let line = std::str::from_utf8(&buf[at..at + len]).unwrap();
+            Some((line, at + len + 2))
+        }
+        let (header, mut at) = line(buf, 0)?;
+        let count: usize = header.strip_prefix('*').unwrap().parse().unwrap();
+        let mut command = Vec::with_capacity(count);
+        for _ in 0..count {
+            let (header, start) = line(buf, at)?;
+            let len: usize = header.strip_prefix('$').unwrap().parse().unwrap();
+            let arg = buf.get(start..start + len)?;
+            command.push(String::from_utf8_lossy(arg).into_owned());
+            at = start + len + 2;
+        }
+        (buf.len() >= at).then_some((command, at))
+    }
+
+    fn config() -> StreamConfig {
+        StreamConfig {
+            stream: "orders".into(),
+            group: "processors".into(),
+            consumer: "spin-orders".into(),
+            field: StreamConfig::DEFAULT_FIELD.into(),
+            max_deliveries: 2,
+            dead_letter_stream: Some("dead".into()),
+            claim_idle: Duration::from_millis(100),
+        }
+    }
+
+    /// Starts a consumer of the fake server, returning the payloads it handles.
+    fn start_consumer(
+        address: String,
+        shutdown: &ShutdownToken,
+    ) -> (
+        mpsc::UnboundedReceiver<String>,
+        tokio::task::JoinHandle<anyhow::Result<()>>,
+    ) {
+        let (handled_tx, handled) = mpsc::unbounded_channel();
+        let handler = Arc::new(RecordingHandler {
+            handled: handled_tx,
+        });
+        let mut consumer = StreamConsumer::new(
+            address,
+            handler,
+            "orders".into(),
+            config(),
+            shutdown.clone(),
+        )
+        .unwrap();
+        consumer.reconnect_delay = Duration::from_millis(50);
+        (handled, tokio::spawn(consumer.run()))
+    }
+
+    /// Waits for a condition on the server's state to hold.
+    async fn eventually(redis: &FakeRedis, condition: impl Fn(&mut State) -> bool) {
+        let wait = async {
+            while !condition(&mut redis.state()) {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), wait)
+            .await
+            .expect("condition didn't hold");
+    }
+
+    fn payload(payload: &str) -> Fields {
+        vec![("payload".into(), payload.into())]
+    }
+
+    fn pending(state: &mut State) -> usize {
+        let key = ("orders".to_owned(), "processors".to_owned());
+        state
+            .groups
+            .get(&key)
+            .map_or(0, |group| group.pending.len())
+    }
+
+    fn has_group(state: &mut State) -> bool {
+        let key = ("orders".to_owned(), "processors".to_owned());
+        state.groups.contains_key(&key)
+    }
+
+    #[tokio::test]
+    async fn entries_are_read_and_acknowledged() {
+        let (redis, address) = FakeRedis::start().await;
+        let shutdown = ShutdownToken::new();
+        let (mut handled, consumer) = start_consumer(address, &shutdown);
+
+        eventually(&redis, has_group).await;
+        for message in ["first", "second"] {
+            redis.state().add("orders", payload(message));
+        }
+        assert_eq!(handled.recv().await.unwrap(), "first");
+        assert_eq!(handled.recv().await.unwrap(), "second");
+        eventually(&redis, |state| pending(state) == 0).await;
+
+        shutdown.shutdown();
+        consumer.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn failed_entries_are_retried_then_dead_lettered() {
+        let (redis, address) = FakeRedis::start().await;
+        let shutdown = ShutdownToken::new();
+        let (mut handled, consumer) = start_consumer(address, &shutdown);
+
+        eventually(&redis, has_group).await;
+        let id = redis.state().add("orders", payload("fail"));
+        // Delivered when read, and again when reclaimed after `claim_idle`.
+        assert_eq!(handled.recv().await.unwrap(), "fail");
+        assert_eq!(handled.recv().await.unwrap(), "fail");
+        eventually(&redis, |state| state.streams.contains_key("dead")).await;
+
+        let (_, fields) = redis.state().streams["dead"][0].clone();
+        let field = |name: &str| &fields.iter().find(|(field, _)| field == name).unwrap().1;
+        assert_eq!(field("payload"), "fail");
+        assert_eq!(field("spin_source_stream"), "orders");
+        assert_eq!(field("spin_source_id"), &id);
+        assert!(field("spin_error").contains("handler failed"));
+        eventually(&redis, |state| pending(state) == 0).await;
+        assert!(handled.try_recv().is_err());
+
+        shutdown.shutdown();
+        consumer.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn entries_pending_for_other_consumers_are_claimed() {
+        let (redis, address) = FakeRedis::start().await;
+        {
+            let mut state = redis.state();
+            let id = state.add("orders", payload("orphaned"));
+            let mut group = Group {
+                delivered: 1,
+                ..Default::default()
+            };
+            group.pending.insert(
+                id,
+                Pending {
+                    consumer: "crashed".into(),
+                    delivered_at: std::time::Instant::now() - Duration::from_secs(1),
+                    deliveries: 1,
+                },
+            );
+            state
+                .groups
+                .insert(("orders".into(), "processors".into()), group);
+        }
+        let shutdown = ShutdownToken::new();
+        let (mut handled, consumer) = start_consumer(address, &shutdown);
+
+        assert_eq!(handled.recv().await.unwrap(), "orphaned");
+        eventually(&redis, |state| pending(state) == 0).await;
+
+        shutdown.shutdown();
+        consumer.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn consumers_reconnect_after_errors() {
+        let (redis, address) = FakeRedis::start().await;
+        let shutdown = ShutdownToken::new();
+        let (mut handled, consumer) = start_consumer(address, &shutdown);
+
+        eventually(&redis, has_group).await;
+        redis.disconnect();
+        redis.state().add("orders", payload("after"));
+        assert_eq!(handled.recv().await.unwrap(), "after");
+        assert!(!consumer.is_finished());
+
+        shutdown.shutdown();
+        consumer.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn unreachable_servers_fail() {
+        // Find a port with nothing listening on it
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = format!("redis://{}", listener.local_addr().unwrap());
+        drop(listener);
+
+        let (_, consumer) = start_consumer(address, &ShutdownToken::new());
+        let err = consumer.await.unwrap().unwrap_err();
+        assert!(err.to_string().contains("failed to connect"), "{err}");
+    }
+
+    #[test]
+    fn dead_letters_record_their_source() {
+        let entry = StreamId {
+            id: "1-0".into(),
+            map: [
+                (
+                    "payload".to_owned(),
+                    redis::Value::BulkString(b"hi".to_vec()),
+                ),
+                (
+                    "kind".to_owned(),
+                    redis::Value::BulkString(b"order".to_vec()),
+                ),
+            ]
+            .into(),
+        };
+        let fields = dead_letter_fields("orders", &entry, "boom");
+        let fields: Vec<_> = fields
+            .iter()
+            .map(|(field, value)| (field.as_str(), std::str::from_utf8(value).unwrap()))
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                ("kind", "order"),
+                ("payload", "hi"),
+                ("spin_source_stream", "orders"),
+                ("spin_source_id", "1-0"),
+                ("spin_error", "boom"),
+            ]
+        );
+    }
+}
@@ -1,11 +1,13 @@
 mod initial_kv_setter;
 mod launch_metadata;
+mod log_rotation;
 mod max_instance_memory;
 mod sqlite_statements;
 mod stdio;
 mod summary;
 
 use std::path::PathBuf;
+use std::time::{Duration, Instant};
 use std::{future::Future, sync::Arc};
 
 use anyhow::{Context, Result};
@@ -17,21 +19,28 @@ use spin_common::url::parse_file_url;
 use spin_factors::RuntimeFactors;
 use spin_factors_executor::{ComponentLoader, FactorsExecutor};
 
-use crate::{loader::ComponentLoader as ComponentLoaderImpl, Trigger, TriggerApp};
+use crate::{loader::ComponentLoader as ComponentLoaderImpl, ShutdownToken, Trigger, TriggerApp};
 pub use initial_kv_setter::InitialKvSetterHook;
 pub use launch_metadata::LaunchMetadata;
+pub use log_rotation::{
+    parse_log_age, parse_log_size, LogRotation, LogRotationOverrides, RotatingLogFile,
+};
 pub use max_instance_memory::MaxInstanceMemoryHook;
 pub use sqlite_statements::SqlStatementExecutorHook;
 use stdio::FollowComponents;
-pub use stdio::StdioLoggingExecutorHooks;
+pub use stdio::{LogFormat, StdioLoggingExecutorHooks};
 pub use summary::{KeyValueDefaultStoreSummaryHook, SqliteDefaultStoreSummaryHook};
 
 pub const APP_LOG_DIR: &str = "APP_LOG_DIR";
 pub const SPIN_TRUNCATE_LOGS: &str = "SPIN_TRUNCATE_LOGS";
+pub const SPIN_LOG_MAX_SIZE: &str = "SPIN_LOG_MAX_SIZE";
+pub const SPIN_LOG_MAX_AGE: &str = "SPIN_LOG_MAX_AGE";
+pub const SPIN_LOG_FORMAT: &str = "SPIN_LOG_FORMAT";
 pub const DISABLE_WASMTIME_CACHE: &str = "DISABLE_WASMTIME_CACHE";
 pub const FOLLOW_LOG_OPT: &str = "FOLLOW_ID";
 pub const WASMTIME_CACHE_FILE: &str = "WASMTIME_CACHE_FILE";
 pub const RUNTIME_CONFIG_FILE: &str = "RUNTIME_CONFIG_FILE";
+pub const SHUTDOWN_GRACE_PERIOD: &str = "SHUTDOWN_GRACE_PERIOD";
 
 // Set by `spin up`
 pub const SPIN_LOCKED_URL: &str = "SPIN_LOCKED_URL";
@@ -62,6 +71,48 @@ pub struct FactorsTriggerCommand<T: Trigger<B::Factors>, B: RuntimeFactorsBuilde
     )]
     pub truncate_logs: bool,
 
+    /// Rotate log files before they grow past this size, e.g. `10M`. Overrides
+    /// `max_size` in `[log_rotation]` in the runtime config.
+    #[clap(
+        name = SPIN_LOG_MAX_SIZE,
+        long = "log-max-size",
+        env = SPIN_LOG_MAX_SIZE,
+        value_parser = parse_log_size,
+    )]
+    pub log_max_size: Option<u64>,
+
+    /// Rotate log files once they are older than this, e.g. `1d`. Overrides
+    /// `max_age` in `[log_rotation]` in the runtime config.
+    #[clap(
+        name = SPIN_LOG_MAX_AGE,
+        long = "log-max-age",
+        env = SPIN_LOG_MAX_AGE,
+        value_parser = parse_log_age,
+    )]
+    pub log_max_age: Option<Duration>,
+
+    /// The number of rotated log files to keep for each log. Overrides
+    /// `max_files` in `[log_rotation]` in the runtime config.
+    #[clap(long = "log-max-files", env = "SPIN_LOG_MAX_FILES")]
+    pub log_max_files: Option<usize>,
+
+    /// Gzip rotated log files. Overrides `compress` in `[log_rotation]` in the
+    /// runtime config.
+    #[clap(long = "log-compress")]
+    pub log_compress: bool,
+
+    /// The format of component stdout/stderr in log files and on the terminal.
+    /// With `json`, each line is written as a JSON object with its timestamp,
+    /// component ID, stream and trace context.
+    #[clap(
+        name = SPIN_LOG_FORMAT,
+        long = "log-format",
+        env = SPIN_LOG_FORMAT,
+        value_enum,
+        default_value = "text",
+    )]
+    pub log_format: LogFormat,
+
     /// Disable Wasmtime cache.
     #[clap(
         name = DISABLE_WASMTIME_CACHE,
@@ -118,6 +169,16 @@ pub struct FactorsTriggerCommand<T: Trigger<B::Factors>, B: RuntimeFactorsBuilde
     #[clap(long)]
     pub state_dir: Option<String>,
 
+    /// On shutdown, how long in seconds to wait for in-flight work to finish
+    /// before exiting. A second interrupt exits immediately.
+    #[clap(
+        name = SHUTDOWN_GRACE_PERIOD,
+        long = "shutdown-grace-period",
+        env = "SPIN_SHUTDOWN_GRACE_PERIOD",
+        default_value = "30",
+    )]
+    pub shutdown_grace_period: u64,
+
     #[clap(flatten)]
     pub trigger_args: T::CliArgs,
 
@@ -148,6 +209,20 @@ pub struct FactorsConfig {
     pub log_dir: UserProvidedPath,
     /// If set, Spin truncates the log files before starting the application.
     pub truncate_logs: bool,
+    /// Rotation options set on the command line.
+    pub log_rotation: LogRotationOverrides,
+    /// The format of component stdout/stderr.
+    pub log_format: LogFormat,
+}
+
+/// Where the application's logs are written, for triggers which write logs of
+/// their own alongside the components' logs.
+#[derive(Clone, Debug, Default)]
+pub struct LogConfig {
+    /// The fully resolved log directory, if logging to disk is enabled.
+    pub log_dir: Option<PathBuf>,
+    /// Rotation of log files, if configured.
+    pub log_rotation: Option<LogRotation>,
 }
 
 /// An empty implementation of clap::Args to be used as TriggerExecutor::RunConfig
@@ -181,6 +256,7 @@ impl<T: Trigger<B::Factors>, B: RuntimeFactorsBuilder> FactorsTriggerCommand<T,
         let local_app_dir = std::env::var(SPIN_LOCAL_APP_DIR).ok();
 
         let follow_components = self.follow_components();
+        let log_rotation = self.log_rotation();
 
         // Load App
         let app = {
@@ -197,7 +273,9 @@ impl<T: Trigger<B::Factors>, B: RuntimeFactorsBuilder> FactorsTriggerCommand<T,
             anyhow::bail!("This application requires the following features that are not available in this version of the '{}' trigger: {unmet}", T::TYPE);
         }
 
-        let trigger = T::new(self.trigger_args, &app)?;
+        let mut trigger = T::new(self.trigger_args, &app)?;
+        let shutdown = ShutdownToken::new();
+        let graceful = trigger.enable_graceful_shutdown(shutdown.clone());
         let mut builder: TriggerAppBuilder<T, B> = TriggerAppBuilder::new(trigger);
         let config = builder.engine_config();
 
@@ -230,6 +308,8 @@ impl<T: Trigger<B::Factors>, B: RuntimeFactorsBuilder> FactorsTriggerCommand<T,
             follow_components,
             log_dir,
             truncate_logs: self.truncate_logs,
+            log_rotation,
+            log_format: self.log_format,
         };
 
         let run_fut = builder
@@ -242,8 +322,31 @@ impl<T: Trigger<B::Factors>, B: RuntimeFactorsBuilder> FactorsTriggerCommand<T,
             .await?;
 
         let (abortable, abort_handle) = futures::future::abortable(run_fut);
-        ctrlc::set_handler(move || abort_handle.abort())?;
-        match abortable.await {
+        let signalled = shutdown.clone();
+        let mut first_signal = None;
+        ctrlc::set_handler(move || match first_signal {
+            None if graceful && !signalled.is_shutdown() => {
+                first_signal = Some(Instant::now());
+                signalled.shutdown();
+            }
+            // Under `spin up`, a single Ctrl-C reaches the trigger twice: from the
+            // terminal, and forwarded by `spin up`.
+            Some(first) if first.elapsed() < REPEAT_SIGNAL_DEBOUNCE => {}
+            _ => abort_handle.abort(),
+        })?;
+        let grace_period = Duration::from_secs(self.shutdown_grace_period);
+        let grace_period_expired = async {
+            shutdown.wait().await;
+            tracing::info!("User requested shutdown: waiting up to {grace_period:?} for in-flight work to finish");
+            tokio::time::sleep(grace_period).await;
+        };
+        let result = tokio::select! {
+            result = abortable => result,
+            () = grace_period_expired => {
+                anyhow::bail!("shutdown grace period expired: exiting with work in flight");
+            }
+        };
+        match result {
             Ok(Ok(())) => {
                 tracing::info!("Trigger executor shut down: exiting");
                 Ok(())
@@ -252,6 +355,9 @@ impl<T: Trigger<B::Factors>, B: RuntimeFactorsBuilder> FactorsTriggerCommand<T,
                 tracing::error!("Trigger executor failed");
                 Err(err)
             }
+            Err(_aborted) if shutdown.is_shutdown() => {
+                anyhow::bail!("shutdown interrupted: exiting with work in flight");
+            }
             Err(_aborted) => {
                 tracing::info!("User requested shutdown: exiting");
                 Ok(())
@@ -259,6 +365,16 @@ impl<T: Trigger<B::Factors>, B: RuntimeFactorsBuilder> FactorsTriggerCommand<T,
         }
     }
 
+    /// Rotation options as set on the command line.
+    fn log_rotation(&self) -> LogRotationOverrides {
+        LogRotationOverrides {
+            max_size: self.log_max_size,
+            max_age: self.log_max_age,
+            max_files: self.log_max_files,
+            compress: self.log_compress.then_some(true),
+        }
+    }
+
     fn follow_components(&self) -> FollowComponents {
         if self.silence_component_logs {
             FollowComponents::None
@@ -273,6 +389,9 @@ impl<T: Trigger<B::Factors>, B: RuntimeFactorsBuilder> FactorsTriggerCommand<T,
 
 const SLOTH_WARNING_DELAY_MILLIS: u64 = 1250;
 
+/// How long after the first shutdown signal a repeat is taken to be the same request.
+const REPEAT_SIGNAL_DEBOUNCE: Duration = Duration::from_millis(500);
+
 fn warn_if_wasm_build_slothful() -> sloth::SlothGuard {
     #[cfg(debug_assertions)]
     let message = "\
@@ -331,8 +450,11 @@ impl<T: Trigger<B::Factors>, B: RuntimeFactorsBuilder> TriggerAppBuilder<T, B> {
         self.trigger.add_to_linker(core_engine_builder.linker())?;
 
         let (factors, runtime_config) = B::build(&common_options, &options)?;
+        self.trigger
+            .configure_logs(&B::log_config(&common_options, &runtime_config)?)?;
 
         let mut executor = FactorsExecutor::new(core_engine_builder, factors)?;
+        executor.skip_loading_components(self.trigger.components_not_run(&app)?);
         B::configure_app(&mut executor, &runtime_config, &common_options, &options)?;
         let executor = Arc::new(executor);
 
@@ -374,6 +496,18 @@ pub trait RuntimeFactorsBuilder {
         args: &Self::CliArgs,
     ) -> anyhow::Result<(Self::Factors, Self::RuntimeConfig)>;
 
+    /// Where and how the application's logs are written.
+    fn log_config(
+        config: &FactorsConfig,
+        runtime_config: &Self::RuntimeConfig,
+    ) -> anyhow::Result<LogConfig> {
+        let _ = runtime_config;
+        Ok(LogConfig {
+            log_dir: None,
+            log_rotation: config.log_rotation.apply(None)?,
+        })
+    }
+
     /// Configure the factors in the executor.
     fn configure_app<U: Send + 'static>(
         executor: &mut FactorsExecutor<Self::Factors, U>,
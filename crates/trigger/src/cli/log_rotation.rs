@@ -0,0 +1,578 @@
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    thread::JoinHandle,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{bail, Context};
+use flate2::{write::GzEncoder, Compression};
+
+/// When to rotate a log file and how many rotated files to keep.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogRotation {
+    /// Rotate the file before it grows past this many bytes.
+    pub max_size: Option<u64>,
+    /// Rotate the file once it is older than this.
+    pub max_age: Option<Duration>,
+    /// The number of rotated files to keep, as `<file>.1` (the most recent)
+    /// to `<file>.<max_files>`.
+    pub max_files: usize,
+    /// Whether to gzip rotated files, as `<file>.<n>.gz`.
+    pub compress: bool,
+}
+
+impl LogRotation {
+    /// The number of rotated files kept if not configured.
+    pub const DEFAULT_MAX_FILES: usize = 5;
+
+    /// A policy under which the file is never rotated.
+    pub fn never() -> Self {
+        Self {
+            max_size: None,
+            max_age: None,
+            max_files: 0,
+            compress: false,
+        }
+    }
+
+    /// Describes when files are rotated, e.g. "at 10 MiB, keeping 5 files",
+    /// or `None` if they never are.
+    pub fn describe(&self) -> Option<String> {
+        let size = self.max_size.map(|size| match size {
+            size if size % (1 << 30) == 0 => format!("at {} GiB", size >> 30),
+            size if size % (1 << 20) == 0 => format!("at {} MiB", size >> 20),
+            size if size % (1 << 10) == 0 => format!("at {} KiB", size >> 10),
+            size => format!("at {size} bytes"),
+        });
+        let age = self
+            .max_age
+            .map(|age| format!("every {}", describe_age(age)));
+        let when = match (size, age) {
+            (Some(size), Some(age)) => format!("{size} or {age}"),
+            (Some(when), None) | (None, Some(when)) => when,
+            (None, None) => return None,
+        };
+        let compressed = if self.compress { " compressed" } else { "" };
+        Some(format!(
+            "{when}, keeping {}{compressed} files",
+            self.max_files
+        ))
+    }
+}
+
+/// Rotation options set on the command line. Each one that is set overrides
+/// the same setting from `[log_rotation]` in the runtime config.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LogRotationOverrides {
+    pub max_size: Option<u64>,
+    pub max_age: Option<Duration>,
+    pub max_files: Option<usize>,
+    pub compress: Option<bool>,
+}
+
+impl LogRotationOverrides {
+    /// Applies the overrides to the rotation from the runtime config, if any.
+    ///
+    /// Rotation is only turned on by a size or age limit, so refining options
+    /// without one, here or in the runtime config, are an error rather than
+    /// being ignored.
+    pub fn apply(&self, configured: Option<LogRotation>) -> anyhow::Result<Option<LogRotation>> {
+        let rotation = match configured {
+            Some(rotation) => rotation,
+            None if self.max_size.is_some() || self.max_age.is_some() => LogRotation {
+                max_files: LogRotation::DEFAULT_MAX_FILES,
+                ..LogRotation::never()
+            },
+            None if self.max_files.is_some() || self.compress.is_some() => bail!(
+                "--log-max-files and --log-compress require a size or age to rotate at, \
+                 from --log-max-size, --log-max-age or `[log_rotation]` in the runtime config"
+            ),
+            None => return Ok(None),
+        };
+        Ok(Some(LogRotation {
+            max_size: self.max_size.or(rotation.max_size),
+            max_age: self.max_age.or(rotation.max_age),
+            max_files: self.max_files.unwrap_or(rotation.max_files),
+            compress: self.compress.unwrap_or(rotation.compress),
+        }))
+    }
+}
+
+fn describe_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    match secs {
+        secs if secs % 86400 == 0 => format!("{}d", secs / 86400),
+        secs if secs % 3600 == 0 => format!("{}h", secs / 3600),
+        secs if secs % 60 == 0 => format!("{}m", secs / 60),
+        secs => format!("{secs}s"),
+    }
+}
+
+impl Default for LogRotation {
+    fn default() -> Self {
+        Self {
+            max_size: Some(10 * 1024 * 1024),
+            max_age: None,
+            max_files: Self::DEFAULT_MAX_FILES,
+            compress: false,
+        }
+    }
+}
+
+/// Parses a size in bytes, with an optional `K`, `M` or `G` suffix for
+/// KiB, MiB or GiB, e.g. `512K`.
+pub fn parse_log_size(size: &str) -> anyhow::Result<u64> {
+    let size = size.trim();
+    let (digits, unit) = match size.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => size.split_at(i),
+        None => (size, ""),
+    };
+    let multiplier = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        _ => bail!("invalid size '{size}': expected e.g. '512K' or '10M'"),
+    };
+    let value: u64 = digits
+        .parse()
+        .with_context(|| format!("invalid size '{size}': expected e.g. '512K' or '10M'"))?;
+    match value.checked_mul(multiplier) {
+        Some(0) => bail!("size must be greater than zero"),
+        Some(bytes) => Ok(bytes),
+        None => bail!("size '{size}' is too large"),
+    }
+}
+
+/// Parses a duration as a number with an `s`, `m`, `h` or `d` suffix, e.g. `12h`.
+pub fn parse_log_age(age: &str) -> anyhow::Result<Duration> {
+    let age = age.trim();
+    let invalid = || format!("invalid duration '{age}': expected e.g. '30m', '12h' or '1d'");
+    let Some(unit_start) = age.find(|c: char| !c.is_ascii_digit()) else {
+        bail!(invalid());
+    };
+    let (digits, unit) = age.split_at(unit_start);
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => bail!(invalid()),
+    };
+    let value: u64 = digits.parse().with_context(invalid)?;
+    match value.checked_mul(seconds) {
+        Some(0) => bail!("duration must be greater than zero"),
+        Some(secs) => Ok(Duration::from_secs(secs)),
+        None => bail!("duration '{age}' is too long"),
+    }
+}
+
+/// A log file which is appended to and rotated according to a [`LogRotation`].
+pub struct RotatingLogFile {
+    path: PathBuf,
+    file: File,
+    /// The current size of the file.
+    size: u64,
+    /// When the current file was started.
+    started: SystemTime,
+    rotation: LogRotation,
+    /// Compression of the most recently rotated file, which runs on its own
+    /// thread so that writes to the log aren't held up by it.
+    compressing: Option<JoinHandle<()>>,
+}
+
+impl RotatingLogFile {
+    /// Opens the log file at `path` for appending, creating it if necessary.
+    pub fn open(path: impl Into<PathBuf>, rotation: LogRotation) -> io::Result<Self> {
+        let path = path.into();
+        let file = open_append(&path)?;
+        let metadata = file.metadata()?;
+        let started = metadata
+            .created()
+            .or_else(|_| metadata.modified())
+            .unwrap_or_else(|_| SystemTime::now());
+        Ok(Self {
+            path,
+            file,
+            size: metadata.len(),
+            started,
+            rotation,
+            compressing: None,
+        })
+    }
+
+    /// The path of the log file being written.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes to the file at `now`, rotating it first if it is full or old.
+    fn write_at(&mut self, buf: &[u8], now: SystemTime) -> io::Result<usize> {
+        // A write larger than the size limit goes to an empty file rather than
+        // being split across files.
+        let full = self
+            .rotation
+            .max_size
+            .is_some_and(|max_size| self.size + buf.len() as u64 > max_size);
+        let old = self.rotation.max_age.is_some_and(|max_age| {
+            now.duration_since(self.started)
+                .is_ok_and(|age| age >= max_age)
+        });
+        if self.size > 0 && (full || old) && !self.is_compressing() {
+            self.rotate(now)?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    /// Moves the current file to `<file>.1`, shifting older rotated files
+    /// along and removing any beyond `max_files`, and starts a new file.
+    fn rotate(&mut self, now: SystemTime) -> io::Result<()> {
+        self.file.flush()?;
+        // The previous rotated file must be compressed before it is moved along.
+        debug_assert!(self.compressing.is_none());
+        let max_files = self.rotation.max_files;
+        if max_files == 0 {
+            self.file = File::create(&self.path)?;
+        } else {
+            // Rotated files may or may not be compressed, depending on the
+            // configuration they were written with.
+            for compressed in [false, true] {
+                remove_if_exists(&self.rotated_path(max_files, compressed))?;
+                for n in (1..max_files).rev() {
+                    rename_if_exists(
+                        &self.rotated_path(n, compressed),
+                        &self.rotated_path(n + 1, compressed),
+                    )?;
+                }
+            }
+            let rotated = self.rotated_path(1, false);
+            std::fs::rename(&self.path, &rotated)?;
+            self.file = open_append(&self.path)?;
+            if self.rotation.compress {
+                let compressed = self.rotated_path(1, true);
+                self.compressing = Some(std::thread::spawn(move || {
+                    // Compression failing shouldn't stop logging, and the log
+                    // is still there uncompressed.
+                    if let Err(err) = compress(&rotated, &compressed) {
+                        tracing::warn!("Failed to compress rotated log {rotated:?}: {err}");
+                    }
+                }));
+            }
+        }
+        self.size = 0;
+        self.started = now;
+        Ok(())
+    }
+
+    /// Whether the most recently rotated file is still being compressed.
+    ///
+    /// That file can't be moved along until it has been compressed, and
+    /// waiting would hold up the write, so rotation is put off until then,
+    /// letting the current file grow past its limits in the meantime.
+    fn is_compressing(&mut self) -> bool {
+        match &self.compressing {
+            Some(compressing) if !compressing.is_finished() => true,
+            _ => {
+                self.finish_compressing();
+                false
+            }
+        }
+    }
+
+    /// Waits for any background compression to finish.
+    fn finish_compressing(&mut self) {
+        if let Some(compressing) = self.compressing.take() {
+            // The thread reports its own errors.
+            let _ = compressing.join();
+        }
+    }
+
+    fn rotated_path(&self, n: usize, compressed: bool) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{n}"));
+        if compressed {
+            path.push(".gz");
+        }
+        path.into()
+    }
+}
+
+impl Write for RotatingLogFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_at(buf, SystemTime::now())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Drop for RotatingLogFile {
+    fn drop(&mut self) {
+        self.finish_compressing();
+    }
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    File::options().create(true).append(true).open(path)
+}
+
+/// Gzips the file at `from` to `to`, removing the original.
+fn compress(from: &Path, to: &Path) -> io::Result<()> {
+    let mut encoder = GzEncoder::new(File::create(to)?, Compression::default());
+    io::copy(&mut File::open(from)?, &mut encoder)?;
+    encoder.finish()?.sync_all()?;
+    std::fs::remove_file(from)
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+fn rename_if_exists(from: &Path, to: &Path) -> io::Result<()> {
+    match std::fs::rename(from, to) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+
+    fn read(path: impl AsRef<Path>) -> String {
+        std::fs::read_to_string(path).unwrap()
+    }
+
+    fn wait_for_compression(log: &RotatingLogFile) {
+        while log.compressing.as_ref().is_some_and(|c| !c.is_finished()) {
+            std::thread::yield_now();
+        }
+    }
+
+    fn rotation(max_size: Option<u64>, max_files: usize) -> LogRotation {
+        LogRotation {
+            max_size,
+            max_files,
+            ..LogRotation::never()
+        }
+    }
+
+    #[test]
+    fn rotates_when_full_and_keeps_max_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        let mut log = RotatingLogFile::open(&path, rotation(Some(10), 2)).unwrap();
+        for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+            log.write_all(line.as_bytes()).unwrap();
+        }
+
+        assert_eq!(read(&path), "fourth\n");
+        assert_eq!(read(dir.path().join("app.log.1")), "third\n");
+        assert_eq!(read(dir.path().join("app.log.2")), "second\n");
+        assert!(!dir.path().join("app.log.3").exists());
+    }
+
+    #[test]
+    fn appends_to_existing_file_and_counts_its_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        std::fs::write(&path, "existing\n").unwrap();
+        let mut log = RotatingLogFile::open(&path, rotation(Some(12), 1)).unwrap();
+        log.write_all(b"new\n").unwrap();
+
+        assert_eq!(read(&path), "new\n");
+        assert_eq!(read(dir.path().join("app.log.1")), "existing\n");
+    }
+
+    #[test]
+    fn without_max_files_the_file_is_truncated() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        let mut log = RotatingLogFile::open(&path, rotation(Some(4), 0)).unwrap();
+        log.write_all(b"one\n").unwrap();
+        log.write_all(b"two\n").unwrap();
+
+        assert_eq!(read(&path), "two\n");
+        assert!(!dir.path().join("app.log.1").exists());
+    }
+
+    #[test]
+    fn rotates_when_old() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        let rotation = LogRotation {
+            max_age: Some(Duration::from_secs(60)),
+            ..rotation(None, 3)
+        };
+        let mut log = RotatingLogFile::open(&path, rotation).unwrap();
+        let start = log.started;
+        log.write_at(b"one\n", start).unwrap();
+        log.write_at(b"two\n", start + Duration::from_secs(59))
+            .unwrap();
+        log.write_at(b"three\n", start + Duration::from_secs(60))
+            .unwrap();
+        log.write_at(b"four\n", start + Duration::from_secs(90))
+            .unwrap();
+
+        assert_eq!(read(&path), "three\nfour\n");
+        assert_eq!(read(dir.path().join("app.log.1")), "one\ntwo\n");
+    }
+
+    #[test]
+    fn compresses_rotated_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        let rotation = LogRotation {
+            compress: true,
+            ..rotation(Some(6), 2)
+        };
+        let mut log = RotatingLogFile::open(&path, rotation).unwrap();
+        for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+            wait_for_compression(&log);
+            log.write_all(line.as_bytes()).unwrap();
+        }
+        // Closing the log waits for compression to finish.
+        drop(log);
+
+        let gunzip = |name: &str| {
+            let file = File::open(dir.path().join(name)).unwrap();
+            let mut contents = String::new();
+            flate2::read::GzDecoder::new(file)
+                .read_to_string(&mut contents)
+                .unwrap();
+            contents
+        };
+        assert_eq!(read(&path), "fourth\n");
+        assert_eq!(gunzip("app.log.1.gz"), "third\n");
+        assert_eq!(gunzip("app.log.2.gz"), "second\n");
+        assert!(!dir.path().join("app.log.1").exists());
+        assert!(!dir.path().join("app.log.3.gz").exists());
+    }
+
+    #[test]
+    fn rotation_waits_for_compression() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        let rotation = LogRotation {
+            compress: true,
+            ..rotation(Some(6), 2)
+        };
+        let mut log = RotatingLogFile::open(&path, rotation).unwrap();
+        let (finish, finished) = std::sync::mpsc::channel::<()>();
+        log.compressing = Some(std::thread::spawn(move || {
+            let _ = finished.recv();
+        }));
+
+        log.write_all(b"first\n").unwrap();
+        log.write_all(b"second\n").unwrap();
+        assert_eq!(read(&path), "first\nsecond\n");
+        assert!(!dir.path().join("app.log.1").exists());
+
+        drop(finish);
+        wait_for_compression(&log);
+        log.write_all(b"third\n").unwrap();
+        drop(log);
+        assert_eq!(read(&path), "third\n");
+        assert!(dir.path().join("app.log.1.gz").exists());
+    }
+
+    #[test]
+    fn overrides_apply_to_the_configured_rotation() {
+        let configured = LogRotation {
+            max_size: Some(1024),
+            max_age: None,
+            max_files: 2,
+            compress: false,
+        };
+        let overrides = LogRotationOverrides {
+            max_age: Some(Duration::from_secs(60)),
+            max_files: Some(7),
+            compress: Some(true),
+            ..Default::default()
+        };
+        assert_eq!(
+            overrides.apply(Some(configured.clone())).unwrap(),
+            Some(LogRotation {
+                max_size: Some(1024),
+                max_age: Some(Duration::from_secs(60)),
+                max_files: 7,
+                compress: true,
+            })
+        );
+
+        let defaults = LogRotationOverrides::default();
+        assert_eq!(defaults.apply(None).unwrap(), None);
+        assert_eq!(
+            defaults.apply(Some(configured.clone())).unwrap(),
+            Some(configured)
+        );
+
+        let size_only = LogRotationOverrides {
+            max_size: Some(10),
+            ..Default::default()
+        };
+        assert_eq!(
+            size_only.apply(None).unwrap(),
+            Some(LogRotation {
+                max_size: Some(10),
+                max_files: LogRotation::DEFAULT_MAX_FILES,
+                ..LogRotation::never()
+            })
+        );
+
+        // Refining options with nothing to refine are not silently ignored.
+        let compress_only = LogRotationOverrides {
+            compress: Some(true),
+            ..Default::default()
+        };
+        compress_only.apply(None).unwrap_err();
+    }
+
+    #[test]
+    fn parses_sizes_and_ages() {
+        assert_eq!(parse_log_size("1024").unwrap(), 1024);
+        assert_eq!(parse_log_size("512K").unwrap(), 512 * 1024);
+        assert_eq!(parse_log_size("10MB").unwrap(), 10 * 1024 * 1024);
+        assert_eq!(parse_log_size("1g").unwrap(), 1024 * 1024 * 1024);
+        parse_log_size("0").unwrap_err();
+        parse_log_size("ten").unwrap_err();
+        parse_log_size("10X").unwrap_err();
+
+        assert_eq!(parse_log_age("90s").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_log_age("30m").unwrap(), Duration::from_secs(1800));
+        assert_eq!(parse_log_age("12h").unwrap(), Duration::from_secs(43200));
+        assert_eq!(parse_log_age("1d").unwrap(), Duration::from_secs(86400));
+        parse_log_age("1").unwrap_err();
+        parse_log_age("0d").unwrap_err();
+        parse_log_age("1w").unwrap_err();
+    }
+
+    #[test]
+    fn describes_policies() {
+        assert_eq!(LogRotation::never().describe(), None);
+        assert_eq!(
+            LogRotation::default().describe().unwrap(),
+            "at 10 MiB, keeping 5 files"
+        );
+        let rotation = LogRotation {
+            max_size: Some(1500),
+            max_age: Some(Duration::from_secs(86400)),
+            max_files: 2,
+            compress: true,
+        };
+        assert_eq!(
+            rotation.describe().unwrap(),
+            "at 1500 bytes or every 1d, keeping 2 compressed files"
+        );
+    }
+}
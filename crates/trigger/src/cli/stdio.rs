@@ -1,10 +1,15 @@
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
+    io::Write as _,
     path::{Path, PathBuf},
+    sync::{Arc, Mutex},
     task::Poll,
 };
 
 use anyhow::{Context, Result};
+use chrono::{SecondsFormat, Utc};
+use clap::ValueEnum;
+use serde::Serialize;
 use spin_common::ui::quoted_path;
 use spin_core::async_trait;
 use spin_factor_wasi::WasiFactor;
@@ -12,9 +17,25 @@ use spin_factors::RuntimeFactors;
 use spin_factors_executor::ExecutorHooks;
 use tokio::io::AsyncWrite;
 
+use super::{LogRotation, RotatingLogFile};
+
 pub const STDOUT_LOG_FILE_SUFFIX: &str = "stdout";
 pub const STDERR_LOG_FILE_SUFFIX: &str = "stderr";
 
+/// A line of JSON output longer than this is split across records.
+const MAX_JSON_LINE_LEN: usize = 64 * 1024;
+
+/// How component stdout/stderr is written to log files and the terminal.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Output is written as the component writes it.
+    #[default]
+    Text,
+    /// Each line of output is written as a JSON object with its timestamp,
+    /// component ID, stream and trace context.
+    Json,
+}
+
 /// Which components should have their logs followed on stdout/stderr.
 #[derive(Clone, Debug, Default)]
 pub enum FollowComponents {
@@ -43,18 +64,29 @@ pub struct StdioLoggingExecutorHooks {
     follow_components: FollowComponents,
     log_dir: Option<PathBuf>,
     truncate_log: bool,
+    log_rotation: LogRotation,
+    log_format: LogFormat,
+    /// Log path -> open log file, shared by all instances of a component.
+    log_files: Mutex<HashMap<PathBuf, SharedLogFile>>,
 }
 
+type SharedLogFile = Arc<Mutex<RotatingLogFile>>;
+
 impl StdioLoggingExecutorHooks {
     pub fn new(
         follow_components: FollowComponents,
         log_dir: Option<PathBuf>,
         truncate_log: bool,
+        log_rotation: Option<LogRotation>,
+        log_format: LogFormat,
     ) -> Self {
         Self {
             follow_components,
             log_dir,
             truncate_log,
+            log_rotation: log_rotation.unwrap_or_else(LogRotation::never),
+            log_format,
+            log_files: Default::default(),
         }
     }
 
@@ -70,11 +102,31 @@ impl StdioLoggingExecutorHooks {
         let log_path = log_path.as_deref();
 
         let follow = self.follow_components.should_follow(component_id);
-        match log_path {
-            Some(log_path) => ComponentStdioWriter::new_forward(log_path, follow)
-                .with_context(|| format!("Failed to open log file {}", quoted_path(log_path))),
-            None => ComponentStdioWriter::new_inherit(),
+        let writer = match log_path {
+            Some(log_path) => {
+                let file = self.log_file(log_path).with_context(|| {
+                    format!("Failed to open log file {}", quoted_path(log_path))
+                })?;
+                ComponentStdioWriter::new_forward(file, follow)
+            }
+            None => ComponentStdioWriter::new_inherit()?,
+        };
+        Ok(match self.log_format {
+            LogFormat::Text => writer,
+            LogFormat::Json => writer.with_json_lines(component_id, log_suffix),
+        })
+    }
+
+    /// Gets the log file at the path, opening it if no instance has yet.
+    fn log_file(&self, log_path: &Path) -> std::io::Result<SharedLogFile> {
+        let mut log_files = self.log_files.lock().unwrap();
+        if let Some(file) = log_files.get(log_path) {
+            return Ok(file.clone());
         }
+        let file = RotatingLogFile::open(log_path, self.log_rotation.clone())?;
+        let file = Arc::new(Mutex::new(file));
+        log_files.insert(log_path.to_owned(), file.clone());
+        Ok(file)
     }
 
     fn validate_follows(&self, app: &spin_app::App) -> anyhow::Result<()> {
@@ -131,7 +183,10 @@ impl<F: RuntimeFactors, U> ExecutorHooks<F, U> for StdioLoggingExecutorHooks {
                 Self::truncate_log_files(dir);
             }
 
-            println!("Logging component stdio to {}", quoted_path(dir.join("")))
+            println!("Logging component stdio to {}", quoted_path(dir.join("")));
+            if let Some(policy) = self.log_rotation.describe() {
+                println!("Rotating component logs {policy}");
+            }
         }
         Ok(())
     }
@@ -162,6 +217,8 @@ impl<F: RuntimeFactors, U> ExecutorHooks<F, U> for StdioLoggingExecutorHooks {
 /// tracing compatibility layer.
 pub struct ComponentStdioWriter {
     inner: ComponentStdioWriterInner,
+    /// If set, output is written as JSON lines rather than as is.
+    json_lines: Option<JsonLines>,
 }
 
 enum ComponentStdioWriterInner {
@@ -169,8 +226,7 @@ enum ComponentStdioWriterInner {
     Inherit,
     /// Forward stdout/stderr to a file in addition to the inherited stdout/stderr.
     Forward {
-        sync_file: std::fs::File,
-        async_file: tokio::fs::File,
+        file: SharedLogFile,
         state: ComponentStdioWriterState,
         follow: bool,
     },
@@ -183,32 +239,51 @@ enum ComponentStdioWriterState {
 }
 
 impl ComponentStdioWriter {
-    fn new_forward(log_path: &Path, follow: bool) -> anyhow::Result<Self> {
-        let sync_file = std::fs::File::options()
-            .create(true)
-            .append(true)
-            .open(log_path)?;
-
-        let async_file = sync_file
-            .try_clone()
-            .context("could not get async file handle")?
-            .into();
-
-        Ok(Self {
+    fn new_forward(file: SharedLogFile, follow: bool) -> Self {
+        Self {
             inner: ComponentStdioWriterInner::Forward {
-                sync_file,
-                async_file,
+                file,
                 state: ComponentStdioWriterState::File,
                 follow,
             },
-        })
+            json_lines: None,
+        }
     }
 
     fn new_inherit() -> anyhow::Result<Self> {
         Ok(Self {
             inner: ComponentStdioWriterInner::Inherit,
+            json_lines: None,
         })
     }
+
+    fn with_json_lines(mut self, component_id: &str, stream: &str) -> Self {
+        self.json_lines = Some(JsonLines::new(component_id, stream));
+        self
+    }
+
+    /// Writes all of `buf` to the log file and/or stderr.
+    fn write_all_output(&mut self, buf: &[u8]) -> std::io::Result<()> {
+        match &mut self.inner {
+            ComponentStdioWriterInner::Inherit => std::io::stderr().write_all(buf),
+            ComponentStdioWriterInner::Forward { file, follow, .. } => {
+                file.lock().unwrap().write_all(buf)?;
+                if *follow {
+                    std::io::stderr().write_all(buf)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Drop for ComponentStdioWriter {
+    fn drop(&mut self) {
+        // Don't lose a final line the component didn't terminate.
+        if let Some(records) = self.json_lines.as_mut().and_then(JsonLines::finish) {
+            _ = self.write_all_output(&records);
+        }
+    }
 }
 
 impl AsyncWrite for ComponentStdioWriter {
@@ -219,6 +294,11 @@ impl AsyncWrite for ComponentStdioWriter {
     ) -> Poll<std::result::Result<usize, std::io::Error>> {
         let this = self.get_mut();
 
+        if this.json_lines.is_some() {
+            // Records are small and log files are written synchronously anyway.
+            return Poll::Ready(std::io::Write::write(this, buf));
+        }
+
         loop {
             match &mut this.inner {
                 ComponentStdioWriterInner::Inherit => {
@@ -232,15 +312,14 @@ impl AsyncWrite for ComponentStdioWriter {
                     return Poll::Ready(Ok(written));
                 }
                 ComponentStdioWriterInner::Forward {
-                    async_file,
+                    file,
                     state,
                     follow,
-                    ..
                 } => match &state {
                     ComponentStdioWriterState::File => {
-                        let written =
-                            futures::ready!(std::pin::Pin::new(async_file).poll_write(cx, buf));
-                        let written = match written {
+                        // Log files are shared between instances (and may be
+                        // rotated), so are written synchronously.
+                        let written = match file.lock().unwrap().write(buf) {
                             Ok(w) => w,
                             Err(e) => return Poll::Ready(Err(e)),
                         };
@@ -282,10 +361,8 @@ impl AsyncWrite for ComponentStdioWriter {
             ComponentStdioWriterInner::Inherit => {
                 std::pin::Pin::new(&mut tokio::io::stderr()).poll_flush(cx)
             }
-            ComponentStdioWriterInner::Forward {
-                async_file, state, ..
-            } => match state {
-                ComponentStdioWriterState::File => std::pin::Pin::new(async_file).poll_flush(cx),
+            ComponentStdioWriterInner::Forward { file, state, .. } => match state {
+                ComponentStdioWriterState::File => Poll::Ready(file.lock().unwrap().flush()),
                 ComponentStdioWriterState::Follow(_) => {
                     std::pin::Pin::new(&mut tokio::io::stderr()).poll_flush(cx)
                 }
@@ -303,10 +380,8 @@ impl AsyncWrite for ComponentStdioWriter {
             ComponentStdioWriterInner::Inherit => {
                 std::pin::Pin::new(&mut tokio::io::stderr()).poll_flush(cx)
             }
-            ComponentStdioWriterInner::Forward {
-                async_file, state, ..
-            } => match state {
-                ComponentStdioWriterState::File => std::pin::Pin::new(async_file).poll_shutdown(cx),
+            ComponentStdioWriterInner::Forward { file, state, .. } => match state {
+                ComponentStdioWriterState::File => Poll::Ready(file.lock().unwrap().flush()),
                 ComponentStdioWriterState::Follow(_) => {
                     std::pin::Pin::new(&mut tokio::io::stderr()).poll_flush(cx)
                 }
@@ -319,15 +394,19 @@ impl std::io::Write for ComponentStdioWriter {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
         spin_telemetry::logs::handle_app_log(buf);
 
+        if let Some(json_lines) = &mut self.json_lines {
+            let records = json_lines.push(buf);
+            self.write_all_output(&records)?;
+            return Ok(buf.len());
+        }
+
         match &mut self.inner {
             ComponentStdioWriterInner::Inherit => {
                 std::io::stderr().write_all(buf)?;
                 Ok(buf.len())
             }
-            ComponentStdioWriterInner::Forward {
-                sync_file, follow, ..
-            } => {
-                let written = sync_file.write(buf)?;
+            ComponentStdioWriterInner::Forward { file, follow, .. } => {
+                let written = file.lock().unwrap().write(buf)?;
                 if *follow {
                     std::io::stderr().write_all(&buf[..written])?;
                 }
@@ -339,10 +418,8 @@ impl std::io::Write for ComponentStdioWriter {
     fn flush(&mut self) -> std::io::Result<()> {
         match &mut self.inner {
             ComponentStdioWriterInner::Inherit => std::io::stderr().flush(),
-            ComponentStdioWriterInner::Forward {
-                sync_file, follow, ..
-            } => {
-                sync_file.flush()?;
+            ComponentStdioWriterInner::Forward { file, follow, .. } => {
+                file.lock().unwrap().flush()?;
                 if *follow {
                     std::io::stderr().flush()?;
                 }
@@ -352,6 +429,80 @@ impl std::io::Write for ComponentStdioWriter {
     }
 }
 
+/// Splits component output into lines and formats each as a JSON record.
+struct JsonLines {
+    component_id: String,
+    stream: String,
+    /// Output since the last complete line.
+    partial: Vec<u8>,
+}
+
+#[derive(Serialize)]
+struct JsonLogRecord<'a> {
+    timestamp: String,
+    component_id: &'a str,
+    stream: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trace_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    span_id: Option<String>,
+    message: &'a str,
+}
+
+impl JsonLines {
+    fn new(component_id: &str, stream: &str) -> Self {
+        Self {
+            component_id: component_id.to_owned(),
+            stream: stream.to_owned(),
+            partial: Vec::new(),
+        }
+    }
+
+    /// Adds output, returning the records of any lines it completes.
+    fn push(&mut self, mut buf: &[u8]) -> Vec<u8> {
+        let mut records = Vec::new();
+        while let Some(end) = buf.iter().position(|&b| b == b'\n') {
+            self.partial.extend_from_slice(&buf[..end]);
+            self.record(&mut records);
+            buf = &buf[end + 1..];
+        }
+        self.partial.extend_from_slice(buf);
+        if self.partial.len() >= MAX_JSON_LINE_LEN {
+            self.record(&mut records);
+        }
+        records
+    }
+
+    /// Returns the record of an unterminated final line, if there is one.
+    fn finish(&mut self) -> Option<Vec<u8>> {
+        if self.partial.is_empty() {
+            return None;
+        }
+        let mut records = Vec::new();
+        self.record(&mut records);
+        Some(records)
+    }
+
+    /// Appends the record of the partial line to `records`, starting a new line.
+    fn record(&mut self, records: &mut Vec<u8>) {
+        let line = std::mem::take(&mut self.partial);
+        let line = line.strip_suffix(b"\r").unwrap_or(&line);
+        let message = String::from_utf8_lossy(line);
+        let (trace_id, span_id) = spin_telemetry::traces::current_span_ids().unzip();
+        let record = JsonLogRecord {
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            component_id: &self.component_id,
+            stream: &self.stream,
+            trace_id,
+            span_id,
+            message: &message,
+        };
+        // Serializing strings to a Vec can't fail.
+        serde_json::to_writer(&mut *records, &record).unwrap();
+        records.push(b'\n');
+    }
+}
+
 fn bullet_list<S: std::fmt::Display>(items: impl IntoIterator<Item = S>) -> String {
     items
         .into_iter()
@@ -359,3 +510,50 @@ fn bullet_list<S: std::fmt::Display>(items: impl IntoIterator<Item = S>) -> Stri
         .collect::<Vec<_>>()
         .join("\n")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_records(output: &[u8]) -> Vec<serde_json::Value> {
+        output
+            .split(|&b| b == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn json_lines_are_split_into_records() {
+        let mut lines = JsonLines::new("hello", STDOUT_LOG_FILE_SUFFIX);
+
+        assert!(lines.push(b"partial").is_empty());
+        let output = lines.push(b" line\r\nsecond\n\nthird");
+        let records = parse_records(&output);
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0]["message"], "partial line");
+        assert_eq!(records[0]["component_id"], "hello");
+        assert_eq!(records[0]["stream"], "stdout");
+        assert!(records[0]["timestamp"].as_str().unwrap().ends_with('Z'));
+        assert!(records[0].get("trace_id").is_none());
+        assert_eq!(records[1]["message"], "second");
+        assert_eq!(records[2]["message"], "");
+
+        let output = lines.finish().unwrap();
+        assert_eq!(parse_records(&output)[0]["message"], "third");
+        assert!(lines.finish().is_none());
+    }
+
+    #[test]
+    fn long_json_lines_are_split() {
+        let mut lines = JsonLines::new("hello", STDERR_LOG_FILE_SUFFIX);
+        let output = lines.push(&vec![b'a'; MAX_JSON_LINE_LEN + 1]);
+        let records = parse_records(&output);
+        assert_eq!(records.len(), 1);
+        assert_eq!(
+            records[0]["message"].as_str().unwrap().len(),
+            MAX_JSON_LINE_LEN + 1
+        );
+        assert!(lines.finish().is_none());
+    }
+}
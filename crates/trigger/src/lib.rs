@@ -1,5 +1,6 @@
 pub mod cli;
 pub mod loader;
+mod shutdown;
 
 use std::future::Future;
 
@@ -8,6 +9,7 @@ use spin_core::Linker;
 use spin_factors::RuntimeFactors;
 use spin_factors_executor::{FactorsExecutorApp, FactorsInstanceBuilder};
 
+pub use shutdown::ShutdownToken;
 pub use spin_app::App;
 
 /// Type alias for a [`spin_factors_executor::FactorsExecutorApp`] specialized to a [`Trigger`].
@@ -59,6 +61,31 @@ pub trait Trigger<F: RuntimeFactors>: Sized + Send {
         Ok(())
     }
 
+    /// Tells this trigger where the application's logs are written, so that it
+    /// can write logs of its own alongside them.
+    fn configure_logs(&mut self, logs: &cli::LogConfig) -> anyhow::Result<()> {
+        let _ = logs;
+        Ok(())
+    }
+
+    /// Prepares this trigger to shut down gracefully when `token` is signalled.
+    ///
+    /// A trigger which supports this should stop accepting new work once the token
+    /// is signalled, and return from [`Trigger::run`] when its in-flight work has
+    /// finished. Returns `false` (the default) if the trigger does not support
+    /// graceful shutdown, in which case [`Trigger::run`] is aborted on shutdown.
+    fn enable_graceful_shutdown(&mut self, token: ShutdownToken) -> bool {
+        let _ = token;
+        false
+    }
+
+    /// Returns the IDs of components which this trigger handles without running
+    /// them, so that their Wasm need not be loaded.
+    fn components_not_run(&self, app: &App) -> anyhow::Result<Vec<String>> {
+        let _ = app;
+        Ok(Vec::new())
+    }
+
     /// Run this trigger.
     fn run(
         self,
@@ -0,0 +1,63 @@
+use std::sync::Arc;
+
+use tokio::sync::watch;
+
+/// A token which is signalled when a trigger should shut down.
+///
+/// Clones share the same state: signalling any clone signals them all.
+#[derive(Clone, Debug)]
+pub struct ShutdownToken {
+    tx: Arc<watch::Sender<bool>>,
+}
+
+impl ShutdownToken {
+    /// Creates a new, unsignalled token.
+    pub fn new() -> Self {
+        Self {
+            tx: Arc::new(watch::Sender::new(false)),
+        }
+    }
+
+    /// Signals that the trigger should shut down.
+    pub fn shutdown(&self) {
+        self.tx.send_replace(true);
+    }
+
+    /// Returns true if shutdown has been signalled.
+    pub fn is_shutdown(&self) -> bool {
+        *self.tx.borrow()
+    }
+
+    /// Waits until shutdown is signalled.
+    pub async fn wait(&self) {
+        let mut rx = self.tx.subscribe();
+        // The sender lives as long as `self`, so this can't fail.
+        let _ = rx.wait_for(|shutdown| *shutdown).await;
+    }
+}
+
+impl Default for ShutdownToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn clones_observe_shutdown() {
+        let token = ShutdownToken::new();
+        let clone = token.clone();
+        assert!(!clone.is_shutdown());
+
+        let waiter = tokio::spawn(async move { clone.wait().await });
+        token.shutdown();
+        waiter.await.unwrap();
+        assert!(token.is_shutdown());
+
+        // Waiting after shutdown returns immediately.
+        token.wait().await;
+    }
+}
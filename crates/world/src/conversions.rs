@@ -3,7 +3,8 @@ use super::*;
 mod rdbms_types {
     use super::*;
     use spin::postgres3_0_0::postgres as pg3;
-    use spin::postgres4_0_0::postgres as pg4;
+    use spin::postgres4_0_0::postgres as pg4_0;
+    use spin::postgres4_1_0::postgres as pg4;
 
     impl From<v2::rdbms_types::Column> for v1::rdbms_types::Column {
         fn from(value: v2::rdbms_types::Column) -> Self {
@@ -15,7 +16,7 @@ mod rdbms_types {
     }
 
     impl From<pg4::Column> for v1::rdbms_types::Column {
-        fn from(value: spin::postgres4_0_0::postgres::Column) -> Self {
+        fn from(value: pg4::Column) -> Self {
             v1::rdbms_types::Column {
                 name: value.name,
                 data_type: value.data_type.into(),
@@ -41,6 +42,15 @@ mod rdbms_types {
         }
     }
 
+    impl From<pg4::Column> for pg4_0::Column {
+        fn from(value: pg4::Column) -> Self {
+            pg4_0::Column {
+                name: value.name,
+                data_type: value.data_type.into(),
+            }
+        }
+    }
+
     impl From<v2::rdbms_types::DbValue> for v1::rdbms_types::DbValue {
         fn from(value: v2::rdbms_types::DbValue) -> v1::rdbms_types::DbValue {
             match value {
@@ -134,6 +144,39 @@ mod rdbms_types {
         }
     }
 
+    impl From<pg4::DbValue> for pg4_0::DbValue {
+        fn from(value: pg4::DbValue) -> pg4_0::DbValue {
+            match value {
+                pg4::DbValue::Boolean(b) => pg4_0::DbValue::Boolean(b),
+                pg4::DbValue::Int8(i) => pg4_0::DbValue::Int8(i),
+                pg4::DbValue::Int16(i) => pg4_0::DbValue::Int16(i),
+                pg4::DbValue::Int32(i) => pg4_0::DbValue::Int32(i),
+                pg4::DbValue::Int64(i) => pg4_0::DbValue::Int64(i),
+                pg4::DbValue::Floating32(r) => pg4_0::DbValue::Floating32(r),
+                pg4::DbValue::Floating64(r) => pg4_0::DbValue::Floating64(r),
+                pg4::DbValue::Str(s) => pg4_0::DbValue::Str(s),
+                pg4::DbValue::Binary(b) => pg4_0::DbValue::Binary(b),
+                pg4::DbValue::Date(d) => pg4_0::DbValue::Date(d),
+                pg4::DbValue::Time(t) => pg4_0::DbValue::Time(t),
+                pg4::DbValue::Datetime(dt) => pg4_0::DbValue::Datetime(dt),
+                pg4::DbValue::Timestamp(t) => pg4_0::DbValue::Timestamp(t),
+                pg4::DbValue::Uuid(u) => pg4_0::DbValue::Uuid(u),
+                pg4::DbValue::Jsonb(j) => pg4_0::DbValue::Jsonb(j),
+                pg4::DbValue::Decimal(d) => pg4_0::DbValue::Decimal(d),
+                pg4::DbValue::ArrayInt32(a) => pg4_0::DbValue::ArrayInt32(a),
+                pg4::DbValue::ArrayInt64(a) => pg4_0::DbValue::ArrayInt64(a),
+                pg4::DbValue::ArrayDecimal(a) => pg4_0::DbValue::ArrayDecimal(a),
+                pg4::DbValue::ArrayStr(a) => pg4_0::DbValue::ArrayStr(a),
+                pg4::DbValue::RangeInt32(r) => pg4_0::DbValue::RangeInt32(convert_range(r)),
+                pg4::DbValue::RangeInt64(r) => pg4_0::DbValue::RangeInt64(convert_range(r)),
+                pg4::DbValue::RangeDecimal(r) => pg4_0::DbValue::RangeDecimal(convert_range(r)),
+                pg4::DbValue::Interval(i) => pg4_0::DbValue::Interval(i.into()),
+                pg4::DbValue::DbNull => pg4_0::DbValue::DbNull,
+                pg4::DbValue::Unsupported(u) => pg4_0::DbValue::Unsupported(u),
+            }
+        }
+    }
+
     impl From<pg4::DbDataType> for v1::rdbms_types::DbDataType {
         fn from(value: pg4::DbDataType) -> v1::rdbms_types::DbDataType {
             match value {
@@ -202,6 +245,38 @@ mod rdbms_types {
         }
     }
 
+    impl From<pg4::DbDataType> for pg4_0::DbDataType {
+        fn from(value: pg4::DbDataType) -> pg4_0::DbDataType {
+            match value {
+                pg4::DbDataType::Boolean => pg4_0::DbDataType::Boolean,
+                pg4::DbDataType::Int8 => pg4_0::DbDataType::Int8,
+                pg4::DbDataType::Int16 => pg4_0::DbDataType::Int16,
+                pg4::DbDataType::Int32 => pg4_0::DbDataType::Int32,
+                pg4::DbDataType::Int64 => pg4_0::DbDataType::Int64,
+                pg4::DbDataType::Floating32 => pg4_0::DbDataType::Floating32,
+                pg4::DbDataType::Floating64 => pg4_0::DbDataType::Floating64,
+                pg4::DbDataType::Str => pg4_0::DbDataType::Str,
+                pg4::DbDataType::Binary => pg4_0::DbDataType::Binary,
+                pg4::DbDataType::Date => pg4_0::DbDataType::Date,
+                pg4::DbDataType::Time => pg4_0::DbDataType::Time,
+                pg4::DbDataType::Datetime => pg4_0::DbDataType::Datetime,
+                pg4::DbDataType::Timestamp => pg4_0::DbDataType::Timestamp,
+                pg4::DbDataType::Uuid => pg4_0::DbDataType::Uuid,
+                pg4::DbDataType::Jsonb => pg4_0::DbDataType::Jsonb,
+                pg4::DbDataType::Decimal => pg4_0::DbDataType::Decimal,
+                pg4::DbDataType::RangeInt32 => pg4_0::DbDataType::RangeInt32,
+                pg4::DbDataType::RangeInt64 => pg4_0::DbDataType::RangeInt64,
+                pg4::DbDataType::RangeDecimal => pg4_0::DbDataType::RangeDecimal,
+                pg4::DbDataType::ArrayInt32 => pg4_0::DbDataType::ArrayInt32,
+                pg4::DbDataType::ArrayInt64 => pg4_0::DbDataType::ArrayInt64,
+                pg4::DbDataType::ArrayDecimal => pg4_0::DbDataType::ArrayDecimal,
+                pg4::DbDataType::ArrayStr => pg4_0::DbDataType::ArrayStr,
+                pg4::DbDataType::Interval => pg4_0::DbDataType::Interval,
+                pg4::DbDataType::Other(o) => pg4_0::DbDataType::Other(o),
+            }
+        }
+    }
+
     impl From<v2::rdbms_types::DbDataType> for v1::rdbms_types::DbDataType {
         fn from(value: v2::rdbms_types::DbDataType) -> v1::rdbms_types::DbDataType {
             match value {
@@ -357,6 +432,94 @@ mod rdbms_types {
         }
     }
 
+    impl From<pg4_0::ParameterValue> for pg4::ParameterValue {
+        fn from(value: pg4_0::ParameterValue) -> pg4::ParameterValue {
+            match value {
+                pg4_0::ParameterValue::Boolean(b) => pg4::ParameterValue::Boolean(b),
+                pg4_0::ParameterValue::Int8(i) => pg4::ParameterValue::Int8(i),
+                pg4_0::ParameterValue::Int16(i) => pg4::ParameterValue::Int16(i),
+                pg4_0::ParameterValue::Int32(i) => pg4::ParameterValue::Int32(i),
+                pg4_0::ParameterValue::Int64(i) => pg4::ParameterValue::Int64(i),
+                pg4_0::ParameterValue::Floating32(r) => pg4::ParameterValue::Floating32(r),
+                pg4_0::ParameterValue::Floating64(r) => pg4::ParameterValue::Floating64(r),
+                pg4_0::ParameterValue::Str(s) => pg4::ParameterValue::Str(s),
+                pg4_0::ParameterValue::Binary(b) => pg4::ParameterValue::Binary(b),
+                pg4_0::ParameterValue::Date(d) => pg4::ParameterValue::Date(d),
+                pg4_0::ParameterValue::Time(t) => pg4::ParameterValue::Time(t),
+                pg4_0::ParameterValue::Datetime(dt) => pg4::ParameterValue::Datetime(dt),
+                pg4_0::ParameterValue::Timestamp(t) => pg4::ParameterValue::Timestamp(t),
+                pg4_0::ParameterValue::Uuid(u) => pg4::ParameterValue::Uuid(u),
+                pg4_0::ParameterValue::Jsonb(j) => pg4::ParameterValue::Jsonb(j),
+                pg4_0::ParameterValue::Decimal(d) => pg4::ParameterValue::Decimal(d),
+                pg4_0::ParameterValue::ArrayInt32(a) => pg4::ParameterValue::ArrayInt32(a),
+                pg4_0::ParameterValue::ArrayInt64(a) => pg4::ParameterValue::ArrayInt64(a),
+                pg4_0::ParameterValue::ArrayDecimal(a) => pg4::ParameterValue::ArrayDecimal(a),
+                pg4_0::ParameterValue::ArrayStr(a) => pg4::ParameterValue::ArrayStr(a),
+                pg4_0::ParameterValue::RangeInt32(r) => {
+                    pg4::ParameterValue::RangeInt32(convert_range(r))
+                }
+                pg4_0::ParameterValue::RangeInt64(r) => {
+                    pg4::ParameterValue::RangeInt64(convert_range(r))
+                }
+                pg4_0::ParameterValue::RangeDecimal(r) => {
+                    pg4::ParameterValue::RangeDecimal(convert_range(r))
+                }
+                pg4_0::ParameterValue::Interval(i) => pg4::ParameterValue::Interval(i.into()),
+                pg4_0::ParameterValue::DbNull => pg4::ParameterValue::DbNull,
+            }
+        }
+    }
+
+    impl From<pg4_0::Interval> for pg4::Interval {
+        fn from(value: pg4_0::Interval) -> pg4::Interval {
+            pg4::Interval {
+                micros: value.micros,
+                days: value.days,
+                months: value.months,
+            }
+        }
+    }
+
+    impl From<pg4::Interval> for pg4_0::Interval {
+        fn from(value: pg4::Interval) -> pg4_0::Interval {
+            pg4_0::Interval {
+                micros: value.micros,
+                days: value.days,
+                months: value.months,
+            }
+        }
+    }
+
+    impl From<pg4_0::RangeBoundKind> for pg4::RangeBoundKind {
+        fn from(value: pg4_0::RangeBoundKind) -> pg4::RangeBoundKind {
+            match value {
+                pg4_0::RangeBoundKind::Inclusive => pg4::RangeBoundKind::Inclusive,
+                pg4_0::RangeBoundKind::Exclusive => pg4::RangeBoundKind::Exclusive,
+            }
+        }
+    }
+
+    impl From<pg4::RangeBoundKind> for pg4_0::RangeBoundKind {
+        fn from(value: pg4::RangeBoundKind) -> pg4_0::RangeBoundKind {
+            match value {
+                pg4::RangeBoundKind::Inclusive => pg4_0::RangeBoundKind::Inclusive,
+                pg4::RangeBoundKind::Exclusive => pg4_0::RangeBoundKind::Exclusive,
+            }
+        }
+    }
+
+    type RangeBound<T, K> = Option<(T, K)>;
+
+    /// Converts the bound kinds of a range between interface versions.
+    fn convert_range<T, K: Into<L>, L>(
+        (lower, upper): (RangeBound<T, K>, RangeBound<T, K>),
+    ) -> (RangeBound<T, L>, RangeBound<T, L>) {
+        (
+            lower.map(|(value, kind)| (value, kind.into())),
+            upper.map(|(value, kind)| (value, kind.into())),
+        )
+    }
+
     impl From<v2::rdbms_types::Error> for v1::mysql::MysqlError {
         fn from(error: v2::rdbms_types::Error) -> v1::mysql::MysqlError {
             match error {
@@ -411,6 +574,34 @@ mod rdbms_types {
         }
     }
 
+    impl From<pg4::Error> for pg4_0::Error {
+        fn from(error: pg4::Error) -> pg4_0::Error {
+            match error {
+                pg4::Error::ConnectionFailed(e) => pg4_0::Error::ConnectionFailed(e),
+                pg4::Error::BadParameter(e) => pg4_0::Error::BadParameter(e),
+                pg4::Error::QueryFailed(e) => pg4_0::Error::QueryFailed(e.into()),
+                pg4::Error::ValueConversionFailed(e) => pg4_0::Error::ValueConversionFailed(e),
+                pg4::Error::Other(e) => pg4_0::Error::Other(e),
+            }
+        }
+    }
+
+    impl From<pg4::QueryError> for pg4_0::QueryError {
+        fn from(error: pg4::QueryError) -> pg4_0::QueryError {
+            match error {
+                pg4::QueryError::Text(text) => pg4_0::QueryError::Text(text),
+                pg4::QueryError::DbError(e) => pg4_0::QueryError::DbError(pg4_0::DbError {
+                    as_text: e.as_text,
+                    severity: e.severity,
+                    code: e.code,
+                    message: e.message,
+                    detail: e.detail,
+                    extras: e.extras,
+                }),
+            }
+        }
+    }
+
     pub fn pg_error_text(error: pg4::QueryError) -> String {
         match error {
             pg4::QueryError::Text(text) => text,
@@ -422,7 +613,8 @@ mod rdbms_types {
 mod postgres {
     use super::*;
     use spin::postgres3_0_0::postgres as pg3;
-    use spin::postgres4_0_0::postgres as pg4;
+    use spin::postgres4_0_0::postgres as pg4_0;
+    use spin::postgres4_1_0::postgres as pg4;
 
     impl From<pg4::RowSet> for v1::postgres::RowSet {
         fn from(value: pg4::RowSet) -> v1::postgres::RowSet {
@@ -462,6 +654,18 @@ mod postgres {
             }
         }
     }
+    impl From<pg4::RowSet> for pg4_0::RowSet {
+        fn from(value: pg4::RowSet) -> pg4_0::RowSet {
+            pg4_0::RowSet {
+                columns: value.columns.into_iter().map(Into::into).collect(),
+                rows: value
+                    .rows
+                    .into_iter()
+                    .map(|r| r.into_iter().map(Into::into).collect())
+                    .collect(),
+            }
+        }
+    }
 }
 
 mod mysql {
@@ -12,7 +12,10 @@ wasmtime::component::bindgen!({
         include fermyon:spin/platform@3.0.0;
         include spin:up/platform@3.2.0;
         include spin:up/platform@3.4.0;
+        include spin:up/platform@3.5.0;
         include wasi:keyvalue/imports@0.2.0-draft2;
+        export spin:mqtt/inbound-mqtt@3.0.0;
+        export spin:cron/inbound-cron@3.0.0;
     }
     "#,
     path: "../../wit",
@@ -35,6 +38,7 @@ wasmtime::component::bindgen!({
         "fermyon:spin/variables@2.0.0/error" => v2::variables::Error,
         "spin:postgres/postgres@3.0.0/error" => spin::postgres3_0_0::postgres::Error,
         "spin:postgres/postgres@4.0.0/error" => spin::postgres4_0_0::postgres::Error,
+        "spin:postgres/postgres@4.1.0/error" => spin::postgres4_1_0::postgres::Error,
         "spin:sqlite/sqlite/error" => spin::sqlite::sqlite::Error,
         "wasi:config/store@0.2.0-draft-2024-09-27/error" => wasi::config::store::Error,
         "wasi:keyvalue/store/error" => wasi::keyvalue::store::Error,
@@ -8,6 +8,7 @@ use spin_cli::commands::{
     cloud::{DeployCommand, LoginCommand},
     doctor::DoctorCommand,
     external::execute_external_subcommand,
+    kv::KeyValueCommands,
     new::{AddCommand, NewCommand},
     plugins::PluginCommands,
     registry::RegistryCommands,
@@ -19,23 +20,28 @@ use spin_cli::{build_info::*, subprocess::ExitStatusError};
 use spin_runtime_factors::FactorsBuilder;
 use spin_trigger::cli::help::HelpArgsOnlyTrigger;
 use spin_trigger::cli::FactorsTriggerCommand;
+use spin_trigger_command::{CommandExit, CommandTrigger};
+use spin_trigger_cron::CronTrigger;
 use spin_trigger_http::HttpTrigger;
+use spin_trigger_mqtt::MqttTrigger;
 use spin_trigger_redis::RedisTrigger;
 
 #[tokio::main]
 async fn main() {
     if let Err(err) = _main().await {
-        let code = match err.downcast_ref::<ExitStatusError>() {
+        let code = if let Some(e) = err.downcast_ref::<ExitStatusError>() {
             // If we encounter an `ExitStatusError` it means a subprocess has already
             // exited unsuccessfully and thus already printed error messages. No need
             // to print anything additional.
-            Some(e) => e.code(),
+            e.code()
+        } else if let Some(e) = err.downcast_ref::<CommandExit>() {
+            // Likewise a command component reports its own errors.
+            e.code()
+        } else {
             // Otherwise we print the error chain.
-            None => {
-                terminal::error!("{err}");
-                print_error_chain(err);
-                1
-            }
+            terminal::error!("{err}");
+            print_error_chain(err);
+            1
         };
 
         std::process::exit(code)
@@ -125,6 +131,8 @@ enum SpinApp {
     Login(LoginCommand),
     #[clap(subcommand, alias = "oci")]
     Registry(RegistryCommands),
+    #[clap(subcommand)]
+    Kv(KeyValueCommands),
     #[clap(alias = "b")]
     Build(BuildCommand),
     #[clap(subcommand, alias = "plugin")]
@@ -144,6 +152,9 @@ enum SpinApp {
 enum TriggerCommands {
     Http(FactorsTriggerCommand<HttpTrigger, FactorsBuilder>),
     Redis(FactorsTriggerCommand<RedisTrigger, FactorsBuilder>),
+    Mqtt(FactorsTriggerCommand<MqttTrigger, FactorsBuilder>),
+    Cron(FactorsTriggerCommand<CronTrigger, FactorsBuilder>),
+    Command(FactorsTriggerCommand<CommandTrigger, FactorsBuilder>),
     #[clap(name = spin_cli::HELP_ARGS_ONLY_TRIGGER_TYPE, hide = true)]
     HelpArgsOnly(FactorsTriggerCommand<HelpArgsOnlyTrigger, FactorsBuilder>),
 }
@@ -159,9 +170,13 @@ impl SpinApp {
             Self::Deploy(cmd) => cmd.run(SpinApp::command()).await,
             Self::Login(cmd) => cmd.run(SpinApp::command()).await,
             Self::Registry(cmd) => cmd.run().await,
+            Self::Kv(cmd) => cmd.run().await,
             Self::Build(cmd) => cmd.run().await,
             Self::Trigger(TriggerCommands::Http(cmd)) => cmd.run().await,
             Self::Trigger(TriggerCommands::Redis(cmd)) => cmd.run().await,
+            Self::Trigger(TriggerCommands::Mqtt(cmd)) => cmd.run().await,
+            Self::Trigger(TriggerCommands::Cron(cmd)) => cmd.run().await,
+            Self::Trigger(TriggerCommands::Command(cmd)) => cmd.run().await,
             Self::Trigger(TriggerCommands::HelpArgsOnly(cmd)) => cmd.run().await,
             Self::Plugins(cmd) => cmd.run().await,
             Self::External(cmd) => execute_external_subcommand(cmd, app).await,
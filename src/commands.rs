@@ -8,6 +8,8 @@ pub mod cloud;
 pub mod doctor;
 /// Commands for external subcommands (i.e. plugins)
 pub mod external;
+/// Commands for inspecting and seeding key-value stores.
+pub mod kv;
 /// Commands for Spin maintenance tasks.
 pub mod maintenance;
 /// Command for creating a new application.
@@ -0,0 +1,420 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use clap::{Args, Parser, Subcommand};
+use serde::{Deserialize, Serialize};
+use spin_factor_key_value::Store;
+use spin_trigger::cli::{UserProvidedPath, RUNTIME_CONFIG_FILE};
+
+use crate::opts::APP_MANIFEST_FILE_OPT;
+
+const DEFAULT_STORE_LABEL: &str = "default";
+/// How many keys to fetch per request when walking a store.
+const LIST_PAGE_SIZE: u32 = 1000;
+
+/// Commands for inspecting and seeding an application's key-value stores.
+#[derive(Subcommand, Debug)]
+pub enum KeyValueCommands {
+    /// Print the value of a key.
+    Get(Get),
+    /// Set the value of a key.
+    Set(Set),
+    /// Delete a key.
+    Delete(Delete),
+    /// List the keys in a store.
+    List(List),
+    /// Write every key and value in a store as JSON lines.
+    Export(Export),
+    /// Set keys and values from JSON lines, as written by `spin kv export`.
+    Import(Import),
+}
+
+impl KeyValueCommands {
+    pub async fn run(self) -> Result<()> {
+        match self {
+            KeyValueCommands::Get(cmd) => cmd.run().await,
+            KeyValueCommands::Set(cmd) => cmd.run().await,
+            KeyValueCommands::Delete(cmd) => cmd.run().await,
+            KeyValueCommands::List(cmd) => cmd.run().await,
+            KeyValueCommands::Export(cmd) => cmd.run().await,
+            KeyValueCommands::Import(cmd) => cmd.run().await,
+        }
+    }
+}
+
+/// Options identifying the store to operate on.
+#[derive(Args, Debug)]
+pub struct StoreOptions {
+    /// The application whose stores to use. This may be a manifest (spin.toml) file, or a
+    /// directory containing a spin.toml file.
+    /// If omitted, it defaults to "spin.toml".
+    #[clap(
+        name = APP_MANIFEST_FILE_OPT,
+        short = 'f',
+        long = "from",
+        alias = "file",
+    )]
+    pub app_source: Option<PathBuf>,
+
+    /// The label of the key-value store.
+    #[clap(short = 's', long = "store", default_value = DEFAULT_STORE_LABEL)]
+    pub store: String,
+
+    /// Configuration file defining the application's key-value stores.
+    #[clap(
+        name = RUNTIME_CONFIG_FILE,
+        long = "runtime-config-file",
+        env = RUNTIME_CONFIG_FILE,
+    )]
+    pub runtime_config_file: Option<PathBuf>,
+
+    /// The application state directory path, as passed to `spin up`.
+    ///
+    /// This defaults to `.spin/` relative to the `spin.toml` file.
+    #[clap(long)]
+    pub state_dir: Option<String>,
+}
+
+impl StoreOptions {
+    /// Opens the store, resolving it the same way `spin up` would.
+    async fn open(&self) -> Result<Arc<dyn Store>> {
+        let local_app_dir =
+            match spin_common::paths::find_manifest_file_path(self.app_source.as_ref()) {
+                Ok((manifest_file, _)) => {
+                    let manifest_file = std::path::absolute(manifest_file)?;
+                    manifest_file.parent().map(ToOwned::to_owned)
+                }
+                // An explicit state directory or runtime config is enough to find stores.
+                Err(_) if self.app_source.is_none() => None,
+                Err(e) => return Err(e),
+            };
+        let state_dir = match &self.state_dir {
+            Some(s) if s.is_empty() => UserProvidedPath::Unset,
+            Some(s) => UserProvidedPath::Provided(PathBuf::from(s)),
+            None => UserProvidedPath::Default,
+        };
+
+        let runtime_config = spin_runtime_config::key_value_runtime_config_from_file(
+            self.runtime_config_file.as_deref(),
+            local_app_dir,
+            state_dir,
+        )?;
+        let manager = runtime_config
+            .get_store_manager(&self.store)
+            .with_context(|| format!("no key-value store with label {:?}", self.store))?;
+        manager
+            .get(&self.store)
+            .await
+            .map_err(|e| anyhow!("failed to open key-value store {:?}: {e}", self.store))
+    }
+}
+
+#[derive(Parser, Debug)]
+pub struct Get {
+    #[clap(flatten)]
+    pub store: StoreOptions,
+
+    /// The key to get.
+    pub key: String,
+}
+
+impl Get {
+    pub async fn run(self) -> Result<()> {
+        let store = self.store.open().await?;
+        let Some(value) = store.get(&self.key).await? else {
+            bail!(
+                "key {:?} not found in store {:?}",
+                self.key,
+                self.store.store
+            );
+        };
+        std::io::stdout().write_all(&value)?;
+        Ok(())
+    }
+}
+
+#[derive(Parser, Debug)]
+pub struct Set {
+    #[clap(flatten)]
+    pub store: StoreOptions,
+
+    /// The key to set.
+    pub key: String,
+
+    /// The value to set. If omitted, the value is read from stdin.
+    pub value: Option<String>,
+
+    /// Expire the key after this many seconds.
+    #[clap(long = "ttl")]
+    pub ttl_seconds: Option<u64>,
+}
+
+impl Set {
+    pub async fn run(self) -> Result<()> {
+        let value = match self.value {
+            Some(value) => value.into_bytes(),
+            None => {
+                let mut value = vec![];
+                std::io::stdin().read_to_end(&mut value)?;
+                value
+            }
+        };
+        let store = self.store.open().await?;
+        match self.ttl_seconds {
+            Some(0) => bail!("--ttl must be greater than zero"),
+            Some(ttl) => {
+                store
+                    .set_with_ttl(&self.key, &value, Duration::from_secs(ttl))
+                    .await?
+            }
+            None => store.set(&self.key, &value).await?,
+        }
+        Ok(())
+    }
+}
+
+#[derive(Parser, Debug)]
+pub struct Delete {
+    #[clap(flatten)]
+    pub store: StoreOptions,
+
+    /// The key to delete.
+    pub key: String,
+}
+
+impl Delete {
+    pub async fn run(self) -> Result<()> {
+        let store = self.store.open().await?;
+        store.delete(&self.key).await?;
+        Ok(())
+    }
+}
+
+#[derive(Parser, Debug)]
+pub struct List {
+    #[clap(flatten)]
+    pub store: StoreOptions,
+
+    /// Only list keys starting with this prefix.
+    #[clap(long = "prefix")]
+    pub prefix: Option<String>,
+}
+
+impl List {
+    pub async fn run(self) -> Result<()> {
+        let store = self.store.open().await?;
+        for_each_key(&*store, self.prefix.as_deref(), |key| {
+            println!("{key}");
+            Ok(())
+        })
+        .await
+    }
+}
+
+#[derive(Parser, Debug)]
+pub struct Export {
+    #[clap(flatten)]
+    pub store: StoreOptions,
+
+    /// Only export keys starting with this prefix.
+    #[clap(long = "prefix")]
+    pub prefix: Option<String>,
+
+    /// The file to which to export. If omitted, it is written to stdout.
+    #[clap(short = 'o', long = "output")]
+    pub output: Option<PathBuf>,
+}
+
+impl Export {
+    pub async fn run(self) -> Result<()> {
+        let store = self.store.open().await?;
+        let mut output: Box<dyn Write> = match &self.output {
+            Some(path) => Box::new(std::io::BufWriter::new(
+                std::fs::File::create(path)
+                    .with_context(|| format!("failed to create {}", path.display()))?,
+            )),
+            None => Box::new(std::io::stdout()),
+        };
+
+        export(&*store, self.prefix.as_deref(), &mut output).await
+    }
+}
+
+#[derive(Parser, Debug)]
+pub struct Import {
+    #[clap(flatten)]
+    pub store: StoreOptions,
+
+    /// The file from which to import. If omitted, it is read from stdin.
+    #[clap(short = 'i', long = "input")]
+    pub input: Option<PathBuf>,
+}
+
+impl Import {
+    pub async fn run(self) -> Result<()> {
+        let input: Box<dyn BufRead> = match &self.input {
+            Some(path) => Box::new(BufReader::new(
+                std::fs::File::open(path)
+                    .with_context(|| format!("failed to open {}", path.display()))?,
+            )),
+            None => Box::new(BufReader::new(std::io::stdin())),
+        };
+        let store = self.store.open().await?;
+        import(&*store, input).await
+    }
+}
+
+/// Writes every key starting with `prefix`, with its value and expiry, as JSON lines.
+async fn export(store: &dyn Store, prefix: Option<&str>, mut output: impl Write) -> Result<()> {
+    let mut keys = vec![];
+    for_each_key(store, prefix, |key| {
+        keys.push(key);
+        Ok(())
+    })
+    .await?;
+    for key in keys {
+        // The key may have been deleted or expired since it was listed.
+        let Some((value, ttl)) = store.get_with_ttl(&key).await? else {
+            continue;
+        };
+        let entry = Entry {
+            key,
+            value: BASE64.encode(value),
+            expires_at: ttl.map(|ttl| unix_millis(SystemTime::now() + ttl)),
+        };
+        serde_json::to_writer(&mut output, &entry)?;
+        writeln!(output)?;
+    }
+    output.flush()?;
+    Ok(())
+}
+
+/// Sets the keys read as JSON lines from `input`, skipping any which have since expired.
+async fn import(store: &dyn Store, input: impl BufRead) -> Result<()> {
+    for (index, line) in input.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry: Entry = serde_json::from_str(&line)
+            .with_context(|| format!("invalid entry on line {}", index + 1))?;
+        let value = BASE64
+            .decode(&entry.value)
+            .with_context(|| format!("invalid base64 value on line {}", index + 1))?;
+        match entry.expires_at {
+            Some(expires_at) => {
+                let expires_at = UNIX_EPOCH + Duration::from_millis(expires_at);
+                let Ok(ttl) = expires_at.duration_since(SystemTime::now()) else {
+                    continue;
+                };
+                if ttl.is_zero() {
+                    continue;
+                }
+                store.set_with_ttl(&entry.key, &value, ttl).await?;
+            }
+            None => store.set(&entry.key, &value).await?,
+        }
+    }
+    Ok(())
+}
+
+/// Milliseconds since the Unix epoch, as written in [`Entry::expires_at`].
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+        .try_into()
+        .unwrap_or(u64::MAX)
+}
+
+/// A key-value pair as written by `spin kv export`, with the value base64-encoded.
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    key: String,
+    value: String,
+    /// When the key expires, in milliseconds since the Unix epoch, if it was set with a TTL.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires_at: Option<u64>,
+}
+
+/// Calls `f` with each key in the store starting with `prefix`, a page at a time.
+async fn for_each_key(
+    store: &dyn Store,
+    prefix: Option<&str>,
+    mut f: impl FnMut(String) -> Result<()>,
+) -> Result<()> {
+    let mut cursor = None;
+    loop {
+        let page = store
+            .list_keys(prefix, cursor.as_deref(), LIST_PAGE_SIZE)
+            .await?;
+        for key in page.keys {
+            f(key)?;
+        }
+        cursor = page.cursor;
+        if cursor.is_none() {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use spin_factor_key_value::{runtime_config::spin::MakeKeyValueStore, StoreManager};
+    use spin_key_value_spin::{SpinKeyValueRuntimeConfig, SpinKeyValueStore};
+
+    use super::*;
+
+    async fn in_memory_store() -> Result<Arc<dyn Store>> {
+        let manager =
+            SpinKeyValueStore::new(None).make_store(SpinKeyValueRuntimeConfig::new(None))?;
+        Ok(manager.get("default").await?)
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn export_import_round_trip() -> Result<()> {
+        let source = in_memory_store().await?;
+        source.set("plain", b"\0binary\xff").await?;
+        source
+            .set_with_ttl("expiring", b"soon", Duration::from_secs(3600))
+            .await?;
+        source
+            .set_with_ttl("expired", b"gone", Duration::ZERO)
+            .await?;
+
+        let mut exported = vec![];
+        export(&*source, None, &mut exported).await?;
+
+        let target = in_memory_store().await?;
+        import(&*target, exported.as_slice()).await?;
+
+        assert_eq!(
+            Some((b"\0binary\xff".to_vec(), None)),
+            target.get_with_ttl("plain").await?
+        );
+        let (value, ttl) = target.get_with_ttl("expiring").await?.unwrap();
+        assert_eq!(b"soon".to_vec(), value);
+        let ttl = ttl.expect("expiry should be imported");
+        assert!(ttl > Duration::from_secs(3500) && ttl <= Duration::from_secs(3600));
+        assert!(!target.exists("expired").await?);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn import_skips_expired_entries() -> Result<()> {
+        let store = in_memory_store().await?;
+        let input = r#"{"key":"old","value":"YQ==","expires_at":1}
+{"key":"new","value":"Yg=="}
+"#;
+        import(&*store, input.as_bytes()).await?;
+        assert!(!store.exists("old").await?);
+        assert_eq!(Some(b"b".to_vec()), store.get("new").await?);
+        Ok(())
+    }
+}
@@ -213,6 +213,8 @@ impl UpCommand {
             )?;
         }
 
+        exclude_command_triggers(&mut locked_app, !self.components.is_empty())?;
+
         let trigger_types: HashSet<&str> = locked_app
             .triggers
             .iter()
@@ -563,6 +565,11 @@ fn set_kill_on_ctrl_c(_pids: &[usize]) -> Result<(), anyhow::Error> {
     Ok(())
 }
 
+/// Forwards each shutdown signal to the trigger processes as a `SIGTERM`.
+///
+/// An interactive Ctrl-C also reaches the triggers directly from the terminal; the
+/// triggers ignore a repeat arriving right after the first signal, so this doesn't cut
+/// their graceful shutdown short.
 #[cfg(not(windows))]
 fn set_kill_on_ctrl_c(pids: &[nix::unistd::Pid]) -> Result<(), anyhow::Error> {
     let pids = pids.to_owned();
@@ -663,6 +670,48 @@ fn resolve_trigger_plugin(trigger_type: &str) -> Result<String> {
     }
 }
 
+/// Command triggers run a component once and exit, which ends `spin up` and
+/// so would stop any other triggers. In applications with other triggers,
+/// they only run if selected with `--component-id`, on their own.
+fn exclude_command_triggers(locked_app: &mut LockedApp, components_selected: bool) -> Result<()> {
+    let is_command = |t: &spin_app::locked::LockedTrigger| t.trigger_type == "command";
+    if locked_app.triggers.iter().all(is_command) || !locked_app.triggers.iter().any(is_command) {
+        return Ok(());
+    }
+    ensure!(
+        !components_selected,
+        "Components with command triggers can't run alongside components with other triggers: select them on their own with --component-id"
+    );
+    let (commands, others): (Vec<_>, Vec<_>) = std::mem::take(&mut locked_app.triggers)
+        .into_iter()
+        .partition(is_command);
+    locked_app.triggers = others;
+    let components: Vec<_> = commands
+        .iter()
+        .filter_map(|t| t.trigger_config.get("component")?.as_str())
+        .collect();
+    println!(
+        "Not running command components {}: select one with --component-id to run it",
+        components.join(", ")
+    );
+    Ok(())
+}
+
+/// Whether the plugin for a trigger type is installed.
+fn is_trigger_plugin_installed(trigger_type: &str) -> bool {
+    use spin_plugins::manager::PluginManager;
+
+    let subcommand = format!("trigger-{trigger_type}");
+    PluginManager::try_default().is_ok_and(|plugin_manager| {
+        plugin_manager
+            .store()
+            .installed_manifests()
+            .unwrap_or_default()
+            .iter()
+            .any(|m| m.name() == subcommand)
+    })
+}
+
 fn trigger_command(trigger_type: &str) -> Vec<String> {
     vec!["trigger".to_owned(), trigger_type.to_owned()]
 }
@@ -671,7 +720,10 @@ fn trigger_commands_for_trigger_types(trigger_types: Vec<&str>) -> Result<Vec<Ve
     trigger_types
         .iter()
         .map(|&t| match t {
-            "http" | "redis" => Ok(trigger_command(t)),
+            "http" | "redis" | "command" => Ok(trigger_command(t)),
+            // Applications written for the MQTT and cron trigger plugins keep
+            // using them if they are installed.
+            "mqtt" | "cron" if !is_trigger_plugin_installed(t) => Ok(trigger_command(t)),
             _ => {
                 let cmd = resolve_trigger_plugin(t)?;
                 Ok(vec![cmd])
@@ -847,6 +899,59 @@ mod test {
             .expect("Failed to parse implicit source with trigger option");
     }
 
+    fn locked_app(triggers: &[(&str, &str)]) -> LockedApp {
+        let triggers: Vec<_> = triggers
+            .iter()
+            .enumerate()
+            .map(|(i, (trigger_type, component))| {
+                serde_json::json!({
+                    "id": format!("trigger{i}"),
+                    "trigger_type": trigger_type,
+                    "trigger_config": { "component": component },
+                })
+            })
+            .collect();
+        serde_json::from_value(serde_json::json!({
+            "spin_lock_version": 1,
+            "triggers": triggers,
+            "components": [],
+        }))
+        .unwrap()
+    }
+
+    fn trigger_types(locked_app: &LockedApp) -> Vec<&str> {
+        locked_app
+            .triggers
+            .iter()
+            .map(|t| t.trigger_type.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn command_triggers_run_on_their_own() {
+        let mut app = locked_app(&[("command", "migrate")]);
+        exclude_command_triggers(&mut app, false).unwrap();
+        assert_eq!(trigger_types(&app), ["command"]);
+
+        let mut app = locked_app(&[("command", "migrate")]);
+        exclude_command_triggers(&mut app, true).unwrap();
+        assert_eq!(trigger_types(&app), ["command"]);
+
+        let mut app = locked_app(&[("http", "web"), ("redis", "worker")]);
+        exclude_command_triggers(&mut app, false).unwrap();
+        assert_eq!(trigger_types(&app), ["http", "redis"]);
+    }
+
+    #[test]
+    fn command_triggers_are_not_run_alongside_other_triggers() {
+        let mut app = locked_app(&[("http", "web"), ("command", "migrate")]);
+        exclude_command_triggers(&mut app, false).unwrap();
+        assert_eq!(trigger_types(&app), ["http"]);
+
+        let mut app = locked_app(&[("http", "web"), ("command", "migrate")]);
+        exclude_command_triggers(&mut app, true).unwrap_err();
+    }
+
     #[test]
     fn group_no_args_is_empty() {
         let cmd = UpCommand::try_parse_from(["up"]).unwrap();